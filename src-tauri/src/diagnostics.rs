@@ -4,13 +4,24 @@ use serde::Serialize;
 use tauri::Emitter;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 const LOG_DIR_NAME: &str = "Local Private LLM";
 const LOG_SUBDIR: &str = "logs";
 const LOG_FILE: &str = "app.log";
 const ROTATE_SIZE_BYTES: u64 = 5 * 1024 * 1024; // 5 MB
 
+/// When false (ephemeral mode), logs are only emitted to the frontend, never written to disk.
+static FILE_LOGGING: AtomicBool = AtomicBool::new(true);
+
+/// Disable persisting logs to logs/app.log. Used by ephemeral mode.
+pub fn disable_file_logging() {
+    FILE_LOGGING.store(false, Ordering::Relaxed);
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct DiagnosticPayload {
     pub ts: u64,
@@ -20,7 +31,21 @@ pub struct DiagnosticPayload {
     pub meta: Option<serde_json::Value>,
 }
 
+/// Serializes appends/rotation with clear_log so a clear never races a write.
+static LOG_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Log directory when a custom data directory is configured (`<data_dir>/logs`).
+static LOG_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Persist logs under `<data_dir>/logs` instead of the default app data dir. Call once at startup.
+pub fn set_data_dir(data_dir: &Path) {
+    let _ = LOG_DIR_OVERRIDE.set(data_dir.join(LOG_SUBDIR));
+}
+
 fn log_dir() -> Option<PathBuf> {
+    if let Some(dir) = LOG_DIR_OVERRIDE.get() {
+        return Some(dir.clone());
+    }
     dirs::data_local_dir()
         .or_else(dirs::home_dir)
         .map(|p| p.join(LOG_DIR_NAME).join(LOG_SUBDIR))
@@ -36,10 +61,134 @@ fn log_path() -> Option<PathBuf> {
     ensure_log_dir().map(|d| d.join(LOG_FILE))
 }
 
+/// Where app.log is moved when it reaches ROTATE_SIZE_BYTES.
+fn rotated_path(path: &Path) -> PathBuf {
+    path.with_extension("log.old")
+}
+
+/// Location and size of the log file, for the log viewer's open/clear actions.
+#[derive(Clone, Debug, Serialize)]
+pub struct LogFileInfo {
+    pub path: String,
+    pub exists: bool,
+    pub size_bytes: u64,
+    /// Previous log moved aside by rotation (`app.log.old`), when present.
+    pub rotated_path: Option<String>,
+    pub rotated_size_bytes: u64,
+    /// False in ephemeral mode: nothing is written to the file.
+    pub file_logging_enabled: bool,
+}
+
+/// Info about app.log; creates the log directory if needed. None if no log directory can be determined.
+pub fn log_file_info() -> Option<LogFileInfo> {
+    let path = log_path()?;
+    let meta = std::fs::metadata(&path).ok();
+    let rotated = rotated_path(&path);
+    let rotated_meta = std::fs::metadata(&rotated).ok();
+    Some(LogFileInfo {
+        path: path.to_string_lossy().to_string(),
+        exists: meta.is_some(),
+        size_bytes: meta.map(|m| m.len()).unwrap_or(0),
+        rotated_path: rotated_meta.as_ref().map(|_| rotated.to_string_lossy().to_string()),
+        rotated_size_bytes: rotated_meta.map(|m| m.len()).unwrap_or(0),
+        file_logging_enabled: FILE_LOGGING.load(Ordering::Relaxed),
+    })
+}
+
+/// Whether `file_name` is a rotated copy of the log named `name`: `<name>.old` (see rotated_path)
+/// or a numbered `<name>.<n>`.
+fn is_rotated_name(file_name: &str, name: &str) -> bool {
+    file_name
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .is_some_and(|suffix| suffix == "old" || (!suffix.is_empty() && suffix.bytes().all(|b| b.is_ascii_digit())))
+}
+
+/// Rotated copies of `path` with their sizes (see is_rotated_name).
+fn rotated_siblings(path: &Path) -> Vec<(PathBuf, u64)> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|n| is_rotated_name(n, name))
+        })
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            meta.is_file().then(|| (e.path(), meta.len()))
+        })
+        .collect()
+}
+
+/// Bytes used by the log file and its rotated copies.
+#[derive(Clone, Debug, Serialize)]
+pub struct LogDiskUsage {
+    pub total_bytes: u64,
+    /// app.log (when present) plus each rotated copy.
+    pub file_count: usize,
+}
+
+fn log_disk_usage_at(path: &Path) -> LogDiskUsage {
+    let mut usage = LogDiskUsage {
+        total_bytes: 0,
+        file_count: 0,
+    };
+    if let Ok(meta) = std::fs::metadata(path) {
+        usage.total_bytes += meta.len();
+        usage.file_count += 1;
+    }
+    for (_, size) in rotated_siblings(path) {
+        usage.total_bytes += size;
+        usage.file_count += 1;
+    }
+    usage
+}
+
+/// Disk usage of app.log and all rotated siblings. None if no log directory can be determined.
+pub fn log_disk_usage() -> Option<LogDiskUsage> {
+    log_path().map(|path| log_disk_usage_at(&path))
+}
+
+/// Truncate `path` and delete all its rotated siblings. Returns the bytes freed.
+fn clear_log_at(path: &Path) -> u64 {
+    let mut freed = 0;
+    if let Ok(meta) = std::fs::metadata(path) {
+        if OpenOptions::new().write(true).truncate(true).open(path).is_ok() {
+            freed += meta.len();
+        }
+    }
+    for (rotated, size) in rotated_siblings(path) {
+        if std::fs::remove_file(&rotated).is_ok() {
+            freed += size;
+        }
+    }
+    freed
+}
+
+/// Empty app.log and remove every rotated copy, then record a single "log cleared" line.
+/// Returns the number of bytes freed.
+pub fn clear_log(window: Option<&tauri::Window>) -> u64 {
+    let freed = match log_path() {
+        Some(path) => {
+            let _guard = LOG_FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            clear_log_at(&path)
+        }
+        None => 0,
+    };
+    log(window, "INFO", "log cleared", Some(serde_json::json!({ "bytes_freed": freed })));
+    freed
+}
+
 fn rotate_if_needed(path: &PathBuf) {
     if let Ok(meta) = std::fs::metadata(path) {
         if meta.len() >= ROTATE_SIZE_BYTES {
-            let old = path.with_extension("log.old");
+            let old = rotated_path(path);
             let _ = std::fs::remove_file(&old);
             let _ = std::fs::rename(path, &old);
         }
@@ -47,16 +196,20 @@ fn rotate_if_needed(path: &PathBuf) {
 }
 
 fn write_to_file(payload: &DiagnosticPayload) {
+    if !FILE_LOGGING.load(Ordering::Relaxed) {
+        return;
+    }
     let path = match log_path() {
         Some(p) => p,
         None => return,
     };
-    rotate_if_needed(&path);
     let line = if let Some(ref m) = payload.meta {
         format!("{} [{}] {} {}\n", payload.ts, payload.level, payload.message, m)
     } else {
         format!("{} [{}] {}\n", payload.ts, payload.level, payload.message)
     };
+    let _guard = LOG_FILE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    rotate_if_needed(&path);
     if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(&path) {
         let _ = f.write_all(line.as_bytes());
     }
@@ -85,3 +238,129 @@ pub fn log(
         let _ = w.emit("diagnostic-log", &payload);
     }
 }
+
+/// Timeout for each connectivity probe in check_network.
+pub const NETWORK_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reachability of one endpoint. `reachable` means the server answered (any HTTP status).
+#[derive(Clone, Debug, Serialize)]
+pub struct NetworkProbe {
+    pub name: String,
+    pub url: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub status: Option<u16>,
+    /// "dns" | "connect" | "tls" | "timeout" | "other" when unreachable.
+    pub error_kind: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Best-effort classification of a failed request. reqwest does not expose DNS/TLS failures as
+/// distinct kinds, so the error source chain is inspected.
+fn classify_request_error(err: &reqwest::Error) -> &'static str {
+    if err.is_timeout() {
+        return "timeout";
+    }
+    let mut chain = String::new();
+    let mut source: Option<&dyn std::error::Error> = Some(err);
+    while let Some(e) = source {
+        chain.push_str(&e.to_string().to_lowercase());
+        chain.push(' ');
+        source = e.source();
+    }
+    if chain.contains("dns error")
+        || chain.contains("failed to lookup address")
+        || chain.contains("name or service not known")
+        || chain.contains("no such host")
+    {
+        "dns"
+    } else if chain.contains("certificate") || chain.contains("tls") || chain.contains("ssl") || chain.contains("handshake") {
+        "tls"
+    } else if err.is_connect() {
+        "connect"
+    } else {
+        "other"
+    }
+}
+
+/// GET `url` and report reachability, latency, and the kind of failure.
+pub async fn probe_url(client: &reqwest::Client, name: &str, url: &str) -> NetworkProbe {
+    let start = Instant::now();
+    let result = client.get(url).timeout(NETWORK_PROBE_TIMEOUT).send().await;
+    let latency_ms = Some(start.elapsed().as_millis() as u64);
+    match result {
+        Ok(res) => NetworkProbe {
+            name: name.to_string(),
+            url: url.to_string(),
+            reachable: true,
+            latency_ms,
+            status: Some(res.status().as_u16()),
+            error_kind: None,
+            error: None,
+        },
+        Err(e) => NetworkProbe {
+            name: name.to_string(),
+            url: url.to_string(),
+            reachable: false,
+            latency_ms: None,
+            status: None,
+            error_kind: Some(classify_request_error(&e).to_string()),
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_log_truncates_and_removes_rotated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let path = dir.join(LOG_FILE);
+        std::fs::write(&path, "0123456789").unwrap();
+        std::fs::write(rotated_path(&path), "abcde").unwrap();
+        std::fs::write(dir.join("app.log.1"), "xyz").unwrap();
+        std::fs::write(dir.join("other.log"), "keep").unwrap();
+        std::fs::write(dir.join("app.log.bak"), "keep").unwrap();
+        std::fs::write(dir.join("app.logger"), "keep").unwrap();
+        let usage = log_disk_usage_at(&path);
+        assert_eq!((usage.total_bytes, usage.file_count), (18, 3));
+        assert_eq!(clear_log_at(&path), 18);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        assert!(!rotated_path(&path).exists());
+        assert!(!dir.join("app.log.1").exists());
+        assert!(dir.join("other.log").exists());
+        assert!(dir.join("app.log.bak").exists());
+        assert!(dir.join("app.logger").exists());
+        assert_eq!(log_disk_usage_at(&path).total_bytes, 0);
+        assert_eq!(clear_log_at(&path), 0);
+    }
+
+    #[tokio::test]
+    async fn probe_reports_reachable_and_connect_failures() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            if let Ok((mut sock, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = sock.read(&mut buf).await;
+                let _ = sock
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok")
+                    .await;
+            }
+        });
+        let client = reqwest::Client::new();
+        let ok = probe_url(&client, "local", &format!("http://{}/", addr)).await;
+        assert!(ok.reachable);
+        assert_eq!(ok.status, Some(200));
+
+        // Bind then drop to get a port with nothing listening.
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let failed = probe_url(&client, "closed", &format!("http://{}/", closed)).await;
+        assert!(!failed.reachable);
+        assert_eq!(failed.error_kind.as_deref(), Some("connect"));
+    }
+}
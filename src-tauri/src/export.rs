@@ -0,0 +1,633 @@
+//! Conversation export: markdown/json/html rendering and deterministic, filesystem-safe filenames,
+//! plus a streaming NDJSON export/import of the whole history.
+
+use crate::storage::{ConversationRecordRow, ConversationRow, MessageRow, MessageVariant, Storage, StorageError};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Max characters kept from the title in an export filename.
+const MAX_SLUG_CHARS: usize = 60;
+
+/// Names Windows refuses as file stems regardless of extension.
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9",
+    "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Lowercase, filesystem-safe slug: letters/digits kept, everything else (illegal path characters,
+/// whitespace, emoji, punctuation) collapsed into single dashes. May be empty.
+fn slugify_title(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(|c| c.to_lowercase()) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.chars().take(MAX_SLUG_CHARS).collect();
+    let slug = slug.trim_matches('-').to_string();
+    if WINDOWS_RESERVED_NAMES.contains(&slug.as_str()) {
+        format!("{}-chat", slug)
+    } else {
+        slug
+    }
+}
+
+fn short_id(id: &str) -> String {
+    id.chars().filter(|c| c.is_ascii_alphanumeric()).take(8).collect()
+}
+
+/// Filename stem `YYYY-MM-DD-title-slug` from the conversation's creation date.
+/// Empty titles (after sanitizing) use the short conversation id instead.
+pub fn export_file_stem(title: &str, created_at: i64, id: &str) -> String {
+    let date = Utc
+        .timestamp_opt(created_at, 0)
+        .single()
+        .unwrap_or_else(Utc::now)
+        .format("%Y-%m-%d");
+    let slug = slugify_title(title);
+    if slug.is_empty() {
+        format!("{}-conversation-{}", date, short_id(id))
+    } else {
+        format!("{}-{}", date, slug)
+    }
+}
+
+/// Full export path in `dir`. If that file already exists (e.g. two chats with the same title on
+/// the same day), the short conversation id is appended so exports never overwrite each other.
+pub fn export_path(dir: &Path, title: &str, created_at: i64, id: &str, ext: &str) -> PathBuf {
+    let stem = export_file_stem(title, created_at, id);
+    let candidate = dir.join(format!("{}.{}", stem, ext));
+    if !candidate.exists() {
+        return candidate;
+    }
+    dir.join(format!("{}-{}.{}", stem, short_id(id), ext))
+}
+
+fn format_timestamp(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+/// Render a conversation as Markdown: title heading, then one section per message.
+pub fn to_markdown(conv: &ConversationRow, messages: &[MessageRow]) -> String {
+    let mut out = format!("# {}\n\n", conv.title);
+    for m in messages {
+        let role = match m.role.as_str() {
+            "user" => "User",
+            "assistant" => "Assistant",
+            "system" => "System",
+            other => other,
+        };
+        out.push_str(&format!("### {} ({})\n\n{}\n\n", role, format_timestamp(m.timestamp), m.content.trim_end()));
+    }
+    out
+}
+
+/// Stylesheet embedded in HTML exports so the file is viewable on its own.
+const HTML_EXPORT_CSS: &str = "body{font-family:system-ui,-apple-system,Segoe UI,sans-serif;max-width:820px;margin:2rem auto;padding:0 1rem;color:#1f2328;line-height:1.55}\
+h1{font-size:1.6rem;margin-bottom:.2rem}.meta{color:#656d76;font-size:.85rem;margin-top:0}\
+.message{border:1px solid #d0d7de;border-radius:8px;padding:.75rem 1rem;margin:1rem 0}\
+.message.user{background:#f6f8fa}.message header{font-size:.8rem;color:#656d76;margin-bottom:.4rem}\
+.message header .role{font-weight:600;color:#1f2328}\
+pre{background:#0d1117;color:#e6edf3;padding:.75rem;border-radius:6px;overflow-x:auto}\
+code{font-family:ui-monospace,SFMono-Regular,Menlo,monospace;font-size:.9em}\
+:not(pre)>code{background:#eff1f3;padding:.1em .3em;border-radius:4px}a{color:#0969da}";
+
+/// Escape text for use in HTML content and double-quoted attribute values.
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Only these link targets become anchors; anything else (javascript:, data:) stays plain text.
+fn is_safe_link(url: &str) -> bool {
+    let lower = url.trim().to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:")
+}
+
+/// Inline Markdown (`code`, **bold**, *italic*, [text](url)) to HTML; all text is escaped.
+fn render_inline(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                out.push_str(&format!("<code>{}</code>", escape_html(&rest[1..1 + end])));
+                rest = &rest[end + 2..];
+                continue;
+            }
+        } else if rest.starts_with("**") {
+            if let Some(end) = rest[2..].find("**").filter(|&e| e > 0) {
+                out.push_str(&format!("<strong>{}</strong>", render_inline(&rest[2..2 + end])));
+                rest = &rest[end + 4..];
+                continue;
+            }
+        } else if c == '*' {
+            if let Some(end) = rest[1..].find('*').filter(|&e| e > 0) {
+                out.push_str(&format!("<em>{}</em>", render_inline(&rest[1..1 + end])));
+                rest = &rest[end + 2..];
+                continue;
+            }
+        } else if c == '[' {
+            if let Some(mid) = rest.find("](") {
+                if let Some(close) = rest[mid + 2..].find(')') {
+                    let label = &rest[1..mid];
+                    let url = &rest[mid + 2..mid + 2 + close];
+                    if is_safe_link(url) {
+                        out.push_str(&format!(
+                            "<a href=\"{}\" rel=\"noopener noreferrer\">{}</a>",
+                            escape_html(url.trim()),
+                            render_inline(label)
+                        ));
+                        rest = &rest[mid + 3 + close..];
+                        continue;
+                    }
+                }
+            }
+        }
+        out.push_str(&escape_html(&rest[..c.len_utf8()]));
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// List item marker: Some((ordered, item text)) for "- x", "* x", "+ x" or "1. x".
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    let trimmed = line.trim_start();
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(marker) {
+            return Some((false, item));
+        }
+    }
+    let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(item) = trimmed[digits..].strip_prefix(". ") {
+            return Some((true, item));
+        }
+    }
+    None
+}
+
+/// Block-level Markdown (headings, fenced code with a language class, lists, paragraphs) to
+/// HTML. Raw HTML in the input is escaped, never passed through.
+fn markdown_to_html(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut list: Option<bool> = None;
+    let flush_paragraph = |out: &mut String, paragraph: &mut Vec<String>| {
+        if !paragraph.is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", paragraph.join("<br>\n")));
+            paragraph.clear();
+        }
+    };
+    let close_list = |out: &mut String, list: &mut Option<bool>| {
+        if let Some(ordered) = list.take() {
+            out.push_str(if ordered { "</ol>\n" } else { "</ul>\n" });
+        }
+    };
+    let mut lines = markdown.lines();
+    while let Some(line) = lines.next() {
+        if let Some(info) = line.trim_start().strip_prefix("```") {
+            flush_paragraph(&mut out, &mut paragraph);
+            close_list(&mut out, &mut list);
+            let lang: String = info
+                .trim()
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+'))
+                .collect();
+            let mut code = Vec::new();
+            for code_line in lines.by_ref() {
+                if code_line.trim_start().starts_with("```") {
+                    break;
+                }
+                code.push(code_line);
+            }
+            let class = if lang.is_empty() { String::new() } else { format!(" class=\"language-{}\"", lang) };
+            out.push_str(&format!("<pre><code{}>{}</code></pre>\n", class, escape_html(&code.join("\n"))));
+            continue;
+        }
+        if line.trim().is_empty() {
+            flush_paragraph(&mut out, &mut paragraph);
+            close_list(&mut out, &mut list);
+            continue;
+        }
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+            flush_paragraph(&mut out, &mut paragraph);
+            close_list(&mut out, &mut list);
+            out.push_str(&format!("<h{0}>{1}</h{0}>\n", hashes, render_inline(line[hashes..].trim())));
+            continue;
+        }
+        if let Some((ordered, item)) = list_item(line) {
+            flush_paragraph(&mut out, &mut paragraph);
+            if list != Some(ordered) {
+                close_list(&mut out, &mut list);
+                out.push_str(if ordered { "<ol>\n" } else { "<ul>\n" });
+                list = Some(ordered);
+            }
+            out.push_str(&format!("<li>{}</li>\n", render_inline(item.trim())));
+            continue;
+        }
+        close_list(&mut out, &mut list);
+        paragraph.push(render_inline(line.trim_end()));
+    }
+    flush_paragraph(&mut out, &mut paragraph);
+    close_list(&mut out, &mut list);
+    out
+}
+
+/// Render a conversation as a self-contained HTML page: title, created/updated times, and one
+/// card per message with its Markdown converted to HTML. All stored text is escaped.
+pub fn to_html(conv: &ConversationRow, messages: &[MessageRow]) -> String {
+    let title = escape_html(&conv.title);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{0}</title>\n<style>{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n\
+         <p class=\"meta\">Created {2} · Updated {3}</p>\n",
+        title,
+        HTML_EXPORT_CSS,
+        format_timestamp(conv.created_at),
+        format_timestamp(conv.updated_at)
+    );
+    for m in messages {
+        let role = match m.role.as_str() {
+            "user" => "User",
+            "assistant" => "Assistant",
+            "system" => "System",
+            other => other,
+        };
+        let model = m
+            .model
+            .as_deref()
+            .filter(|_| m.role == "assistant")
+            .map(|model| format!(" · {}", escape_html(model)))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "<article class=\"message {}\">\n<header><span class=\"role\">{}</span> · <time>{}</time>{}</header>\n{}</article>\n",
+            escape_html(&m.role),
+            escape_html(role),
+            format_timestamp(m.timestamp),
+            model,
+            markdown_to_html(&m.content)
+        ));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Render a conversation as pretty-printed JSON.
+pub fn to_json(conv: &ConversationRow, messages: &[MessageRow]) -> String {
+    let value = serde_json::json!({
+        "id": conv.id,
+        "title": conv.title,
+        "created_at": conv.created_at,
+        "updated_at": conv.updated_at,
+        "messages": messages.iter().map(|m| serde_json::json!({
+            "id": m.id,
+            "role": m.role,
+            "content": m.content,
+            "timestamp": m.timestamp,
+            "model": m.model,
+            "starred": m.starred,
+            "interrupted": m.interrupted,
+        })).collect::<Vec<_>>(),
+    });
+    serde_json::to_string_pretty(&value).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// First record of an NDJSON export; imports reject files without it.
+pub const NDJSON_FORMAT: &str = "local-private-llm-ndjson";
+pub const NDJSON_VERSION: u32 = 1;
+/// Records between progress callbacks.
+const NDJSON_PROGRESS_EVERY: usize = 500;
+
+/// One line of an NDJSON export: a header, then all conversations, then all messages.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NdjsonRecord {
+    Header {
+        format: String,
+        version: u32,
+        exported_at: i64,
+    },
+    Conversation {
+        id: String,
+        title: String,
+        created_at: i64,
+        updated_at: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model_override: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        system_prompt: Option<String>,
+    },
+    Message {
+        id: String,
+        conversation_id: String,
+        role: String,
+        content: String,
+        timestamp: i64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+        #[serde(default)]
+        starred: bool,
+        #[serde(default)]
+        interrupted: bool,
+        /// Regeneration group (see MessageVariant); absent for ordinary messages.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        variant_group_id: Option<String>,
+        #[serde(default)]
+        variant_index: i64,
+        #[serde(default = "default_variant_active")]
+        variant_active: bool,
+    },
+}
+
+fn default_variant_active() -> bool {
+    true
+}
+
+/// Progress of an NDJSON export ("conversations", "messages") or import ("importing").
+/// `total` is known for exports only.
+#[derive(Debug, Clone, Serialize)]
+pub struct NdjsonProgress {
+    pub phase: &'static str,
+    pub done: usize,
+    pub total: Option<usize>,
+}
+
+/// Counts from an NDJSON import. Skipped rows already existed or reference a missing conversation.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NdjsonImportSummary {
+    pub conversations_imported: usize,
+    pub messages_imported: usize,
+    pub skipped: usize,
+}
+
+fn write_record(out: &mut impl Write, record: &NdjsonRecord) -> Result<(), StorageError> {
+    serde_json::to_writer(&mut *out, record).map_err(std::io::Error::from)?;
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+fn invalid_data(message: String) -> StorageError {
+    StorageError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+/// Write every conversation and message as one JSON object per line. Rows are streamed from
+/// SQLite straight to `out`, so memory use does not grow with the history.
+pub fn write_ndjson(
+    storage: &Storage,
+    mut out: impl Write,
+    mut progress: impl FnMut(NdjsonProgress),
+) -> Result<(usize, usize), StorageError> {
+    let (total_conversations, total_messages) = storage.count_conversations_and_messages()?;
+    write_record(
+        &mut out,
+        &NdjsonRecord::Header {
+            format: NDJSON_FORMAT.to_string(),
+            version: NDJSON_VERSION,
+            exported_at: Utc::now().timestamp(),
+        },
+    )?;
+    let mut done = 0;
+    let conversations = storage.for_each_conversation_record(|c| {
+        write_record(
+            &mut out,
+            &NdjsonRecord::Conversation {
+                id: c.id,
+                title: c.title,
+                created_at: c.created_at,
+                updated_at: c.updated_at,
+                model_override: c.model_override,
+                system_prompt: c.system_prompt,
+            },
+        )?;
+        done += 1;
+        if done % NDJSON_PROGRESS_EVERY == 0 {
+            progress(NdjsonProgress { phase: "conversations", done, total: Some(total_conversations) });
+        }
+        Ok(())
+    })?;
+    progress(NdjsonProgress { phase: "conversations", done: conversations, total: Some(total_conversations) });
+    done = 0;
+    let messages = storage.for_each_message_record(|conversation_id, m| {
+        write_record(
+            &mut out,
+            &NdjsonRecord::Message {
+                id: m.id,
+                conversation_id: conversation_id.to_string(),
+                role: m.role,
+                content: m.content,
+                timestamp: m.timestamp,
+                model: m.model,
+                starred: m.starred,
+                interrupted: m.interrupted,
+                variant_group_id: m.variant.as_ref().map(|v| v.group_id.clone()),
+                variant_index: m.variant.as_ref().map_or(0, |v| v.index),
+                variant_active: m.variant.as_ref().map_or(true, |v| v.active),
+            },
+        )?;
+        done += 1;
+        if done % NDJSON_PROGRESS_EVERY == 0 {
+            progress(NdjsonProgress { phase: "messages", done, total: Some(total_messages) });
+        }
+        Ok(())
+    })?;
+    progress(NdjsonProgress { phase: "messages", done: messages, total: Some(total_messages) });
+    out.flush()?;
+    Ok((conversations, messages))
+}
+
+/// Import an NDJSON export line by line in one transaction (all or nothing). Existing ids are
+/// kept as they are, so importing the same file twice adds nothing.
+pub fn read_ndjson(
+    storage: &mut Storage,
+    input: impl BufRead,
+    mut progress: impl FnMut(NdjsonProgress),
+) -> Result<NdjsonImportSummary, StorageError> {
+    let mut import = storage.begin_record_import()?;
+    let mut summary = NdjsonImportSummary::default();
+    let mut seen_header = false;
+    let mut done = 0;
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: NdjsonRecord = serde_json::from_str(&line)
+            .map_err(|e| invalid_data(format!("line {}: {}", index + 1, e)))?;
+        match record {
+            NdjsonRecord::Header { format, version, .. } => {
+                if format != NDJSON_FORMAT || version > NDJSON_VERSION {
+                    return Err(invalid_data(format!("unsupported export format {} v{}", format, version)));
+                }
+                seen_header = true;
+                continue;
+            }
+            _ if !seen_header => {
+                return Err(invalid_data("not a local-private-llm NDJSON export (missing header)".into()));
+            }
+            NdjsonRecord::Conversation { id, title, created_at, updated_at, model_override, system_prompt } => {
+                let row = ConversationRecordRow { id, title, created_at, updated_at, model_override, system_prompt };
+                if import.conversation(&row)? {
+                    summary.conversations_imported += 1;
+                } else {
+                    summary.skipped += 1;
+                }
+            }
+            NdjsonRecord::Message {
+                id,
+                conversation_id,
+                role,
+                content,
+                timestamp,
+                model,
+                starred,
+                interrupted,
+                variant_group_id,
+                variant_index,
+                variant_active,
+            } => {
+                let variant = variant_group_id.map(|group_id| MessageVariant {
+                    group_id,
+                    index: variant_index,
+                    count: 0,
+                    active: variant_active,
+                });
+                let row = MessageRow { id, role, content, timestamp, model, starred, interrupted, variant };
+                if import.message(&conversation_id, &row)? {
+                    summary.messages_imported += 1;
+                } else {
+                    summary.skipped += 1;
+                }
+            }
+        }
+        done += 1;
+        if done % NDJSON_PROGRESS_EVERY == 0 {
+            progress(NdjsonProgress { phase: "importing", done, total: None });
+        }
+    }
+    if !seen_header {
+        return Err(invalid_data("empty or not a local-private-llm NDJSON export".into()));
+    }
+    import.commit()?;
+    progress(NdjsonProgress { phase: "importing", done, total: None });
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-02-10T12:00:00Z
+    const TS: i64 = 1_770_724_800;
+
+    #[test]
+    fn stem_uses_date_and_slug() {
+        assert_eq!(export_file_stem("My chat", TS, "abc"), "2026-02-10-my-chat");
+        assert_eq!(export_file_stem("  Lots   of\tspace  ", TS, "abc"), "2026-02-10-lots-of-space");
+    }
+
+    #[test]
+    fn stem_strips_illegal_characters_and_emoji() {
+        assert_eq!(export_file_stem("a/b\\c:d*e?f\"g<h>i|j", TS, "abc"), "2026-02-10-a-b-c-d-e-f-g-h-i-j");
+        assert_eq!(export_file_stem("🚀 Launch plan 🚀", TS, "abc"), "2026-02-10-launch-plan");
+        assert_eq!(export_file_stem("../../etc/passwd", TS, "abc"), "2026-02-10-etc-passwd");
+        assert_eq!(export_file_stem("CON", TS, "abc"), "2026-02-10-con-chat");
+    }
+
+    #[test]
+    fn stem_handles_empty_and_long_titles() {
+        assert_eq!(
+            export_file_stem("🔥🔥", TS, "1234abcd-ef00-0000"),
+            "2026-02-10-conversation-1234abcd"
+        );
+        let long = "word ".repeat(100);
+        let stem = export_file_stem(&long, TS, "abc");
+        assert!(stem.len() <= "2026-02-10-".len() + MAX_SLUG_CHARS);
+        assert!(!stem.ends_with('-'));
+    }
+
+    #[test]
+    fn ndjson_round_trips_and_skips_existing_rows() {
+        let mut source = Storage::new_in_memory().unwrap();
+        let c = source.create_conversation("Old chat").unwrap();
+        source.add_message(&c.id, "user", "line one\nline two", None).unwrap();
+        source.add_message(&c.id, "assistant", "reply", Some("llama3.2")).unwrap();
+        let mut buf = Vec::new();
+        let mut phases = Vec::new();
+        let counts = write_ndjson(&source, &mut buf, |p| phases.push(p.phase)).unwrap();
+        assert_eq!(counts, (1, 2));
+        assert_eq!(phases, ["conversations", "messages"]);
+        let text = String::from_utf8(buf.clone()).unwrap();
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.starts_with("{\"type\":\"header\""));
+
+        let mut target = Storage::new_in_memory().unwrap();
+        let summary = read_ndjson(&mut target, buf.as_slice(), |_| {}).unwrap();
+        assert_eq!((summary.conversations_imported, summary.messages_imported, summary.skipped), (1, 2, 0));
+        let (conv, messages) = target.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(conv.title, "Old chat");
+        assert_eq!(messages[0].content, "line one\nline two");
+        assert_eq!(messages[1].model.as_deref(), Some("llama3.2"));
+
+        let again = read_ndjson(&mut target, buf.as_slice(), |_| {}).unwrap();
+        assert_eq!((again.conversations_imported, again.messages_imported, again.skipped), (0, 0, 3));
+        assert!(read_ndjson(&mut target, "{\"type\":\"conversation\"}".as_bytes(), |_| {}).is_err());
+    }
+
+    #[test]
+    fn html_export_converts_markdown_and_escapes_content() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("<b>Plans</b>").unwrap();
+        storage
+            .add_message(&c.id, "user", "Hi <script>alert(1)</script> [x](javascript:alert(1))", None)
+            .unwrap();
+        storage
+            .add_message(
+                &c.id,
+                "assistant",
+                "## Steps\n\n1. **First**\n2. Then `run`\n\n- see [docs](https://example.com/?a=1&b=2)\n\n```rust\nfn main() { println!(\"<hi>\"); }\n```",
+                Some("llama3"),
+            )
+            .unwrap();
+        let (conv, messages) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        let html = to_html(&conv, &messages);
+        assert!(html.contains("<title>&lt;b&gt;Plans&lt;/b&gt;</title>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("href=\"javascript"));
+        assert!(html.contains("<h2>Steps</h2>"));
+        assert!(html.contains("<ol>\n<li><strong>First</strong></li>\n<li>Then <code>run</code></li>\n</ol>"));
+        assert!(html.contains("<a href=\"https://example.com/?a=1&amp;b=2\" rel=\"noopener noreferrer\">docs</a>"));
+        assert!(html.contains("<pre><code class=\"language-rust\">fn main() { println!(&quot;&lt;hi&gt;&quot;); }</code></pre>"));
+        assert!(html.contains("· llama3"));
+    }
+
+    #[test]
+    fn duplicate_names_get_short_id() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let first = export_path(&dir, "Same", TS, "aaaa1111-x", "md");
+        std::fs::write(&first, "x").unwrap();
+        let second = export_path(&dir, "Same", TS, "bbbb2222-y", "md");
+        assert_eq!(second.file_name().unwrap(), "2026-02-10-same-bbbb2222.md");
+    }
+}
@@ -58,17 +58,37 @@ pub fn detect_gpu() -> GpuInfo {
     }
 }
 
-/// Ollama does not expose device (GPU/CPU) in the API. We return a best-effort label.
-/// "unknown" = Ollama-managed; we cannot reliably detect runtime device.
+/// Best-effort device label for the running model: "gpu", "cpu" or "unknown".
+/// Ollama does not report the device directly, so this is a heuristic based on /api/ps.
 #[derive(Debug, Clone, Serialize)]
 pub struct OllamaDeviceInfo {
     pub active_device: String,
 }
 
-pub fn get_ollama_device_info(_gpu_detected: bool) -> OllamaDeviceInfo {
-    // Ollama API and `ollama ps` do not report GPU vs CPU. User can set OLLAMA_NUM_GPU=0
-    // when starting Ollama for CPU-only. We cannot read that from here.
+/// Infer the device from the loaded model's `size_vram` (bytes in GPU memory, from /api/ps).
+/// Heuristic: any VRAM use means "gpu" (possibly partially offloaded); none while a GPU is
+/// present means "cpu" (e.g. OLLAMA_NUM_GPU=0 or the model did not fit); no loaded model or no
+/// GPU to compare against means "unknown".
+pub fn get_ollama_device_info(gpu_detected: bool, size_vram: Option<u64>) -> OllamaDeviceInfo {
+    let active_device = match size_vram {
+        Some(v) if v > 0 => "gpu",
+        Some(_) if gpu_detected => "cpu",
+        _ => "unknown",
+    };
     OllamaDeviceInfo {
-        active_device: "unknown".to_string(),
+        active_device: active_device.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_is_inferred_from_vram_use() {
+        assert_eq!(get_ollama_device_info(true, Some(4 << 30)).active_device, "gpu");
+        assert_eq!(get_ollama_device_info(true, Some(0)).active_device, "cpu");
+        assert_eq!(get_ollama_device_info(false, Some(0)).active_device, "unknown");
+        assert_eq!(get_ollama_device_info(true, None).active_device, "unknown");
     }
 }
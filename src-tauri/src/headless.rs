@@ -0,0 +1,370 @@
+//! Headless mode (`--headless`): no window; a subset of the app's commands is served as JSON lines
+//! over a loopback TCP socket so conversations and tool calls can be scripted.
+//!
+//! Each request is one line: `{"id": 1, "token": "…", "command": "get_conversations", "args": {}}`.
+//! Each response is one line: `{"id": 1, "ok": true, "result": …}` or `{"id": 1, "ok": false,
+//! "error": "…"}`. The token is generated per run and written to `headless.token` in the data dir
+//! (in ephemeral mode, to a per-process file in the temp dir instead).
+
+use crate::{diagnostics, ollama, AppError, AppState};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+pub const DEFAULT_HEADLESS_PORT: u16 = 11435;
+/// Longest request line accepted; longer ones close the connection.
+const MAX_REQUEST_BYTES: u64 = 4 * 1024 * 1024;
+const TOKEN_FILE_NAME: &str = "headless.token";
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: serde_json::Value,
+    #[serde(default)]
+    token: String,
+    command: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    id: serde_json::Value,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IdArgs {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct CreateConversationArgs {
+    title: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AddMessageArgs {
+    conversation_id: String,
+    role: String,
+    content: String,
+    model: Option<String>,
+    #[serde(default)]
+    interrupted: bool,
+}
+
+#[derive(Deserialize)]
+struct ListModelsArgs {
+    capability: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatArgs {
+    model: String,
+    messages: Vec<ollama::ChatMessage>,
+    #[serde(default)]
+    options: ollama::ChatOptions,
+    /// Use this conversation's own system prompt when `messages` has none.
+    #[serde(default)]
+    conversation_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExecuteToolArgs {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+/// Commands a request may name; `help` lists them.
+const COMMANDS: &[&str] = &[
+    "ping",
+    "help",
+    "get_conversations",
+    "get_conversation",
+    "create_conversation",
+    "add_message",
+    "list_models",
+    "chat",
+    "execute_tool",
+];
+
+fn parse_args<T: serde::de::DeserializeOwned>(args: serde_json::Value) -> Result<T, AppError> {
+    let args = if args.is_null() { serde_json::json!({}) } else { args };
+    serde_json::from_value(args).map_err(|e| AppError::InvalidSettings(format!("invalid args: {}", e)))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<serde_json::Value, AppError> {
+    serde_json::to_value(value).map_err(|e| AppError::Ollama(e.to_string()))
+}
+
+/// Run one command with the same functions the Tauri commands use.
+async fn dispatch(state: &AppState, command: &str, args: serde_json::Value) -> Result<serde_json::Value, AppError> {
+    match command {
+        "ping" => Ok(serde_json::json!({ "version": env!("CARGO_PKG_VERSION") })),
+        "help" => to_value(COMMANDS),
+        "get_conversations" => to_value(crate::conversation_list(state)?),
+        "get_conversation" => {
+            let args: IdArgs = parse_args(args)?;
+            to_value(crate::conversation_with_messages(state, &args.id)?)
+        }
+        "create_conversation" => {
+            let args: CreateConversationArgs = parse_args(args)?;
+            to_value(crate::new_conversation(state, args.title)?)
+        }
+        "add_message" => {
+            let args: AddMessageArgs = parse_args(args)?;
+            to_value(crate::append_message(
+                state,
+                &args.conversation_id,
+                &args.role,
+                &args.content,
+                args.model.as_deref(),
+                args.interrupted,
+            )?)
+        }
+        "list_models" => {
+            let args: ListModelsArgs = parse_args(args)?;
+            to_value(crate::installed_models(state, args.capability).await?)
+        }
+        "chat" => {
+            let mut args: ChatArgs = parse_args(args)?;
+            crate::insert_default_system_prompt(state, &args.model, args.conversation_id.as_deref(), &mut args.messages);
+            let reply = state
+                .ollama
+                .chat(&args.model, args.messages, args.options)
+                .await
+                .map_err(AppError::Ollama)?;
+            Ok(serde_json::json!({ "content": reply }))
+        }
+        "execute_tool" => {
+            // Headless clients cannot answer a confirmation prompt: unacknowledged high-risk and
+            // write tools are refused, as in a batch.
+            let args: ExecuteToolArgs = parse_args(args)?;
+            to_value(crate::run_tool(state, args.name, args.arguments, false, None).await?)
+        }
+        _ => Err(AppError::InvalidSettings(format!(
+            "unknown command \"{}\" (use one of: {})",
+            command,
+            COMMANDS.join(", ")
+        ))),
+    }
+}
+
+/// The response line (without newline) for one request line.
+async fn handle_line(state: &AppState, token: &str, line: &str) -> String {
+    let response = match serde_json::from_str::<Request>(line) {
+        Err(e) => Response {
+            id: serde_json::Value::Null,
+            ok: false,
+            result: None,
+            error: Some(format!("invalid request: {}", e)),
+        },
+        Ok(req) if req.token != token => Response {
+            id: req.id,
+            ok: false,
+            result: None,
+            error: Some("invalid token".to_string()),
+        },
+        Ok(req) => match dispatch(state, &req.command, req.args).await {
+            Ok(result) => Response {
+                id: req.id,
+                ok: true,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => Response {
+                id: req.id,
+                ok: false,
+                result: None,
+                error: Some(e.to_string()),
+            },
+        },
+    };
+    serde_json::to_string(&response).unwrap_or_else(|_| r#"{"id":null,"ok":false,"error":"serialize"}"#.to_string())
+}
+
+async fn serve_connection(state: Arc<AppState>, token: Arc<String>, stream: tokio::net::TcpStream) {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    loop {
+        let mut line = String::new();
+        match (&mut reader).take(MAX_REQUEST_BYTES + 1).read_line(&mut line).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) if n as u64 > MAX_REQUEST_BYTES => {
+                let _ = write.write_all(b"{\"id\":null,\"ok\":false,\"error\":\"request too large\"}\n").await;
+                return;
+            }
+            Ok(_) => {}
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut response = handle_line(&state, &token, line.trim()).await;
+        response.push('\n');
+        if write.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Where the token is written: the data dir, or the temp dir in ephemeral mode, which must not
+/// write to the data dir.
+fn token_file_path(state: &AppState) -> PathBuf {
+    if state.ephemeral {
+        std::env::temp_dir().join(format!("local-private-llm-{}.{}", std::process::id(), TOKEN_FILE_NAME))
+    } else {
+        state.data_dir.join(TOKEN_FILE_NAME)
+    }
+}
+
+/// Write the token where scripts can read it, readable only by the current user on Unix: the file
+/// is created with mode 0600, and one left by an earlier run is narrowed to 0600 before the new
+/// token goes in.
+fn write_token_file(path: &Path, token: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(token.as_bytes())
+}
+
+/// Serve commands on 127.0.0.1:`port` until Ctrl-C/SIGTERM, then shut down like the app does.
+/// Only binds to loopback; other machines cannot connect.
+pub fn run_headless(state: AppState, port: u16) -> Result<(), String> {
+    crate::startup(&state);
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let token_path = token_file_path(&state);
+    write_token_file(&token_path, &token)
+        .map_err(|e| format!("The headless token could not be written to {}: {}", token_path.display(), e))?;
+    let state = Arc::new(state);
+    let result = tauri::async_runtime::block_on(async {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| format!("127.0.0.1:{} could not be bound: {}", port, e))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        diagnostics::log(
+            None,
+            "INFO",
+            "headless mode started",
+            Some(serde_json::json!({ "addr": addr.to_string(), "token_file": token_path })),
+        );
+        // Also on the terminal when there is one; release builds on Windows have no console.
+        eprintln!("Headless mode listening on {} (token in {})", addr, token_path.display());
+        let token = Arc::new(token);
+        let signal = crate::termination_signal();
+        tokio::pin!(signal);
+        loop {
+            tokio::select! {
+                name = &mut signal => {
+                    diagnostics::log(None, "INFO", "termination signal received", Some(serde_json::json!({ "signal": name })));
+                    return Ok(());
+                }
+                accepted = listener.accept() => {
+                    let Ok((stream, _)) = accepted else { continue };
+                    tauri::async_runtime::spawn(serve_connection(state.clone(), token.clone(), stream));
+                }
+            }
+        }
+    });
+    let _ = std::fs::remove_file(&token_path);
+    crate::shutdown_state(&state);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn test_state() -> AppState {
+        AppState {
+            storage: std::sync::Mutex::new(Storage::new_in_memory().unwrap()),
+            data_dir: std::env::temp_dir(),
+            ollama: crate::OllamaClient::new("http://127.0.0.1:9".to_string()),
+            chat_cancel_txs: Default::default(),
+            active_pulls: Default::default(),
+            tool_cancels: Default::default(),
+            health_watchdog_stop: Default::default(),
+            tool_limiter: Default::default(),
+            session_tool_acknowledgments: Default::default(),
+            startup_notices: Vec::new(),
+            database_locked: Default::default(),
+            ephemeral: false,
+        }
+    }
+
+    #[test]
+    fn ephemeral_mode_keeps_the_token_out_of_the_data_dir() {
+        let mut state = test_state();
+        state.data_dir = PathBuf::from("data-dir-that-is-not-written");
+        assert_eq!(token_file_path(&state), state.data_dir.join(TOKEN_FILE_NAME));
+        state.ephemeral = true;
+        assert!(token_file_path(&state).starts_with(std::env::temp_dir()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn token_file_is_private_even_when_it_already_exists() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("token");
+        std::fs::write(&path, "an older, longer token").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_token_file(&path, "new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn requests_need_the_token_and_reach_the_command_functions() {
+        let state = test_state();
+        let call = |line: &str| -> serde_json::Value {
+            serde_json::from_str(&tauri::async_runtime::block_on(handle_line(&state, "secret", line))).unwrap()
+        };
+        let denied = call(r#"{"id":1,"token":"wrong","command":"get_conversations"}"#);
+        assert_eq!(denied["ok"], false);
+        assert_eq!(denied["error"], "invalid token");
+
+        let created = call(r#"{"id":2,"token":"secret","command":"create_conversation","args":{"title":"Scripted"}}"#);
+        assert_eq!(created["id"], 2);
+        let id = created["result"]["id"].as_str().unwrap().to_string();
+        let added = call(&format!(
+            r#"{{"id":3,"token":"secret","command":"add_message","args":{{"conversation_id":"{}","role":"user","content":"hi"}}}}"#,
+            id
+        ));
+        assert_eq!(added["result"]["content"], "hi");
+        let listed = call(r#"{"id":4,"token":"secret","command":"get_conversations"}"#);
+        assert_eq!(listed["result"][0]["title"], "Scripted");
+
+        let unknown = call(r#"{"id":5,"token":"secret","command":"drop_tables"}"#);
+        assert!(unknown["error"].as_str().unwrap().contains("unknown command"));
+        let malformed = call("not json");
+        assert_eq!(malformed["ok"], false);
+    }
+
+    #[test]
+    fn unacknowledged_write_tools_are_refused() {
+        let state = test_state();
+        let line = r#"{"id":1,"token":"secret","command":"execute_tool","args":{"name":"write_file","arguments":{"path":"x.txt","content":"x"}}}"#;
+        let response: serde_json::Value =
+            serde_json::from_str(&tauri::async_runtime::block_on(handle_line(&state, "secret", line))).unwrap();
+        assert_eq!(response["result"]["ok"], false);
+        assert!(response["result"]["error"].as_str().unwrap().contains("needs the user's confirmation"));
+    }
+}
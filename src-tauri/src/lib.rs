@@ -1,19 +1,27 @@
 mod diagnostics;
+mod export;
 mod gpu;
+mod headless;
 mod mcp;
 mod ollama;
 mod provider;
+mod semantic;
 mod storage;
 
+pub use diagnostics::{disable_file_logging, set_data_dir};
+pub use headless::{run_headless, DEFAULT_HEADLESS_PORT};
 pub use ollama::OllamaClient;
-pub use storage::Storage;
+pub use storage::{is_plaintext_db, Storage};
 
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tauri::{Emitter, Manager, State};
 use thiserror::Error;
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -21,10 +29,16 @@ pub enum AppError {
     Storage(#[from] storage::StorageError),
     #[error("Ollama error: {0}")]
     Ollama(String),
+    /// Chat request rejected by Ollama; serialized as an object (see below) so the UI gets the
+    /// status, raw body and kind instead of one string.
+    #[error("{0}")]
+    OllamaRequest(ollama::OllamaRequestError),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("MCP tool error: {0}")]
     Mcp(#[from] mcp::McpToolError),
+    #[error("Invalid settings: {0}")]
+    InvalidSettings(String),
 }
 
 impl serde::Serialize for AppError {
@@ -32,15 +46,125 @@ impl serde::Serialize for AppError {
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        match self {
+            AppError::OllamaRequest(e) => e.serialize(serializer),
+            _ => serializer.serialize_str(&self.to_string()),
+        }
     }
 }
 
 pub struct AppState {
     pub storage: Mutex<Storage>,
+    /// App data directory (database and logs). Configurable via LPLLM_DATA_DIR or --data-dir.
+    pub data_dir: PathBuf,
     pub ollama: OllamaClient,
-    /// Sender to cancel the current chat stream. Set when stream starts, taken when cancel is requested.
-    pub chat_cancel_tx: Mutex<Option<oneshot::Sender<()>>>,
+    /// Senders to cancel running chat streams, keyed by conversation id ("" for streams without
+    /// one); the value sent is whether to keep the partial reply. Inserted when a stream starts,
+    /// removed when it ends or cancel is requested.
+    pub chat_cancel_txs: Mutex<HashMap<String, oneshot::Sender<bool>>>,
+    /// Model tags (normalized) with a pull in progress, so the same tag is never pulled twice at once.
+    pub active_pulls: Mutex<HashSet<String>>,
+    /// Cancel tokens of the tool calls in flight, set by cancel_tool_execution.
+    pub tool_cancels: ToolCancels,
+    /// Stops the background health watchdog; taken and fired by shutdown.
+    pub health_watchdog_stop: Mutex<Option<oneshot::Sender<()>>>,
+    /// Bounds how many tool calls execute at once (McpSettings.max_concurrent_tools).
+    pub tool_limiter: ToolLimiter,
+    /// Tools the user allowed for the rest of this session, as (tool name, root) like the stored
+    /// acknowledgments; see tool_needs_confirmation.
+    pub session_tool_acknowledgments: Mutex<HashSet<(String, String)>>,
+    /// Problems found before the window opened that the user should see (e.g. the data directory
+    /// fell back to a less suitable location). Logged at startup and shown by the UI.
+    pub startup_notices: Vec<String>,
+    /// The encrypted database is waiting for its passphrase; `storage` is an empty in-memory
+    /// placeholder until unlock_database swaps the real one in.
+    pub database_locked: AtomicBool,
+    /// Ephemeral mode (`--ephemeral`): nothing may be written to `data_dir`.
+    pub ephemeral: bool,
+}
+
+/// Semaphore for tool execution, sized from the current MCP settings. When the configured limit
+/// changes a new semaphore replaces the old one; calls holding a permit of the old one just finish.
+#[derive(Default)]
+pub struct ToolLimiter {
+    current: Mutex<Option<(usize, Arc<Semaphore>)>>,
+}
+
+impl ToolLimiter {
+    /// The semaphore for `limit` slots (clamped 1–16), reused while the limit is unchanged.
+    fn semaphore(&self, limit: usize) -> Result<Arc<Semaphore>, AppError> {
+        let limit = mcp::clamp_max_concurrent_tools(limit);
+        let mut current = self.current.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        match current.as_ref() {
+            Some((n, sem)) if *n == limit => Ok(sem.clone()),
+            _ => {
+                let sem = Arc::new(Semaphore::new(limit));
+                *current = Some((limit, sem.clone()));
+                Ok(sem)
+            }
+        }
+    }
+
+    /// Wait for a free slot; the slot is released when the permit is dropped.
+    async fn acquire(&self, limit: usize, window: Option<&tauri::Window>) -> Result<OwnedSemaphorePermit, AppError> {
+        let sem = self.semaphore(limit)?;
+        if sem.available_permits() == 0 {
+            diagnostics::log(
+                window,
+                "INFO",
+                "tool call queued",
+                Some(serde_json::json!({ "max_concurrent_tools": mcp::clamp_max_concurrent_tools(limit) })),
+            );
+        }
+        sem.acquire_owned().await.map_err(|e| AppError::Ollama(e.to_string()))
+    }
+}
+
+/// Cancel tokens for the tool calls in flight, one per call. A call registers its token before it
+/// waits for a slot, so a cancel also reaches queued calls, and starting a call never clears the
+/// cancel of another.
+#[derive(Default)]
+pub struct ToolCancels {
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+}
+
+impl ToolCancels {
+    /// A fresh token for one call, unregistered when the guard is dropped.
+    fn register(&self) -> Result<ToolCancelGuard<'_>, AppError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let token = Arc::new(AtomicBool::new(false));
+        let mut active = self.active.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        active.insert(id, token.clone());
+        Ok(ToolCancelGuard { cancels: self, id, token })
+    }
+
+    /// Set the token of every call in flight. Returns how many there were.
+    fn cancel_all(&self) -> usize {
+        let active = match self.active.lock() {
+            Ok(active) => active,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for token in active.values() {
+            token.store(true, Ordering::SeqCst);
+        }
+        active.len()
+    }
+}
+
+/// A registered tool call's cancel token; see ToolCancels.
+struct ToolCancelGuard<'a> {
+    cancels: &'a ToolCancels,
+    id: u64,
+    token: Arc<AtomicBool>,
+}
+
+impl Drop for ToolCancelGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.cancels.active.lock() {
+            active.remove(&self.id);
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +174,35 @@ pub struct ConversationDto {
     pub created_at: i64,
     pub updated_at: i64,
     pub message_ids: Vec<String>,
+    /// Model to pre-select when opening the conversation: its override, else the model of the latest
+    /// assistant message, else the global selected_model.
+    #[serde(default)]
+    pub last_model_used: Option<String>,
+    /// Chat options to prefill: those last sent for this conversation, with any unset field taken
+    /// from the global settings (temperature, max_tokens as num_predict).
+    #[serde(default)]
+    pub chat_options: ollama::ChatOptions,
+    /// Manual position from reorder_conversations; None when the conversation is not hand-ordered.
+    #[serde(default)]
+    pub sort_order: Option<i64>,
+    /// The conversation's own system prompt (from a template); the chat view uses it instead of
+    /// the per-model or global prompt.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+/// Stored per-conversation options (JSON) with gaps filled from the global settings. Unparseable
+/// JSON is treated as absent.
+fn conversation_chat_options(stored: Option<&str>, settings: &storage::Settings) -> ollama::ChatOptions {
+    let stored: ollama::ChatOptions = stored
+        .and_then(|json| serde_json::from_str(json).ok())
+        .unwrap_or_default();
+    ollama::ChatOptions {
+        temperature: stored.temperature.or(Some(settings.temperature)),
+        num_predict: stored
+            .num_predict
+            .or_else(|| u32::try_from(settings.max_tokens).ok()),
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,6 +211,68 @@ pub struct MessageDto {
     pub role: String,
     pub content: String,
     pub timestamp: i64,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub starred: bool,
+    /// The reply did not finish (stopped by the user, or the stream was cut off) and the partial
+    /// text was kept.
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Present when the message is one of several regenerated answers ("2/3" switcher).
+    #[serde(default)]
+    pub variant: Option<MessageVariantDto>,
+    /// Web sources the answer was based on; only filled by get_conversation.
+    #[serde(default)]
+    pub citations: Vec<CitationDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CitationDto {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+impl From<storage::CitationRow> for CitationDto {
+    fn from(c: storage::CitationRow) -> Self {
+        Self {
+            title: c.title,
+            url: c.url,
+            provider: c.provider,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MessageVariantDto {
+    pub group_id: String,
+    pub index: i64,
+    pub count: i64,
+    pub active: bool,
+}
+
+impl From<storage::MessageVariant> for MessageVariantDto {
+    fn from(v: storage::MessageVariant) -> Self {
+        Self {
+            group_id: v.group_id,
+            index: v.index,
+            count: v.count,
+            active: v.active,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationStatsDto {
+    pub message_count: i64,
+    pub user_chars: i64,
+    pub assistant_chars: i64,
+    pub total_words: i64,
+    pub estimated_tokens: i64,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -71,6 +286,126 @@ pub struct SettingsDto {
     pub tool_calling_mode: bool,
     #[serde(default = "default_inference_device_preference")]
     pub inference_device_preference: String,
+    #[serde(default)]
+    pub rolling_summary_enabled: bool,
+    #[serde(default = "default_rolling_summary_threshold")]
+    pub rolling_summary_threshold: i64,
+    #[serde(default)]
+    pub response_cache_enabled: bool,
+    #[serde(default = "default_health_check_interval_secs")]
+    pub health_check_interval_secs: i64,
+    #[serde(default = "default_agent_max_iterations")]
+    pub agent_max_iterations: i64,
+    #[serde(default = "default_agent_max_tool_calls")]
+    pub agent_max_tool_calls: i64,
+    #[serde(default = "default_agent_timeout_secs")]
+    pub agent_timeout_secs: i64,
+}
+
+fn default_rolling_summary_threshold() -> i64 {
+    40
+}
+
+fn default_health_check_interval_secs() -> i64 {
+    5
+}
+
+fn default_agent_max_iterations() -> i64 {
+    10
+}
+
+fn default_agent_max_tool_calls() -> i64 {
+    20
+}
+
+fn default_agent_timeout_secs() -> i64 {
+    300
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsFieldErrorDto {
+    pub field: String,
+    pub message: String,
+}
+
+const INFERENCE_DEVICE_PREFERENCES: &[&str] = &["auto", "prefer_gpu", "force_cpu"];
+const MAX_TOKENS_LIMIT: i64 = 131_072;
+/// Messages always sent verbatim (never folded into the rolling summary).
+const ROLLING_SUMMARY_KEEP_RECENT: usize = 10;
+const ROLLING_SUMMARY_MAX_THRESHOLD: i64 = 1000;
+const HEALTH_CHECK_INTERVAL_RANGE: std::ops::RangeInclusive<i64> = 1..=300;
+const AGENT_MAX_ITERATIONS_RANGE: std::ops::RangeInclusive<i64> = 1..=50;
+const AGENT_MAX_TOOL_CALLS_RANGE: std::ops::RangeInclusive<i64> = 1..=200;
+const AGENT_TIMEOUT_RANGE: std::ops::RangeInclusive<i64> = 10..=3600;
+/// Per-file and combined caps on conversation context files injected into each chat turn.
+const MAX_CONTEXT_FILE_CHARS: usize = 32_000;
+const MAX_CONTEXT_FILES_TOTAL_CHARS: usize = 64_000;
+
+/// Range checks shared by validate_settings and save_settings. Empty when the settings are valid.
+/// save_settings rejects every field except an unknown inference_device_preference, which it
+/// replaces with "auto".
+fn settings_field_errors(settings: &SettingsDto) -> Vec<SettingsFieldErrorDto> {
+    let mut errors = Vec::new();
+    let mut push = |field: &str, message: String| {
+        errors.push(SettingsFieldErrorDto {
+            field: field.to_string(),
+            message,
+        })
+    };
+    if !(0.0..=2.0).contains(&settings.temperature) {
+        push("temperature", format!("must be between 0 and 2 (got {})", settings.temperature));
+    }
+    if !(1..=MAX_TOKENS_LIMIT).contains(&settings.max_tokens) {
+        push(
+            "max_tokens",
+            format!("must be between 1 and {} (got {})", MAX_TOKENS_LIMIT, settings.max_tokens),
+        );
+    }
+    if !INFERENCE_DEVICE_PREFERENCES.contains(&settings.inference_device_preference.trim()) {
+        push(
+            "inference_device_preference",
+            format!(
+                "must be one of {} (got \"{}\"); it is saved as \"auto\"",
+                INFERENCE_DEVICE_PREFERENCES.join(", "),
+                settings.inference_device_preference
+            ),
+        );
+    }
+    let min_threshold = ROLLING_SUMMARY_KEEP_RECENT as i64 + 2;
+    if !(min_threshold..=ROLLING_SUMMARY_MAX_THRESHOLD).contains(&settings.rolling_summary_threshold) {
+        push(
+            "rolling_summary_threshold",
+            format!(
+                "must be between {} and {} (got {})",
+                min_threshold, ROLLING_SUMMARY_MAX_THRESHOLD, settings.rolling_summary_threshold
+            ),
+        );
+    }
+    if !HEALTH_CHECK_INTERVAL_RANGE.contains(&settings.health_check_interval_secs) {
+        push(
+            "health_check_interval_secs",
+            format!(
+                "must be between {} and {} seconds (got {})",
+                HEALTH_CHECK_INTERVAL_RANGE.start(),
+                HEALTH_CHECK_INTERVAL_RANGE.end(),
+                settings.health_check_interval_secs
+            ),
+        );
+    }
+    let agent_budgets = [
+        ("agent_max_iterations", &AGENT_MAX_ITERATIONS_RANGE, settings.agent_max_iterations, ""),
+        ("agent_max_tool_calls", &AGENT_MAX_TOOL_CALLS_RANGE, settings.agent_max_tool_calls, ""),
+        ("agent_timeout_secs", &AGENT_TIMEOUT_RANGE, settings.agent_timeout_secs, " seconds"),
+    ];
+    for (field, range, value, unit) in agent_budgets {
+        if !range.contains(&value) {
+            push(
+                field,
+                format!("must be between {} and {}{} (got {})", range.start(), range.end(), unit, value),
+            );
+        }
+    }
+    errors
 }
 
 fn default_inference_device_preference() -> String {
@@ -89,6 +424,66 @@ pub struct McpSettingsDto {
     pub obsidian_vault_path: String,
     pub web_search_enabled: bool,
     pub terminal_enabled: bool,
+    #[serde(default = "default_web_search_enable_fallbacks")]
+    pub web_search_enable_fallbacks: bool,
+    #[serde(default)]
+    pub terminal_extra_blocked_patterns: Vec<String>,
+    #[serde(default)]
+    pub terminal_block_word_boundaries: bool,
+    #[serde(default)]
+    pub web_search_html_fallback: bool,
+    #[serde(default = "default_tool_result_max_chars")]
+    pub tool_result_max_chars: usize,
+    /// Shell for run_command; PowerShell and cmd quote arguments differently, so commands the
+    /// model writes depend on this. Empty = platform default (sh, or cmd on Windows).
+    #[serde(default)]
+    pub terminal_shell: String,
+    /// Seconds; saved clamped to 1–30.
+    #[serde(default = "default_fetch_timeout_secs")]
+    pub fetch_timeout_secs: u64,
+    #[serde(default = "default_web_search_timeout_secs")]
+    pub web_search_timeout_secs: u64,
+    /// Hide and refuse high-risk tools (and write tools, with safe_mode_block_writes).
+    #[serde(default)]
+    pub safe_mode: bool,
+    #[serde(default = "default_safe_mode_block_writes")]
+    pub safe_mode_block_writes: bool,
+    /// Saved clamped to 1–16.
+    #[serde(default = "default_max_concurrent_tools")]
+    pub max_concurrent_tools: usize,
+    /// No outbound connections: network tools are refused and Ollama must be on loopback.
+    #[serde(default)]
+    pub offline_mode: bool,
+    /// Let open_path open programs, scripts and other non-document files.
+    #[serde(default)]
+    pub open_path_allow_any_file: bool,
+    /// Let fetch_url follow redirects from https to plain http.
+    #[serde(default)]
+    pub fetch_allow_insecure_redirect: bool,
+}
+
+fn default_tool_result_max_chars() -> usize {
+    storage::DEFAULT_TOOL_RESULT_MAX_CHARS
+}
+
+fn default_fetch_timeout_secs() -> u64 {
+    mcp::DEFAULT_FETCH_TIMEOUT_SECS
+}
+
+fn default_web_search_timeout_secs() -> u64 {
+    mcp::DEFAULT_WEB_SEARCH_TIMEOUT_SECS
+}
+
+fn default_max_concurrent_tools() -> usize {
+    mcp::DEFAULT_MAX_CONCURRENT_TOOLS
+}
+
+fn default_safe_mode_block_writes() -> bool {
+    true
+}
+
+fn default_web_search_enable_fallbacks() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -108,50 +503,119 @@ pub struct DiagnosticStepDto {
     pub meta: Option<serde_json::Value>,
 }
 
+/// One web_search pipeline step (validate, request, parse, a fallback, done) for a pass/fail list.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolStepDto {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct McpToolResultDto {
     pub ok: bool,
     pub content: String,
     pub error: Option<String>,
+    /// For failed calls: the compact `{tool, ok: false, error, hint}` JSON to feed back to the
+    /// model instead of the full error (see mcp::compact_tool_error).
+    #[serde(default)]
+    pub model_error: Option<String>,
     #[serde(default)]
     pub diagnostic_steps: Option<Vec<DiagnosticStepDto>>,
+    /// Structured steps for web_search; diagnostic_steps stays the free-form log.
+    #[serde(default)]
+    pub steps: Option<Vec<ToolStepDto>>,
+    /// Set by test_mcp_tool: a settings-page check, not a call made during a chat.
+    #[serde(default)]
+    pub test: bool,
 }
 
 #[tauri::command]
 fn get_conversations(state: State<AppState>) -> Result<Vec<ConversationDto>, AppError> {
+    conversation_list(&state)
+}
+
+fn conversation_list(state: &AppState) -> Result<Vec<ConversationDto>, AppError> {
     let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
     let convos = storage.list_conversations()?;
+    let settings = storage.get_settings()?;
     Ok(convos
         .into_iter()
         .map(|c| ConversationDto {
+            chat_options: conversation_chat_options(c.chat_options.as_deref(), &settings),
             id: c.id,
             title: c.title,
             created_at: c.created_at,
             updated_at: c.updated_at,
             message_ids: c.message_ids,
+            last_model_used: Some(c.last_model_used.unwrap_or_else(|| settings.selected_model.clone())),
+            sort_order: c.sort_order,
+            system_prompt: c.system_prompt,
         })
         .collect())
 }
 
+/// Just the conversation (title, dates, model, message ids) without loading its messages, for
+/// headers and breadcrumbs. None if it doesn't exist.
+#[tauri::command]
+fn get_conversation_metadata(state: State<AppState>, id: String) -> Result<Option<ConversationDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let Some(c) = storage.get_conversation(&id)? else {
+        return Ok(None);
+    };
+    let settings = storage.get_settings()?;
+    Ok(Some(ConversationDto {
+        chat_options: conversation_chat_options(c.chat_options.as_deref(), &settings),
+        id: c.id,
+        title: c.title,
+        created_at: c.created_at,
+        updated_at: c.updated_at,
+        message_ids: c.message_ids,
+        last_model_used: Some(c.last_model_used.unwrap_or(settings.selected_model)),
+        sort_order: c.sort_order,
+        system_prompt: c.system_prompt,
+    }))
+}
+
 #[tauri::command]
 fn get_conversation(state: State<AppState>, id: String) -> Result<Option<(ConversationDto, Vec<MessageDto>)>, AppError> {
+    conversation_with_messages(&state, &id)
+}
+
+fn conversation_with_messages(state: &AppState, id: &str) -> Result<Option<(ConversationDto, Vec<MessageDto>)>, AppError> {
     let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    let out = storage.get_conversation_with_messages(&id)?;
+    let out = storage.get_conversation_with_messages(id)?;
+    let settings = storage.get_settings()?;
+    let mut citations = storage.list_conversation_citations(id)?;
     Ok(out.map(|(c, msgs)| {
         (
             ConversationDto {
+                chat_options: conversation_chat_options(c.chat_options.as_deref(), &settings),
                 id: c.id,
                 title: c.title,
                 created_at: c.created_at,
                 updated_at: c.updated_at,
                 message_ids: c.message_ids,
+                last_model_used: Some(c.last_model_used.unwrap_or(settings.selected_model)),
+                sort_order: c.sort_order,
+                system_prompt: c.system_prompt,
             },
             msgs.into_iter()
                 .map(|m| MessageDto {
+                    citations: citations
+                        .remove(&m.id)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(CitationDto::from)
+                        .collect(),
                     id: m.id,
                     role: m.role,
                     content: m.content,
                     timestamp: m.timestamp,
+                    model: m.model,
+                    starred: m.starred,
+                    interrupted: m.interrupted,
+                    variant: m.variant.map(MessageVariantDto::from),
                 })
                 .collect(),
         )
@@ -160,18 +624,181 @@ fn get_conversation(state: State<AppState>, id: String) -> Result<Option<(Conver
 
 #[tauri::command]
 fn create_conversation(state: State<AppState>, title: Option<String>) -> Result<ConversationDto, AppError> {
+    new_conversation(&state, title)
+}
+
+fn new_conversation(state: &AppState, title: Option<String>) -> Result<ConversationDto, AppError> {
     let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
     let title = title.unwrap_or_else(|| "New chat".to_string());
     let c = storage.create_conversation(&title)?;
+    let settings = storage.get_settings()?;
+    Ok(ConversationDto {
+        chat_options: conversation_chat_options(None, &settings),
+        id: c.id,
+        title: c.title,
+        created_at: c.created_at,
+        updated_at: c.updated_at,
+        message_ids: c.message_ids,
+        last_model_used: Some(c.last_model_used.unwrap_or(settings.selected_model)),
+        sort_order: c.sort_order,
+        system_prompt: c.system_prompt,
+    })
+}
+
+/// Most seed messages a conversation template may hold.
+const MAX_TEMPLATE_SEED_MESSAGES: usize = 50;
+
+/// A message a template adds to each conversation created from it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeedMessageDto {
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationTemplateDto {
+    pub id: String,
+    pub name: String,
+    /// Model override for new conversations; None uses the selected model.
+    pub model: Option<String>,
+    /// Stored as the new conversation's own system prompt (conversations.system_prompt).
+    pub system_prompt: Option<String>,
+    pub seed_messages: Vec<SeedMessageDto>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<storage::ConversationTemplateRow> for ConversationTemplateDto {
+    fn from(t: storage::ConversationTemplateRow) -> Self {
+        Self {
+            // Unparseable seed JSON (hand-edited database) is treated as no seed messages.
+            seed_messages: serde_json::from_str(&t.seed_messages).unwrap_or_default(),
+            id: t.id,
+            name: t.name,
+            model: t.model,
+            system_prompt: t.system_prompt,
+            created_at: t.created_at,
+            updated_at: t.updated_at,
+        }
+    }
+}
+
+/// Seed messages need a known role and some content.
+fn validate_seed_messages(messages: &[SeedMessageDto]) -> Result<(), AppError> {
+    if messages.len() > MAX_TEMPLATE_SEED_MESSAGES {
+        return Err(AppError::InvalidSettings(format!(
+            "a template can hold at most {} seed messages",
+            MAX_TEMPLATE_SEED_MESSAGES
+        )));
+    }
+    for (i, m) in messages.iter().enumerate() {
+        if !matches!(m.role.as_str(), "user" | "assistant" | "system") {
+            return Err(AppError::InvalidSettings(format!(
+                "seed message {}: role must be user, assistant or system",
+                i + 1
+            )));
+        }
+        if m.content.trim().is_empty() {
+            return Err(AppError::InvalidSettings(format!("seed message {} is empty", i + 1)));
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_conversation_templates(state: State<AppState>) -> Result<Vec<ConversationTemplateDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage
+        .list_conversation_templates()?
+        .into_iter()
+        .map(ConversationTemplateDto::from)
+        .collect())
+}
+
+/// Create a template, or update the one with `id`.
+#[tauri::command]
+fn save_conversation_template(
+    state: State<AppState>,
+    id: Option<String>,
+    name: String,
+    model: Option<String>,
+    system_prompt: Option<String>,
+    seed_messages: Option<Vec<SeedMessageDto>>,
+) -> Result<ConversationTemplateDto, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidSettings("template name is empty".into()));
+    }
+    let seed_messages = seed_messages.unwrap_or_default();
+    validate_seed_messages(&seed_messages)?;
+    let seed_json = serde_json::to_string(&seed_messages).map_err(|e| AppError::Ollama(e.to_string()))?;
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let id = id.filter(|id| !id.trim().is_empty());
+    Ok(storage
+        .save_conversation_template(id.as_deref(), name, model.as_deref(), system_prompt.as_deref(), &seed_json)?
+        .into())
+}
+
+#[tauri::command]
+fn delete_conversation_template(state: State<AppState>, id: String) -> Result<bool, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.delete_conversation_template(&id)?)
+}
+
+/// Start a conversation from a template: titled after it, with its model override and system
+/// prompt as conversation settings, then its seed messages.
+#[tauri::command]
+fn create_conversation_from_template(state: State<AppState>, template_id: String) -> Result<ConversationDto, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let template: ConversationTemplateDto = storage
+        .get_conversation_template(&template_id)?
+        .ok_or_else(|| AppError::Ollama(format!("Template not found: {}", template_id)))?
+        .into();
+    let c = storage.create_conversation(&template.name)?;
+    if template.model.is_some() {
+        storage.set_conversation_model(&c.id, template.model.as_deref())?;
+    }
+    // Kept out of the message list so it is never trimmed from the prompt or folded into a summary.
+    storage.set_conversation_system_prompt(&c.id, template.system_prompt.as_deref())?;
+    let messages: Vec<(String, String)> = template
+        .seed_messages
+        .into_iter()
+        .map(|m| (m.role, m.content))
+        .collect();
+    storage.add_messages(&c.id, &messages)?;
+    let (c, _) = storage
+        .get_conversation_with_messages(&c.id)?
+        .ok_or_else(|| AppError::Ollama(format!("Conversation not found: {}", c.id)))?;
+    let settings = storage.get_settings()?;
     Ok(ConversationDto {
+        chat_options: conversation_chat_options(None, &settings),
         id: c.id,
         title: c.title,
         created_at: c.created_at,
         updated_at: c.updated_at,
         message_ids: c.message_ids,
+        last_model_used: Some(c.last_model_used.unwrap_or(settings.selected_model)),
+        sort_order: c.sort_order,
+        system_prompt: c.system_prompt,
     })
 }
 
+/// Pin the model used for a conversation; pass None to go back to "last assistant message's model".
+#[tauri::command]
+fn set_conversation_model(state: State<AppState>, id: String, model: Option<String>) -> Result<(), AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    storage.set_conversation_model(&id, model.as_deref())?;
+    Ok(())
+}
+
+/// Hand-arrange the sidebar: `ordered_ids` come first in that order, all other conversations
+/// follow by last update. An empty list restores the default order. Returns how many were ordered.
+#[tauri::command]
+fn reorder_conversations(state: State<AppState>, ordered_ids: Vec<String>) -> Result<usize, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.reorder_conversations(&ordered_ids)?)
+}
+
 #[tauri::command]
 fn update_conversation_title(state: State<AppState>, id: String, title: String) -> Result<(), AppError> {
     let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
@@ -186,97 +813,1094 @@ fn delete_conversation(state: State<AppState>, id: String) -> Result<(), AppErro
     Ok(())
 }
 
+/// Conversations must be empty for at least this long before prune_empty_conversations removes them.
+const EMPTY_CONVERSATION_MIN_AGE_SECS: i64 = 60 * 60;
+
+/// Remove empty conversations left by accidental "New chat" clicks. Skips `keep_id` (the open
+/// conversation), hand-ordered ones, and any updated within `min_age_secs` (default one hour).
+/// Returns the number deleted.
+#[tauri::command]
+fn prune_empty_conversations(
+    state: State<AppState>,
+    keep_id: Option<String>,
+    min_age_secs: Option<i64>,
+    window: tauri::Window,
+) -> Result<usize, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let deleted = storage.delete_empty_conversations(
+        min_age_secs.unwrap_or(EMPTY_CONVERSATION_MIN_AGE_SECS),
+        keep_id.as_deref(),
+    )?;
+    drop(storage);
+    if deleted > 0 {
+        diagnostics::log(
+            Some(&window),
+            "INFO",
+            "empty conversations pruned",
+            Some(serde_json::json!({ "deleted": deleted })),
+        );
+    }
+    Ok(deleted)
+}
+
 #[tauri::command]
 fn add_message(
     state: State<AppState>,
     conversation_id: String,
     role: String,
     content: String,
+    model: Option<String>,
+    interrupted: Option<bool>,
+) -> Result<MessageDto, AppError> {
+    append_message(&state, &conversation_id, &role, &content, model.as_deref(), interrupted.unwrap_or(false))
+}
+
+/// Save a message; `interrupted` marks a partial reply kept after the user stopped it.
+fn append_message(
+    state: &AppState,
+    conversation_id: &str,
+    role: &str,
+    content: &str,
+    model: Option<&str>,
+    interrupted: bool,
 ) -> Result<MessageDto, AppError> {
     let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    let m = storage.add_message(&conversation_id, &role, &content)?;
+    let mut m = storage.add_message(conversation_id, role, content, model)?;
+    if interrupted {
+        storage.set_message_interrupted(&m.id, true)?;
+        m.interrupted = true;
+    }
     Ok(MessageDto {
         id: m.id,
         role: m.role,
         content: m.content,
         timestamp: m.timestamp,
+        model: m.model,
+        starred: m.starred,
+        interrupted: m.interrupted,
+        variant: m.variant.map(MessageVariantDto::from),
+        citations: Vec::new(),
     })
 }
 
-#[tauri::command]
-fn get_settings(state: State<AppState>) -> Result<SettingsDto, AppError> {
-    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    let s = storage.get_settings()?;
-    Ok(SettingsDto {
-        theme: s.theme,
-        selected_model: s.selected_model,
-        system_prompt: s.system_prompt,
-        temperature: s.temperature,
-        max_tokens: s.max_tokens,
-        tool_calling_mode: s.tool_calling_mode,
-        inference_device_preference: s.inference_device_preference,
-    })
+#[derive(Deserialize)]
+struct NewMessageDto {
+    role: String,
+    content: String,
 }
 
+/// Bulk insert (e.g. importing a chat): all messages are written in one transaction.
 #[tauri::command]
-fn save_settings(
+fn add_messages(
     state: State<AppState>,
-    settings: SettingsDto,
-    window: tauri::Window,
-) -> Result<(), AppError> {
+    conversation_id: String,
+    messages: Vec<NewMessageDto>,
+) -> Result<Vec<MessageDto>, AppError> {
+    let messages: Vec<(String, String)> = messages.into_iter().map(|m| (m.role, m.content)).collect();
     let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    let prev = storage.get_settings().ok().map(|s| s.selected_model);
-    let pref = settings
-        .inference_device_preference
-        .trim();
-    let inference_device_preference = if matches!(pref, "auto" | "prefer_gpu" | "force_cpu") {
-        pref.to_string()
-    } else {
-        "auto".to_string()
-    };
-    storage.save_settings(storage::Settings {
-        theme: settings.theme,
-        selected_model: settings.selected_model.clone(),
-        system_prompt: settings.system_prompt,
-        temperature: settings.temperature,
-        max_tokens: settings.max_tokens,
-        tool_calling_mode: settings.tool_calling_mode,
-        inference_device_preference,
-    })?;
-    if prev.as_deref() != Some(settings.selected_model.as_str()) {
-        diagnostics::log(
-            Some(&window),
-            "INFO",
-            "active_model change",
-            Some(serde_json::json!({ "active_model": settings.selected_model })),
-        );
+    let rows = storage.add_messages(&conversation_id, &messages)?;
+    Ok(rows
+        .into_iter()
+        .map(|m| MessageDto {
+            id: m.id,
+            role: m.role,
+            content: m.content,
+            timestamp: m.timestamp,
+            model: m.model,
+            starred: m.starred,
+            interrupted: m.interrupted,
+            variant: m.variant.map(MessageVariantDto::from),
+            citations: Vec::new(),
+        })
+        .collect())
+}
+
+#[tauri::command]
+fn set_message_starred(state: State<AppState>, id: String, starred: bool) -> Result<(), AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    if !storage.set_message_starred(&id, starred)? {
+        return Err(AppError::Ollama(format!("Message not found: {}", id)));
+    }
+    Ok(())
+}
+
+/// Max citations stored per message.
+const MAX_MESSAGE_CITATIONS: usize = 20;
+
+/// Store the web sources an assistant answer was based on (replacing any earlier ones).
+#[tauri::command]
+fn set_message_citations(state: State<AppState>, message_id: String, citations: Vec<CitationDto>) -> Result<(), AppError> {
+    let rows: Vec<storage::CitationRow> = citations
+        .into_iter()
+        .filter(|c| c.url.starts_with("http://") || c.url.starts_with("https://"))
+        .take(MAX_MESSAGE_CITATIONS)
+        .map(|c| storage::CitationRow {
+            title: c.title.trim().to_string(),
+            url: c.url,
+            provider: c.provider,
+        })
+        .collect();
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    if !storage.set_message_citations(&message_id, &rows)? {
+        return Err(AppError::Ollama(format!("Message not found: {}", message_id)));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_starred_messages(state: State<AppState>, conversation_id: String) -> Result<Vec<MessageDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage
+        .list_starred_messages(&conversation_id)?
+        .into_iter()
+        .map(|m| MessageDto {
+            id: m.id,
+            role: m.role,
+            content: m.content,
+            timestamp: m.timestamp,
+            model: m.model,
+            starred: m.starred,
+            interrupted: m.interrupted,
+            variant: m.variant.map(MessageVariantDto::from),
+            citations: Vec::new(),
+        })
+        .collect())
+}
+
+/// Save a regenerated answer to `message_id` as a new, active variant of that turn.
+#[tauri::command]
+fn add_message_variant(
+    state: State<AppState>,
+    conversation_id: String,
+    message_id: String,
+    content: String,
+    model: Option<String>,
+) -> Result<MessageDto, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let m = storage
+        .add_message_variant(&conversation_id, &message_id, &content, model.as_deref())?
+        .ok_or_else(|| AppError::Ollama("Assistant message not found in this conversation".into()))?;
+    Ok(MessageDto {
+        id: m.id,
+        role: m.role,
+        content: m.content,
+        timestamp: m.timestamp,
+        model: m.model,
+        starred: m.starred,
+        interrupted: m.interrupted,
+        variant: m.variant.map(MessageVariantDto::from),
+        citations: Vec::new(),
+    })
+}
+
+#[tauri::command]
+fn list_message_variants(state: State<AppState>, group_id: String) -> Result<Vec<MessageDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage
+        .list_message_variants(&group_id)?
+        .into_iter()
+        .map(|m| MessageDto {
+            id: m.id,
+            role: m.role,
+            content: m.content,
+            timestamp: m.timestamp,
+            model: m.model,
+            starred: m.starred,
+            interrupted: m.interrupted,
+            variant: m.variant.map(MessageVariantDto::from),
+            citations: Vec::new(),
+        })
+        .collect())
+}
+
+/// Show `message_id` instead of the other variants of its turn; the chat history only includes
+/// the active variant.
+#[tauri::command]
+fn select_variant(state: State<AppState>, message_id: String) -> Result<(), AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    if !storage.select_variant(&message_id)? {
+        return Err(AppError::Ollama(format!("Message has no variants: {}", message_id)));
     }
     Ok(())
 }
 
+/// Remove the last user+assistant exchange. Returns the remaining message count.
+#[tauri::command]
+fn undo_last_exchange(state: State<AppState>, conversation_id: String) -> Result<usize, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.undo_last_exchange(&conversation_id)?)
+}
+
+/// Rewind: delete every message after `message_id`. Returns the remaining message count.
+#[tauri::command]
+fn trim_conversation_to(state: State<AppState>, conversation_id: String, message_id: String) -> Result<usize, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    storage
+        .trim_after(&conversation_id, &message_id)?
+        .ok_or_else(|| AppError::Ollama("Message not found in this conversation".into()))
+}
+
+#[tauri::command]
+fn conversation_stats(state: State<AppState>, id: String) -> Result<ConversationStatsDto, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let s = storage.conversation_stats(&id)?;
+    Ok(ConversationStatsDto {
+        message_count: s.message_count,
+        user_chars: s.user_chars,
+        assistant_chars: s.assistant_chars,
+        total_words: s.total_words,
+        estimated_tokens: s.estimated_tokens,
+        first_timestamp: s.first_timestamp,
+        last_timestamp: s.last_timestamp,
+    })
+}
+
+/// Export a conversation as "markdown" or "json" into `directory` (default: `<data dir>/exports`).
+/// Returns the written file path.
+#[tauri::command]
+fn export_conversation(
+    state: State<AppState>,
+    id: String,
+    format: String,
+    directory: Option<String>,
+) -> Result<String, AppError> {
+    let (conv, messages) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage
+            .get_conversation_with_messages(&id)?
+            .ok_or_else(|| AppError::Ollama(format!("Conversation not found: {}", id)))?
+    };
+    let (ext, body) = match format.as_str() {
+        "markdown" | "md" => ("md", export::to_markdown(&conv, &messages)),
+        "json" => ("json", export::to_json(&conv, &messages)),
+        other => return Err(AppError::Ollama(format!("Unsupported export format: {}", other))),
+    };
+    let dir = directory
+        .filter(|d| !d.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| state.data_dir.join("exports"));
+    std::fs::create_dir_all(&dir)?;
+    let path = export::export_path(&dir, &conv.title, conv.created_at, &conv.id, ext);
+    std::fs::write(&path, body)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Export a conversation as a self-contained HTML page into `directory` (default:
+/// `<data dir>/exports`). Returns the written file path.
+#[tauri::command]
+fn export_conversation_html(state: State<AppState>, id: String, directory: Option<String>) -> Result<String, AppError> {
+    let (conv, messages) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage
+            .get_conversation_with_messages(&id)?
+            .ok_or_else(|| AppError::Ollama(format!("Conversation not found: {}", id)))?
+    };
+    let dir = directory
+        .filter(|d| !d.trim().is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| state.data_dir.join("exports"));
+    std::fs::create_dir_all(&dir)?;
+    let path = export::export_path(&dir, &conv.title, conv.created_at, &conv.id, "html");
+    std::fs::write(&path, export::to_html(&conv, &messages))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Export every conversation and message as NDJSON (one JSON object per line) into `directory`
+/// (default: `<data dir>/exports`), streaming rows to the file so memory stays flat.
+/// Emits "ndjson-export-progress". Returns the written file path.
+#[tauri::command]
+async fn export_all_ndjson(directory: Option<String>, window: tauri::Window) -> Result<String, AppError> {
+    let app = window.app_handle().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let dir = directory
+            .filter(|d| !d.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| state.data_dir.join("exports"));
+        std::fs::create_dir_all(&dir)?;
+        let stamp = chrono::Utc::now().format("%Y-%m-%d-%H%M%S");
+        let path = dir.join(format!("local-private-llm-history-{}.ndjson", stamp));
+        let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let (conversations, messages) = export::write_ndjson(&storage, file, |p| {
+            let _ = window.emit("ndjson-export-progress", p);
+        })?;
+        drop(storage);
+        diagnostics::log(
+            Some(&window),
+            "INFO",
+            "history exported",
+            Some(serde_json::json!({ "conversations": conversations, "messages": messages })),
+        );
+        Ok(path.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| AppError::Ollama(e.to_string()))?
+}
+
+/// Import an NDJSON history export, reading it line by line. Conversations and messages whose ids
+/// already exist are skipped. Emits "ndjson-import-progress".
 #[tauri::command]
-async fn ollama_health(state: State<'_, AppState>, window: tauri::Window) -> Result<bool, AppError> {
-    let result = state.ollama.health().await;
-    match &result {
-        Ok(ok) => diagnostics::log(
+async fn import_ndjson(path: String, window: tauri::Window) -> Result<export::NdjsonImportSummary, AppError> {
+    let app = window.app_handle().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let file = std::io::BufReader::new(std::fs::File::open(&path)?);
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let summary = export::read_ndjson(&mut storage, file, |p| {
+            let _ = window.emit("ndjson-import-progress", p);
+        })?;
+        drop(storage);
+        diagnostics::log(
+            Some(&window),
+            "INFO",
+            "history imported",
+            Some(serde_json::json!({
+                "conversations": summary.conversations_imported,
+                "messages": summary.messages_imported,
+                "skipped": summary.skipped,
+            })),
+        );
+        Ok(summary)
+    })
+    .await
+    .map_err(|e| AppError::Ollama(e.to_string()))?
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConversationSummaryDto {
+    pub content: String,
+    /// Messages up to and including this id are covered by the summary; send only later ones verbatim.
+    pub summarized_through_id: String,
+    pub updated_at: i64,
+}
+
+#[tauri::command]
+fn get_conversation_summary(state: State<AppState>, id: String) -> Result<Option<ConversationSummaryDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.get_conversation_summary(&id)?.map(|s| ConversationSummaryDto {
+        content: s.content,
+        summarized_through_id: s.summarized_through_id,
+        updated_at: s.updated_at,
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContextFileDto {
+    /// "filesystem" (MCP filesystem root) or "obsidian" (vault)
+    pub root: String,
+    pub path: String,
+    pub added_at: i64,
+}
+
+/// Directory a context file root name refers to, resolved like the tools resolve it
+/// (effective_filesystem_root / effective_obsidian_root): a disabled root is not configured.
+fn context_file_root(s: &storage::McpSettings, root: &str) -> Result<PathBuf, mcp::McpToolError> {
+    let dir = match root {
+        "filesystem" => effective_filesystem_root(s),
+        "obsidian" => effective_obsidian_root(s),
+        other => {
+            return Err(mcp::McpToolError::InvalidArg(format!(
+                "Unknown root '{}' (use \"filesystem\" or \"obsidian\")",
+                other
+            )))
+        }
+    };
+    dir.map(PathBuf::from).ok_or(mcp::McpToolError::RootNotConfigured)
+}
+
+#[tauri::command]
+fn add_context_file(
+    state: State<AppState>,
+    conversation_id: String,
+    root: String,
+    path: String,
+) -> Result<ContextFileDto, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let dir = context_file_root(&storage.get_mcp_settings()?, &root)?;
+    let path = path.trim().replace('\\', "/");
+    let full = mcp::validate_path_under_root(&dir, &path)?;
+    if !full.is_file() {
+        return Err(mcp::McpToolError::InvalidArg("Path is not a file".into()).into());
+    }
+    storage.add_context_file(&conversation_id, &root, &path)?;
+    storage
+        .list_context_files(&conversation_id)?
+        .into_iter()
+        .find(|f| f.root == root && f.path == path)
+        .map(|f| ContextFileDto {
+            root: f.root,
+            path: f.path,
+            added_at: f.added_at,
+        })
+        .ok_or_else(|| AppError::Ollama("context file not saved".into()))
+}
+
+#[tauri::command]
+fn remove_context_file(
+    state: State<AppState>,
+    conversation_id: String,
+    root: String,
+    path: String,
+) -> Result<bool, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.remove_context_file(&conversation_id, &root, &path.trim().replace('\\', "/"))?)
+}
+
+#[tauri::command]
+fn list_context_files(state: State<AppState>, conversation_id: String) -> Result<Vec<ContextFileDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage
+        .list_context_files(&conversation_id)?
+        .into_iter()
+        .map(|f| ContextFileDto {
+            root: f.root,
+            path: f.path,
+            added_at: f.added_at,
+        })
+        .collect())
+}
+
+/// System message carrying the conversation's context files, read fresh so edits show up on the
+/// next turn. Files that can't be read are listed with the reason instead of failing the chat.
+/// Returns the message and the number of files included, or None if the conversation has none.
+fn context_files_message(
+    files: &[storage::ContextFileRow],
+    mcp_settings: &storage::McpSettings,
+) -> Option<(ollama::ChatMessage, usize)> {
+    if files.is_empty() {
+        return None;
+    }
+    let mut body = String::from(
+        "The user attached these files to the conversation as reference context. Their current contents follow.\n",
+    );
+    let mut budget = MAX_CONTEXT_FILES_TOTAL_CHARS;
+    let mut included = 0;
+    for f in files {
+        let read = context_file_root(mcp_settings, &f.root)
+            .and_then(|dir| mcp::read_context_file(&dir, &f.path, MAX_CONTEXT_FILE_CHARS.min(budget)));
+        match read {
+            Ok((text, cut)) => {
+                budget -= text.chars().count();
+                included += 1;
+                body.push_str(&format!("\n--- {} ---\n{}\n", f.path, text.trim_end()));
+                if cut {
+                    body.push_str("... (truncated)\n");
+                }
+            }
+            Err(e) => body.push_str(&format!("\n--- {} ---\n[unavailable: {}]\n", f.path, e)),
+        }
+        if budget == 0 {
+            body.push_str("\n[context file limit reached; remaining files omitted]\n");
+            break;
+        }
+    }
+    Some((
+        ollama::ChatMessage {
+            role: "system".to_string(),
+            content: body,
+        },
+        included,
+    ))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteIndexReportDto {
+    /// Files (re-)embedded because they were new or changed.
+    pub indexed_files: usize,
+    pub unchanged_files: usize,
+    /// Files dropped from the cache because they no longer exist.
+    pub removed_files: usize,
+    pub chunks_embedded: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SemanticSearchHitDto {
+    pub path: String,
+    pub chunk_index: i64,
+    pub chunk: String,
+    pub score: f32,
+}
+
+const SEMANTIC_SEARCH_DEFAULT_TOP_K: usize = 5;
+const SEMANTIC_SEARCH_MAX_TOP_K: usize = 50;
+
+/// Bring the embeddings cache for `root` up to date: embed new/changed notes (by content hash and
+/// model) and drop deleted ones. The storage lock is never held across Ollama calls. Fails with
+/// RootNotConfigured when the user turned the root off (see context_file_root), so neither
+/// indexing nor search reads notes the tools could not.
+async fn refresh_note_index(
+    state: &AppState,
+    root: &str,
+    model: &str,
+    window: &tauri::Window,
+) -> Result<NoteIndexReportDto, AppError> {
+    let (dir, cached) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let dir = context_file_root(&storage.get_mcp_settings()?, root)?;
+        (dir, storage.embedded_file_hashes(root)?)
+    };
+    let notes = semantic::collect_note_files(&dir);
+    let mut report = NoteIndexReportDto {
+        indexed_files: 0,
+        unchanged_files: 0,
+        removed_files: 0,
+        chunks_embedded: 0,
+    };
+    let mut seen = HashSet::new();
+    for note in &notes {
+        seen.insert(note.path.as_str());
+        let Ok(content) = std::fs::read_to_string(&note.full_path) else { continue };
+        let hash = semantic::content_hash(&content);
+        if cached.get(&note.path).map(|(h, m)| h == &hash && m == model).unwrap_or(false) {
+            report.unchanged_files += 1;
+            continue;
+        }
+        let chunks = semantic::chunk_text(&content);
+        let embeddings = if chunks.is_empty() {
+            Vec::new()
+        } else {
+            state.ollama.embed(model, &chunks).await.map_err(|e| {
+                diagnostics::log(
+                    Some(window),
+                    "ERROR",
+                    "note embedding failed",
+                    Some(serde_json::json!({ "path": note.path, "model": model, "error": e })),
+                );
+                AppError::Ollama(e)
+            })?
+        };
+        let pairs: Vec<(String, Vec<f32>)> = chunks.into_iter().zip(embeddings).collect();
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.replace_file_embeddings(root, &note.path, &hash, model, &pairs)?;
+        report.indexed_files += 1;
+        report.chunks_embedded += pairs.len();
+    }
+    let removed: Vec<&String> = cached.keys().filter(|p| !seen.contains(p.as_str())).collect();
+    if !removed.is_empty() {
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        for path in &removed {
+            storage.replace_file_embeddings(root, path, "", model, &[])?;
+        }
+        report.removed_files = removed.len();
+    }
+    diagnostics::log(
+        Some(window),
+        "INFO",
+        "note index refreshed",
+        Some(serde_json::json!({
+            "root": root,
+            "model": model,
+            "indexed_files": report.indexed_files,
+            "unchanged_files": report.unchanged_files,
+            "removed_files": report.removed_files,
+            "chunks_embedded": report.chunks_embedded,
+        })),
+    );
+    Ok(report)
+}
+
+/// Embed notes under the filesystem root or Obsidian vault that are new or changed since the last run.
+#[tauri::command]
+async fn index_notes(
+    state: State<'_, AppState>,
+    root: String,
+    model: Option<String>,
+    window: tauri::Window,
+) -> Result<NoteIndexReportDto, AppError> {
+    let model = model.filter(|m| !m.trim().is_empty()).unwrap_or_else(|| semantic::DEFAULT_EMBEDDING_MODEL.to_string());
+    refresh_note_index(&state, &root, &model, &window).await
+}
+
+/// Top-k note chunks by cosine similarity to the query. Refreshes the index first, so the first
+/// search builds it and later searches only re-embed changed files.
+#[tauri::command]
+async fn semantic_search_notes(
+    state: State<'_, AppState>,
+    query: String,
+    root: String,
+    top_k: Option<usize>,
+    model: Option<String>,
+    window: tauri::Window,
+) -> Result<Vec<SemanticSearchHitDto>, AppError> {
+    if query.trim().is_empty() {
+        return Err(mcp::McpToolError::InvalidArg("query is empty".into()).into());
+    }
+    let model = model.filter(|m| !m.trim().is_empty()).unwrap_or_else(|| semantic::DEFAULT_EMBEDDING_MODEL.to_string());
+    let top_k = top_k.unwrap_or(SEMANTIC_SEARCH_DEFAULT_TOP_K).clamp(1, SEMANTIC_SEARCH_MAX_TOP_K);
+    refresh_note_index(&state, &root, &model, &window).await?;
+    let query_embedding = state
+        .ollama
+        .embed(&model, &[query])
+        .await
+        .map_err(AppError::Ollama)?
+        .pop()
+        .unwrap_or_default();
+    let rows = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.list_embeddings(&root, &model)?
+    };
+    let mut hits: Vec<SemanticSearchHitDto> = rows
+        .into_iter()
+        .map(|r| SemanticSearchHitDto {
+            score: semantic::cosine_similarity(&query_embedding, &r.embedding),
+            path: r.path,
+            chunk_index: r.chunk_index,
+            chunk: r.chunk,
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(top_k);
+    Ok(hits)
+}
+
+/// Prompt for one rolling-summary pass: the previous summary (if any) plus the chunk being folded in.
+fn rolling_summary_prompt(previous: Option<&str>, chunk: &[storage::MessageRow]) -> Vec<ollama::ChatMessage> {
+    let mut transcript = String::new();
+    if let Some(prev) = previous {
+        transcript.push_str(&format!("Summary so far:\n{}\n\nNew messages:\n", prev));
+    }
+    for m in chunk {
+        transcript.push_str(&format!("{}: {}\n\n", m.role, m.content.trim()));
+    }
+    vec![
+        ollama::ChatMessage {
+            role: "system".to_string(),
+            content: "You maintain a running summary of a chat so it can continue without the full history. \
+                Write a concise summary (at most ~300 words) of the facts, decisions, user preferences and open \
+                questions below. Keep names, numbers, file paths and code identifiers exact. Output only the summary."
+                .to_string(),
+        },
+        ollama::ChatMessage {
+            role: "user".to_string(),
+            content: transcript,
+        },
+    ]
+}
+
+/// If rolling summaries are enabled and the conversation has grown past the threshold, summarize the
+/// oldest unsummarized messages (plus the previous summary) with `model` and store the result.
+/// Original messages are kept. Returns true when a new summary was written.
+#[tauri::command]
+async fn summarize_conversation_if_needed(
+    state: State<'_, AppState>,
+    id: String,
+    model: String,
+    window: tauri::Window,
+) -> Result<bool, AppError> {
+    let (previous, chunk) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let settings = storage.get_settings()?;
+        if !settings.rolling_summary_enabled {
+            return Ok(false);
+        }
+        let threshold = settings.rolling_summary_threshold.max(0) as usize;
+        let Some(chunk) = storage.pending_summary_chunk(&id, threshold, ROLLING_SUMMARY_KEEP_RECENT)? else {
+            return Ok(false);
+        };
+        (storage.get_conversation_summary(&id)?.map(|s| s.content), chunk)
+    };
+    let Some(through_id) = chunk.last().map(|m| m.id.clone()) else {
+        return Ok(false);
+    };
+    let start = std::time::Instant::now();
+    let summary = state
+        .ollama
+        .chat(
+            &model,
+            rolling_summary_prompt(previous.as_deref(), &chunk),
+            ollama::ChatOptions {
+                temperature: Some(0.2),
+                num_predict: Some(600),
+            },
+        )
+        .await
+        .map_err(AppError::Ollama)?;
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err(AppError::Ollama("summary model returned an empty response".into()));
+    }
+    {
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.save_conversation_summary(&id, summary, &through_id)?;
+    }
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "rolling summary updated",
+        Some(serde_json::json!({
+            "conversation_id": id,
+            "messages_summarized": chunk.len(),
+            "summary_chars": summary.chars().count(),
+            "elapsed_ms": start.elapsed().as_millis() as u64,
+        })),
+    );
+    Ok(true)
+}
+
+#[tauri::command]
+fn get_settings(state: State<AppState>) -> Result<SettingsDto, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let s = storage.get_settings()?;
+    Ok(SettingsDto {
+        theme: s.theme,
+        selected_model: s.selected_model,
+        system_prompt: s.system_prompt,
+        temperature: s.temperature,
+        max_tokens: s.max_tokens,
+        tool_calling_mode: s.tool_calling_mode,
+        inference_device_preference: s.inference_device_preference,
+        rolling_summary_enabled: s.rolling_summary_enabled,
+        rolling_summary_threshold: s.rolling_summary_threshold,
+        response_cache_enabled: s.response_cache_enabled,
+        health_check_interval_secs: s.health_check_interval_secs,
+        agent_max_iterations: s.agent_max_iterations,
+        agent_max_tool_calls: s.agent_max_tool_calls,
+        agent_timeout_secs: s.agent_timeout_secs,
+    })
+}
+
+/// Check settings without saving. Returns one entry per invalid field (empty when valid).
+#[tauri::command]
+fn validate_settings(settings: SettingsDto) -> Vec<SettingsFieldErrorDto> {
+    settings_field_errors(&settings)
+}
+
+#[tauri::command]
+fn save_settings(
+    state: State<AppState>,
+    settings: SettingsDto,
+    window: tauri::Window,
+) -> Result<(), AppError> {
+    let mut errors = settings_field_errors(&settings);
+    errors.retain(|e| e.field != "inference_device_preference");
+    if !errors.is_empty() {
+        let summary: Vec<String> = errors.iter().map(|e| format!("{} {}", e.field, e.message)).collect();
+        return Err(AppError::InvalidSettings(summary.join("; ")));
+    }
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let prev = storage.get_settings().ok().map(|s| s.selected_model);
+    let mut inference_device_preference = settings.inference_device_preference.trim().to_string();
+    if !INFERENCE_DEVICE_PREFERENCES.contains(&inference_device_preference.as_str()) {
+        diagnostics::log(
+            Some(&window),
+            "WARN",
+            "unknown inference device preference, using auto",
+            Some(serde_json::json!({ "inference_device_preference": inference_device_preference })),
+        );
+        inference_device_preference = default_inference_device_preference();
+    }
+    storage.save_settings(storage::Settings {
+        theme: settings.theme,
+        selected_model: settings.selected_model.clone(),
+        system_prompt: settings.system_prompt,
+        temperature: settings.temperature,
+        max_tokens: settings.max_tokens,
+        tool_calling_mode: settings.tool_calling_mode,
+        inference_device_preference,
+        rolling_summary_enabled: settings.rolling_summary_enabled,
+        rolling_summary_threshold: settings.rolling_summary_threshold,
+        response_cache_enabled: settings.response_cache_enabled,
+        health_check_interval_secs: settings.health_check_interval_secs,
+        agent_max_iterations: settings.agent_max_iterations,
+        agent_max_tool_calls: settings.agent_max_tool_calls,
+        agent_timeout_secs: settings.agent_timeout_secs,
+    })?;
+    if prev.as_deref() != Some(settings.selected_model.as_str()) {
+        diagnostics::log(
+            Some(&window),
+            "INFO",
+            "active_model change",
+            Some(serde_json::json!({ "active_model": settings.selected_model })),
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct OllamaHealthDto {
+    pub reachable: bool,
+    pub base_url: String,
+    /// None when unreachable or when /api/version did not answer.
+    pub version: Option<String>,
+    /// Installed models (from /api/tags); 0 when unreachable.
+    pub model_count: usize,
+    /// Names of the installed models; empty when unreachable.
+    pub models: Vec<String>,
+    /// Round trip of the /api/tags request.
+    pub latency_ms: u64,
+}
+
+/// Reachability, version and model count in one call for the status page. An unreachable server
+/// is reported in the result, not as an error.
+#[tauri::command]
+async fn ollama_health(state: State<'_, AppState>, window: tauri::Window) -> Result<OllamaHealthDto, AppError> {
+    let start = std::time::Instant::now();
+    let (models, version) = futures_util::future::join(
+        async {
+            let models = state.ollama.list_models().await;
+            (models, start.elapsed().as_millis() as u64)
+        },
+        state.ollama.version(),
+    )
+    .await;
+    let (models, latency_ms) = models;
+    let health = OllamaHealthDto {
+        reachable: models.is_ok(),
+        base_url: state.ollama.base_url().to_string(),
+        version: models.as_ref().ok().and(version.ok()),
+        model_count: models.as_ref().map(Vec::len).unwrap_or(0),
+        models: models.as_ref().map(|m| m.iter().map(|m| m.name.clone()).collect()).unwrap_or_default(),
+        latency_ms,
+    };
+    match &models {
+        Ok(_) => diagnostics::log(
             Some(&window),
             "INFO",
             "ollama health",
-            Some(serde_json::json!({ "ok": *ok })),
+            Some(serde_json::to_value(&health).unwrap_or_default()),
         ),
         Err(e) => diagnostics::log(
             Some(&window),
             "WARN",
             "ollama health error",
-            Some(serde_json::json!({ "error": e.to_string() })),
+            Some(serde_json::json!({ "error": e, "base_url": health.base_url, "latency_ms": latency_ms })),
         ),
     }
-    result.map_err(AppError::Ollama)
+    Ok(health)
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenCountDto {
+    pub tokens: u64,
+    /// False when Ollama could not be queried and `tokens` is an estimate.
+    pub exact: bool,
+}
+
+/// Token count of `text` with the model's own tokenizer, for context-budget displays. Falls back
+/// to a chars-per-token estimate (measured for the model when possible) if Ollama can't answer.
+#[tauri::command]
+async fn count_tokens(state: State<'_, AppState>, model: String, text: String) -> Result<TokenCountDto, AppError> {
+    match state.ollama.count_tokens(&model, &text).await {
+        Ok(tokens) => Ok(TokenCountDto { tokens, exact: true }),
+        Err(e) => {
+            diagnostics::log(
+                None,
+                "WARN",
+                "token count fell back to estimate",
+                Some(serde_json::json!({ "model": model, "error": e })),
+            );
+            Ok(TokenCountDto {
+                tokens: state.ollama.estimate_tokens(&model, &text),
+                exact: false,
+            })
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContextUsageDto {
+    pub model: String,
+    /// Estimated prompt tokens for the next request: system prompt, context files, all messages.
+    pub used_tokens: u64,
+    /// Window Ollama uses for our requests (min of its num_ctx default and the model's length).
+    pub context_window: u64,
+    /// The model's trained context length from /api/show; None when Ollama could not be asked.
+    pub model_context_length: Option<u64>,
+    /// Tokens kept free for the reply (the conversation's num_predict, at most half the window).
+    pub reserved_tokens: u64,
+    /// context_window minus reserved_tokens: what the prompt may use.
+    pub budget_tokens: u64,
+    /// Messages in the conversation (excluding the system prompt and context files).
+    pub message_count: usize,
+    /// Oldest messages that would be cut to fit the budget; 0 when everything fits.
+    pub dropped_messages: usize,
+    /// Always true for now: per-message counts are estimates from the model's chars-per-token ratio.
+    pub estimated: bool,
+}
+
+/// Oldest messages to drop so `fixed + sum(messages)` fits in `budget`. The latest message is
+/// never dropped, so an oversized last turn reports everything before it.
+fn messages_dropped_to_fit(message_tokens: &[u64], fixed_tokens: u64, budget: u64) -> usize {
+    let mut total: u64 = fixed_tokens + message_tokens.iter().sum::<u64>();
+    let mut dropped = 0;
+    for tokens in message_tokens.iter().take(message_tokens.len().saturating_sub(1)) {
+        if total <= budget {
+            break;
+        }
+        total -= tokens;
+        dropped += 1;
+    }
+    dropped
+}
+
+/// How full the model's context is for a conversation, for a fill bar that warns before the
+/// oldest turns silently fall out of the window.
+#[tauri::command]
+async fn context_usage(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    model: Option<String>,
+) -> Result<ContextUsageDto, AppError> {
+    let (model, system_prompt, context_message, messages, num_predict) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let (conv, messages) = storage
+            .get_conversation_with_messages(&conversation_id)?
+            .ok_or_else(|| AppError::Ollama(format!("Conversation not found: {}", conversation_id)))?;
+        let settings = storage.get_settings()?;
+        let model = model
+            .filter(|m| !m.trim().is_empty())
+            .or(conv.last_model_used.clone())
+            .unwrap_or_else(|| settings.selected_model.clone());
+        let system_prompt = effective_system_prompt(&storage, conv.system_prompt.as_deref(), &model)?;
+        let files = storage.list_context_files(&conversation_id)?;
+        let context_message = context_files_message(&files, &storage.get_mcp_settings()?).map(|(m, _)| m.content);
+        let options = conversation_chat_options(conv.chat_options.as_deref(), &settings);
+        (model, system_prompt, context_message, messages, options.num_predict)
+    };
+    let model_context_length = state
+        .ollama
+        .show_model(&model)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|show| ollama::context_length_from_show(&show));
+    let context_window = model_context_length.map_or(ollama::default_num_ctx(), |len| len.min(ollama::default_num_ctx()));
+    let reserved_tokens = num_predict.map_or(0, u64::from).min(context_window / 2);
+    let budget_tokens = context_window - reserved_tokens;
+    let fixed_tokens = state.ollama.estimate_tokens(&model, &system_prompt)
+        + context_message.map_or(0, |c| state.ollama.estimate_tokens(&model, &c));
+    let message_tokens: Vec<u64> = messages
+        .iter()
+        .map(|m| state.ollama.estimate_tokens(&model, &m.content))
+        .collect();
+    Ok(ContextUsageDto {
+        used_tokens: fixed_tokens + message_tokens.iter().sum::<u64>(),
+        dropped_messages: messages_dropped_to_fit(&message_tokens, fixed_tokens, budget_tokens),
+        message_count: messages.len(),
+        model,
+        context_window,
+        model_context_length,
+        reserved_tokens,
+        budget_tokens,
+        estimated: true,
+    })
 }
 
 #[tauri::command]
-async fn ollama_list_models(state: State<'_, AppState>) -> Result<Vec<ollama::ModelInfo>, AppError> {
-    state.ollama.list_models().await.map_err(AppError::Ollama)
+async fn ollama_list_models(
+    state: State<'_, AppState>,
+    capability: Option<String>,
+) -> Result<Vec<ollama::ModelInfo>, AppError> {
+    installed_models(&state, capability).await
+}
+
+async fn installed_models(state: &AppState, capability: Option<String>) -> Result<Vec<ollama::ModelInfo>, AppError> {
+    let Some(capability) = capability.map(|c| c.trim().to_lowercase()).filter(|c| !c.is_empty()) else {
+        return state.ollama.list_models().await.map_err(AppError::Ollama);
+    };
+    let capability = if capability == "embeddings" { "embedding".to_string() } else { capability };
+    if !ollama::MODEL_CAPABILITIES.contains(&capability.as_str()) {
+        return Err(AppError::Ollama(format!(
+            "Unknown capability '{}' (use chat, vision or embedding)",
+            capability
+        )));
+    }
+    state
+        .ollama
+        .list_models_with_capability(&capability)
+        .await
+        .map_err(AppError::Ollama)
+}
+
+/// Whether `model` differs from the build with `known_digest`: true if the digest changed, the
+/// model is gone, or either digest is unknown (so callers invalidate rather than trust a stale cache).
+fn digest_changed(current: Option<&str>, known_digest: &str) -> bool {
+    let known = known_digest.trim();
+    match current {
+        Some(current) if !known.is_empty() => {
+            // /api/tags reports the bare hex digest; accept a "sha256:" prefix on either side.
+            current.trim_start_matches("sha256:") != known.trim_start_matches("sha256:")
+        }
+        _ => true,
+    }
+}
+
+#[tauri::command]
+async fn model_changed_since(
+    state: State<'_, AppState>,
+    model: String,
+    known_digest: String,
+) -> Result<bool, AppError> {
+    let current = state.ollama.model_digest(&model).await.map_err(AppError::Ollama)?;
+    Ok(digest_changed(current.as_deref(), &known_digest))
+}
+
+/// Favorites that are still installed; names are compared with an implicit ":latest".
+fn prune_favorite_models(favorites: &[String], installed: &[String]) -> Vec<String> {
+    let installed: HashSet<String> = installed.iter().map(|m| ollama::normalize_model_name(m)).collect();
+    favorites
+        .iter()
+        .filter(|f| installed.contains(&ollama::normalize_model_name(f)))
+        .cloned()
+        .collect()
+}
+
+/// Pinned models for the quick-access section. Favorites no longer installed are dropped (and the
+/// pruned list saved); if Ollama is unreachable the stored list is returned unchanged.
+#[tauri::command]
+async fn list_favorite_models(state: State<'_, AppState>) -> Result<Vec<String>, AppError> {
+    let favorites = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_favorite_models()?
+    };
+    let Ok(installed) = state.ollama.list_models().await else {
+        return Ok(favorites);
+    };
+    let installed: Vec<String> = installed.into_iter().map(|m| m.name).collect();
+    let pruned = prune_favorite_models(&favorites, &installed);
+    if pruned.len() != favorites.len() {
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.set_favorite_models(&pruned)?;
+    }
+    Ok(pruned)
+}
+
+/// Pin a model; returns the updated favorites. Adding an existing favorite is a no-op.
+#[tauri::command]
+fn add_favorite_model(state: State<AppState>, model: String) -> Result<Vec<String>, AppError> {
+    let model = model.trim();
+    if model.is_empty() {
+        return Err(AppError::InvalidSettings("model name is empty".into()));
+    }
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let mut favorites = storage.get_favorite_models()?;
+    let key = ollama::normalize_model_name(model);
+    if !favorites.iter().any(|f| ollama::normalize_model_name(f) == key) {
+        favorites.push(model.to_string());
+        storage.set_favorite_models(&favorites)?;
+    }
+    Ok(favorites)
+}
+
+/// Unpin a model; returns the updated favorites.
+#[tauri::command]
+fn remove_favorite_model(state: State<AppState>, model: String) -> Result<Vec<String>, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let mut favorites = storage.get_favorite_models()?;
+    let key = ollama::normalize_model_name(&model);
+    let before = favorites.len();
+    favorites.retain(|f| ollama::normalize_model_name(f) != key);
+    if favorites.len() != before {
+        storage.set_favorite_models(&favorites)?;
+    }
+    Ok(favorites)
+}
+
+/// Installed models with family, parameter size and quantization (merged from tags and /api/show).
+#[tauri::command]
+async fn ollama_models_detailed(state: State<'_, AppState>) -> Result<Vec<ollama::ModelInfo>, AppError> {
+    state.ollama.list_models_detailed().await.map_err(AppError::Ollama)
 }
 
 #[derive(Clone, Serialize)]
@@ -290,14 +1914,98 @@ struct ModelPullProgressPayload {
     total: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     percent: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes_per_sec: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eta_seconds: Option<u64>,
+}
+
+/// Samples kept for the pull rate; older ones fall out so the estimate follows the current speed.
+const PULL_RATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(10);
+/// No new bytes for this long counts as stalled: the ETA is dropped instead of shown from the
+/// older, faster samples still in the window.
+const PULL_STALL_AFTER: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Moving-window download rate for one layer. Reset on every digest change, since each layer
+/// restarts its completed count and mixing layers would make the ETA jump.
+#[derive(Default)]
+struct PullRate {
+    digest: String,
+    samples: std::collections::VecDeque<(std::time::Instant, u64)>,
+}
+
+impl PullRate {
+    /// Record `completed` bytes for `digest` at `now`; returns (bytes_per_sec, eta_seconds) once
+    /// the window spans enough time to measure. A stalled download reports a zero rate and no ETA.
+    fn sample(&mut self, digest: &str, completed: u64, total: u64, now: std::time::Instant) -> (Option<u64>, Option<u64>) {
+        if self.digest != digest {
+            self.digest = digest.to_string();
+            self.samples.clear();
+        }
+        self.samples.push_back((now, completed));
+        while self.samples.len() > 2
+            && self.samples.front().map(|(t, _)| now.duration_since(*t) > PULL_RATE_WINDOW).unwrap_or(false)
+        {
+            self.samples.pop_front();
+        }
+        let Some(&(first_at, first_completed)) = self.samples.front() else { return (None, None) };
+        let stalled_since = self
+            .samples
+            .iter()
+            .rev()
+            .take_while(|(_, c)| *c == completed)
+            .last()
+            .map(|(t, _)| *t);
+        if completed < total && stalled_since.is_some_and(|t| now.duration_since(t) >= PULL_STALL_AFTER) {
+            return (Some(0), None);
+        }
+        let elapsed = now.duration_since(first_at).as_secs_f64();
+        if elapsed < 0.5 {
+            return (None, None);
+        }
+        let rate = completed.saturating_sub(first_completed) as f64 / elapsed;
+        if rate <= 0.0 {
+            return (Some(0), None);
+        }
+        let eta = total.saturating_sub(completed) as f64 / rate;
+        (Some(rate.round() as u64), Some(eta.ceil() as u64))
+    }
+}
+
+/// Removes a tag from AppState::active_pulls when the pull command returns (including on error).
+struct ActivePullGuard<'a> {
+    pulls: &'a Mutex<HashSet<String>>,
+    key: String,
+}
+
+impl Drop for ActivePullGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut pulls) = self.pulls.lock() {
+            pulls.remove(&self.key);
+        }
+    }
 }
 
+/// Pull a model, streaming progress events. Re-pulling a partially downloaded model resumes on the
+/// Ollama side; progress is reported per layer and never moves backwards, and status-only events
+/// (manifest, verifying) carry no percent so the UI keeps the last value instead of jumping to 0%.
 #[tauri::command]
 async fn ollama_pull_model(
     state: State<'_, AppState>,
     model: String,
     window: tauri::Window,
 ) -> Result<(), AppError> {
+    let key = ollama::normalize_model_name(&model);
+    let _guard = {
+        let mut pulls = state.active_pulls.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        if !pulls.insert(key.clone()) {
+            return Err(AppError::Ollama(format!("{} is already being downloaded", model)));
+        }
+        ActivePullGuard {
+            pulls: &state.active_pulls,
+            key,
+        }
+    };
     let tag = model.clone();
     let _ = window.emit(
         "model-pull-start",
@@ -324,21 +2032,54 @@ async fn ollama_pull_model(
     })?;
     futures_util::pin_mut!(stream);
     let mut last_pct: Option<u64> = None;
+    // Highest completed byte count seen per layer digest, so progress never goes backwards.
+    let mut layer_completed: HashMap<String, u64> = HashMap::new();
+    let mut rate = PullRate::default();
     while let Some(evt) = stream.next().await {
         if let Ok(evt) = evt {
-            let completed = evt.completed.unwrap_or(0);
             let total = evt.total.unwrap_or(0);
-            let percent = if total > 0 { (100 * completed) / total } else { 0 };
+            if total == 0 {
+                let payload = ModelPullProgressPayload {
+                    tag: tag.clone(),
+                    status: evt.status.clone(),
+                    completed: None,
+                    total: None,
+                    percent: None,
+                    bytes_per_sec: None,
+                    eta_seconds: None,
+                };
+                let _ = window.emit("model-pull-progress", &payload);
+                let _ = window.emit("ollama-pull-progress", &evt);
+                continue;
+            }
+            let digest = evt.digest.clone().unwrap_or_default();
+            let first_for_layer = !layer_completed.contains_key(&digest);
+            let seen = layer_completed.entry(digest.clone()).or_insert(0);
+            *seen = (*seen).max(evt.completed.unwrap_or(0));
+            let completed = *seen;
+            if first_for_layer && completed > 0 {
+                diagnostics::log(
+                    Some(&window),
+                    "INFO",
+                    "model pull resumed",
+                    Some(serde_json::json!({ "model": model, "digest": digest, "completed": completed, "total": total })),
+                );
+            }
+            let percent = (100 * completed).checked_div(total).unwrap_or(0).min(100);
+            let (bytes_per_sec, eta_seconds) =
+                rate.sample(&digest, completed, total, std::time::Instant::now());
             let payload = ModelPullProgressPayload {
                 tag: tag.clone(),
                 status: evt.status.clone(),
                 completed: Some(completed),
                 total: Some(total),
                 percent: Some(percent),
+                bytes_per_sec,
+                eta_seconds,
             };
             let _ = window.emit("model-pull-progress", &payload);
             let _ = window.emit("ollama-pull-progress", &evt);
-            if total > 0 && last_pct.map(|p| percent.saturating_sub(p) >= 10).unwrap_or(true) {
+            if last_pct.map(|p| percent.saturating_sub(p) >= 10 || percent < p).unwrap_or(true) {
                 last_pct = Some(percent);
                 diagnostics::log(
                     Some(&window),
@@ -377,6 +2118,23 @@ async fn ollama_delete_model(
     state.ollama.delete_model(&model).await.map_err(AppError::Ollama)
 }
 
+/// Unload a model from memory without restarting Ollama. Returns true when /api/ps confirms it is gone.
+#[tauri::command]
+async fn ollama_unload_model(
+    state: State<'_, AppState>,
+    model: String,
+    window: tauri::Window,
+) -> Result<bool, AppError> {
+    let unloaded = state.ollama.unload_model(&model).await.map_err(AppError::Ollama)?;
+    diagnostics::log(
+        Some(&window),
+        if unloaded { "INFO" } else { "WARN" },
+        "model unload",
+        Some(serde_json::json!({ "model": model, "unloaded": unloaded })),
+    );
+    Ok(unloaded)
+}
+
 #[tauri::command]
 async fn ollama_show_model(
     state: State<'_, AppState>,
@@ -385,27 +2143,644 @@ async fn ollama_show_model(
     state.ollama.show_model(&model).await.map_err(AppError::Ollama)
 }
 
+/// A model's Modelfile, system prompt, template and parameters, for editing into a new model
+/// with create_model.
+#[tauri::command]
+async fn get_modelfile(state: State<'_, AppState>, model: String) -> Result<ollama::ModelfileInfo, AppError> {
+    let show = state
+        .ollama
+        .show_model(&model)
+        .await
+        .map_err(AppError::Ollama)?
+        .unwrap_or_default();
+    Ok(ollama::modelfile_from_show(&model, &show))
+}
+
+/// Save an edited copy of `from` as the new tag `model`. Fields left out are inherited from `from`.
+#[tauri::command]
+async fn create_model(
+    state: State<'_, AppState>,
+    model: String,
+    from: String,
+    system: Option<String>,
+    template: Option<String>,
+    parameters: Option<Vec<ollama::ModelParameter>>,
+    window: tauri::Window,
+) -> Result<(), AppError> {
+    let model = model.trim().to_string();
+    if model.is_empty() || model.contains(char::is_whitespace) {
+        return Err(AppError::InvalidSettings("Model name must be non-empty and contain no spaces".into()));
+    }
+    if from.trim().is_empty() {
+        return Err(AppError::InvalidSettings("Choose the model to copy from".into()));
+    }
+    if ollama::normalize_model_name(&model) == ollama::normalize_model_name(&from) {
+        return Err(AppError::InvalidSettings("Save under a new name; the source model would be replaced".into()));
+    }
+    let parameters = parameters.unwrap_or_default();
+    state
+        .ollama
+        .create_model(&model, from.trim(), system.as_deref(), template.as_deref(), &parameters)
+        .await
+        .map_err(AppError::Ollama)?;
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "model created",
+        Some(serde_json::json!({ "model": model, "from": from, "parameters": parameters.len() })),
+    );
+    Ok(())
+}
+
 #[derive(Clone, Serialize)]
 struct ChatDonePayload {
     canceled: bool,
+    /// Ollama's done_reason ("stop", "length", ...) when the stream finished normally.
+    done_reason: Option<String>,
+    /// The reply hit num_predict and likely stopped mid-sentence; continue_generation picks it up.
+    truncated: bool,
+    /// Served from the response cache; Ollama was not called.
+    cached: bool,
+    /// Set when the stream ended without Ollama's final `done` chunk (Ollama crashed, was
+    /// restarted, or the connection dropped): the reply is incomplete.
+    error: Option<String>,
+    /// What to do about `error`.
+    hint: Option<String>,
+    /// When canceled: whether the partial reply should be saved (see cancel_chat_generation).
+    keep_partial: Option<bool>,
+}
+
+/// Shown with an interrupted stream; Continue resumes from the partial reply.
+const STREAM_INTERRUPTED_HINT: &str =
+    "Check that Ollama is still running (it may have crashed or run out of memory), then use Continue to resume the reply.";
+
+/// Cached replies older than this are ignored (and pruned on the next insert).
+const RESPONSE_CACHE_TTL_SECS: i64 = 24 * 60 * 60;
+/// Cached replies are replayed as deltas of this many characters.
+const CACHED_REPLY_DELTA_CHARS: usize = 64;
+
+/// Cache key and canonical request text for a chat request, or None when the reply must not be
+/// cached: temperature other than 0 (any other temperature is meant to vary), or tools in play
+/// (`tools_enabled` from the caller, or tool results already in the history).
+fn response_cache_key(
+    model: &str,
+    messages: &[ollama::ChatMessage],
+    options: &ollama::ChatOptions,
+    tools_enabled: bool,
+) -> Option<(String, String)> {
+    if options.temperature != Some(0.0) || tools_enabled {
+        return None;
+    }
+    if messages.iter().any(|m| m.content.starts_with("[Tool result from ")) {
+        return None;
+    }
+    let request = serde_json::json!({
+        "model": ollama::normalize_model_name(model),
+        "messages": messages,
+        "options": options,
+    })
+    .to_string();
+    Some((semantic::content_hash(&request), request))
+}
+
+/// Drop every cached reply. Returns the number removed.
+#[tauri::command]
+fn clear_response_cache(state: State<AppState>) -> Result<usize, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.clear_response_cache()?)
+}
+
+/// "inference-started": emitted once per chat request, before the request goes to Ollama.
+#[derive(Clone, Serialize)]
+struct InferenceStartedPayload {
+    model: String,
+    conversation_id: Option<String>,
+    inference_device_preference: String,
+    gpu_detected: bool,
+    gpu_name: String,
+}
+
+/// A chat request with no first token after this long is reported as a model (cold) load.
+const MODEL_LOADING_THRESHOLD_MS: u64 = 2_500;
+
+/// "model-loading": emitted with `loading: true` when the first token is slower than
+/// MODEL_LOADING_THRESHOLD_MS, then with `loading: false` once it arrives (or the request ends).
+/// `was_loaded` is whether /api/ps listed the model before the request; if it was, a long wait is
+/// more likely a slow or hung request than a cold load.
+#[derive(Clone, Serialize)]
+struct ModelLoadingPayload {
+    model: String,
+    conversation_id: Option<String>,
+    loading: bool,
+    was_loaded: bool,
+    elapsed_ms: u64,
+}
+
+#[derive(Clone, Serialize)]
+struct InferenceStats {
+    canceled: bool,
+    done_reason: Option<String>,
+    truncated: bool,
+    chunk_count: u32,
+    duration_ms: u64,
+    time_to_first_token_ms: u64,
+    tokens_per_sec: f64,
+}
+
+/// "inference-finished": pairs with inference-started. `error` is set when the request failed
+/// before streaming; `stats` is None in that case.
+#[derive(Clone, Serialize)]
+struct InferenceFinishedPayload {
+    model: String,
+    conversation_id: Option<String>,
+    stats: Option<InferenceStats>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelSystemPromptDto {
+    /// Exact model name ("qwen2.5-coder:7b") or name prefix ("qwen2.5-coder").
+    pub pattern: String,
+    pub prompt: String,
+    pub updated_at: i64,
+}
+
+/// Per-model prompt for `model`: an exact name match wins (a missing tag means ":latest"),
+/// then the longest pattern the name starts with.
+fn match_model_system_prompt<'a>(rows: &'a [storage::ModelSystemPromptRow], model: &str) -> Option<&'a str> {
+    let normalized = ollama::normalize_model_name(model);
+    let model = model.trim();
+    rows.iter()
+        .find(|r| ollama::normalize_model_name(&r.pattern) == normalized)
+        .or_else(|| {
+            rows.iter()
+                .filter(|r| !r.pattern.trim().is_empty() && model.starts_with(r.pattern.trim()))
+                .max_by_key(|r| r.pattern.trim().len())
+        })
+        .map(|r| r.prompt.as_str())
+}
+
+/// System prompt for `model` in a conversation: the conversation's own prompt if it has one, else
+/// the model's per-model prompt if one matches, else the global setting.
+fn effective_system_prompt(
+    storage: &storage::Storage,
+    conversation_prompt: Option<&str>,
+    model: &str,
+) -> Result<String, AppError> {
+    if let Some(prompt) = conversation_prompt.filter(|p| !p.trim().is_empty()) {
+        return Ok(prompt.to_string());
+    }
+    let rows = storage.list_model_system_prompts()?;
+    match match_model_system_prompt(&rows, model) {
+        Some(prompt) => Ok(prompt.to_string()),
+        None => Ok(storage.get_settings()?.system_prompt),
+    }
+}
+
+#[tauri::command]
+fn list_model_system_prompts(state: State<AppState>) -> Result<Vec<ModelSystemPromptDto>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage
+        .list_model_system_prompts()?
+        .into_iter()
+        .map(|r| ModelSystemPromptDto {
+            pattern: r.pattern,
+            prompt: r.prompt,
+            updated_at: r.updated_at,
+        })
+        .collect())
+}
+
+#[tauri::command]
+fn set_model_system_prompt(state: State<AppState>, pattern: String, prompt: String) -> Result<(), AppError> {
+    let pattern = pattern.trim();
+    if pattern.is_empty() {
+        return Err(AppError::InvalidSettings("model pattern is empty".into()));
+    }
+    if prompt.trim().is_empty() {
+        return Err(AppError::InvalidSettings("system prompt is empty".into()));
+    }
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.set_model_system_prompt(pattern, &prompt)?)
+}
+
+#[tauri::command]
+fn delete_model_system_prompt(state: State<AppState>, pattern: String) -> Result<bool, AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage.delete_model_system_prompt(pattern.trim())?)
+}
+
+/// Longest accepted ui_state key and value (JSON text); the store is for small UI preferences.
+const UI_STATE_MAX_KEY_CHARS: usize = 128;
+const UI_STATE_MAX_VALUE_BYTES: usize = 64 * 1024;
+
+fn ui_state_key(key: &str) -> Result<&str, AppError> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(AppError::InvalidSettings("UI state key is empty".into()));
+    }
+    if key.chars().count() > UI_STATE_MAX_KEY_CHARS {
+        return Err(AppError::InvalidSettings(format!(
+            "UI state key is longer than {} characters",
+            UI_STATE_MAX_KEY_CHARS
+        )));
+    }
+    Ok(key)
+}
+
+/// Frontend-owned UI state (sidebar width, last tab…) kept apart from the typed settings.
+/// None when the key is unset or its stored value is not valid JSON.
+#[tauri::command]
+fn get_ui_state(state: State<AppState>, key: String) -> Result<Option<serde_json::Value>, AppError> {
+    let key = ui_state_key(&key)?;
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(storage
+        .get_ui_state(key)?
+        .and_then(|value| serde_json::from_str(&value).ok()))
+}
+
+/// Store any JSON value under `key`; null removes the key.
+#[tauri::command]
+fn set_ui_state(state: State<AppState>, key: String, value: serde_json::Value) -> Result<(), AppError> {
+    let key = ui_state_key(&key)?;
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    if value.is_null() {
+        storage.delete_ui_state(key)?;
+        return Ok(());
+    }
+    let json = value.to_string();
+    if json.len() > UI_STATE_MAX_VALUE_BYTES {
+        return Err(AppError::InvalidSettings(format!(
+            "UI state value for {} is larger than {} KiB",
+            key,
+            UI_STATE_MAX_VALUE_BYTES / 1024
+        )));
+    }
+    Ok(storage.set_ui_state(key, &json)?)
+}
+
+/// The per-model system prompt that applies to `model`, or None to use the global one.
+#[tauri::command]
+fn get_model_system_prompt(state: State<AppState>, model: String) -> Result<Option<String>, AppError> {
+    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let rows = storage.list_model_system_prompts()?;
+    Ok(match_model_system_prompt(&rows, &model).map(str::to_string))
+}
+
+/// Turn appended by continue_generation; not stored in the conversation.
+const CONTINUE_PROMPT: &str =
+    "Your previous reply was cut off by the length limit. Continue exactly where it stopped, without repeating anything or adding a preamble.";
+/// Most recent messages sent with a continue request (matches the chat view's prompt window).
+const CONTINUE_MAX_HISTORY: usize = 50;
+
+/// Messages for continuing a truncated reply: system prompt, rolling summary (if any), recent
+/// history ending with the cut-off assistant message, then CONTINUE_PROMPT.
+fn continue_prompt_messages(
+    system_prompt: &str,
+    summary: Option<&storage::ConversationSummaryRow>,
+    history: &[storage::MessageRow],
+) -> Vec<ollama::ChatMessage> {
+    let mut out = history_prompt_messages(system_prompt, summary, history);
+    out.push(ollama::ChatMessage {
+        role: "user".to_string(),
+        content: CONTINUE_PROMPT.to_string(),
+    });
+    out
+}
+
+/// System prompt, rolling summary (if it covers part of `history`) and the most recent
+/// CONTINUE_MAX_HISTORY messages of `history`.
+fn history_prompt_messages(
+    system_prompt: &str,
+    summary: Option<&storage::ConversationSummaryRow>,
+    history: &[storage::MessageRow],
+) -> Vec<ollama::ChatMessage> {
+    let mut out = Vec::new();
+    if !system_prompt.trim().is_empty() {
+        out.push(ollama::ChatMessage {
+            role: "system".to_string(),
+            content: system_prompt.to_string(),
+        });
+    }
+    let covered = summary.and_then(|s| history.iter().position(|m| m.id == s.summarized_through_id));
+    let history = match (summary, covered) {
+        (Some(s), Some(idx)) => {
+            out.push(ollama::ChatMessage {
+                role: "system".to_string(),
+                content: format!("Summary of earlier messages in this conversation:\n{}", s.content),
+            });
+            &history[idx + 1..]
+        }
+        _ => history,
+    };
+    let start = history.len().saturating_sub(CONTINUE_MAX_HISTORY);
+    out.extend(history[start..].iter().map(|m| ollama::ChatMessage {
+        role: m.role.clone(),
+        content: m.content.clone(),
+    }));
+    out
+}
+
+/// Per-request switches for a chat stream that are not Ollama options.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct StreamFlags {
+    /// Also emit Ollama's final object (eval counts, durations, ...) as-is.
+    #[serde(default)]
+    emit_final_chunk: bool,
+    /// Tools are offered in this request, so its reply must not be cached.
+    #[serde(default)]
+    tools_enabled: bool,
 }
 
+/// Stream a chat reply. The options are remembered on the conversation so reopening it prefills them.
 #[tauri::command]
 async fn ollama_chat_stream(
     state: State<'_, AppState>,
     model: String,
     messages: Vec<ollama::ChatMessage>,
     options: Option<ollama::ChatOptions>,
+    conversation_id: Option<String>,
+    flags: Option<StreamFlags>,
     window: tauri::Window,
 ) -> Result<(), AppError> {
-    let inference_preference = state
+    if let (Some(cid), Some(opts)) = (conversation_id.as_deref(), options.as_ref()) {
+        let json = serde_json::to_string(opts).map_err(|e| AppError::Ollama(e.to_string()))?;
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.set_conversation_chat_options(cid, &json)?;
+    }
+    let flags = flags.unwrap_or_default();
+    stream_chat(&state, model, messages, options, conversation_id, flags, window)
+        .await
+        .map(|_| ())
+}
+
+/// Stream more of the conversation's last assistant reply after it was cut off (done_reason
+/// "length"). Emits the same ollama-chat-delta/done events as ollama_chat_stream, then appends
+/// the continuation to the truncated message and saves it here. Returns the updated message, or
+/// None when nothing was appended (empty continuation, or canceled with discard).
+#[tauri::command]
+async fn continue_generation(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    model: String,
+    options: Option<ollama::ChatOptions>,
+    window: tauri::Window,
+) -> Result<Option<MessageDto>, AppError> {
+    let (messages, cut_off) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let (conv, history) = storage
+            .get_conversation_with_messages(&conversation_id)?
+            .ok_or_else(|| AppError::Ollama("Conversation not found".into()))?;
+        let Some(cut_off) = history.last().filter(|m| m.role == "assistant").cloned() else {
+            return Err(AppError::Ollama("Nothing to continue: the last message is not an assistant reply".into()));
+        };
+        let summary = storage.get_conversation_summary(&conversation_id)?;
+        let system_prompt = effective_system_prompt(&storage, conv.system_prompt.as_deref(), &model)?;
+        (continue_prompt_messages(&system_prompt, summary.as_ref(), &history), cut_off)
+    };
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "continuing truncated reply",
+        Some(serde_json::json!({ "conversation_id": conversation_id, "model": model })),
+    );
+    let outcome = stream_chat(&state, model, messages, options, Some(conversation_id), StreamFlags::default(), window).await?;
+    if outcome.discarded || outcome.reply.is_empty() {
+        return Ok(None);
+    }
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    if !storage.append_message_content(&cut_off.id, &outcome.reply)? {
+        return Err(AppError::Ollama("The reply being continued was deleted".into()));
+    }
+    // Continuing finishes an interrupted reply, unless it was stopped again.
+    storage.set_message_interrupted(&cut_off.id, outcome.interrupted)?;
+    Ok(Some(MessageDto {
+        id: cut_off.id,
+        role: cut_off.role,
+        content: cut_off.content + &outcome.reply,
+        timestamp: cut_off.timestamp,
+        model: cut_off.model,
+        starred: cut_off.starred,
+        interrupted: outcome.interrupted,
+        variant: cut_off.variant.map(MessageVariantDto::from),
+        citations: Vec::new(),
+    }))
+}
+
+/// Index of the conversation's last user message and the id of the assistant reply after it
+/// (None while it is unanswered). None if there is no user message.
+fn retry_target(history: &[storage::MessageRow]) -> Option<(usize, Option<String>)> {
+    let user_idx = history.iter().rposition(|m| m.role == "user")?;
+    let reply = history[user_idx + 1..]
+        .iter()
+        .find(|m| m.role == "assistant")
+        .map(|m| m.id.clone());
+    Some((user_idx, reply))
+}
+
+/// Answer the last user message again with `new_model`, e.g. to compare local models. Streams like
+/// ollama_chat_stream and saves the reply here, tagged with `new_model`: as a new variant of the
+/// previous answer when `keep_previous` (default true), else in its place. Returns the saved
+/// message, or None when nothing was saved (empty reply, or canceled with discard).
+#[tauri::command]
+async fn retry_with_model(
+    state: State<'_, AppState>,
+    conversation_id: String,
+    new_model: String,
+    options: Option<ollama::ChatOptions>,
+    keep_previous: Option<bool>,
+    window: tauri::Window,
+) -> Result<Option<MessageDto>, AppError> {
+    let new_model = new_model.trim().to_string();
+    if new_model.is_empty() {
+        return Err(AppError::InvalidSettings("Choose a model to retry with".into()));
+    }
+    let keep_previous = keep_previous.unwrap_or(true);
+    let (messages, user_message_id, previous_reply) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let (conv, history) = storage
+            .get_conversation_with_messages(&conversation_id)?
+            .ok_or_else(|| AppError::Ollama("Conversation not found".into()))?;
+        let (user_idx, previous_reply) = retry_target(&history)
+            .ok_or_else(|| AppError::Ollama("Nothing to retry: the conversation has no user message".into()))?;
+        let summary = storage.get_conversation_summary(&conversation_id)?;
+        let system_prompt = effective_system_prompt(&storage, conv.system_prompt.as_deref(), &new_model)?;
+        let messages = history_prompt_messages(&system_prompt, summary.as_ref(), &history[..=user_idx]);
+        (messages, history[user_idx].id.clone(), previous_reply)
+    };
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "retrying with another model",
+        Some(serde_json::json!({
+            "conversation_id": conversation_id,
+            "model": new_model,
+            "previous_reply": previous_reply,
+            "keep_previous": keep_previous,
+        })),
+    );
+    let outcome = stream_chat(&state, new_model.clone(), messages, options, Some(conversation_id.clone()), StreamFlags::default(), window).await?;
+    if outcome.discarded || outcome.reply.trim().is_empty() {
+        return Ok(None);
+    }
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let mut m = match previous_reply {
+        Some(previous) if keep_previous => storage
+            .add_message_variant(&conversation_id, &previous, &outcome.reply, Some(&new_model))?
+            .ok_or_else(|| AppError::Ollama("The previous answer was deleted during the retry".into()))?,
+        Some(_) => {
+            storage.trim_after(&conversation_id, &user_message_id)?;
+            storage.add_message(&conversation_id, "assistant", &outcome.reply, Some(&new_model))?
+        }
+        None => storage.add_message(&conversation_id, "assistant", &outcome.reply, Some(&new_model))?,
+    };
+    if outcome.interrupted {
+        storage.set_message_interrupted(&m.id, true)?;
+        m.interrupted = true;
+    }
+    Ok(Some(MessageDto {
+        id: m.id,
+        role: m.role,
+        content: m.content,
+        timestamp: m.timestamp,
+        model: m.model,
+        starred: m.starred,
+        interrupted: m.interrupted,
+        variant: m.variant.map(MessageVariantDto::from),
+        citations: Vec::new(),
+    }))
+}
+
+/// Whether offline mode is on; false if the settings cannot be read.
+fn offline_mode_enabled(state: &AppState) -> bool {
+    state
         .storage
         .lock()
         .ok()
-        .and_then(|s| s.get_settings().ok())
+        .and_then(|s| s.get_mcp_settings().ok())
+        .is_some_and(|s| s.offline_mode)
+}
+
+/// The chat view sends its own system prompt (already model-specific); callers that send none get
+/// the prompt effective_system_prompt picks for `conversation_id` (its own prompt, else the
+/// model's, else the global one).
+fn insert_default_system_prompt(
+    state: &AppState,
+    model: &str,
+    conversation_id: Option<&str>,
+    messages: &mut Vec<ollama::ChatMessage>,
+) {
+    if messages.first().map(|m| m.role != "system").unwrap_or(true) {
+        let prompt = state
+            .storage
+            .lock()
+            .ok()
+            .and_then(|s| {
+                let own = match conversation_id {
+                    Some(cid) => s.get_conversation(cid).ok().flatten().and_then(|c| c.system_prompt),
+                    None => None,
+                };
+                effective_system_prompt(&s, own.as_deref(), model).ok()
+            })
+            .filter(|p| !p.trim().is_empty());
+        if let Some(prompt) = prompt {
+            messages.insert(
+                0,
+                ollama::ChatMessage {
+                    role: "system".to_string(),
+                    content: prompt,
+                },
+            );
+        }
+    }
+}
+
+/// What stream_chat produced: the reply as streamed (partial if canceled or interrupted), whether
+/// the user canceled and asked for it to be discarded, and whether the kept reply is incomplete
+/// (canceled, or ended without Ollama's final `done` chunk).
+struct StreamOutcome {
+    reply: String,
+    discarded: bool,
+    interrupted: bool,
+}
+
+/// Shared by ollama_chat_stream, continue_generation and retry_with_model: attach context files, stream deltas to
+/// the window, handle cancel, and emit ollama-chat-done. With `flags.emit_final_chunk`, Ollama's final
+/// (`done: true`) object is also emitted as ollama-chat-final.
+async fn stream_chat(
+    state: &AppState,
+    model: String,
+    mut messages: Vec<ollama::ChatMessage>,
+    options: Option<ollama::ChatOptions>,
+    conversation_id: Option<String>,
+    flags: StreamFlags,
+    window: tauri::Window,
+) -> Result<StreamOutcome, AppError> {
+    insert_default_system_prompt(state, &model, conversation_id.as_deref(), &mut messages);
+    if let Some(cid) = conversation_id.as_deref() {
+        let context = state.storage.lock().ok().and_then(|s| {
+            let files = s.list_context_files(cid).ok()?;
+            let mcp_settings = s.get_mcp_settings().ok()?;
+            context_files_message(&files, &mcp_settings)
+        });
+        if let Some((message, included)) = context {
+            diagnostics::log(
+                Some(&window),
+                "INFO",
+                "context files attached",
+                Some(serde_json::json!({ "files": included, "chars": message.content.chars().count() })),
+            );
+            // After the leading system prompt(s), before the conversation itself.
+            let at = messages.iter().take_while(|m| m.role == "system").count();
+            messages.insert(at, message);
+        }
+    }
+    let settings = state.storage.lock().ok().and_then(|s| s.get_settings().ok());
+    let options = options.unwrap_or_default();
+    let cache_key = settings
+        .as_ref()
+        .filter(|s| s.response_cache_enabled)
+        .and_then(|_| response_cache_key(&model, &messages, &options, flags.tools_enabled));
+    if let Some((key, request)) = &cache_key {
+        let cached = state
+            .storage
+            .lock()
+            .ok()
+            .and_then(|s| s.get_cached_response(key, request, RESPONSE_CACHE_TTL_SECS).ok().flatten());
+        if let Some(reply) = cached {
+            diagnostics::log(
+                Some(&window),
+                "INFO",
+                "response cache hit",
+                Some(serde_json::json!({ "model": model, "chars": reply.chars().count() })),
+            );
+            let chars: Vec<char> = reply.chars().collect();
+            for piece in chars.chunks(CACHED_REPLY_DELTA_CHARS) {
+                let _ = window.emit("ollama-chat-delta", piece.iter().collect::<String>());
+            }
+            let _ = window.emit(
+                "ollama-chat-done",
+                ChatDonePayload {
+                    canceled: false,
+                    done_reason: Some("stop".to_string()),
+                    truncated: false,
+                    cached: true,
+                    error: None,
+                    hint: None,
+                    keep_partial: None,
+                },
+            );
+            return Ok(StreamOutcome { reply, discarded: false, interrupted: false });
+        }
+    }
+    let inference_preference = settings
         .map(|s| s.inference_device_preference)
         .unwrap_or_else(|| "auto".to_string());
     let gpu_info = gpu::detect_gpu();
+    // Heuristic from /api/ps: only known once the model is loaded (e.g. by an earlier turn).
+    let loaded_vram = state.ollama.loaded_model_vram(Some(&model)).await.unwrap_or(None);
+    let model_was_loaded = loaded_vram.is_some();
+    let active_device = gpu::get_ollama_device_info(gpu_info.detected, loaded_vram).active_device;
     if inference_preference == "force_cpu" {
         diagnostics::log(
             Some(&window),
@@ -424,47 +2799,143 @@ async fn ollama_chat_stream(
             "inference_device_preference": inference_preference,
             "gpu_detected": gpu_info.detected,
             "gpu_name": gpu_info.name,
-            "active_device": "unknown",
+            "active_device": active_device,
             "model": model
         })),
     );
-    let stream = state
-        .ollama
-        .chat_stream(&model, messages.clone(), options.unwrap_or_default())
-        .await
+    let _ = window.emit(
+        "inference-started",
+        InferenceStartedPayload {
+            model: model.clone(),
+            conversation_id: conversation_id.clone(),
+            inference_device_preference: inference_preference.clone(),
+            gpu_detected: gpu_info.detected,
+            gpu_name: gpu_info.name.clone(),
+        },
+    );
+    let start = std::time::Instant::now();
+    let emit_model_loading = |loading: bool| {
+        if loading {
+            diagnostics::log(
+                Some(&window),
+                "INFO",
+                "waiting for model to load",
+                Some(serde_json::json!({ "model": model, "was_loaded": model_was_loaded })),
+            );
+        }
+        let _ = window.emit(
+            "model-loading",
+            ModelLoadingPayload {
+                model: model.clone(),
+                conversation_id: conversation_id.clone(),
+                loading,
+                was_loaded: model_was_loaded,
+                elapsed_ms: start.elapsed().as_millis() as u64,
+            },
+        );
+    };
+    // Ollama may not even send response headers until the model is in memory, so the deadline
+    // covers the request itself as well as the wait for the first chunk.
+    let loading_deadline = tokio::time::sleep(std::time::Duration::from_millis(MODEL_LOADING_THRESHOLD_MS));
+    tokio::pin!(loading_deadline);
+    let mut loading_shown = false;
+    let connected = {
+        let connect = state.ollama.chat_stream(&model, messages.clone(), options);
+        tokio::pin!(connect);
+        loop {
+            tokio::select! {
+                res = &mut connect => break res,
+                _ = &mut loading_deadline, if !loading_shown => {
+                    loading_shown = true;
+                    emit_model_loading(true);
+                }
+            }
+        }
+    };
+    if connected.is_err() && loading_shown {
+        emit_model_loading(false);
+    }
+    let stream = connected
         .map_err(|e| {
             diagnostics::log(
                 Some(&window),
                 "ERROR",
                 "chat stream error",
-                Some(serde_json::json!({ "error": e })),
+                Some(serde_json::json!({ "error": e.message, "status": e.status, "kind": e.kind, "body": e.body })),
             );
-            AppError::Ollama(e)
+            let _ = window.emit(
+                "inference-finished",
+                InferenceFinishedPayload {
+                    model: model.clone(),
+                    conversation_id: conversation_id.clone(),
+                    stats: None,
+                    error: Some(e.to_string()),
+                },
+            );
+            AppError::OllamaRequest(e)
         })?;
-    futures_util::pin_mut!(stream);
-    let (cancel_tx, mut cancel_rx) = oneshot::channel::<()>();
+    // Boxed rather than stack-pinned so it can be dropped as soon as the loop ends: dropping the
+    // response body closes the connection, which makes Ollama abort the generation.
+    let mut stream = Box::pin(stream);
+    let (cancel_tx, mut cancel_rx) = oneshot::channel::<bool>();
+    let cancel_key = conversation_id.clone().unwrap_or_default();
     {
-        let mut tx = state.chat_cancel_tx.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-        *tx = Some(cancel_tx);
+        let mut txs = state.chat_cancel_txs.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        txs.insert(cancel_key.clone(), cancel_tx);
     }
-    let start = std::time::Instant::now();
     let mut chunk_count: u32 = 0;
     let mut first_token = true;
     let mut ttft_ms: u64 = 0;
     let mut canceled = false;
+    // Only meaningful when canceled; a dropped sender (stream replaced) keeps the partial reply.
+    let mut keep_partial = true;
+    let mut done_reason: Option<String> = None;
+    // Ollama's last chunk has done: true; a stream that ends without it was cut off.
+    let mut saw_done = false;
+    let mut stream_error: Option<String> = None;
+    // Full reply text, for the response cache and callers that save the reply themselves.
+    let mut reply = String::new();
+    // keep-alive expiry read while the model is still generating, the baseline a cancel is
+    // confirmed against. Probed in the background so neither streaming nor a cancel waits on it.
+    let mut expires_probe = None;
     loop {
         tokio::select! {
-            _ = &mut cancel_rx => {
+            keep = &mut cancel_rx => {
                 canceled = true;
-                diagnostics::log(Some(&window), "INFO", "chat stream canceled", None);
+                keep_partial = keep.unwrap_or(true);
+                diagnostics::log(
+                    Some(&window),
+                    "INFO",
+                    "chat stream canceled",
+                    Some(serde_json::json!({ "keep_partial": keep_partial })),
+                );
                 break;
             }
+            _ = &mut loading_deadline, if first_token && !loading_shown => {
+                loading_shown = true;
+                emit_model_loading(true);
+            }
             chunk = stream.next() => {
                 match chunk {
-                    Some(Ok(text)) => {
+                    Some(Ok(ollama::ChatStreamEvent::Done { done_reason: reason, raw })) => {
+                        saw_done = true;
+                        done_reason = reason;
+                        if flags.emit_final_chunk {
+                            let _ = window.emit("ollama-chat-final", raw);
+                        }
+                    }
+                    Some(Ok(ollama::ChatStreamEvent::Delta(text))) => {
                         if first_token {
                             first_token = false;
                             ttft_ms = start.elapsed().as_millis() as u64;
+                            if loading_shown {
+                                emit_model_loading(false);
+                            }
+                            let ollama = state.ollama.clone();
+                            let probe_model = model.clone();
+                            expires_probe = Some(tauri::async_runtime::spawn(async move {
+                                ollama.model_expires_at(&probe_model).await.ok().flatten()
+                            }));
                             diagnostics::log(
                                 Some(&window),
                                 "INFO",
@@ -473,6 +2944,7 @@ async fn ollama_chat_stream(
                             );
                         }
                         chunk_count += 1;
+                        reply.push_str(&text);
                         let _ = window.emit("ollama-chat-delta", text);
                     }
                     Some(Err(e)) => {
@@ -482,6 +2954,7 @@ async fn ollama_chat_stream(
                             "stream chunk error",
                             Some(serde_json::json!({ "error": e })),
                         );
+                        stream_error = Some(e);
                         break;
                     }
                     None => break,
@@ -489,9 +2962,50 @@ async fn ollama_chat_stream(
             }
         }
     }
+    if first_token && loading_shown {
+        emit_model_loading(false);
+    }
+    drop(stream);
+    if canceled {
+        // Confirm in the background so the UI is not kept waiting on /api/ps polling.
+        let ollama = state.ollama.clone();
+        let model = model.clone();
+        let window = window.clone();
+        tauri::async_runtime::spawn(async move {
+            // Canceled before the first token: there is no mid-generation baseline to compare with.
+            let expires_before = match expires_probe {
+                Some(probe) => probe.await.ok().flatten(),
+                None => None,
+            };
+            match ollama.confirm_generation_stopped(&model, expires_before).await {
+                Ok(true) => diagnostics::log(
+                    Some(&window),
+                    "INFO",
+                    "generation stopped after cancel",
+                    Some(serde_json::json!({ "model": model, "confirmed_via": "/api/ps" })),
+                ),
+                Ok(false) => diagnostics::log(
+                    Some(&window),
+                    "WARN",
+                    "could not confirm generation stopped after cancel",
+                    Some(serde_json::json!({ "model": model })),
+                ),
+                Err(e) => diagnostics::log(
+                    Some(&window),
+                    "WARN",
+                    "generation stop check failed",
+                    Some(serde_json::json!({ "model": model, "error": e })),
+                ),
+            }
+        });
+    }
+    drop(cancel_rx);
     {
-        let mut tx = state.chat_cancel_tx.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-        *tx = None;
+        let mut txs = state.chat_cancel_txs.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        // A newer stream for the same conversation may have replaced our sender; leave it alone.
+        if txs.get(&cancel_key).is_some_and(|tx| tx.is_closed()) {
+            txs.remove(&cancel_key);
+        }
     }
     let duration_ms = start.elapsed().as_millis() as f64;
     let tokens_per_sec = if duration_ms > 0.0 && chunk_count > 0 {
@@ -505,6 +3019,7 @@ async fn ollama_chat_stream(
         "chat stream done",
         Some(serde_json::json!({
             "canceled": canceled,
+            "done_reason": done_reason,
             "chunk_count": chunk_count,
             "duration_ms": duration_ms,
             "time_to_first_token_ms": ttft_ms,
@@ -512,23 +3027,108 @@ async fn ollama_chat_stream(
             "inference_device_preference": inference_preference,
             "gpu_detected": gpu_info.detected,
             "gpu_name": gpu_info.name,
-            "active_device": "unknown",
+            "active_device": gpu::get_ollama_device_info(
+                gpu_info.detected,
+                state.ollama.loaded_model_vram(Some(&model)).await.unwrap_or(None),
+            )
+            .active_device,
             "model": model
         })),
     );
-    let _ = window.emit("ollama-chat-done", ChatDonePayload { canceled });
-    Ok(())
+    let truncated = !canceled && done_reason.as_deref() == Some("length");
+    let interrupted = (!canceled && !saw_done).then(|| match &stream_error {
+        Some(e) => format!("The connection to Ollama was lost mid-reply: {}", e),
+        None => "Ollama stopped sending before the reply was finished.".to_string(),
+    });
+    if let Some(error) = &interrupted {
+        diagnostics::log(
+            Some(&window),
+            "ERROR",
+            "chat stream ended without done",
+            Some(serde_json::json!({ "error": error, "chunk_count": chunk_count, "model": model })),
+        );
+    }
+    if let Some((key, request)) = &cache_key {
+        // Only complete replies: not canceled, cut off, or ended by a stream error.
+        if !canceled && done_reason.as_deref() == Some("stop") && !reply.is_empty() {
+            if let Ok(mut storage) = state.storage.lock() {
+                if let Err(e) = storage.put_cached_response(key, request, &model, &reply, RESPONSE_CACHE_TTL_SECS) {
+                    diagnostics::log(
+                        Some(&window),
+                        "WARN",
+                        "response cache write failed",
+                        Some(serde_json::json!({ "error": e.to_string() })),
+                    );
+                }
+            }
+        }
+    }
+    let _ = window.emit(
+        "inference-finished",
+        InferenceFinishedPayload {
+            model,
+            conversation_id,
+            stats: Some(InferenceStats {
+                canceled,
+                done_reason: done_reason.clone(),
+                truncated,
+                chunk_count,
+                duration_ms: duration_ms as u64,
+                time_to_first_token_ms: ttft_ms,
+                tokens_per_sec,
+            }),
+            error: interrupted.clone(),
+        },
+    );
+    let _ = window.emit(
+        "ollama-chat-done",
+        ChatDonePayload {
+            canceled,
+            done_reason,
+            truncated,
+            cached: false,
+            hint: interrupted.as_ref().map(|_| STREAM_INTERRUPTED_HINT.to_string()),
+            error: interrupted,
+            keep_partial: canceled.then_some(keep_partial),
+        },
+    );
+    let discarded = canceled && !keep_partial;
+    Ok(StreamOutcome {
+        reply,
+        discarded,
+        interrupted: !saw_done && !discarded,
+    })
 }
 
+/// Cancels the stream for `conversation_id`, or every running stream when it is omitted.
+/// `keep_partial` (default true) is passed through to ollama-chat-done so the UI knows whether to
+/// discard the partial reply or save it marked as interrupted.
 #[tauri::command]
-fn cancel_chat_generation(state: State<'_, AppState>) -> Result<(), AppError> {
-    let mut tx = state.chat_cancel_tx.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    if let Some(send) = tx.take() {
-        let _ = send.send(());
+fn cancel_chat_generation(
+    state: State<'_, AppState>,
+    conversation_id: Option<String>,
+    keep_partial: Option<bool>,
+) -> Result<(), AppError> {
+    let keep_partial = keep_partial.unwrap_or(true);
+    let mut txs = state.chat_cancel_txs.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let senders: Vec<_> = match conversation_id {
+        Some(cid) => txs.remove(&cid).into_iter().collect(),
+        None => txs.drain().map(|(_, tx)| tx).collect(),
+    };
+    for send in senders {
+        let _ = send.send(keep_partial);
     }
     Ok(())
 }
 
+/// Whether a chat stream is running for the conversation, so the UI can restore its stop button
+/// after a reload.
+#[tauri::command]
+fn is_generating(state: State<'_, AppState>, conversation_id: String) -> Result<bool, AppError> {
+    let txs = state.chat_cancel_txs.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    Ok(txs.get(&conversation_id).is_some_and(|tx| !tx.is_closed()))
+}
+
 #[tauri::command]
 fn emit_diagnostic_log(
     window: tauri::Window,
@@ -561,15 +3161,48 @@ fn get_gpu_info() -> GpuInfoDto {
     }
 }
 
+/// Probe outbound connectivity used by the web tools (DuckDuckGo, Wikipedia) and the Ollama server.
+/// Probes run concurrently with a short timeout; results are logged and returned per target.
+/// In offline mode only Ollama is probed.
+#[tauri::command]
+async fn check_network(state: State<'_, AppState>, window: tauri::Window) -> Result<Vec<diagnostics::NetworkProbe>, AppError> {
+    let offline_mode = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_mcp_settings()?.offline_mode
+    };
+    let client = reqwest::Client::builder()
+        .timeout(diagnostics::NETWORK_PROBE_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::Ollama(e.to_string()))?;
+    let ollama_url = format!("{}/", state.ollama.base_url().trim_end_matches('/'));
+    let mut targets = vec![("ollama", ollama_url.as_str())];
+    if !offline_mode {
+        targets.insert(0, ("wikipedia", "https://en.wikipedia.org/"));
+        targets.insert(0, ("duckduckgo", "https://api.duckduckgo.com/"));
+    }
+    let probes =
+        futures_util::future::join_all(targets.into_iter().map(|(name, url)| diagnostics::probe_url(&client, name, url)))
+            .await;
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "network check",
+        Some(serde_json::to_value(&probes).unwrap_or_default()),
+    );
+    Ok(probes)
+}
+
+/// Device of the loaded model, inferred from /api/ps (see gpu::get_ollama_device_info).
 #[tauri::command]
-fn get_performance_status() -> PerformanceStatusDto {
+async fn get_performance_status(state: State<'_, AppState>) -> Result<PerformanceStatusDto, AppError> {
     let gpu_info = gpu::detect_gpu();
-    let device_info = gpu::get_ollama_device_info(gpu_info.detected);
-    PerformanceStatusDto {
+    let size_vram = state.ollama.loaded_model_vram(None).await.unwrap_or(None);
+    let device_info = gpu::get_ollama_device_info(gpu_info.detected, size_vram);
+    Ok(PerformanceStatusDto {
         gpu_detected: gpu_info.detected,
         gpu_name: gpu_info.name,
         active_device: device_info.active_device,
-    }
+    })
 }
 
 /// Open a URL in the system default browser. Used for "Install Ollama", model library, etc.
@@ -579,44 +3212,109 @@ fn open_url(url: String) -> Result<String, AppError> {
     if url.is_empty() {
         return Err(AppError::Ollama("url cannot be empty".into()));
     }
-    #[cfg(windows)]
-    {
-        std::process::Command::new("cmd")
-            .args(["/C", "start", "", url])
-            .spawn()
-            .map_err(|e| AppError::Io(e))?;
+    open::that_detached(url).map_err(AppError::Io)?;
+    Ok(url.to_string())
+}
+
+const DATA_DIR_NAME: &str = "Local Private LLM";
+
+/// Default app data directory: `<local data dir>/Local Private LLM`, falling back to the home dir.
+pub fn default_data_dir() -> Option<PathBuf> {
+    data_dir_candidates().into_iter().next()
+}
+
+/// Data directories to try in order when none is configured: the local and roaming data dirs,
+/// the home dir, a `data` folder next to the executable (portable installs) and finally the temp
+/// dir. Startup uses the first one that is writable.
+pub fn data_dir_candidates() -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = [dirs::data_local_dir(), dirs::data_dir(), dirs::home_dir()]
+        .into_iter()
+        .flatten()
+        .map(|p| p.join(DATA_DIR_NAME))
+        .collect();
+    if let Some(exe_dir) = std::env::current_exe().ok().and_then(|p| p.parent().map(Path::to_path_buf)) {
+        out.push(exe_dir.join("data"));
+    }
+    out.push(std::env::temp_dir().join(DATA_DIR_NAME));
+    let mut seen = HashSet::new();
+    out.retain(|p| seen.insert(p.clone()));
+    out
+}
+
+/// No data or home directory to put the log directory in.
+fn log_dir_unavailable() -> AppError {
+    AppError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "Could not determine the log directory"))
+}
+
+#[tauri::command]
+fn get_diagnostics_file_path() -> Result<diagnostics::LogFileInfo, AppError> {
+    diagnostics::log_file_info().ok_or_else(log_dir_unavailable)
+}
+
+/// Empties the diagnostics log and deletes its rotated copies; returns the bytes freed.
+#[tauri::command]
+fn clear_diagnostics(window: tauri::Window) -> u64 {
+    diagnostics::clear_log(Some(&window))
+}
+
+/// Total size of the diagnostics log and its rotated copies.
+#[tauri::command]
+fn get_log_disk_usage() -> Result<diagnostics::LogDiskUsage, AppError> {
+    diagnostics::log_disk_usage().ok_or_else(log_dir_unavailable)
+}
+
+/// Whether the app must show its unlock screen before using the database.
+#[tauri::command]
+fn database_locked(state: State<AppState>) -> bool {
+    state.database_locked.load(Ordering::SeqCst)
+}
+
+/// Open the encrypted database with `passphrase` and use it from now on in place of the
+/// placeholder the app started with. A wrong passphrase leaves the app locked.
+#[tauri::command]
+fn unlock_database(state: State<AppState>, passphrase: String, window: tauri::Window) -> Result<(), AppError> {
+    if !state.database_locked.load(Ordering::SeqCst) {
+        return Ok(());
     }
-    #[cfg(target_os = "macos")]
+    #[cfg(feature = "encryption")]
     {
-        std::process::Command::new("open")
-            .arg(url)
-            .spawn()
-            .map_err(|e| AppError::Io(e))?;
+        let unlocked = Storage::new_encrypted(&state.data_dir.to_string_lossy(), &passphrase)?;
+        *state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))? = unlocked;
+        state.database_locked.store(false, Ordering::SeqCst);
+        diagnostics::log(Some(&window), "INFO", "database unlocked", None);
+        state.ollama.set_offline_mode(offline_mode_enabled(&state));
+        if let Err(e) = migrate_tool_roots(&state) {
+            diagnostics::log(Some(&window), "WARN", "tool root migration failed", Some(serde_json::json!({ "error": e.to_string() })));
+        }
+        Ok(())
     }
-    #[cfg(not(any(windows, target_os = "macos")))]
+    #[cfg(not(feature = "encryption"))]
     {
-        std::process::Command::new("xdg-open")
-            .arg(url)
-            .spawn()
-            .map_err(|e| AppError::Io(e))?;
+        let _ = (passphrase, window);
+        Err(AppError::InvalidSettings("this build does not include database encryption".into()))
     }
-    Ok(url.to_string())
 }
 
+/// See AppState::startup_notices.
+#[tauri::command]
+fn get_startup_notices(state: State<AppState>) -> Vec<String> {
+    state.startup_notices.clone()
+}
+
+/// The data directory, created if needed. In ephemeral mode nothing is written to disk, so the
+/// path is returned as is.
 #[tauri::command]
-fn get_app_data_dir() -> Result<String, AppError> {
-    let dir = dirs::data_local_dir()
-        .or_else(dirs::home_dir)
-        .ok_or_else(|| AppError::Ollama("Could not determine app data dir".into()))?;
-    let app_dir = dir.join("Local Private LLM");
-    std::fs::create_dir_all(&app_dir).map_err(AppError::Io)?;
-    Ok(app_dir.to_string_lossy().to_string())
+fn get_app_data_dir(state: State<AppState>) -> Result<String, AppError> {
+    if !state.ephemeral {
+        std::fs::create_dir_all(&state.data_dir).map_err(AppError::Io)?;
+    }
+    Ok(state.data_dir.to_string_lossy().to_string())
 }
 
 fn default_filesystem_root() -> String {
     dirs::home_dir()
         .map(|p| p.to_string_lossy().to_string())
-        .unwrap_or_else(|| String::new())
+        .unwrap_or_default()
 }
 
 #[tauri::command]
@@ -635,20 +3333,182 @@ fn get_mcp_settings(state: State<AppState>) -> Result<McpSettingsDto, AppError>
         obsidian_vault_path: s.obsidian_vault_path,
         web_search_enabled: s.web_search_enabled,
         terminal_enabled: s.terminal_enabled,
+        web_search_enable_fallbacks: s.web_search_enable_fallbacks,
+        terminal_extra_blocked_patterns: s.terminal_extra_blocked_patterns,
+        terminal_block_word_boundaries: s.terminal_block_word_boundaries,
+        web_search_html_fallback: s.web_search_html_fallback,
+        tool_result_max_chars: s.tool_result_max_chars,
+        terminal_shell: s.terminal_shell,
+        fetch_timeout_secs: s.fetch_timeout_secs,
+        web_search_timeout_secs: s.web_search_timeout_secs,
+        safe_mode: s.safe_mode,
+        safe_mode_block_writes: s.safe_mode_block_writes,
+        max_concurrent_tools: s.max_concurrent_tools,
+        offline_mode: s.offline_mode,
+        open_path_allow_any_file: s.open_path_allow_any_file,
+        fetch_allow_insecure_redirect: s.fetch_allow_insecure_redirect,
     })
 }
 
+#[derive(Clone, Serialize)]
+struct McpSettingsChangedPayload {
+    /// McpSettingsDto field names whose value changed.
+    changed: Vec<&'static str>,
+    /// Names of the tools enabled under the new settings (what get_mcp_tool_definitions(true) returns).
+    enabled_tools: Vec<String>,
+}
+
+/// Field names that differ between two MCP settings snapshots.
+fn changed_mcp_settings(old: &storage::McpSettings, new: &storage::McpSettings) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    let mut check = |name: &'static str, differs: bool| {
+        if differs {
+            changed.push(name);
+        }
+    };
+    check("filesystem_enabled", old.filesystem_enabled != new.filesystem_enabled);
+    check("filesystem_root", old.filesystem_root != new.filesystem_root);
+    check("obsidian_enabled", old.obsidian_enabled != new.obsidian_enabled);
+    check("obsidian_vault_path", old.obsidian_vault_path != new.obsidian_vault_path);
+    check("web_search_enabled", old.web_search_enabled != new.web_search_enabled);
+    check("terminal_enabled", old.terminal_enabled != new.terminal_enabled);
+    check("web_search_enable_fallbacks", old.web_search_enable_fallbacks != new.web_search_enable_fallbacks);
+    check(
+        "terminal_extra_blocked_patterns",
+        old.terminal_extra_blocked_patterns != new.terminal_extra_blocked_patterns,
+    );
+    check(
+        "terminal_block_word_boundaries",
+        old.terminal_block_word_boundaries != new.terminal_block_word_boundaries,
+    );
+    check("web_search_html_fallback", old.web_search_html_fallback != new.web_search_html_fallback);
+    check("tool_result_max_chars", old.tool_result_max_chars != new.tool_result_max_chars);
+    check("terminal_shell", old.terminal_shell != new.terminal_shell);
+    check("fetch_timeout_secs", old.fetch_timeout_secs != new.fetch_timeout_secs);
+    check("web_search_timeout_secs", old.web_search_timeout_secs != new.web_search_timeout_secs);
+    check("safe_mode", old.safe_mode != new.safe_mode);
+    check("safe_mode_block_writes", old.safe_mode_block_writes != new.safe_mode_block_writes);
+    check("max_concurrent_tools", old.max_concurrent_tools != new.max_concurrent_tools);
+    check("offline_mode", old.offline_mode != new.offline_mode);
+    check("open_path_allow_any_file", old.open_path_allow_any_file != new.open_path_allow_any_file);
+    check("fetch_allow_insecure_redirect", old.fetch_allow_insecure_redirect != new.fetch_allow_insecure_redirect);
+    changed
+}
+
+/// Tools available under `s` (an empty filesystem root means the default root). Safe mode removes
+/// high-risk tools, and write tools too when it blocks writes; offline mode removes network tools.
+fn enabled_tool_definitions(s: &storage::McpSettings) -> Vec<mcp::McpToolDef> {
+    let fs_root = if s.filesystem_root.trim().is_empty() {
+        default_filesystem_root()
+    } else {
+        s.filesystem_root.clone()
+    };
+    let mut defs = mcp::enabled_tool_definitions(
+        s.filesystem_enabled,
+        &fs_root,
+        s.obsidian_enabled,
+        &s.obsidian_vault_path,
+        s.web_search_enabled,
+        s.terminal_enabled,
+    );
+    if s.safe_mode {
+        defs.retain(|d| !mcp::blocked_by_safe_mode(&d.risk, s.safe_mode_block_writes));
+    }
+    if s.offline_mode {
+        defs.retain(|d| !mcp::needs_network(&d.id));
+    }
+    defs
+}
+
+/// A tool root as it should be stored: its normalized form (see mcp::normalize_tool_root). A root
+/// that does not resolve is an error while its tools are enabled; otherwise it is kept as typed
+/// (trimmed) so turning the tools off doesn't require fixing the path first.
+fn normalized_tool_root(root: &str, enabled: bool) -> Result<String, String> {
+    match mcp::normalize_tool_root(root) {
+        Ok(clean) => Ok(clean),
+        Err(e) if enabled => Err(e),
+        Err(_) => Ok(root.trim().to_string()),
+    }
+}
+
+/// Rewrite tool roots saved before they were normalized on save (quotes, `~`, trailing slashes).
+/// Roots that don't resolve are left alone; the tools report them when used.
+fn migrate_tool_roots(state: &AppState) -> Result<(), AppError> {
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let mut s = storage.get_mcp_settings()?;
+    let mut changed = Vec::new();
+    for (field, root) in [
+        ("filesystem_root", &mut s.filesystem_root),
+        ("obsidian_vault_path", &mut s.obsidian_vault_path),
+    ] {
+        if let Ok(clean) = mcp::normalize_tool_root(root) {
+            if clean != *root {
+                *root = clean;
+                changed.push(field);
+            }
+        }
+    }
+    if !changed.is_empty() {
+        storage.save_mcp_settings(&s)?;
+        diagnostics::log(None, "INFO", "normalized stored tool roots", Some(serde_json::json!({ "fields": changed })));
+    }
+    Ok(())
+}
+
+/// Saves MCP settings and, if anything changed, emits "mcp-settings-changed" so the UI can
+/// refresh its tool list without polling.
 #[tauri::command]
-fn save_mcp_settings(state: State<AppState>, settings: McpSettingsDto) -> Result<(), AppError> {
+fn save_mcp_settings(state: State<AppState>, settings: McpSettingsDto, window: tauri::Window) -> Result<(), AppError> {
+    mcp::validate_terminal_shell(&settings.terminal_shell)
+        .map_err(|e| AppError::InvalidSettings(format!("terminal_shell: {}", e)))?;
+    let mut settings = settings;
+    if settings.filesystem_enabled && settings.filesystem_root.trim().is_empty() {
+        mcp::validate_tool_root(&default_filesystem_root())
+            .map_err(|e| AppError::InvalidSettings(format!("filesystem_root: {}", e)))?;
+    } else {
+        settings.filesystem_root = normalized_tool_root(&settings.filesystem_root, settings.filesystem_enabled)
+            .map_err(|e| AppError::InvalidSettings(format!("filesystem_root: {}", e)))?;
+    }
+    settings.obsidian_vault_path = normalized_tool_root(&settings.obsidian_vault_path, settings.obsidian_enabled)
+        .map_err(|e| AppError::InvalidSettings(format!("obsidian_vault_path: {}", e)))?;
     let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    storage.save_mcp_settings(&storage::McpSettings {
+    let previous = storage.get_mcp_settings()?;
+    let next = storage::McpSettings {
         filesystem_enabled: settings.filesystem_enabled,
         filesystem_root: settings.filesystem_root,
         obsidian_enabled: settings.obsidian_enabled,
         obsidian_vault_path: settings.obsidian_vault_path,
         web_search_enabled: settings.web_search_enabled,
         terminal_enabled: settings.terminal_enabled,
-    })?;
+        web_search_enable_fallbacks: settings.web_search_enable_fallbacks,
+        terminal_extra_blocked_patterns: settings.terminal_extra_blocked_patterns,
+        terminal_block_word_boundaries: settings.terminal_block_word_boundaries,
+        web_search_html_fallback: settings.web_search_html_fallback,
+        tool_result_max_chars: settings.tool_result_max_chars,
+        terminal_shell: settings.terminal_shell.trim().to_string(),
+        fetch_timeout_secs: mcp::clamp_timeout_secs(settings.fetch_timeout_secs),
+        web_search_timeout_secs: mcp::clamp_timeout_secs(settings.web_search_timeout_secs),
+        safe_mode: settings.safe_mode,
+        safe_mode_block_writes: settings.safe_mode_block_writes,
+        max_concurrent_tools: mcp::clamp_max_concurrent_tools(settings.max_concurrent_tools),
+        offline_mode: settings.offline_mode,
+        open_path_allow_any_file: settings.open_path_allow_any_file,
+        fetch_allow_insecure_redirect: settings.fetch_allow_insecure_redirect,
+    };
+    storage.save_mcp_settings(&next)?;
+    state.ollama.set_offline_mode(next.offline_mode);
+    drop(storage);
+    let changed = changed_mcp_settings(&previous, &next);
+    if !changed.is_empty() {
+        let enabled_tools: Vec<String> = enabled_tool_definitions(&next).into_iter().map(|d| d.name).collect();
+        diagnostics::log(
+            Some(&window),
+            "INFO",
+            "MCP settings changed",
+            Some(serde_json::json!({ "changed": changed, "enabled_tools": enabled_tools })),
+        );
+        let _ = window.emit("mcp-settings-changed", McpSettingsChangedPayload { changed, enabled_tools });
+    }
     Ok(())
 }
 
@@ -659,20 +3519,7 @@ fn get_mcp_tool_definitions(
 ) -> Result<Vec<McpToolDefDto>, AppError> {
     let defs = if enabled_only {
         let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-        let s = storage.get_mcp_settings()?;
-        let fs_root = if s.filesystem_root.trim().is_empty() {
-            default_filesystem_root()
-        } else {
-            s.filesystem_root.clone()
-        };
-        mcp::enabled_tool_definitions(
-            s.filesystem_enabled,
-            &fs_root,
-            s.obsidian_enabled,
-            &s.obsidian_vault_path,
-            s.web_search_enabled,
-            s.terminal_enabled,
-        )
+        enabled_tool_definitions(&storage.get_mcp_settings()?)
     } else {
         mcp::all_tool_definitions()
     };
@@ -689,35 +3536,527 @@ fn get_mcp_tool_definitions(
         .collect())
 }
 
+/// Every tool with its enabled flag and whether it can run right now (root/vault exist, network
+/// reachable). The network is only probed when web tools are enabled.
 #[tauri::command]
-fn execute_mcp_tool(
-    state: State<AppState>,
+async fn get_mcp_tool_availability(state: State<'_, AppState>) -> Result<Vec<mcp::ToolAvailability>, AppError> {
+    let s = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_mcp_settings()?
+    };
+    let network_reachable = if s.web_search_enabled && !s.offline_mode {
+        tauri::async_runtime::spawn_blocking(|| mcp::network_reachable(std::time::Duration::from_secs(2)))
+            .await
+            .unwrap_or(false)
+    } else {
+        false
+    };
+    let fs_root = if s.filesystem_root.trim().is_empty() {
+        default_filesystem_root()
+    } else {
+        s.filesystem_root.clone()
+    };
+    Ok(mcp::tool_availability(&mcp::ToolPreconditions {
+        filesystem_enabled: s.filesystem_enabled,
+        filesystem_root: &fs_root,
+        obsidian_enabled: s.obsidian_enabled,
+        obsidian_vault: &s.obsidian_vault_path,
+        web_search_enabled: s.web_search_enabled,
+        terminal_enabled: s.terminal_enabled,
+        network_reachable,
+        safe_mode: s.safe_mode,
+        safe_mode_block_writes: s.safe_mode_block_writes,
+        offline_mode: s.offline_mode,
+    }))
+}
+
+#[derive(Clone, Serialize)]
+struct ToolRunningPayload {
+    name: String,
+}
+
+#[derive(Clone, Serialize)]
+struct ToolFinishedPayload {
+    name: String,
+    ok: bool,
+    elapsed_ms: u64,
+}
+
+/// Tools block (network, processes), so they run on the blocking pool; this keeps the main thread
+/// free for cancel_tool_execution. Calls beyond max_concurrent_tools wait for a free slot.
+/// High-risk and write tools the user has not acknowledged are refused unless `confirmed` says
+/// the user approved this call (see tool_needs_confirmation).
+#[tauri::command]
+async fn execute_mcp_tool(
+    state: State<'_, AppState>,
     name: String,
     arguments: serde_json::Value,
+    confirmed: Option<bool>,
+    window: tauri::Window,
 ) -> Result<McpToolResultDto, AppError> {
-    let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
-    let s = storage.get_mcp_settings()?;
-    let root = if s.filesystem_enabled {
-        let r = if s.filesystem_root.trim().is_empty() {
-            default_filesystem_root()
-        } else {
-            s.filesystem_root.clone()
-        };
-        if r.is_empty() { None } else { Some(r) }
+    run_tool(&state, name, arguments, confirmed.unwrap_or(false), Some(window)).await
+}
+
+/// execute_mcp_tool without the IPC wrapper; `window` (None when headless) receives the events.
+/// Unless `confirmed`, calls that need confirmation are refused here, so every caller is gated.
+async fn run_tool(
+    state: &AppState,
+    name: String,
+    arguments: serde_json::Value,
+    confirmed: bool,
+    window: Option<tauri::Window>,
+) -> Result<McpToolResultDto, AppError> {
+    if !confirmed && call_needs_confirmation(state, &name)? {
+        diagnostics::log(
+            window.as_ref(),
+            "WARN",
+            "tool call refused: not confirmed",
+            Some(serde_json::json!({ "tool": name })),
+        );
+        return Ok(unconfirmed_tool_result(&name));
+    }
+    let s = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_mcp_settings()?
+    };
+    let summarizer = Some(file_summarizer(state)?);
+    let cancel_guard = state.tool_cancels.register()?;
+    let cancel = cancel_guard.token.clone();
+    let permit = state.tool_limiter.acquire(s.max_concurrent_tools, window.as_ref()).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = run_mcp_tool_call(&s, name, &arguments, window.as_ref(), &cancel, summarizer);
+        drop(permit);
+        result
+    })
+    .await
+    .map_err(|e| AppError::Ollama(e.to_string()))
+}
+
+/// Run one tool exactly as execute_mcp_tool would, for a settings-page "Test" button. The result
+/// is tagged as a test and always carries diagnostic steps: the settings it ran under, the tool's
+/// own steps (if any), and the outcome.
+#[tauri::command]
+async fn test_mcp_tool(
+    state: State<'_, AppState>,
+    name: String,
+    arguments: serde_json::Value,
+    window: tauri::Window,
+) -> Result<McpToolResultDto, AppError> {
+    let s = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_mcp_settings()?
+    };
+    let summarizer = Some(file_summarizer(&state)?);
+    let cancel_guard = state.tool_cancels.register()?;
+    let cancel = cancel_guard.token.clone();
+    let permit = state.tool_limiter.acquire(s.max_concurrent_tools, Some(&window)).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let start = std::time::Instant::now();
+        let result = run_mcp_tool_call(&s, name.clone(), &arguments, Some(&window), &cancel, summarizer);
+        drop(permit);
+        let result = with_test_diagnostics(result, &name, &arguments, &s, start.elapsed().as_millis() as u64);
+        diagnostics::log(
+            Some(&window),
+            if result.ok { "INFO" } else { "WARN" },
+            "tool test run",
+            Some(serde_json::json!({ "tool": name, "ok": result.ok, "error": result.error })),
+        );
+        result
+    })
+    .await
+    .map_err(|e| AppError::Ollama(e.to_string()))
+}
+
+/// Mark `result` as a test run and wrap its diagnostic steps with the settings the tool saw and
+/// the outcome, so tools that log nothing still explain themselves.
+fn with_test_diagnostics(
+    mut result: McpToolResultDto,
+    name: &str,
+    arguments: &serde_json::Value,
+    s: &storage::McpSettings,
+    elapsed_ms: u64,
+) -> McpToolResultDto {
+    let enabled = enabled_tool_definitions(s).iter().any(|d| d.name == name);
+    let filesystem_root = if s.filesystem_root.trim().is_empty() {
+        default_filesystem_root()
+    } else {
+        s.filesystem_root.clone()
+    };
+    let mut steps = vec![DiagnosticStepDto {
+        level: "INFO".to_string(),
+        message: format!("Testing {}", name),
+        meta: Some(serde_json::json!({
+            "arguments": arguments,
+            "enabled": enabled,
+            "filesystem_root": if s.filesystem_enabled { Some(filesystem_root) } else { None },
+            "obsidian_vault": if s.obsidian_enabled { Some(s.obsidian_vault_path.clone()) } else { None },
+            "safe_mode": s.safe_mode,
+            "offline_mode": s.offline_mode,
+        })),
+    }];
+    match result.diagnostic_steps.take() {
+        Some(tool_steps) if !tool_steps.is_empty() => steps.extend(tool_steps),
+        _ => steps.push(DiagnosticStepDto {
+            level: "INFO".to_string(),
+            message: "Tool recorded no steps of its own".to_string(),
+            meta: None,
+        }),
+    }
+    steps.push(DiagnosticStepDto {
+        level: if result.ok { "INFO" } else { "ERROR" }.to_string(),
+        message: if result.ok { "Test passed" } else { "Test failed" }.to_string(),
+        meta: Some(serde_json::json!({
+            "elapsed_ms": elapsed_ms,
+            "content_chars": result.content.chars().count(),
+            "error": result.error,
+        })),
+    });
+    result.diagnostic_steps = Some(steps);
+    result.test = true;
+    result
+}
+
+/// Ask the running and queued tool calls to stop early. Only web_search (between page excerpt
+/// fetches), summarize_file and batches (between calls) check it; calls started afterwards are
+/// not affected.
+#[tauri::command]
+fn cancel_tool_execution(state: State<'_, AppState>) {
+    state.tool_cancels.cancel_all();
+}
+
+/// One call in an execute_mcp_tools_batch request.
+#[derive(Debug, Deserialize)]
+pub struct McpToolCallDto {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+/// Run several tool calls in order with one settings read and one IPC round trip. Stops after the
+/// first failed call unless `continue_on_error` is true; results cover only the calls that ran.
+/// A cancel stops the batch after the current call.
+#[tauri::command]
+async fn execute_mcp_tools_batch(
+    state: State<'_, AppState>,
+    calls: Vec<McpToolCallDto>,
+    continue_on_error: Option<bool>,
+    window: tauri::Window,
+) -> Result<Vec<McpToolResultDto>, AppError> {
+    let s = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_mcp_settings()?
+    };
+    let continue_on_error = continue_on_error.unwrap_or(false);
+    // Batches are not confirmed call by call: unacknowledged high-risk and write tools fail.
+    let unconfirmed = calls
+        .iter()
+        .map(|c| call_needs_confirmation(&state, &c.name))
+        .collect::<Result<Vec<bool>, AppError>>()?;
+    // The batch runs its calls one after another, so it holds a single slot throughout.
+    let summarizer = Some(file_summarizer(&state)?);
+    let cancel_guard = state.tool_cancels.register()?;
+    let cancel = cancel_guard.token.clone();
+    let permit = state.tool_limiter.acquire(s.max_concurrent_tools, Some(&window)).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let _permit = permit;
+        let mut results = Vec::with_capacity(calls.len());
+        for (call, unconfirmed) in calls.into_iter().zip(unconfirmed) {
+            let result = if unconfirmed {
+                unconfirmed_tool_result(&call.name)
+            } else {
+                run_mcp_tool_call(&s, call.name, &call.arguments, Some(&window), &cancel, summarizer.clone())
+            };
+            let failed = !result.ok;
+            results.push(result);
+            if (failed && !continue_on_error) || cancel.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        results
+    })
+    .await
+    .map_err(|e| AppError::Ollama(e.to_string()))
+}
+
+/// The filesystem tools' root: the configured one, else the home directory. None when the
+/// filesystem tools are off.
+fn effective_filesystem_root(s: &storage::McpSettings) -> Option<String> {
+    if !s.filesystem_enabled {
+        return None;
+    }
+    let r = if s.filesystem_root.trim().is_empty() {
+        default_filesystem_root()
+    } else {
+        s.filesystem_root.clone()
+    };
+    if r.is_empty() { None } else { Some(r) }
+}
+
+/// The Obsidian vault tools may use: None when the vault is disabled or not set.
+fn effective_obsidian_root(s: &storage::McpSettings) -> Option<String> {
+    if s.obsidian_enabled && !s.obsidian_vault_path.trim().is_empty() {
+        Some(s.obsidian_vault_path.clone())
     } else {
         None
+    }
+}
+
+/// The root a tool acts on, which acknowledgments can be scoped to: the filesystem root or the
+/// Obsidian vault. None for tools without one (terminal, web) and while the root is disabled or
+/// not set; "" is what an any-root acknowledgment stores, so it is never returned.
+fn acknowledgment_root(tool_id: &str, s: &storage::McpSettings) -> Option<String> {
+    match tool_id {
+        "filesystem" => effective_filesystem_root(s),
+        "obsidian" => effective_obsidian_root(s),
+        _ => None,
+    }
+    .filter(|r| !r.trim().is_empty())
+}
+
+/// Risk levels whose calls the user confirms unless the tool was acknowledged.
+fn risk_needs_confirmation(risk: &str) -> bool {
+    risk == "high" || risk == "write"
+}
+
+/// Whether (tool, root) is covered by an acknowledgment: one for that root or for any root ("").
+fn tool_acknowledged<'a>(
+    acknowledgments: impl IntoIterator<Item = (&'a str, &'a str)>,
+    tool_name: &str,
+    root: &str,
+) -> bool {
+    acknowledgments
+        .into_iter()
+        .any(|(tool, ack_root)| tool == tool_name && (ack_root.is_empty() || ack_root == root))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolAcknowledgmentDto {
+    pub tool_name: String,
+    /// None when the acknowledgment covers every root.
+    pub root: Option<String>,
+    /// "session" (until the app quits) or "always".
+    pub scope: String,
+}
+
+/// Whether a call to `name` should be confirmed first: high-risk and write tools, unless the user
+/// acknowledged the tool for this session or permanently (for any root or the current one).
+#[tauri::command]
+fn tool_needs_confirmation(state: State<AppState>, name: String) -> Result<bool, AppError> {
+    if !mcp::all_tool_definitions().iter().any(|d| d.name == name) {
+        return Err(AppError::InvalidSettings(format!("Unknown tool: {}", name)));
+    }
+    call_needs_confirmation(&state, &name)
+}
+
+/// tool_needs_confirmation for the execute commands; false for unknown tools, which execute_tool
+/// reports itself.
+fn call_needs_confirmation(state: &AppState, name: &str) -> Result<bool, AppError> {
+    let Some(def) = mcp::all_tool_definitions().into_iter().find(|d| d.name == name) else {
+        return Ok(false);
+    };
+    if !risk_needs_confirmation(&def.risk) {
+        return Ok(false);
+    }
+    let (stored, root) = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        let s = storage.get_mcp_settings()?;
+        (storage.list_tool_acknowledgments()?, acknowledgment_root(&def.id, &s).unwrap_or_default())
+    };
+    let session = state
+        .session_tool_acknowledgments
+        .lock()
+        .map_err(|e| AppError::Ollama(e.to_string()))?;
+    let all = stored
+        .iter()
+        .chain(session.iter())
+        .map(|(tool, ack_root)| (tool.as_str(), ack_root.as_str()));
+    Ok(!tool_acknowledged(all, name, &root))
+}
+
+/// Result for a call refused because the user neither confirmed it nor acknowledged the tool.
+fn unconfirmed_tool_result(name: &str) -> McpToolResultDto {
+    let error = format!("{} needs the user's confirmation before it runs", name);
+    McpToolResultDto {
+        ok: false,
+        content: String::new(),
+        model_error: Some(mcp::compact_tool_error(
+            name,
+            &error,
+            "The user has not approved this tool; do not call it again unless they ask.",
+        )),
+        error: Some(error),
+        diagnostic_steps: None,
+        steps: None,
+        test: false,
+    }
+}
+
+/// Stop confirming `tool_name`: for this session, or permanently with `always`. With
+/// `current_root_only` the acknowledgment applies only while the tool's root is the current one.
+#[tauri::command]
+fn grant_tool_acknowledgment(
+    state: State<AppState>,
+    tool_name: String,
+    always: bool,
+    current_root_only: Option<bool>,
+    window: tauri::Window,
+) -> Result<ToolAcknowledgmentDto, AppError> {
+    let Some(def) = mcp::all_tool_definitions().into_iter().find(|d| d.name == tool_name) else {
+        return Err(AppError::InvalidSettings(format!("Unknown tool: {}", tool_name)));
+    };
+    let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+    let root = if current_root_only.unwrap_or(false) {
+        acknowledgment_root(&def.id, &storage.get_mcp_settings()?).ok_or_else(|| {
+            AppError::InvalidSettings(format!(
+                "{} has no folder configured, so it cannot be acknowledged for the current folder only",
+                tool_name
+            ))
+        })?
+    } else {
+        String::new()
+    };
+    if always {
+        storage.add_tool_acknowledgment(&tool_name, &root)?;
+    } else {
+        state
+            .session_tool_acknowledgments
+            .lock()
+            .map_err(|e| AppError::Ollama(e.to_string()))?
+            .insert((tool_name.clone(), root.clone()));
+    }
+    let scope = if always { "always" } else { "session" };
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "tool acknowledged",
+        Some(serde_json::json!({ "tool": tool_name, "risk": def.risk, "scope": scope, "root": root })),
+    );
+    Ok(ToolAcknowledgmentDto {
+        tool_name,
+        root: Some(root).filter(|r| !r.is_empty()),
+        scope: scope.to_string(),
+    })
+}
+
+/// Confirm `tool_name` again. `root` picks one root-scoped acknowledgment ("" for the any-root
+/// one); None revokes all of the tool's acknowledgments, session and permanent. Returns how many
+/// were removed.
+#[tauri::command]
+fn revoke_tool_acknowledgment(
+    state: State<AppState>,
+    tool_name: String,
+    root: Option<String>,
+    window: tauri::Window,
+) -> Result<usize, AppError> {
+    let removed_stored = {
+        let mut storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.remove_tool_acknowledgments(&tool_name, root.as_deref())?
+    };
+    let mut session = state
+        .session_tool_acknowledgments
+        .lock()
+        .map_err(|e| AppError::Ollama(e.to_string()))?;
+    let before = session.len();
+    session.retain(|(tool, ack_root)| !(*tool == tool_name && root.as_ref().map_or(true, |r| r == ack_root)));
+    let removed = removed_stored + before - session.len();
+    diagnostics::log(
+        Some(&window),
+        "INFO",
+        "tool acknowledgment revoked",
+        Some(serde_json::json!({ "tool": tool_name, "root": root, "removed": removed })),
+    );
+    Ok(removed)
+}
+
+#[tauri::command]
+fn list_tool_acknowledgments(state: State<AppState>) -> Result<Vec<ToolAcknowledgmentDto>, AppError> {
+    let stored = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.list_tool_acknowledgments()?
+    };
+    let session = state
+        .session_tool_acknowledgments
+        .lock()
+        .map_err(|e| AppError::Ollama(e.to_string()))?;
+    let mut session: Vec<_> = session.iter().cloned().collect();
+    session.sort();
+    let dto = |(tool_name, root): (String, String), scope: &str| ToolAcknowledgmentDto {
+        tool_name,
+        root: Some(root).filter(|r| !r.is_empty()),
+        scope: scope.to_string(),
+    };
+    Ok(stored
+        .into_iter()
+        .map(|a| dto(a, "always"))
+        .chain(session.into_iter().map(|a| dto(a, "session")))
+        .collect())
+}
+
+/// Model access for summarize_file: a non-streaming chat call with the selected model, made from
+/// the blocking tool thread.
+fn file_summarizer(state: &AppState) -> Result<mcp::Summarizer, AppError> {
+    let model = {
+        let storage = state.storage.lock().map_err(|e| AppError::Ollama(e.to_string()))?;
+        storage.get_settings()?.selected_model
     };
+    let client = state.ollama.clone();
+    Ok(mcp::Summarizer(Arc::new(move |prompt: &str, max_tokens: u32| {
+        let messages = vec![ollama::ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        let options = ollama::ChatOptions {
+            temperature: Some(0.2),
+            num_predict: Some(max_tokens),
+        };
+        tauri::async_runtime::block_on(client.chat(&model, messages, options))
+    })))
+}
+
+/// Execute one tool with the given MCP settings, emitting tool-running/tool-finished events to
+/// `window` when there is one.
+/// Tool errors are returned as a failed result rather than an AppError.
+fn run_mcp_tool_call(
+    s: &storage::McpSettings,
+    name: String,
+    arguments: &serde_json::Value,
+    window: Option<&tauri::Window>,
+    cancel: &Arc<AtomicBool>,
+    summarizer: Option<mcp::Summarizer>,
+) -> McpToolResultDto {
+    let root = effective_filesystem_root(s);
     let fs_root = root.as_deref();
-    let obs_root = if s.obsidian_enabled && !s.obsidian_vault_path.is_empty() {
-        Some(s.obsidian_vault_path.as_str())
-    } else {
-        None
+    let obs_root = effective_obsidian_root(s);
+    let obs_root = obs_root.as_deref();
+    let options = mcp::ToolOptions {
+        web_search_enable_fallbacks: s.web_search_enable_fallbacks,
+        terminal_extra_blocked_patterns: s.terminal_extra_blocked_patterns.clone(),
+        terminal_block_word_boundaries: s.terminal_block_word_boundaries,
+        web_search_html_fallback: s.web_search_html_fallback,
+        terminal_shell: s.terminal_shell.clone(),
+        cancel: cancel.clone(),
+        fetch_timeout_secs: s.fetch_timeout_secs,
+        web_search_timeout_secs: s.web_search_timeout_secs,
+        safe_mode: s.safe_mode,
+        safe_mode_block_writes: s.safe_mode_block_writes,
+        offline_mode: s.offline_mode,
+        open_path_allow_any_file: s.open_path_allow_any_file,
+        fetch_allow_insecure_redirect: s.fetch_allow_insecure_redirect,
+        summarizer,
     };
-    match mcp::execute_tool(&name, &arguments, fs_root, obs_root) {
-        Ok(r) => Ok(McpToolResultDto {
+    // Let the UI show a spinner while slow tools (web_search, run_command) are running.
+    if let Some(window) = window {
+        let _ = window.emit("tool-running", ToolRunningPayload { name: name.clone() });
+    }
+    let start = std::time::Instant::now();
+    let mut hint = mcp::DEFAULT_TOOL_ERROR_HINT;
+    let mut result = match mcp::execute_tool(&name, arguments, fs_root, obs_root, &options) {
+        Ok(r) => McpToolResultDto {
             ok: r.ok,
             content: r.content,
             error: r.error,
+            model_error: None,
+            test: false,
             diagnostic_steps: r.diagnostic_steps.map(|steps| {
                 steps
                     .into_iter()
@@ -728,13 +4067,217 @@ fn execute_mcp_tool(
                     })
                     .collect()
             }),
-        }),
-        Err(e) => Ok(McpToolResultDto {
-            ok: false,
-            content: String::new(),
-            error: Some(e.to_string()),
-            diagnostic_steps: None,
-        }),
+            steps: r.steps.map(|steps| {
+                steps
+                    .into_iter()
+                    .map(|s| ToolStepDto {
+                        name: s.name,
+                        ok: s.ok,
+                        detail: s.detail,
+                    })
+                    .collect()
+            }),
+        },
+        Err(e) => {
+            if matches!(e, mcp::McpToolError::SafeMode(_)) {
+                diagnostics::log(
+                    window,
+                    "WARN",
+                    "tool call blocked by safe mode",
+                    Some(serde_json::json!({ "tool": name, "error": e.to_string() })),
+                );
+            }
+            if matches!(e, mcp::McpToolError::OfflineMode(_)) {
+                diagnostics::log(
+                    window,
+                    "WARN",
+                    "tool call blocked by offline mode",
+                    Some(serde_json::json!({ "tool": name })),
+                );
+            }
+            hint = e.hint();
+            McpToolResultDto {
+                ok: false,
+                content: String::new(),
+                error: Some(e.to_string()),
+                model_error: None,
+                diagnostic_steps: None,
+                steps: None,
+                test: false,
+            }
+        }
+    };
+    if s.tool_result_max_chars > 0 {
+        let original_chars = result.content.chars().count();
+        if let Some(truncated) = mcp::truncate_tool_content(&result.content, s.tool_result_max_chars) {
+            result.content = truncated;
+            diagnostics::log(
+                window,
+                "WARN",
+                "tool result truncated",
+                Some(serde_json::json!({
+                    "tool": name,
+                    "original_chars": original_chars,
+                    "max_chars": s.tool_result_max_chars,
+                })),
+            );
+        }
+    }
+    if !result.ok {
+        // The model only gets the compact form; keep the whole error for diagnostics.
+        let error = result.error.clone().unwrap_or_else(|| "unknown error".to_string());
+        diagnostics::log(
+            window,
+            "ERROR",
+            "tool call failed",
+            Some(serde_json::json!({ "tool": name, "error": error, "error_chars": error.chars().count() })),
+        );
+        result.model_error = Some(mcp::compact_tool_error(&name, &error, hint));
+    }
+    if let Some(window) = window {
+        let _ = window.emit(
+            "tool-finished",
+            ToolFinishedPayload {
+                name,
+                ok: result.ok,
+                elapsed_ms: start.elapsed().as_millis() as u64,
+            },
+        );
+    }
+    result
+}
+
+/// "ollama-status-changed": emitted by the health watchdog when Ollama becomes reachable or
+/// unreachable (and once for the initial state).
+#[derive(Clone, Serialize)]
+struct OllamaStatusPayload {
+    reachable: bool,
+    /// Server version from /api/version; None while unreachable.
+    version: Option<String>,
+}
+
+/// Sleep between health checks; the setting is clamped in case the stored value predates validation.
+fn health_check_interval(secs: i64) -> std::time::Duration {
+    let secs = secs.clamp(*HEALTH_CHECK_INTERVAL_RANGE.start(), *HEALTH_CHECK_INTERVAL_RANGE.end());
+    std::time::Duration::from_secs(secs as u64)
+}
+
+/// Background task started in run: checks Ollama every health_check_interval_secs and emits
+/// ollama-status-changed only when reachability flips, so the UI does not need to poll.
+async fn health_watchdog(app: tauri::AppHandle, mut stop: oneshot::Receiver<()>) {
+    let mut last_reachable: Option<bool> = None;
+    loop {
+        let state = app.state::<AppState>();
+        let version = state.ollama.version().await.ok();
+        let reachable = version.is_some();
+        if last_reachable != Some(reachable) {
+            last_reachable = Some(reachable);
+            diagnostics::log(
+                None,
+                if reachable { "INFO" } else { "WARN" },
+                "ollama status changed",
+                Some(serde_json::json!({ "reachable": reachable, "version": version })),
+            );
+            let _ = app.emit("ollama-status-changed", OllamaStatusPayload { reachable, version });
+        }
+        let interval_secs = state
+            .storage
+            .lock()
+            .ok()
+            .and_then(|s| s.get_settings().ok())
+            .map(|s| s.health_check_interval_secs)
+            .unwrap_or_else(default_health_check_interval_secs);
+        tokio::select! {
+            _ = &mut stop => break,
+            _ = tokio::time::sleep(health_check_interval(interval_secs)) => {}
+        }
+    }
+}
+
+/// Startup steps shared by the app and headless mode: log startup notices, move tool roots to
+/// their current settings keys, and report offline mode.
+fn startup(state: &AppState) {
+    for notice in &state.startup_notices {
+        diagnostics::log(None, "WARN", "startup notice", Some(serde_json::json!({ "notice": notice })));
+    }
+    if let Err(e) = migrate_tool_roots(state) {
+        diagnostics::log(None, "WARN", "tool root migration failed", Some(serde_json::json!({ "error": e.to_string() })));
+    }
+    state.ollama.set_offline_mode(offline_mode_enabled(state));
+    if offline_mode_enabled(state) {
+        let loopback = state.ollama.is_loopback();
+        diagnostics::log(
+            None,
+            if loopback { "INFO" } else { "WARN" },
+            "offline mode enabled: network tools disabled, Ollama restricted to loopback",
+            Some(serde_json::json!({ "ollama_url": state.ollama.base_url(), "ollama_loopback": loopback })),
+        );
+    }
+}
+
+/// Set once shutdown has run; the window-destroyed and exit events can both fire on quit.
+static SHUTDOWN_DONE: AtomicBool = AtomicBool::new(false);
+
+/// Graceful shutdown: stop an in-flight chat stream, flush storage and kill the persistent
+/// terminal so no PowerShell window is orphaned. Runs at most once.
+fn shutdown(app: &tauri::AppHandle) {
+    if SHUTDOWN_DONE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    diagnostics::log(None, "INFO", "app shutting down", None);
+    shutdown_state(&app.state::<AppState>());
+}
+
+/// The app-independent part of shutdown, shared with headless mode.
+fn shutdown_state(state: &AppState) {
+    if let Ok(mut txs) = state.chat_cancel_txs.lock() {
+        for (_, tx) in txs.drain() {
+            let _ = tx.send(true);
+        }
+    }
+    if let Some(tx) = state.health_watchdog_stop.lock().ok().and_then(|mut tx| tx.take()) {
+        let _ = tx.send(());
+    }
+    // The lock may be poisoned by a panicked command; the connection is still usable.
+    let mut storage = state.storage.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(e) = storage.flush() {
+        diagnostics::log(None, "WARN", "storage flush on shutdown failed", Some(serde_json::json!({ "error": e.to_string() })));
+    }
+    drop(storage);
+    mcp::shutdown_persistent_terminal();
+    diagnostics::log(None, "INFO", "shutdown complete", None);
+}
+
+/// Resolves on Ctrl-C (SIGINT), or SIGTERM on Unix, e.g. `cargo tauri dev` being stopped or a
+/// service manager stopping a headless run.
+async fn termination_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = term.recv() => "SIGTERM",
+            },
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
+/// Exit status after `signal` (a name from termination_signal): 128 plus the signal number, as
+/// shells report it. SIGTERM is 15; SIGINT and Ctrl-C are 2.
+fn signal_exit_code(signal: &str) -> i32 {
+    match signal {
+        "SIGTERM" => 143,
+        _ => 130,
     }
 }
 
@@ -748,47 +4291,464 @@ pub fn run(state: AppState) {
             if let Some(window) = app.get_webview_window("main") {
                 window.open_devtools();
             }
+            let (stop_tx, stop_rx) = oneshot::channel();
+            if let Ok(mut slot) = app.state::<AppState>().health_watchdog_stop.lock() {
+                *slot = Some(stop_tx);
+            }
+            tauri::async_runtime::spawn(health_watchdog(app.handle().clone(), stop_rx));
+            // Without this a Ctrl-C in the dev terminal kills the process before shutdown runs,
+            // leaving the persistent terminal child and an unflushed database behind.
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let signal = termination_signal().await;
+                diagnostics::log(None, "INFO", "termination signal received", Some(serde_json::json!({ "signal": signal })));
+                shutdown(&handle);
+                handle.exit(signal_exit_code(signal));
+            });
+            startup(&app.state::<AppState>());
             Ok(())
         })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                if window.label() == "main" {
+                    shutdown(window.app_handle());
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             get_conversations,
             get_conversation,
+            get_conversation_metadata,
             create_conversation,
             update_conversation_title,
+            reorder_conversations,
+            prune_empty_conversations,
             delete_conversation,
+            set_conversation_model,
+            list_conversation_templates,
+            save_conversation_template,
+            delete_conversation_template,
+            create_conversation_from_template,
             add_message,
+            add_messages,
+            set_message_starred,
+            set_message_citations,
+            list_starred_messages,
+            add_message_variant,
+            list_message_variants,
+            select_variant,
+            undo_last_exchange,
+            trim_conversation_to,
+            conversation_stats,
+            export_conversation,
+            export_conversation_html,
+            export_all_ndjson,
+            import_ndjson,
+            get_conversation_summary,
+            add_context_file,
+            remove_context_file,
+            list_context_files,
+            index_notes,
+            semantic_search_notes,
+            summarize_conversation_if_needed,
             get_settings,
+            validate_settings,
             save_settings,
             get_mcp_settings,
             save_mcp_settings,
             get_mcp_tool_definitions,
+            get_mcp_tool_availability,
             execute_mcp_tool,
+            test_mcp_tool,
+            tool_needs_confirmation,
+            grant_tool_acknowledgment,
+            revoke_tool_acknowledgment,
+            list_tool_acknowledgments,
+            execute_mcp_tools_batch,
+            cancel_tool_execution,
             get_gpu_info,
             get_performance_status,
+            check_network,
             ollama_health,
+            count_tokens,
+            context_usage,
             ollama_list_models,
+            ollama_models_detailed,
             ollama_pull_model,
             ollama_delete_model,
+            ollama_unload_model,
             ollama_show_model,
+            get_modelfile,
+            create_model,
+            model_changed_since,
+            list_favorite_models,
+            clear_response_cache,
+            add_favorite_model,
+            remove_favorite_model,
             ollama_chat_stream,
+            continue_generation,
+            retry_with_model,
+            list_model_system_prompts,
+            set_model_system_prompt,
+            delete_model_system_prompt,
+            get_ui_state,
+            set_ui_state,
+            get_model_system_prompt,
             cancel_chat_generation,
+            is_generating,
             emit_diagnostic_log,
             get_app_data_dir,
+            get_startup_notices,
+            database_locked,
+            unlock_database,
+            get_diagnostics_file_path,
+            clear_diagnostics,
+            get_log_disk_usage,
             open_url,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running Local Private LLM");
+        .build(tauri::generate_context!())
+        .expect("error while building Local Private LLM")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                shutdown(app);
+            }
+        });
 }
 
 #[cfg(test)]
 mod tests {
-    use super::storage::Storage;
+    use super::storage::{is_plaintext_db, McpSettings, Settings, Storage};
+    use super::{changed_mcp_settings, context_file_root, default_filesystem_root, risk_needs_confirmation, tool_acknowledged, context_files_message, messages_dropped_to_fit, validate_seed_messages, SeedMessageDto, MAX_TEMPLATE_SEED_MESSAGES, with_test_diagnostics, McpToolResultDto, conversation_chat_options, health_check_interval, digest_changed, continue_prompt_messages, retry_target, match_model_system_prompt, prune_favorite_models, response_cache_key, settings_field_errors, PullRate, SettingsDto, ToolCancels, ToolLimiter, CONTINUE_PROMPT};
+
+    #[test]
+    fn tool_limiter_reuses_semaphore_until_limit_changes() {
+        let limiter = ToolLimiter::default();
+        let sem = limiter.semaphore(2).unwrap();
+        let _a = sem.clone().try_acquire_owned().unwrap();
+        let _b = sem.clone().try_acquire_owned().unwrap();
+        // A third call has to wait; the same semaphore is handed out while the limit stays 2.
+        assert!(limiter.semaphore(2).unwrap().try_acquire_owned().is_err());
+        let resized = limiter.semaphore(3).unwrap();
+        assert_eq!(resized.available_permits(), 3);
+        // Out-of-range limits are clamped; 0 would otherwise block every call forever.
+        assert_eq!(limiter.semaphore(0).unwrap().available_permits(), 1);
+    }
+
+    #[test]
+    fn termination_signals_map_to_shell_exit_codes() {
+        assert_eq!(super::signal_exit_code("SIGTERM"), 143);
+        assert_eq!(super::signal_exit_code("SIGINT"), 130);
+        assert_eq!(super::signal_exit_code("Ctrl-C"), 130);
+    }
+
+    #[test]
+    fn tool_cancel_reaches_calls_in_flight_only() {
+        let cancels = ToolCancels::default();
+        let first = cancels.register().unwrap();
+        let second = cancels.register().unwrap();
+        assert_eq!(cancels.cancel_all(), 2);
+        assert!(first.token.load(std::sync::atomic::Ordering::SeqCst) && second.token.load(std::sync::atomic::Ordering::SeqCst));
+        // A call registered after the cancel starts clean and does not clear the others.
+        let third = cancels.register().unwrap();
+        assert!(!third.token.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(first.token.load(std::sync::atomic::Ordering::SeqCst));
+        drop((first, second));
+        assert_eq!(cancels.cancel_all(), 1);
+    }
+
+    #[test]
+    fn settings_validation_reports_out_of_range_fields() {
+        let mut settings = SettingsDto {
+            theme: "dark".to_string(),
+            selected_model: "llama3".to_string(),
+            system_prompt: String::new(),
+            temperature: 0.7,
+            max_tokens: 2048,
+            tool_calling_mode: true,
+            inference_device_preference: "auto".to_string(),
+            rolling_summary_enabled: false,
+            rolling_summary_threshold: 40,
+            response_cache_enabled: false,
+            health_check_interval_secs: 5,
+            agent_max_iterations: 10,
+            agent_max_tool_calls: 20,
+            agent_timeout_secs: 300,
+        };
+        assert!(settings_field_errors(&settings).is_empty());
+        settings.temperature = 50.0;
+        settings.max_tokens = -1;
+        settings.inference_device_preference = "tpu".to_string();
+        let fields: Vec<String> = settings_field_errors(&settings).into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["temperature", "max_tokens", "inference_device_preference"]);
+        settings.temperature = f64::NAN;
+        assert_eq!(settings_field_errors(&settings)[0].field, "temperature");
+        settings.temperature = 0.7;
+        settings.max_tokens = 2048;
+        settings.inference_device_preference = "auto".to_string();
+        settings.agent_max_iterations = 0;
+        settings.agent_timeout_secs = 5;
+        let fields: Vec<String> = settings_field_errors(&settings).into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["agent_max_iterations", "agent_timeout_secs"]);
+    }
+
+    #[test]
+    fn reorder_conversations_puts_ordered_ids_first() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let a = storage.create_conversation("A").unwrap();
+        let b = storage.create_conversation("B").unwrap();
+        let c = storage.create_conversation("C").unwrap();
+        let ordered = storage
+            .reorder_conversations(&[c.id.clone(), "missing".to_string(), a.id.clone()])
+            .unwrap();
+        assert_eq!(ordered, 2);
+        let ids: Vec<String> = storage.list_conversations().unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![c.id.clone(), a.id.clone(), b.id.clone()]);
+        assert_eq!(storage.get_conversation_with_messages(&a.id).unwrap().unwrap().0.sort_order, Some(1));
+        // A new order replaces the old one; an empty list goes back to updated_at order.
+        storage.reorder_conversations(std::slice::from_ref(&b.id)).unwrap();
+        let convos = storage.list_conversations().unwrap();
+        assert_eq!(convos[0].id, b.id);
+        assert!(convos[1..].iter().all(|c| c.sort_order.is_none()));
+        storage.reorder_conversations(&[]).unwrap();
+        assert!(storage.list_conversations().unwrap().iter().all(|c| c.sort_order.is_none()));
+    }
+
+    #[test]
+    fn conversation_templates_save_update_and_delete() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let seeds = r#"[{"role":"user","content":"Review this diff"}]"#;
+        let t = storage
+            .save_conversation_template(None, "Code review", Some(" qwen2.5-coder "), Some("Be strict."), seeds)
+            .unwrap();
+        assert_eq!(t.model.as_deref(), Some("qwen2.5-coder"));
+        let updated = storage
+            .save_conversation_template(Some(&t.id), "Code review", Some(""), Some("  "), "[]")
+            .unwrap();
+        assert_eq!(updated.id, t.id);
+        assert_eq!(updated.created_at, t.created_at);
+        assert_eq!((updated.model, updated.system_prompt), (None, None));
+        assert_eq!(storage.list_conversation_templates().unwrap().len(), 1);
+        assert!(storage.delete_conversation_template(&t.id).unwrap());
+        assert!(storage.get_conversation_template(&t.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn conversation_system_prompt_outranks_model_and_global_prompts() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        storage.set_model_system_prompt("llama3.2", "Chatty.").unwrap();
+        let c = storage.create_conversation("From template").unwrap();
+        storage.set_conversation_system_prompt(&c.id, Some("Be strict.")).unwrap();
+        let conv = storage.get_conversation(&c.id).unwrap().unwrap();
+        assert_eq!(conv.system_prompt.as_deref(), Some("Be strict."));
+        assert!(conv.message_ids.is_empty());
+        assert_eq!(super::effective_system_prompt(&storage, conv.system_prompt.as_deref(), "llama3.2").unwrap(), "Be strict.");
+        assert_eq!(super::effective_system_prompt(&storage, None, "llama3.2").unwrap(), "Chatty.");
+        storage.set_conversation_system_prompt(&c.id, Some("  ")).unwrap();
+        assert!(storage.get_conversation(&c.id).unwrap().unwrap().system_prompt.is_none());
+    }
+
+    #[test]
+    fn seed_messages_need_known_roles_and_content() {
+        let seed = |role: &str, content: &str| SeedMessageDto { role: role.to_string(), content: content.to_string() };
+        assert!(validate_seed_messages(&[seed("system", "x"), seed("user", "hi"), seed("assistant", "ok")]).is_ok());
+        assert!(validate_seed_messages(&[seed("tool", "x")]).is_err());
+        assert!(validate_seed_messages(&[seed("user", "  ")]).is_err());
+        assert!(validate_seed_messages(&vec![seed("user", "x"); MAX_TEMPLATE_SEED_MESSAGES + 1]).is_err());
+    }
+
+    #[test]
+    fn ui_state_round_trips_and_deletes() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        assert_eq!(storage.get_ui_state("sidebar.width").unwrap(), None);
+        storage.set_ui_state("sidebar.width", "280").unwrap();
+        storage.set_ui_state("sidebar.width", "320").unwrap();
+        assert_eq!(storage.get_ui_state("sidebar.width").unwrap().as_deref(), Some("320"));
+        assert!(storage.delete_ui_state("sidebar.width").unwrap());
+        assert!(!storage.delete_ui_state("sidebar.width").unwrap());
+        assert_eq!(storage.get_ui_state("sidebar.width").unwrap(), None);
+    }
+
+    #[test]
+    fn conversation_metadata_loads_without_messages() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let conv = storage.create_conversation("Trip plan").unwrap();
+        storage.add_message(&conv.id, "user", "Where to?", None).unwrap();
+        let row = storage.get_conversation(&conv.id).unwrap().unwrap();
+        assert_eq!(row.title, "Trip plan");
+        assert_eq!(row.message_ids.len(), 1);
+        assert!(storage.get_conversation("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn tool_acknowledgments_are_scoped_to_tool_and_root() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        storage.add_tool_acknowledgment("write_file", "/home/me/notes").unwrap();
+        storage.add_tool_acknowledgment("write_file", "/home/me/notes").unwrap();
+        storage.add_tool_acknowledgment("run_command", "").unwrap();
+        let stored = storage.list_tool_acknowledgments().unwrap();
+        assert_eq!(stored.len(), 2);
+        let acks = || stored.iter().map(|(t, r)| (t.as_str(), r.as_str()));
+        assert!(tool_acknowledged(acks(), "write_file", "/home/me/notes"));
+        assert!(!tool_acknowledged(acks(), "write_file", "/home/me/other"));
+        assert!(tool_acknowledged(acks(), "run_command", ""));
+        assert!(!tool_acknowledged(acks(), "replace_in_file", "/home/me/notes"));
+        assert!(risk_needs_confirmation("write") && !risk_needs_confirmation("read_only"));
+        assert_eq!(storage.remove_tool_acknowledgments("write_file", Some("")).unwrap(), 0);
+        assert_eq!(storage.remove_tool_acknowledgments("write_file", None).unwrap(), 1);
+        assert_eq!(storage.list_tool_acknowledgments().unwrap(), vec![("run_command".to_string(), String::new())]);
+    }
+
+    fn test_state() -> super::AppState {
+        super::AppState {
+            storage: std::sync::Mutex::new(Storage::new_in_memory().unwrap()),
+            data_dir: std::env::temp_dir(),
+            ollama: super::OllamaClient::new("http://127.0.0.1:9".to_string()),
+            chat_cancel_txs: Default::default(),
+            active_pulls: Default::default(),
+            tool_cancels: Default::default(),
+            health_watchdog_stop: Default::default(),
+            tool_limiter: Default::default(),
+            session_tool_acknowledgments: Default::default(),
+            startup_notices: Vec::new(),
+            database_locked: Default::default(),
+            ephemeral: false,
+        }
+    }
+
+    #[test]
+    fn unacknowledged_risky_tools_are_refused_without_confirmation() {
+        let state = test_state();
+        assert!(super::call_needs_confirmation(&state, "write_file").unwrap());
+        assert!(super::call_needs_confirmation(&state, "open_path").unwrap());
+        assert!(!super::call_needs_confirmation(&state, "read_file").unwrap());
+        assert!(!super::call_needs_confirmation(&state, "no_such_tool").unwrap());
+        state
+            .session_tool_acknowledgments
+            .lock()
+            .unwrap()
+            .insert(("write_file".to_string(), String::new()));
+        assert!(!super::call_needs_confirmation(&state, "write_file").unwrap());
+
+        let refused = super::unconfirmed_tool_result("run_command");
+        assert!(!refused.ok);
+        let compact: serde_json::Value = serde_json::from_str(refused.model_error.as_deref().unwrap()).unwrap();
+        assert_eq!(compact["tool"], "run_command");
+        assert_eq!(compact["ok"], false);
+    }
+
+    #[test]
+    fn default_system_prompt_prefers_the_conversation_prompt() {
+        let state = test_state();
+        let cid = {
+            let mut storage = state.storage.lock().unwrap();
+            let mut settings = storage.get_settings().unwrap();
+            settings.system_prompt = "global".to_string();
+            storage.save_settings(settings).unwrap();
+            let c = storage.create_conversation("Own prompt").unwrap();
+            storage.set_conversation_system_prompt(&c.id, Some("conversation")).unwrap();
+            c.id
+        };
+        let user = || crate::ollama::ChatMessage { role: "user".to_string(), content: "hi".to_string() };
+        let mut messages = vec![user()];
+        super::insert_default_system_prompt(&state, "llama3", Some(&cid), &mut messages);
+        assert_eq!(messages[0].content, "conversation");
+        let mut messages = vec![user()];
+        super::insert_default_system_prompt(&state, "llama3", None, &mut messages);
+        assert_eq!(messages[0].content, "global");
+    }
+
+    #[test]
+    fn oldest_messages_are_dropped_until_the_prompt_fits() {
+        assert_eq!(messages_dropped_to_fit(&[100, 100, 100], 50, 1000), 0);
+        assert_eq!(messages_dropped_to_fit(&[100, 200, 100, 100], 50, 300), 2);
+        // The latest message always stays, even when it alone overflows.
+        assert_eq!(messages_dropped_to_fit(&[10, 10, 500], 0, 100), 2);
+        assert_eq!(messages_dropped_to_fit(&[], 50, 10), 0);
+    }
+
+    #[test]
+    fn test_diagnostics_wrap_results_without_steps() {
+        let s = McpSettings {
+            filesystem_enabled: true,
+            filesystem_root: "/tmp".to_string(),
+            ..Default::default()
+        };
+        let failed = McpToolResultDto {
+            ok: false,
+            content: String::new(),
+            error: Some("Root not configured".to_string()),
+            model_error: None,
+            diagnostic_steps: None,
+            steps: None,
+            test: false,
+        };
+        let result = with_test_diagnostics(failed, "list_dir", &serde_json::json!({}), &s, 12);
+        assert!(result.test);
+        let steps = result.diagnostic_steps.unwrap();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].message, "Testing list_dir");
+        assert_eq!(steps[0].meta.as_ref().unwrap()["enabled"], true);
+        assert_eq!(steps[0].meta.as_ref().unwrap()["filesystem_root"], "/tmp");
+        assert_eq!(steps[2].level, "ERROR");
+        assert_eq!(steps[2].meta.as_ref().unwrap()["error"], "Root not configured");
+    }
+
+    #[test]
+    fn plaintext_db_is_detected_by_header() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        Storage::new(&dir.to_string_lossy()).unwrap();
+        assert!(is_plaintext_db(&dir.join("local_private_llm.db")));
+        std::fs::write(dir.join("other.db"), b"not a database at all").unwrap();
+        assert!(!is_plaintext_db(&dir.join("other.db")));
+        assert!(!is_plaintext_db(&dir.join("missing.db")));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_storage_migrates_plaintext_and_rejects_wrong_passphrase() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let data_dir = dir.to_string_lossy().to_string();
+        let id = {
+            let mut storage = Storage::new(&data_dir).unwrap();
+            let c = storage.create_conversation("Secret").unwrap();
+            storage.add_message(&c.id, "user", "hello", None).unwrap();
+            c.id
+        };
+        {
+            let storage = Storage::new_encrypted(&data_dir, "correct horse").unwrap();
+            let (_, messages) = storage.get_conversation_with_messages(&id).unwrap().unwrap();
+            assert_eq!(messages[0].content, "hello");
+        }
+        assert!(!is_plaintext_db(&dir.join("local_private_llm.db")));
+        assert!(matches!(
+            Storage::new_encrypted(&data_dir, "wrong"),
+            Err(super::storage::StorageError::WrongPassphrase)
+        ));
+        assert!(Storage::new_encrypted(&data_dir, "correct horse").is_ok());
+    }
+
+    #[test]
+    fn prune_removes_only_old_empty_unpinned_conversations() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let empty = storage.create_conversation("Empty").unwrap();
+        let open = storage.create_conversation("Open").unwrap();
+        let pinned = storage.create_conversation("Pinned").unwrap();
+        let used = storage.create_conversation("Used").unwrap();
+        storage.add_message(&used.id, "user", "hi", None).unwrap();
+        storage.reorder_conversations(std::slice::from_ref(&pinned.id)).unwrap();
+        // Just created, so too recent under a one-hour threshold.
+        assert_eq!(storage.delete_empty_conversations(3600, Some(&open.id)).unwrap(), 0);
+        assert_eq!(storage.delete_empty_conversations(0, Some(&open.id)).unwrap(), 1);
+        let ids: Vec<String> = storage.list_conversations().unwrap().into_iter().map(|c| c.id).collect();
+        assert!(!ids.contains(&empty.id));
+        assert!(ids.contains(&open.id) && ids.contains(&pinned.id) && ids.contains(&used.id));
+    }
 
     #[test]
     fn test_storage_conversation_crud() {
-        let dir = std::env::temp_dir().join("lpllm_test");
-        let _ = std::fs::remove_dir_all(&dir);
-        let mut storage = Storage::new(dir.to_str().unwrap()).unwrap();
+        let mut storage = Storage::new_in_memory().unwrap();
         let c = storage.create_conversation("Test").unwrap();
         assert!(!c.id.is_empty());
         assert_eq!(c.title, "Test");
@@ -797,9 +4757,493 @@ mod tests {
         let (conv, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
         assert_eq!(conv.title, "Test");
         assert!(msgs.is_empty());
-        storage.add_message(&c.id, "user", "Hello").unwrap();
+        storage.add_message(&c.id, "user", "Hello", None).unwrap();
         let (_, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
         assert_eq!(msgs.len(), 1);
         storage.delete_conversation(&c.id).unwrap();
     }
+
+    #[test]
+    fn test_undo_last_exchange() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Undo").unwrap();
+        assert_eq!(storage.undo_last_exchange(&c.id).unwrap(), 0);
+        storage.add_message(&c.id, "user", "one", None).unwrap();
+        storage.add_message(&c.id, "assistant", "two", None).unwrap();
+        storage.add_message(&c.id, "user", "three", None).unwrap();
+        storage.add_message(&c.id, "assistant", "four", None).unwrap();
+        assert_eq!(storage.undo_last_exchange(&c.id).unwrap(), 2);
+        storage.add_message(&c.id, "user", "unanswered", None).unwrap();
+        assert_eq!(storage.undo_last_exchange(&c.id).unwrap(), 2);
+        let (_, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(msgs.last().unwrap().content, "two");
+    }
+
+    #[test]
+    fn message_citations_are_stored_and_removed_with_messages() {
+        use super::storage::CitationRow;
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Cited").unwrap();
+        storage.add_message(&c.id, "user", "who?", None).unwrap();
+        let answer = storage.add_message(&c.id, "assistant", "them", None).unwrap();
+        let cite = |n: &str| CitationRow {
+            title: n.to_string(),
+            url: format!("https://example.com/{}", n),
+            provider: Some("duckduckgo".to_string()),
+        };
+        assert!(!storage.set_message_citations("missing", &[cite("a")]).unwrap());
+        assert!(storage.set_message_citations(&answer.id, &[cite("a"), cite("b")]).unwrap());
+        let all = storage.list_conversation_citations(&c.id).unwrap();
+        assert_eq!(all[&answer.id], vec![cite("a"), cite("b")]);
+        assert!(storage.set_message_citations(&answer.id, &[cite("c")]).unwrap());
+        assert_eq!(storage.list_conversation_citations(&c.id).unwrap()[&answer.id], vec![cite("c")]);
+        storage.undo_last_exchange(&c.id).unwrap();
+        assert!(storage.list_conversation_citations(&c.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_conversation_stats() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Stats").unwrap();
+        let empty = storage.conversation_stats(&c.id).unwrap();
+        assert_eq!(empty.message_count, 0);
+        assert_eq!(empty.user_chars, 0);
+        assert_eq!(empty.estimated_tokens, 0);
+        assert!(empty.first_timestamp.is_none());
+        storage.add_message(&c.id, "user", "Hello there", None).unwrap();
+        storage.add_message(&c.id, "assistant", "Hi!", None).unwrap();
+        let stats = storage.conversation_stats(&c.id).unwrap();
+        assert_eq!(stats.message_count, 2);
+        assert_eq!(stats.user_chars, 11);
+        assert_eq!(stats.assistant_chars, 3);
+        assert_eq!(stats.total_words, 3);
+        assert_eq!(stats.estimated_tokens, 4);
+        assert!(stats.first_timestamp.is_some());
+    }
+
+    #[test]
+    fn test_rolling_summary_chunks() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Long").unwrap();
+        let mut ids = Vec::new();
+        for i in 0..6 {
+            let role = if i % 2 == 0 { "user" } else { "assistant" };
+            ids.push(storage.add_message(&c.id, role, &format!("m{}", i), None).unwrap().id);
+        }
+        assert!(storage.pending_summary_chunk(&c.id, 6, 2).unwrap().is_none());
+        let chunk = storage.pending_summary_chunk(&c.id, 5, 2).unwrap().unwrap();
+        assert_eq!(chunk.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["m0", "m1", "m2", "m3"]);
+        storage.save_conversation_summary(&c.id, "summary", &ids[3]).unwrap();
+        assert_eq!(storage.get_conversation_summary(&c.id).unwrap().unwrap().summarized_through_id, ids[3]);
+        // Only m4, m5 remain unsummarized.
+        assert!(storage.pending_summary_chunk(&c.id, 1, 2).unwrap().is_none());
+        let chunk = storage.pending_summary_chunk(&c.id, 1, 1).unwrap().unwrap();
+        assert_eq!(chunk.len(), 1);
+        assert_eq!(chunk[0].content, "m4");
+        // Originals stay for display.
+        assert_eq!(storage.get_conversation_with_messages(&c.id).unwrap().unwrap().1.len(), 6);
+        storage.delete_conversation(&c.id).unwrap();
+        assert!(storage.get_conversation_summary(&c.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_interrupted_messages() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Stopped").unwrap();
+        storage.add_message(&c.id, "user", "question", None).unwrap();
+        let b = storage.add_message(&c.id, "assistant", "partial", None).unwrap();
+        assert!(!b.interrupted);
+        assert!(storage.set_message_interrupted(&b.id, true).unwrap());
+        let (_, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(msgs.iter().map(|m| m.interrupted).collect::<Vec<_>>(), [false, true]);
+        assert!(storage.set_message_interrupted(&b.id, false).unwrap());
+        let (_, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert!(!msgs[1].interrupted);
+        assert!(!storage.set_message_interrupted("missing", true).unwrap());
+    }
+
+    #[test]
+    fn test_starred_messages() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Stars").unwrap();
+        storage.add_message(&c.id, "user", "question", None).unwrap();
+        let b = storage.add_message(&c.id, "assistant", "key answer", None).unwrap();
+        assert!(!b.starred);
+        assert!(storage.list_starred_messages(&c.id).unwrap().is_empty());
+        assert!(storage.set_message_starred(&b.id, true).unwrap());
+        let starred = storage.list_starred_messages(&c.id).unwrap();
+        assert_eq!(starred.len(), 1);
+        assert_eq!(starred[0].id, b.id);
+        let (_, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(msgs.iter().map(|m| m.starred).collect::<Vec<_>>(), [false, true]);
+        assert!(storage.set_message_starred(&b.id, false).unwrap());
+        assert!(storage.list_starred_messages(&c.id).unwrap().is_empty());
+        assert!(!storage.set_message_starred("missing", true).unwrap());
+    }
+
+    #[test]
+    fn health_check_interval_is_clamped() {
+        assert_eq!(health_check_interval(5).as_secs(), 5);
+        assert_eq!(health_check_interval(0).as_secs(), 1);
+        assert_eq!(health_check_interval(100_000).as_secs(), 300);
+    }
+
+    #[test]
+    fn test_conversation_chat_options_fall_back_to_settings() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Options").unwrap();
+        let settings = Settings::default();
+        let stored = |s: &Storage| s.get_conversation_with_messages(&c.id).unwrap().unwrap().0.chat_options;
+        let opts = conversation_chat_options(stored(&storage).as_deref(), &settings);
+        assert_eq!((opts.temperature, opts.num_predict), (Some(0.7), Some(2048)));
+        storage.set_conversation_chat_options(&c.id, r#"{"temperature":0.2,"num_predict":null}"#).unwrap();
+        let opts = conversation_chat_options(stored(&storage).as_deref(), &settings);
+        assert_eq!((opts.temperature, opts.num_predict), (Some(0.2), Some(2048)));
+        assert_eq!(storage.list_conversations().unwrap()[0].chat_options, stored(&storage));
+        let opts = conversation_chat_options(Some("not json"), &settings);
+        assert_eq!(opts.temperature, Some(0.7));
+    }
+
+    #[test]
+    fn test_message_variants_keep_only_active_in_history() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Variants").unwrap();
+        let q = storage.add_message(&c.id, "user", "q", None).unwrap();
+        let a0 = storage.add_message(&c.id, "assistant", "first", Some("llama3.2")).unwrap();
+        assert!(storage.add_message_variant(&c.id, &q.id, "not an answer", None).unwrap().is_none());
+        let a1 = storage.add_message_variant(&c.id, &a0.id, "second", None).unwrap().unwrap();
+        let a2 = storage.add_message_variant(&c.id, &a0.id, "third", None).unwrap().unwrap();
+        let v = a2.variant.clone().unwrap();
+        assert_eq!((v.group_id.as_str(), v.index, v.count), (a0.id.as_str(), 2, 3));
+        let history = |s: &Storage| {
+            let (conv, msgs) = s.get_conversation_with_messages(&c.id).unwrap().unwrap();
+            assert_eq!(conv.message_ids.len(), msgs.len());
+            msgs.into_iter().map(|m| m.content).collect::<Vec<_>>()
+        };
+        assert_eq!(history(&storage), ["q", "third"]);
+        assert!(storage.select_variant(&a1.id).unwrap());
+        assert_eq!(history(&storage), ["q", "second"]);
+        let variants = storage.list_message_variants(&a0.id).unwrap();
+        assert_eq!(variants.iter().map(|m| m.variant.as_ref().unwrap().active).collect::<Vec<_>>(), [false, true, false]);
+        assert!(!storage.select_variant(&q.id).unwrap());
+        assert_eq!(storage.conversation_stats(&c.id).unwrap().message_count, 2);
+        assert_eq!(storage.undo_last_exchange(&c.id).unwrap(), 0);
+        assert!(storage.list_message_variants(&a0.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_last_model_used() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Models").unwrap();
+        let last = |s: &Storage| s.get_conversation_with_messages(&c.id).unwrap().unwrap().0.last_model_used;
+        assert_eq!(last(&storage), None);
+        storage.add_message(&c.id, "user", "q1", None).unwrap();
+        storage.add_message(&c.id, "assistant", "a1", Some("llama3.2")).unwrap();
+        storage.add_message(&c.id, "user", "q2", None).unwrap();
+        storage.add_message(&c.id, "assistant", "a2", Some("qwen2.5:7b")).unwrap();
+        assert_eq!(last(&storage).as_deref(), Some("qwen2.5:7b"));
+        storage.set_conversation_model(&c.id, Some("mistral")).unwrap();
+        assert_eq!(last(&storage).as_deref(), Some("mistral"));
+        storage.set_conversation_model(&c.id, None).unwrap();
+        assert_eq!(storage.list_conversations().unwrap()[0].last_model_used.as_deref(), Some("qwen2.5:7b"));
+    }
+
+    #[test]
+    fn test_context_files_reread_each_turn() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("notes.md"), "first draft").unwrap();
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Context").unwrap();
+        storage.add_context_file(&c.id, "filesystem", "notes.md").unwrap();
+        storage.add_context_file(&c.id, "filesystem", "notes.md").unwrap();
+        storage.add_context_file(&c.id, "filesystem", "missing.md").unwrap();
+        let files = storage.list_context_files(&c.id).unwrap();
+        assert_eq!(files.len(), 2);
+        let mut mcp_settings = McpSettings {
+            filesystem_root: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        // A root the user turned off is not read, even though its path is still saved.
+        let (msg, included) = context_files_message(&files, &mcp_settings).unwrap();
+        assert_eq!(included, 0);
+        assert!(msg.content.contains("--- notes.md ---\n[unavailable: Root not configured]"));
+        mcp_settings.filesystem_enabled = true;
+        let (msg, included) = context_files_message(&files, &mcp_settings).unwrap();
+        assert_eq!(msg.role, "system");
+        assert_eq!(included, 1);
+        assert!(msg.content.contains("first draft"));
+        assert!(msg.content.contains("--- missing.md ---\n[unavailable:"));
+        std::fs::write(dir.join("notes.md"), "edited").unwrap();
+        let (msg, _) = context_files_message(&files, &mcp_settings).unwrap();
+        assert!(msg.content.contains("edited") && !msg.content.contains("first draft"));
+        assert!(storage.remove_context_file(&c.id, "filesystem", "missing.md").unwrap());
+        assert!(!storage.remove_context_file(&c.id, "filesystem", "missing.md").unwrap());
+        storage.delete_conversation(&c.id).unwrap();
+        assert!(storage.list_context_files(&c.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_acknowledgment_root_is_never_empty() {
+        let mut s = McpSettings {
+            filesystem_root: "/work".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(super::acknowledgment_root("filesystem", &s), None);
+        assert_eq!(super::acknowledgment_root("obsidian", &s), None);
+        assert_eq!(super::acknowledgment_root("terminal", &s), None);
+        s.filesystem_enabled = true;
+        s.obsidian_enabled = true;
+        assert_eq!(super::acknowledgment_root("filesystem", &s).as_deref(), Some("/work"));
+        // Enabled but no vault chosen: still nothing to scope to.
+        assert_eq!(super::acknowledgment_root("obsidian", &s), None);
+    }
+
+    #[test]
+    fn test_note_index_roots_follow_tool_toggles() {
+        let mut s = McpSettings {
+            obsidian_vault_path: "/vault".to_string(),
+            ..Default::default()
+        };
+        for root in ["filesystem", "obsidian"] {
+            assert!(matches!(context_file_root(&s, root), Err(crate::mcp::McpToolError::RootNotConfigured)));
+        }
+        s.obsidian_enabled = true;
+        s.filesystem_enabled = true;
+        assert_eq!(context_file_root(&s, "obsidian").unwrap(), std::path::PathBuf::from("/vault"));
+        // Same default the tools use when the filesystem root is left empty.
+        assert_eq!(context_file_root(&s, "filesystem").unwrap(), std::path::PathBuf::from(default_filesystem_root()));
+        assert!(matches!(context_file_root(&s, "home"), Err(crate::mcp::McpToolError::InvalidArg(_))));
+    }
+
+    #[test]
+    fn test_write_tools_route_frontmatter_handling_by_tool() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let (fs_root, vault) = (dir.join("fs"), dir.join("vault"));
+        std::fs::create_dir_all(&fs_root).unwrap();
+        std::fs::create_dir_all(&vault).unwrap();
+        let old = "---\ntags: [project]\n---\nold body\n";
+        std::fs::write(fs_root.join("note.md"), old).unwrap();
+        std::fs::write(vault.join("note.md"), old).unwrap();
+        let s = McpSettings {
+            filesystem_enabled: true,
+            filesystem_root: fs_root.to_string_lossy().to_string(),
+            obsidian_enabled: true,
+            obsidian_vault_path: vault.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let call = |name: &str, args: serde_json::Value| super::run_mcp_tool_call(&s, name.to_string(), &args, None, &cancel, None);
+
+        // write_file writes exactly what it is given.
+        let r = call("write_file", serde_json::json!({ "path": "note.md", "content": "new body\n" }));
+        assert!(r.ok, "{:?}", r.error);
+        assert_eq!(std::fs::read_to_string(fs_root.join("note.md")).unwrap(), "new body\n");
+
+        // obsidian_write_note keeps the note's frontmatter unless told to replace it.
+        let r = call("obsidian_write_note", serde_json::json!({ "path": "note.md", "content": "new body\n" }));
+        assert!(r.ok && r.content.contains("kept existing frontmatter"), "{:?}", r.error);
+        assert_eq!(std::fs::read_to_string(vault.join("note.md")).unwrap(), "---\ntags: [project]\n---\nnew body\n");
+        let r = call(
+            "obsidian_write_note",
+            serde_json::json!({ "path": "note.md", "content": "plain\n", "replace_frontmatter": true }),
+        );
+        assert!(r.ok, "{:?}", r.error);
+        assert_eq!(std::fs::read_to_string(vault.join("note.md")).unwrap(), "plain\n");
+    }
+
+    #[test]
+    fn test_continue_prompt_uses_summary_and_ends_with_continue() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Long").unwrap();
+        let q1 = storage.add_message(&c.id, "user", "q1", None).unwrap();
+        storage.add_message(&c.id, "assistant", "a1", None).unwrap();
+        storage.add_message(&c.id, "user", "q2", None).unwrap();
+        let cut = storage.add_message(&c.id, "assistant", "cut off mid-", None).unwrap();
+        let (_, history) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        let msgs = continue_prompt_messages("sys", None, &history);
+        assert_eq!(msgs.len(), 6);
+        assert_eq!(msgs[4].content, "cut off mid-");
+        assert_eq!(msgs[5].content, CONTINUE_PROMPT);
+        // The continuation is saved onto the cut-off reply rather than as a new message.
+        assert!(storage.append_message_content(&cut.id, "sentence.").unwrap());
+        assert!(!storage.append_message_content("missing", "x").unwrap());
+        let (_, after) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(after.len(), 4);
+        assert_eq!(after[3].content, "cut off mid-sentence.");
+        storage.save_conversation_summary(&c.id, "earlier stuff", &q1.id).unwrap();
+        let summary = storage.get_conversation_summary(&c.id).unwrap();
+        let msgs = continue_prompt_messages("sys", summary.as_ref(), &history);
+        assert_eq!(msgs.iter().map(|m| m.role.as_str()).collect::<Vec<_>>(), ["system", "system", "assistant", "user", "assistant", "user"]);
+        assert!(msgs[1].content.contains("earlier stuff"));
+    }
+
+    #[test]
+    fn test_retry_target_finds_last_user_message_and_its_answer() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Retry").unwrap();
+        let (_, history) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(retry_target(&history), None);
+        storage.add_message(&c.id, "user", "q1", None).unwrap();
+        storage.add_message(&c.id, "assistant", "a1", Some("llama3.2")).unwrap();
+        storage.add_message(&c.id, "user", "q2", None).unwrap();
+        let (_, history) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(retry_target(&history), Some((2, None)));
+        let a2 = storage.add_message(&c.id, "assistant", "a2", Some("llama3.2")).unwrap();
+        let (_, history) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(retry_target(&history), Some((2, Some(a2.id.clone()))));
+
+        let retried = storage.add_message_variant(&c.id, &a2.id, "a2 again", Some("qwen2.5")).unwrap().unwrap();
+        assert_eq!(retried.model.as_deref(), Some("qwen2.5"));
+        let (_, history) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        assert_eq!(history.last().unwrap().content, "a2 again");
+        assert_eq!(retry_target(&history), Some((2, Some(retried.id))));
+    }
+
+    #[test]
+    fn test_model_system_prompt_matching() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        storage.set_model_system_prompt("qwen2.5-coder", "You write code.").unwrap();
+        storage.set_model_system_prompt("qwen2.5-coder:14b", "Big coder.").unwrap();
+        storage.set_model_system_prompt("llama3.2", "Chatty.").unwrap();
+        storage.set_model_system_prompt("qwen", "Generic qwen.").unwrap();
+        let rows = storage.list_model_system_prompts().unwrap();
+        assert_eq!(match_model_system_prompt(&rows, "qwen2.5-coder:14b"), Some("Big coder."));
+        assert_eq!(match_model_system_prompt(&rows, "qwen2.5-coder:7b"), Some("You write code."));
+        assert_eq!(match_model_system_prompt(&rows, "llama3.2:latest"), Some("Chatty."));
+        assert_eq!(match_model_system_prompt(&rows, "qwen2.5:3b-instruct"), Some("Generic qwen."));
+        assert_eq!(match_model_system_prompt(&rows, "mistral"), None);
+        assert!(storage.delete_model_system_prompt("qwen").unwrap());
+        let rows = storage.list_model_system_prompts().unwrap();
+        assert_eq!(match_model_system_prompt(&rows, "qwen2.5:3b-instruct"), None);
+    }
+
+    #[test]
+    fn test_changed_mcp_settings() {
+        let old = McpSettings::default();
+        assert!(changed_mcp_settings(&old, &old.clone()).is_empty());
+        let new = McpSettings {
+            filesystem_root: "/tmp/notes".to_string(),
+            web_search_enabled: !old.web_search_enabled,
+            ..old.clone()
+        };
+        assert_eq!(changed_mcp_settings(&old, &new), ["filesystem_root", "web_search_enabled"]);
+    }
+
+    #[test]
+    fn test_digest_changed() {
+        assert!(!digest_changed(Some("abc123"), "abc123"));
+        assert!(!digest_changed(Some("abc123"), "sha256:abc123"));
+        assert!(digest_changed(Some("abc123"), "def456"));
+        assert!(digest_changed(None, "abc123"));
+        assert!(digest_changed(Some("abc123"), "  "));
+    }
+
+    #[test]
+    fn test_pull_rate_resets_per_layer() {
+        let start = std::time::Instant::now();
+        let at = |ms: u64| start + std::time::Duration::from_millis(ms);
+        let mut rate = PullRate::default();
+        assert_eq!(rate.sample("a", 0, 1000, at(0)), (None, None));
+        assert_eq!(rate.sample("a", 100, 1000, at(1000)), (Some(100), Some(9)));
+        assert_eq!(rate.sample("a", 300, 1000, at(2000)), (Some(150), Some(5)));
+        // A new layer starts a fresh window instead of mixing in the old layer's bytes.
+        assert_eq!(rate.sample("b", 0, 500, at(2500)), (None, None));
+        assert_eq!(rate.sample("b", 50, 500, at(3500)), (Some(50), Some(9)));
+        // Stalled: the window still holds earlier progress, but no ETA is reported from it.
+        assert_eq!(rate.sample("b", 50, 500, at(5000)), (Some(20), Some(23)));
+        assert_eq!(rate.sample("b", 50, 500, at(6500)), (Some(0), None));
+        assert_eq!(rate.sample("b", 150, 500, at(7500)), (Some(30), Some(12)));
+    }
+
+    #[test]
+    fn test_add_messages_in_one_transaction() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Import").unwrap();
+        assert!(storage.add_messages(&c.id, &[]).unwrap().is_empty());
+        let batch = vec![
+            ("user".to_string(), "q".to_string()),
+            ("assistant".to_string(), "a".to_string()),
+            ("user".to_string(), "q2".to_string()),
+        ];
+        let rows = storage.add_messages(&c.id, &batch).unwrap();
+        assert_eq!(rows.len(), 3);
+        let (_, stored) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        let contents: Vec<&str> = stored.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["q", "a", "q2"]);
+        assert_eq!(stored[1].id, rows[1].id);
+    }
+
+    #[test]
+    fn test_prune_favorite_models() {
+        let favorites = vec!["llama3".to_string(), "qwen2.5:7b".to_string(), "gone:1b".to_string()];
+        let installed = vec!["llama3:latest".to_string(), "qwen2.5:7b".to_string()];
+        assert_eq!(prune_favorite_models(&favorites, &installed), ["llama3", "qwen2.5:7b"]);
+        assert!(prune_favorite_models(&favorites, &[]).is_empty());
+
+        let mut storage = Storage::new_in_memory().unwrap();
+        assert!(storage.get_favorite_models().unwrap().is_empty());
+        storage.set_favorite_models(&favorites).unwrap();
+        assert_eq!(storage.get_favorite_models().unwrap(), favorites);
+    }
+
+    #[test]
+    fn test_response_cache_key_and_storage() {
+        let msg = |role: &str, content: &str| crate::ollama::ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let deterministic = crate::ollama::ChatOptions { temperature: Some(0.0), num_predict: Some(2048) };
+        let warm = crate::ollama::ChatOptions { temperature: Some(0.7), ..deterministic.clone() };
+        let history = vec![msg("system", "Be brief."), msg("user", "2+2?")];
+        let (key, request) = response_cache_key("llama3", &history, &deterministic, false).unwrap();
+        assert_eq!(response_cache_key("llama3:latest", &history, &deterministic, false).unwrap().0, key);
+        assert_ne!(response_cache_key("llama3", &history[1..], &deterministic, false).unwrap().0, key);
+        assert!(response_cache_key("llama3", &history, &warm, false).is_none());
+        assert!(response_cache_key("llama3", &history, &deterministic, true).is_none());
+        let tool_round = vec![msg("user", "[Tool result from read_file]\ntext")];
+        assert!(response_cache_key("llama3", &tool_round, &deterministic, false).is_none());
+
+        let mut storage = Storage::new_in_memory().unwrap();
+        assert_eq!(storage.get_cached_response(&key, &request, 60).unwrap(), None);
+        storage.put_cached_response(&key, &request, "llama3", "4", 60).unwrap();
+        assert_eq!(storage.get_cached_response(&key, &request, 60).unwrap().as_deref(), Some("4"));
+        assert_eq!(storage.get_cached_response(&key, "other request", 60).unwrap(), None);
+        assert_eq!(storage.clear_response_cache().unwrap(), 1);
+        assert_eq!(storage.get_cached_response(&key, &request, 60).unwrap(), None);
+    }
+
+    #[test]
+    fn test_trim_after_keeps_messages_up_to_anchor() {
+        let mut storage = Storage::new_in_memory().unwrap();
+        let c = storage.create_conversation("Rewind").unwrap();
+        let other = storage.create_conversation("Other").unwrap();
+        let q1 = storage.add_message(&c.id, "user", "q1", None).unwrap();
+        let a1 = storage.add_message(&c.id, "assistant", "a1", None).unwrap();
+        storage.add_message(&c.id, "user", "q2", None).unwrap();
+        storage.add_message(&c.id, "assistant", "a2", None).unwrap();
+        storage.add_message(&other.id, "user", "elsewhere", None).unwrap();
+        storage.save_conversation_summary(&c.id, "q1 and a1", &a1.id).unwrap();
+        assert_eq!(storage.trim_after(&other.id, &a1.id).unwrap(), None);
+        assert_eq!(storage.trim_after(&c.id, &a1.id).unwrap(), Some(2));
+        let (_, msgs) = storage.get_conversation_with_messages(&c.id).unwrap().unwrap();
+        let contents: Vec<&str> = msgs.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["q1", "a1"]);
+        assert!(storage.get_conversation_summary(&c.id).unwrap().is_some());
+        assert_eq!(storage.trim_after(&c.id, &q1.id).unwrap(), Some(1));
+        // The summary's anchor was trimmed, so the summary goes with it.
+        assert!(storage.get_conversation_summary(&c.id).unwrap().is_none());
+        let (_, other_msgs) = storage.get_conversation_with_messages(&other.id).unwrap().unwrap();
+        assert_eq!(other_msgs.len(), 1);
+    }
+
+    #[test]
+    fn data_dir_candidates_are_unique_and_end_in_temp() {
+        let candidates = super::data_dir_candidates();
+        assert_eq!(candidates.last(), Some(&std::env::temp_dir().join("Local Private LLM")));
+        let unique: std::collections::HashSet<_> = candidates.iter().collect();
+        assert_eq!(unique.len(), candidates.len());
+        assert_eq!(super::default_data_dir().as_ref(), candidates.first());
+    }
 }
@@ -1,21 +1,229 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::{Path, PathBuf};
+
+/// Ephemeral mode: `--ephemeral` or LPLLM_EPHEMERAL=1. Nothing (database or logs) is written to disk.
+fn ephemeral_mode() -> bool {
+    std::env::args().any(|a| a == "--ephemeral")
+        || std::env::var("LPLLM_EPHEMERAL")
+            .map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+            .unwrap_or(false)
+}
+
+/// Encrypted database: `--encrypted` or LPLLM_ENCRYPTED=1. Needs a build with the `encryption` feature.
+fn encryption_requested() -> bool {
+    std::env::args().any(|a| a == "--encrypted")
+        || std::env::var("LPLLM_ENCRYPTED")
+            .map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+            .unwrap_or(false)
+}
+
+/// Headless mode: `--headless` (or LPLLM_HEADLESS=1) serves commands on a loopback port instead of
+/// opening the window. The port comes from `--headless-port <n>`, then LPLLM_HEADLESS_PORT.
+fn headless_port() -> Option<u16> {
+    let requested = std::env::args().any(|a| a == "--headless")
+        || std::env::var("LPLLM_HEADLESS")
+            .map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+    if !requested {
+        return None;
+    }
+    let mut args = std::env::args().skip(1);
+    let mut port = None;
+    while let Some(arg) = args.next() {
+        if arg == "--headless-port" {
+            port = args.next();
+        } else if let Some(v) = arg.strip_prefix("--headless-port=") {
+            port = Some(v.to_string());
+        }
+    }
+    let port = port.or_else(|| std::env::var("LPLLM_HEADLESS_PORT").ok());
+    Some(match port {
+        Some(p) => p
+            .trim()
+            .parse()
+            .unwrap_or_else(|_| fatal(&format!("--headless-port must be a port number, got \"{}\"", p.trim()))),
+        None => local_private_llm::DEFAULT_HEADLESS_PORT,
+    })
+}
+
+/// Database passphrase from LPLLM_DB_PASSPHRASE (cleared from the environment once read), else
+/// typed on the terminal without echo. None when there is no terminal to ask on (release GUI
+/// builds); the app's unlock screen asks instead. It is never written anywhere.
+#[cfg(feature = "encryption")]
+fn read_passphrase() -> Result<Option<String>, String> {
+    use std::io::IsTerminal;
+    if let Ok(passphrase) = std::env::var("LPLLM_DB_PASSPHRASE") {
+        std::env::remove_var("LPLLM_DB_PASSPHRASE");
+        return Ok(Some(passphrase));
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    rpassword::prompt_password("Database passphrase: ")
+        .map(Some)
+        .map_err(|e| format!("The database passphrase could not be read: {e}"))
+}
+
+/// Open the on-disk database, encrypted when requested. None when the encrypted database has to
+/// be unlocked from the app (see read_passphrase).
+fn open_storage(data_dir: &Path) -> Result<Option<local_private_llm::Storage>, String> {
+    let dir = data_dir.to_string_lossy();
+    if !encryption_requested() {
+        return local_private_llm::Storage::new(&dir).map(Some).map_err(|e| e.to_string());
+    }
+    #[cfg(feature = "encryption")]
+    {
+        let Some(passphrase) = read_passphrase()? else {
+            return Ok(None);
+        };
+        local_private_llm::Storage::new_encrypted(&dir, &passphrase)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+    #[cfg(not(feature = "encryption"))]
+    Err("Encryption was requested, but this build does not include it (rebuild with --features encryption).".to_string())
+}
+
+/// Data directory set explicitly: `--data-dir <path>` (or `--data-dir=<path>`), then LPLLM_DATA_DIR.
+fn configured_data_dir() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--data-dir" {
+            if let Some(v) = args.next().filter(|v| !v.trim().is_empty()) {
+                return Some(PathBuf::from(v.trim()));
+            }
+        } else if let Some(v) = arg.strip_prefix("--data-dir=").filter(|v| !v.trim().is_empty()) {
+            return Some(PathBuf::from(v.trim()));
+        }
+    }
+    std::env::var("LPLLM_DATA_DIR")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| PathBuf::from(v.trim()))
+}
+
+/// Create the data directory and check we can write to it.
+fn check_writable(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("{} could not be created: {}", dir.display(), e))?;
+    let probe = dir.join(".write_test");
+    std::fs::write(&probe, b"ok").map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Pick the data directory. An explicitly configured one must be writable; otherwise the first
+/// writable OS default wins, with a notice for the user when that was not the first choice. The
+/// error lists every directory tried.
+fn resolve_data_dir() -> Result<(PathBuf, Option<String>), String> {
+    if let Some(dir) = configured_data_dir() {
+        return check_writable(&dir).map(|_| (dir, None)).map_err(|e| {
+            format!("The configured data directory {e}.\n\nPoint LPLLM_DATA_DIR or --data-dir at a writable folder.")
+        });
+    }
+    let mut failures = Vec::new();
+    for dir in local_private_llm::data_dir_candidates() {
+        match check_writable(&dir) {
+            Ok(()) => {
+                let notice = (!failures.is_empty()).then(|| {
+                    let temp = if dir.starts_with(std::env::temp_dir()) {
+                        " The system may delete files in the temp folder."
+                    } else {
+                        ""
+                    };
+                    format!(
+                        "Your data is being saved in {} because the usual folder could not be used ({}).{} Set LPLLM_DATA_DIR or --data-dir to choose a folder.",
+                        dir.display(),
+                        failures.join("; "),
+                        temp
+                    )
+                });
+                return Ok((dir, notice));
+            }
+            Err(e) => failures.push(e),
+        }
+    }
+    Err(format!(
+        "No writable data directory was found. Tried:\n  {}\n\nSet LPLLM_DATA_DIR or --data-dir to a writable folder, or start with --ephemeral to run without saving anything.",
+        failures.join("\n  ")
+    ))
+}
+
+/// Report a startup failure and exit. Release builds on Windows have no console, so the message is
+/// also written to the temp dir where the user can find it.
+fn fatal(message: &str) -> ! {
+    eprintln!("Local Private LLM could not start:\n{message}");
+    let report = std::env::temp_dir().join("local-private-llm-startup-error.txt");
+    if std::fs::write(&report, message).is_ok() {
+        eprintln!("(also written to {})", report.display());
+    }
+    std::process::exit(1);
+}
+
 fn main() {
-    let data_dir = dirs::data_local_dir()
-        .or_else(dirs::home_dir)
-        .map(|p| p.join("Local Private LLM"))
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
-    let _ = std::fs::create_dir_all(&data_dir);
-    let db_path = data_dir.join("local_private_llm.db");
-    let storage = local_private_llm::Storage::new(db_path.parent().unwrap().to_str().unwrap())
-        .expect("Failed to initialize storage");
+    let headless_port = headless_port();
+    let ephemeral = ephemeral_mode();
+    let mut startup_notices = Vec::new();
+    let mut database_locked = false;
+    let (data_dir, storage) = if ephemeral {
+        local_private_llm::disable_file_logging();
+        let storage = local_private_llm::Storage::new_in_memory()
+            .unwrap_or_else(|e| fatal(&format!("The in-memory database could not be created: {e}")));
+        let data_dir = configured_data_dir()
+            .or_else(local_private_llm::default_data_dir)
+            .unwrap_or_else(|| PathBuf::from("."));
+        (data_dir, storage)
+    } else {
+        let (data_dir, notice) = resolve_data_dir().unwrap_or_else(|e| fatal(&e));
+        startup_notices.extend(notice);
+        local_private_llm::set_data_dir(&data_dir);
+        let storage = open_storage(&data_dir).unwrap_or_else(|e| {
+            let db_path = data_dir.join("local_private_llm.db");
+            let hint = if encryption_requested() {
+                "Check the passphrase and try again."
+            } else if db_path.exists() && !local_private_llm::is_plaintext_db(&db_path) {
+                "The database looks encrypted; start with --encrypted (or LPLLM_ENCRYPTED=1) to unlock it."
+            } else {
+                "Check the folder's permissions or set LPLLM_DATA_DIR / --data-dir to another folder."
+            };
+            fatal(&format!("The database in {} could not be opened: {e}\n\n{hint}", data_dir.display()))
+        });
+        let storage = match storage {
+            Some(storage) => storage,
+            None if headless_port.is_some() => fatal(
+                "The encrypted database needs a passphrase, and headless mode has no window to ask for it.\n\nSet LPLLM_DB_PASSPHRASE or start from a terminal.",
+            ),
+            // Placeholder until the unlock screen opens the real database (unlock_database).
+            None => {
+                database_locked = true;
+                local_private_llm::Storage::new_in_memory()
+                    .unwrap_or_else(|e| fatal(&format!("The in-memory database could not be created: {e}")))
+            }
+        };
+        (data_dir, storage)
+    };
     let ollama = local_private_llm::OllamaClient::new("http://127.0.0.1:11434".to_string());
     let state = local_private_llm::AppState {
         storage: std::sync::Mutex::new(storage),
+        data_dir,
         ollama,
-        chat_cancel_tx: std::sync::Mutex::new(None),
+        chat_cancel_txs: std::sync::Mutex::new(std::collections::HashMap::new()),
+        active_pulls: std::sync::Mutex::new(std::collections::HashSet::new()),
+        tool_cancels: Default::default(),
+        health_watchdog_stop: std::sync::Mutex::new(None),
+        tool_limiter: Default::default(),
+        session_tool_acknowledgments: std::sync::Mutex::new(std::collections::HashSet::new()),
+        startup_notices,
+        database_locked: std::sync::atomic::AtomicBool::new(database_locked),
+        ephemeral,
     };
 
+    if let Some(port) = headless_port {
+        if let Err(e) = local_private_llm::run_headless(state, port) {
+            fatal(&e);
+        }
+        return;
+    }
     local_private_llm::run(state)
 }
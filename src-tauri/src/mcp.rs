@@ -3,16 +3,27 @@
 //! Models never execute tools; the app does.
 
 use chrono::Datelike;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(windows)]
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Child, ChildStdin, Command, Stdio};
+use std::process::Command;
+#[cfg(windows)]
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(windows)]
 use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 use thiserror::Error;
 
 const MAX_FILE_SIZE_BYTES: u64 = 512 * 1024; // 512 KiB
 const MAX_READ_LINES: usize = 2000;
+/// read_bytes: max bytes per call, and the default when no length is given.
+const MAX_READ_BYTES: u32 = 8 * 1024;
+const DEFAULT_READ_BYTES: u32 = 256;
 
 
 #[derive(Error, Debug)]
@@ -31,8 +42,36 @@ pub enum McpToolError {
     Network(String),
     #[error("Command execution failed: {0}")]
     CommandFailed(String),
+    #[error("Blocked by safe mode: {0}")]
+    SafeMode(String),
+    #[error("Offline mode enabled: {0}")]
+    OfflineMode(String),
+}
+
+impl McpToolError {
+    /// One-line advice for the model on what to do next, sent with the compact error.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            McpToolError::PathNotAllowed(_) => "Use a path relative to the tool's root, without '..'.",
+            McpToolError::RootNotConfigured => {
+                "This tool's folder is not configured; answer without it or ask the user to set it in Settings."
+            }
+            McpToolError::Io(_) => "Check that the path exists (list_dir shows what is there).",
+            McpToolError::InvalidArg(_) => "Fix the arguments to match the tool's parameters and try once more.",
+            McpToolError::UnknownTool(_) => "Only call tools listed in the system prompt.",
+            McpToolError::Network(_) => "The network request failed; answer from what you know and say the tool failed.",
+            McpToolError::CommandFailed(_) => "Do not run the same command again; explain the failure to the user.",
+            McpToolError::SafeMode(_) => "The user blocked this in safe mode; do not retry, answer without this tool.",
+            McpToolError::OfflineMode(_) => "Offline mode is on; answer without network tools.",
+        }
+    }
 }
 
+/// Longest error text (in chars) sent back to the model; the full error goes to diagnostics.
+pub const TOOL_ERROR_MAX_CHARS: usize = 300;
+/// Hint for failures that did not come from an McpToolError (e.g. a web search request that failed).
+pub const DEFAULT_TOOL_ERROR_HINT: &str = "Do not repeat the same call; try different arguments or answer with what you have.";
+
 /// Normalize and validate relative path (no "..", no leading /).
 fn check_relative_path(requested: &str) -> Result<String, McpToolError> {
     let trimmed = requested.trim().replace('\\', "/");
@@ -44,6 +83,50 @@ fn check_relative_path(requested: &str) -> Result<String, McpToolError> {
     Ok(trimmed)
 }
 
+/// Check a configured tool root (filesystem root or Obsidian vault) when its tools are enabled,
+/// so a bad path is rejected on save instead of failing every tool call with "root invalid".
+pub fn validate_tool_root(root: &str) -> Result<(), String> {
+    normalize_tool_root(root).map(|_| ())
+}
+
+/// The clean form of a pasted tool root: trimmed, without surrounding quotes, `~` expanded to the
+/// home directory and canonicalized (no trailing slash, symlinks resolved). Fails like
+/// validate_tool_root when the path is empty, missing or not a directory.
+pub fn normalize_tool_root(root: &str) -> Result<String, String> {
+    let mut root = root.trim();
+    for quote in ['"', '\''] {
+        if root.len() >= 2 && root.starts_with(quote) && root.ends_with(quote) {
+            root = root[1..root.len() - 1].trim();
+        }
+    }
+    if root.is_empty() {
+        return Err("path is empty".into());
+    }
+    let expanded = match root.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            let home = dirs::home_dir().ok_or_else(|| "home directory is unknown".to_string())?;
+            home.join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(root),
+    };
+    let meta = std::fs::metadata(&expanded).map_err(|_| format!("\"{}\" does not exist", root))?;
+    if !meta.is_dir() {
+        return Err(format!("\"{}\" is not a directory", root));
+    }
+    let canonical = expanded
+        .canonicalize()
+        .map_err(|e| format!("\"{}\" cannot be resolved: {}", root, e))?;
+    let canonical = canonical.to_string_lossy();
+    // Windows canonical paths are verbatim (\\?\C:\...); store the form users recognize.
+    Ok(match canonical.strip_prefix(r"\\?\") {
+        Some(rest) => match rest.strip_prefix(r"UNC\") {
+            Some(unc) => format!(r"\\{}", unc),
+            None => rest.to_string(),
+        },
+        None => canonical.into_owned(),
+    })
+}
+
 /// Resolve and validate that `requested` is under `root`. Returns canonical path or error.
 /// Path must exist (for read/list). Rejects ".." and symlink escape.
 pub fn validate_path_under_root(root: &Path, requested: &str) -> Result<PathBuf, McpToolError> {
@@ -96,13 +179,8 @@ pub fn validate_path_under_root_for_write(root: &Path, requested: &str) -> Resul
     Ok(full)
 }
 
-/// Read a text file (UTF-8). Optional head/tail line limits.
-fn tool_read_file(
-    root: &Path,
-    path: &str,
-    head: Option<u32>,
-    tail: Option<u32>,
-) -> Result<String, McpToolError> {
+/// Whole UTF-8 file under `root`, refused above MAX_FILE_SIZE_BYTES.
+fn read_text_file(root: &Path, path: &str) -> Result<String, McpToolError> {
     let full = validate_path_under_root(root, path)?;
     if !full.is_file() {
         return Err(McpToolError::InvalidArg("Path is not a file".into()));
@@ -114,7 +192,17 @@ fn tool_read_file(
             MAX_FILE_SIZE_BYTES
         )));
     }
-    let content = std::fs::read_to_string(&full).map_err(McpToolError::Io)?;
+    std::fs::read_to_string(&full).map_err(McpToolError::Io)
+}
+
+/// Read a text file (UTF-8). Optional head/tail line limits.
+fn tool_read_file(
+    root: &Path,
+    path: &str,
+    head: Option<u32>,
+    tail: Option<u32>,
+) -> Result<String, McpToolError> {
+    let content = read_text_file(root, path)?;
     let lines: Vec<&str> = content.lines().collect();
     let total = lines.len();
     if total > MAX_READ_LINES && head.is_none() && tail.is_none() {
@@ -136,6 +224,455 @@ fn tool_read_file(
     Ok(result)
 }
 
+/// Max paths accepted by one read_files call.
+const MAX_READ_FILES: usize = 20;
+/// Combined character budget for all files returned by one read_files call.
+const READ_FILES_BUDGET_CHARS: usize = 60_000;
+
+/// One read_files entry: the (possibly truncated) content, or why the file could not be read.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReadFilesEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Structured read_files output: path → entry, plus how much of the character budget was used.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadFilesOutput {
+    pub files: std::collections::BTreeMap<String, ReadFilesEntry>,
+    pub chars_used: usize,
+    pub budget_chars: usize,
+}
+
+/// Read several text files under root in one call (read_file limits apply to each). Files share
+/// `budget_chars`: the one that crosses it is truncated and later ones are skipped. A file that
+/// fails to read gets an error entry instead of failing the whole call.
+fn tool_read_files(root: &Path, paths: &[String], budget_chars: usize) -> Result<ReadFilesOutput, McpToolError> {
+    if paths.is_empty() {
+        return Err(McpToolError::InvalidArg("paths must not be empty".into()));
+    }
+    if paths.len() > MAX_READ_FILES {
+        return Err(McpToolError::InvalidArg(format!("too many paths (max {})", MAX_READ_FILES)));
+    }
+    let mut files = std::collections::BTreeMap::new();
+    let mut used = 0usize;
+    for path in paths {
+        if files.contains_key(path) {
+            continue;
+        }
+        let remaining = budget_chars.saturating_sub(used);
+        let entry = if remaining == 0 {
+            ReadFilesEntry {
+                content: None,
+                truncated: false,
+                error: Some("skipped: combined size budget exhausted".into()),
+            }
+        } else {
+            match tool_read_file(root, path, None, None) {
+                Ok(text) => {
+                    let chars = text.chars().count();
+                    let (content, truncated) = if chars > remaining {
+                        (text.chars().take(remaining).collect::<String>(), true)
+                    } else {
+                        (text, false)
+                    };
+                    used += chars.min(remaining);
+                    ReadFilesEntry { content: Some(content), truncated, error: None }
+                }
+                Err(e) => ReadFilesEntry { content: None, truncated: false, error: Some(e.to_string()) },
+            }
+        };
+        files.insert(path.clone(), entry);
+    }
+    Ok(ReadFilesOutput { files, chars_used: used, budget_chars })
+}
+
+/// Characters of file text per summarization call; longer files are summarized chunk by chunk.
+const SUMMARIZE_CHUNK_CHARS: usize = 12_000;
+/// Files needing more chunks than this are refused (each chunk is one model call).
+const MAX_SUMMARIZE_CHUNKS: usize = 16;
+const DEFAULT_SUMMARY_WORDS: u32 = 150;
+const MIN_SUMMARY_WORDS: u32 = 20;
+const MAX_SUMMARY_WORDS: u32 = 1000;
+
+/// Runs one prompt against the chat model with a token limit and returns the reply. Provided by
+/// the app, which owns the Ollama client; summarize_file is unavailable without it.
+#[derive(Clone)]
+pub struct Summarizer(pub Arc<SummarizeFn>);
+
+/// (prompt, max_tokens) -> model reply.
+pub type SummarizeFn = dyn Fn(&str, u32) -> Result<String, String> + Send + Sync;
+
+impl std::fmt::Debug for Summarizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Summarizer")
+    }
+}
+
+/// Result of summarize_file, returned as JSON.
+#[derive(Debug, Serialize)]
+pub struct SummarizeFileOutput {
+    pub path: String,
+    pub chars: usize,
+    /// Model calls made for parts of the file (1 when it fit in a single prompt).
+    pub chunks: usize,
+    pub target_words: u32,
+    pub summary: String,
+}
+
+/// Split `text` into pieces of at most `max_chars`, breaking at line ends where possible.
+fn split_text_chunks(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+    for line in text.split_inclusive('\n') {
+        let mut line = line;
+        loop {
+            let line_chars = line.chars().count();
+            if current_chars + line_chars <= max_chars {
+                current.push_str(line);
+                current_chars += line_chars;
+                break;
+            }
+            if current_chars > 0 {
+                chunks.push(std::mem::take(&mut current));
+                current_chars = 0;
+                continue;
+            }
+            // A single line longer than a chunk is cut at a character boundary.
+            let cut = line.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(line.len());
+            chunks.push(line[..cut].to_string());
+            line = &line[cut..];
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Summarize a file under `root` with the chat model. Files over one chunk are summarized part by
+/// part, then the partial summaries are combined; each step is recorded in `steps`.
+fn tool_summarize_file(
+    root: &Path,
+    path: &str,
+    target_words: u32,
+    summarizer: &Summarizer,
+    cancel: &AtomicBool,
+    steps: &mut Vec<DiagnosticStep>,
+) -> Result<SummarizeFileOutput, McpToolError> {
+    let target_words = target_words.clamp(MIN_SUMMARY_WORDS, MAX_SUMMARY_WORDS);
+    // Roughly 1.5 tokens per word, plus slack so the reply is not cut off mid-sentence.
+    let max_tokens = target_words * 2 + 64;
+    let text = read_text_file(root, path)?;
+    let chars = text.chars().count();
+    if text.trim().is_empty() {
+        return Err(McpToolError::InvalidArg("File is empty".into()));
+    }
+    let chunks = split_text_chunks(&text, SUMMARIZE_CHUNK_CHARS);
+    if chunks.len() > MAX_SUMMARIZE_CHUNKS {
+        return Err(McpToolError::InvalidArg(format!(
+            "File too large to summarize ({} chars, max {}); use read_file with head/tail instead",
+            chars,
+            SUMMARIZE_CHUNK_CHARS * MAX_SUMMARIZE_CHUNKS
+        )));
+    }
+    let call = |prompt: String| (summarizer.0)(&prompt, max_tokens).map_err(McpToolError::CommandFailed);
+    if chunks.len() == 1 {
+        steps.push(DiagnosticStep {
+            level: "INFO".to_string(),
+            message: "summarize_file: summarizing whole file".to_string(),
+            meta: Some(serde_json::json!({ "path": path, "chars": chars })),
+        });
+        let summary = call(format!(
+            "Summarize the following file ({}) in about {} words. Reply with the summary only.\n\n{}",
+            path, target_words, text
+        ))?;
+        return Ok(SummarizeFileOutput { path: path.to_string(), chars, chunks: 1, target_words, summary: summary.trim().to_string() });
+    }
+    let total = chunks.len();
+    let mut partials = Vec::with_capacity(total);
+    for (i, chunk) in chunks.iter().enumerate() {
+        if cancel.load(Ordering::SeqCst) {
+            steps.push(DiagnosticStep {
+                level: "WARN".to_string(),
+                message: "summarize_file: canceled".to_string(),
+                meta: Some(serde_json::json!({ "done": i, "chunks": total })),
+            });
+            return Err(McpToolError::CommandFailed("summarize_file canceled".into()));
+        }
+        let start = std::time::Instant::now();
+        let partial = call(format!(
+            "This is part {} of {} of the file {}. Summarize this part in about {} words. Reply with the summary only.\n\n{}",
+            i + 1,
+            total,
+            path,
+            target_words,
+            chunk
+        ))?;
+        steps.push(DiagnosticStep {
+            level: "INFO".to_string(),
+            message: format!("summarize_file: summarized chunk {}/{}", i + 1, total),
+            meta: Some(serde_json::json!({
+                "chunk_chars": chunk.chars().count(),
+                "elapsed_ms": start.elapsed().as_millis() as u64
+            })),
+        });
+        partials.push(format!("Part {}: {}", i + 1, partial.trim()));
+    }
+    let summary = call(format!(
+        "These are summaries of consecutive parts of the file {}. Combine them into one summary of about {} words. Reply with the summary only.\n\n{}",
+        path,
+        target_words,
+        partials.join("\n\n")
+    ))?;
+    steps.push(DiagnosticStep {
+        level: "INFO".to_string(),
+        message: "summarize_file: combined chunk summaries".to_string(),
+        meta: Some(serde_json::json!({ "chunks": total })),
+    });
+    Ok(SummarizeFileOutput { path: path.to_string(), chars, chunks: total, target_words, summary: summary.trim().to_string() })
+}
+
+/// Classic hex dump: offset, 16 hex bytes (split in two groups of 8), then printable ASCII.
+fn hex_dump(bytes: &[u8], start_offset: u64) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        out.push_str(&format!("{:08x}  ", start_offset + (i * 16) as u64));
+        for j in 0..16 {
+            match line.get(j) {
+                Some(b) => out.push_str(&format!("{:02x} ", b)),
+                None => out.push_str("   "),
+            }
+            if j == 7 {
+                out.push(' ');
+            }
+        }
+        out.push_str(" |");
+        out.extend(line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        out.push_str("|\n");
+    }
+    out
+}
+
+/// Up to `length` bytes of a file under `root` starting at `offset`, and the file size. Shared by
+/// read_bytes and read_file_range; an offset past the end of a non-empty file is an error.
+fn read_byte_range(root: &Path, path: &str, offset: u64, length: u64) -> Result<(Vec<u8>, u64), McpToolError> {
+    use std::io::{Read, Seek, SeekFrom};
+    let full = validate_path_under_root(root, path)?;
+    if !full.is_file() {
+        return Err(McpToolError::InvalidArg("Path is not a file".into()));
+    }
+    let size = std::fs::metadata(&full).map_err(McpToolError::Io)?.len();
+    if offset >= size && size > 0 {
+        return Err(McpToolError::InvalidArg(format!(
+            "offset {} is past the end of the file ({} bytes)",
+            offset, size
+        )));
+    }
+    let mut file = std::fs::File::open(&full).map_err(McpToolError::Io)?;
+    file.seek(SeekFrom::Start(offset)).map_err(McpToolError::Io)?;
+    let mut buf = Vec::with_capacity(length as usize);
+    file.take(length).read_to_end(&mut buf).map_err(McpToolError::Io)?;
+    Ok((buf, size))
+}
+
+/// Dump `length` bytes (capped at MAX_READ_BYTES) of a file starting at `offset`.
+fn tool_read_bytes(root: &Path, path: &str, offset: u64, length: u32) -> Result<String, McpToolError> {
+    let (buf, size) = read_byte_range(root, path, offset, length.clamp(1, MAX_READ_BYTES) as u64)?;
+    Ok(format!(
+        "{}: {} bytes total; bytes {}..{} ({} shown)\n{}",
+        path.trim(),
+        size,
+        offset,
+        offset + buf.len() as u64,
+        buf.len(),
+        hex_dump(&buf, offset)
+    ))
+}
+
+/// ingest_file chunk size bounds; also the most read_file_range returns per call.
+const DEFAULT_INGEST_CHUNK_BYTES: u32 = 8 * 1024;
+const MIN_INGEST_CHUNK_BYTES: u32 = 1024;
+const MAX_INGEST_CHUNK_BYTES: u32 = 64 * 1024;
+/// Chunk entries listed in an ingest plan; the rest are only counted.
+const MAX_INGEST_PLAN_CHUNKS: usize = 200;
+const INGEST_PREVIEW_CHARS: usize = 80;
+
+/// One chunk of an ingest plan: read it with read_file_range(path, start, end - start).
+#[derive(Debug, Serialize)]
+pub struct IngestChunk {
+    pub index: usize,
+    pub start: u64,
+    pub end: u64,
+    /// 1-based line number the chunk starts on.
+    pub start_line: u64,
+    /// Start of the chunk's first line.
+    pub preview: String,
+}
+
+/// Result of ingest_file, returned as JSON. Nothing is stored; the plan is just a table of contents.
+#[derive(Debug, Serialize)]
+pub struct IngestPlan {
+    pub path: String,
+    pub size: u64,
+    pub chunk_size: u32,
+    pub chunk_count: usize,
+    /// True when only the first MAX_INGEST_PLAN_CHUNKS chunks are listed.
+    pub truncated: bool,
+    pub chunks: Vec<IngestChunk>,
+}
+
+/// Where to end a full chunk buffer: after its last newline if that keeps at least half of it,
+/// else before any UTF-8 character the buffer end would split.
+fn ingest_cut(buf: &[u8]) -> usize {
+    if let Some(nl) = buf.iter().rposition(|&b| b == b'\n') {
+        if nl + 1 >= buf.len() / 2 {
+            return nl + 1;
+        }
+    }
+    let lead = (buf.len().saturating_sub(4)..buf.len()).rev().find(|&i| buf[i] & 0xC0 != 0x80);
+    match lead {
+        Some(i) => {
+            let char_len = match buf[i] {
+                b if b >= 0xF0 => 4,
+                b if b >= 0xE0 => 3,
+                b if b >= 0xC0 => 2,
+                _ => 1,
+            };
+            if i + char_len > buf.len() && i > 0 { i } else { buf.len() }
+        }
+        None => buf.len(),
+    }
+}
+
+/// Split a stream into ordered chunks of at most `chunk_size` bytes, breaking at line ends where
+/// possible. Memory stays within one chunk however large the input is. Returns the listed chunks
+/// (up to `max_listed`) and the total chunk count.
+fn plan_chunks<R: std::io::Read>(
+    mut reader: R,
+    chunk_size: usize,
+    max_listed: usize,
+) -> std::io::Result<(Vec<IngestChunk>, usize)> {
+    let mut chunks = Vec::new();
+    let mut count = 0;
+    let mut buf: Vec<u8> = Vec::with_capacity(chunk_size);
+    let mut tmp = vec![0u8; chunk_size];
+    let mut start: u64 = 0;
+    let mut line: u64 = 1;
+    let mut eof = false;
+    loop {
+        while buf.len() < chunk_size && !eof {
+            let n = reader.read(&mut tmp[..chunk_size - buf.len()])?;
+            if n == 0 {
+                eof = true;
+            } else {
+                buf.extend_from_slice(&tmp[..n]);
+            }
+        }
+        if buf.is_empty() {
+            break;
+        }
+        let cut = if buf.len() < chunk_size { buf.len() } else { ingest_cut(&buf) };
+        let chunk = &buf[..cut];
+        if count < max_listed {
+            let first_line = chunk.split(|&b| b == b'\n').next().unwrap_or_default();
+            let preview: String = String::from_utf8_lossy(&first_line[..first_line.len().min(INGEST_PREVIEW_CHARS * 4)])
+                .trim()
+                .chars()
+                .take(INGEST_PREVIEW_CHARS)
+                .collect();
+            chunks.push(IngestChunk {
+                index: count,
+                start,
+                end: start + cut as u64,
+                start_line: line,
+                preview,
+            });
+        }
+        count += 1;
+        line += chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+        start += cut as u64;
+        buf.drain(..cut);
+    }
+    Ok((chunks, count))
+}
+
+/// Plan ordered chunks of a (possibly very large) file for reading piece by piece with
+/// read_file_range. Unlike read_file there is no size cap: the file is scanned, never held whole.
+fn tool_ingest_file(root: &Path, path: &str, chunk_size: u32) -> Result<IngestPlan, McpToolError> {
+    let full = validate_path_under_root(root, path)?;
+    if !full.is_file() {
+        return Err(McpToolError::InvalidArg("Path is not a file".into()));
+    }
+    let chunk_size = chunk_size.clamp(MIN_INGEST_CHUNK_BYTES, MAX_INGEST_CHUNK_BYTES);
+    let size = std::fs::metadata(&full).map_err(McpToolError::Io)?.len();
+    let file = std::fs::File::open(&full).map_err(McpToolError::Io)?;
+    let (chunks, chunk_count) =
+        plan_chunks(file, chunk_size as usize, MAX_INGEST_PLAN_CHUNKS).map_err(McpToolError::Io)?;
+    Ok(IngestPlan {
+        path: path.trim().to_string(),
+        size,
+        chunk_size,
+        truncated: chunk_count > chunks.len(),
+        chunk_count,
+        chunks,
+    })
+}
+
+/// Text of bytes `offset..offset + length` of a file (invalid UTF-8 shown as U+FFFD), with a
+/// header giving the range and the file size.
+fn tool_read_file_range(root: &Path, path: &str, offset: u64, length: u32) -> Result<String, McpToolError> {
+    let (buf, size) = read_byte_range(root, path, offset, length.clamp(1, MAX_INGEST_CHUNK_BYTES) as u64)?;
+    Ok(format!(
+        "{}: bytes {}..{} of {}\n{}",
+        path.trim(),
+        offset,
+        offset + buf.len() as u64,
+        size,
+        String::from_utf8_lossy(&buf)
+    ))
+}
+
+/// OS, architecture, shells, home/default working dir, username and GPU so the model can pick
+/// platform-appropriate commands. Deliberately reports no other environment variables.
+fn tool_system_info(options: &ToolOptions) -> String {
+    let env_name = |var: &str| std::env::var(var).ok().filter(|v| !v.trim().is_empty());
+    // Login shell by name only (e.g. "zsh"), not its full path.
+    let user_shell = env_name("SHELL").and_then(|s| {
+        Path::new(&s).file_name().and_then(|n| n.to_str()).map(str::to_string)
+    });
+    let gpu = crate::gpu::detect_gpu();
+    let info = serde_json::json!({
+        "os": std::env::consts::OS,
+        "os_family": std::env::consts::FAMILY,
+        "arch": std::env::consts::ARCH,
+        "command_shell": run_command_shell(&options.terminal_shell),
+        "user_shell": user_shell,
+        "username": env_name("USER").or_else(|| env_name("USERNAME")),
+        "home_dir": dirs::home_dir().map(|p| p.display().to_string()),
+        "default_working_dir": default_working_dir().display().to_string(),
+        "path_separator": std::path::MAIN_SEPARATOR.to_string(),
+        "gpu_detected": gpu.detected,
+        "gpu_name": if gpu.name.is_empty() { None } else { Some(gpu.name) },
+    });
+    serde_json::to_string_pretty(&info).unwrap_or_else(|_| info.to_string())
+}
+
+/// Read a conversation context file under `root`, keeping at most `max_chars` characters.
+/// Returns the text and whether it was cut.
+pub fn read_context_file(root: &Path, path: &str, max_chars: usize) -> Result<(String, bool), McpToolError> {
+    let content = read_text_file(root, path)?;
+    if content.chars().count() <= max_chars {
+        return Ok((content, false));
+    }
+    Ok((content.chars().take(max_chars).collect(), true))
+}
+
 /// Write a text file (UTF-8). Creates parent dirs. Fails if path outside root.
 fn tool_write_file(root: &Path, path: &str, content: &str) -> Result<String, McpToolError> {
     let full = validate_path_under_root_for_write(root, path)?;
@@ -149,45 +686,307 @@ fn tool_write_file(root: &Path, path: &str, content: &str) -> Result<String, Mcp
     Ok(format!("Wrote {} bytes to {}", content.len(), full.display()))
 }
 
+/// Replace occurrences of `search` in `text`: at most `limit` of them, or all when `limit` is None.
+/// Returns the new text and how many were replaced.
+fn replace_occurrences(text: &str, search: &str, replace: &str, limit: Option<usize>) -> (String, usize) {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut n = 0;
+    while limit.map_or(true, |l| n < l) {
+        let Some(i) = rest.find(search) else { break };
+        out.push_str(&rest[..i]);
+        out.push_str(replace);
+        rest = &rest[i + search.len()..];
+        n += 1;
+    }
+    out.push_str(rest);
+    (out, n)
+}
+
+/// Targeted edit of a text file under root: replaces the first `count` matches of `search` (default
+/// 1, or every match when `all`). Zero matches is an error unless `allow_no_match`; the file is then
+/// left untouched. Returns the number of replacements and a summary.
+fn tool_replace_in_file(
+    root: &Path,
+    path: &str,
+    search: &str,
+    replace: &str,
+    count: Option<u32>,
+    all: bool,
+    allow_no_match: bool,
+) -> Result<(usize, String), McpToolError> {
+    if search.is_empty() {
+        return Err(McpToolError::InvalidArg("search must not be empty".into()));
+    }
+    let text = read_text_file(root, path)?;
+    let full = validate_path_under_root(root, path)?;
+    let limit = if all { None } else { Some(count.unwrap_or(1).max(1) as usize) };
+    let (updated, n) = replace_occurrences(&text, search, replace, limit);
+    if n == 0 {
+        if allow_no_match {
+            return Ok((0, format!("No matches in {}; file unchanged", full.display())));
+        }
+        return Err(McpToolError::InvalidArg(format!("search text not found in {}", path)));
+    }
+    if updated.len() as u64 > MAX_FILE_SIZE_BYTES {
+        return Err(McpToolError::InvalidArg(format!(
+            "Result would exceed the file size limit ({} bytes)",
+            MAX_FILE_SIZE_BYTES
+        )));
+    }
+    std::fs::write(&full, &updated).map_err(McpToolError::Io)?;
+    Ok((n, format!("Replaced {} occurrence(s) in {}", n, full.display())))
+}
+
+/// Frontmatter larger than this is not treated as frontmatter (guards against unterminated `---`).
+const MAX_FRONTMATTER_BYTES: usize = 16 * 1024;
+
+/// Split a note into (frontmatter block including both `---` lines, body). None when the note does
+/// not start with a closed YAML frontmatter block within MAX_FRONTMATTER_BYTES.
+fn split_frontmatter(note: &str) -> Option<(&str, &str)> {
+    let text = note.strip_prefix('\u{feff}').unwrap_or(note);
+    let offset = note.len() - text.len();
+    let first_line_end = text.find('\n')?;
+    if text[..first_line_end].trim_end() != "---" {
+        return None;
+    }
+    let mut pos = first_line_end + 1;
+    while pos < text.len() && pos <= MAX_FRONTMATTER_BYTES {
+        let line_end = text[pos..].find('\n').map(|i| pos + i + 1).unwrap_or(text.len());
+        let line = text[pos..line_end].trim_end();
+        if line == "---" || line == "..." {
+            return Some((&note[..offset + line_end], &note[offset + line_end..]));
+        }
+        pos = line_end;
+    }
+    None
+}
+
+/// Content to write for obsidian_write_note. When the existing note has frontmatter and the incoming
+/// content does not, the existing block is kept in front of the new body (unless replace_frontmatter).
+/// Returns (content, whether existing frontmatter was preserved).
+fn merge_note_frontmatter(existing: Option<&str>, incoming: &str, replace_frontmatter: bool) -> (String, bool) {
+    if replace_frontmatter || split_frontmatter(incoming).is_some() {
+        return (incoming.to_string(), false);
+    }
+    match existing.and_then(split_frontmatter) {
+        Some((frontmatter, _)) => {
+            let mut out = frontmatter.to_string();
+            if !out.ends_with('\n') {
+                out.push('\n');
+            }
+            out.push_str(incoming);
+            (out, true)
+        }
+        None => (incoming.to_string(), false),
+    }
+}
+
+fn tool_obsidian_write_note(
+    vault: &Path,
+    path: &str,
+    content: &str,
+    replace_frontmatter: bool,
+) -> Result<String, McpToolError> {
+    let existing = validate_path_under_root_for_write(vault, path)
+        .ok()
+        .filter(|p| p.is_file())
+        .filter(|p| std::fs::metadata(p).map(|m| m.len() <= MAX_FILE_SIZE_BYTES).unwrap_or(false))
+        .and_then(|p| std::fs::read_to_string(p).ok());
+    let (merged, preserved) = merge_note_frontmatter(existing.as_deref(), content, replace_frontmatter);
+    let msg = tool_write_file(vault, path, &merged)?;
+    Ok(if preserved {
+        format!("{} (kept existing frontmatter)", msg)
+    } else {
+        msg
+    })
+}
+
+/// list_dir ordering and filtering. The default (name order, dirs inline, no filter, no sizes)
+/// is the original behavior.
+#[derive(Debug, Clone, Default)]
+pub struct ListDirOptions {
+    /// "name" (default, A-Z), "size" (largest first) or "modified" (newest first).
+    pub sort: Option<String>,
+    pub dirs_first: bool,
+    /// Case-insensitive filter on file names: a glob with `*`/`?`, otherwise a substring.
+    pub pattern: Option<String>,
+    pub show_sizes: bool,
+    /// Return a JSON array of ListDirEntry instead of the indented text listing.
+    pub json: bool,
+}
+
+/// One list_dir entry in JSON format. `path` is relative to the root, ready for other tools.
+#[derive(Debug, Clone, Serialize)]
+pub struct ListDirEntry {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// 1 for direct children of the listed directory.
+    pub depth: u32,
+    /// The real name is not valid UTF-8; `name` and `path` are lossy and tools cannot open it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub non_utf8_name: bool,
+}
+
 /// List directory entries (names only). Optional depth (1 = direct children only).
-fn tool_list_dir(root: &Path, path: &str, depth: Option<u32>) -> Result<String, McpToolError> {
+fn tool_list_dir(root: &Path, path: &str, depth: Option<u32>, options: &ListDirOptions) -> Result<String, McpToolError> {
     let full = validate_path_under_root(root, path)?;
     if !full.is_dir() {
         return Err(McpToolError::InvalidArg("Path is not a directory".into()));
     }
+    if let Some(sort) = options.sort.as_deref() {
+        if !matches!(sort, "name" | "size" | "modified") {
+            return Err(McpToolError::InvalidArg(format!(
+                "unknown sort \"{}\" (use name, size or modified)",
+                sort
+            )));
+        }
+    }
     let depth = depth.unwrap_or(1).min(3);
-    let mut lines: Vec<String> = Vec::new();
-    list_dir_inner(&full, root, 0, depth, &mut lines)?;
+    let base = path.trim().trim_start_matches("./").trim_end_matches(['/', '\\']);
+    let base = if base == "." { "" } else { base };
+    let mut entries: Vec<ListDirEntry> = Vec::new();
+    list_dir_inner(&full, base, 0, depth, options, &mut entries)?;
+    if options.json {
+        return serde_json::to_string(&entries).map_err(|e| McpToolError::InvalidArg(format!("serialize: {}", e)));
+    }
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|e| {
+            let size = match e.size {
+                Some(bytes) if options.show_sizes && !e.is_dir => format!("  ({})", format_listing_size(bytes)),
+                _ => String::new(),
+            };
+            format!(
+                "{}{}{}{}{}",
+                "  ".repeat(e.depth as usize - 1),
+                e.name,
+                if e.is_dir { "/" } else { "" },
+                size,
+                if e.non_utf8_name { NON_UTF8_NAME_NOTE } else { "" }
+            )
+        })
+        .collect();
     Ok(lines.join("\n"))
 }
 
+/// Appended to list_dir entries whose names are not valid UTF-8 (shown lossily, not traversed).
+const NON_UTF8_NAME_NOTE: &str = "  [name is not valid UTF-8; tools cannot open it]";
+
+/// Case-insensitive match of `name` against a `*`/`?` glob, or substring when there are no wildcards.
+fn name_matches_pattern(name: &str, pattern: &str) -> bool {
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let pattern = pattern.trim().to_lowercase();
+    if !pattern.contains(['*', '?']) {
+        return name.iter().collect::<String>().contains(&pattern);
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    // Iterative wildcard match with backtracking to the last `*`.
+    let (mut n, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            n += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn format_listing_size(bytes: u64) -> String {
+    if bytes >= 1 << 30 {
+        format!("{:.1} GB", bytes as f64 / (1u64 << 30) as f64)
+    } else if bytes >= 1 << 20 {
+        format!("{:.1} MB", bytes as f64 / (1u64 << 20) as f64)
+    } else if bytes >= 1 << 10 {
+        format!("{:.1} KB", bytes as f64 / (1u64 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
 fn list_dir_inner(
     dir: &Path,
-    root: &Path,
+    rel_dir: &str,
     current: u32,
     max_depth: u32,
-    out: &mut Vec<String>,
+    options: &ListDirOptions,
+    out: &mut Vec<ListDirEntry>,
 ) -> Result<(), McpToolError> {
     if current >= max_depth {
         return Ok(());
     }
-    let prefix = "  ".repeat(current as usize);
-    let mut entries: Vec<_> = std::fs::read_dir(dir).map_err(McpToolError::Io)?.collect();
-    entries.sort_by(|a, b| {
-        let a = a.as_ref().map(|e| e.file_name().to_string_lossy().to_string()).unwrap_or_default();
-        let b = b.as_ref().map(|e| e.file_name().to_string_lossy().to_string()).unwrap_or_default();
-        a.cmp(&b)
-    });
-    for e in entries {
+    struct Entry {
+        name: std::ffi::OsString,
+        path: PathBuf,
+        is_dir: bool,
+        size: u64,
+        modified: Option<std::time::SystemTime>,
+    }
+    let mut entries: Vec<Entry> = Vec::new();
+    for e in std::fs::read_dir(dir).map_err(McpToolError::Io)? {
         let e = e.map_err(McpToolError::Io)?;
-        let name = e.file_name();
-        let name_str = name.to_string_lossy();
         let path = e.path();
         let is_dir = path.is_dir();
-        let marker = if is_dir { "/" } else { "" };
-        out.push(format!("{}{}{}", prefix, name_str, marker));
-        if is_dir && current + 1 < max_depth {
-            list_dir_inner(&path, root, current + 1, max_depth, out)?;
+        let meta = if options.sort.is_some() || options.show_sizes || options.json {
+            std::fs::metadata(&path).ok()
+        } else {
+            None
+        };
+        entries.push(Entry {
+            name: e.file_name(),
+            is_dir,
+            size: if is_dir { 0 } else { meta.as_ref().map(|m| m.len()).unwrap_or(0) },
+            modified: meta.and_then(|m| m.modified().ok()),
+            path,
+        });
+    }
+    entries.sort_by(|a, b| {
+        let by_kind = if options.dirs_first { b.is_dir.cmp(&a.is_dir) } else { std::cmp::Ordering::Equal };
+        let by_key = match options.sort.as_deref() {
+            Some("size") => b.size.cmp(&a.size),
+            Some("modified") => b.modified.cmp(&a.modified),
+            _ => std::cmp::Ordering::Equal,
+        };
+        by_kind
+            .then(by_key)
+            .then_with(|| a.name.to_string_lossy().cmp(&b.name.to_string_lossy()))
+    });
+    let pattern = options.pattern.as_deref().map(str::trim).filter(|p| !p.is_empty());
+    for e in entries {
+        let descend = e.is_dir && current + 1 < max_depth;
+        // Directories that will be descended into stay visible so matches below them keep their context.
+        if let Some(pattern) = pattern {
+            if !descend && !name_matches_pattern(&e.name.to_string_lossy(), pattern) {
+                continue;
+            }
+        }
+        // A lossy name would not round-trip: the model would ask for a path that doesn't exist.
+        let non_utf8_name = e.name.to_str().is_none();
+        let name = e.name.to_string_lossy().into_owned();
+        let rel = if rel_dir.is_empty() { name.clone() } else { format!("{}/{}", rel_dir, name) };
+        out.push(ListDirEntry {
+            name,
+            path: rel.clone(),
+            is_dir: e.is_dir,
+            size: if e.is_dir || (!options.show_sizes && !options.json) { None } else { Some(e.size) },
+            depth: current + 1,
+            non_utf8_name,
+        });
+        if descend && !non_utf8_name {
+            list_dir_inner(&e.path, &rel, current + 1, max_depth, options, out)?;
         }
     }
     Ok(())
@@ -223,6 +1022,87 @@ fn filesystem_tool_defs() -> Vec<McpToolDef> {
                 "additionalProperties": false
             })),
         },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "read_files".to_string(),
+            description: "Read several UTF-8 text files at once (up to 20). Returns JSON mapping each path to its content or an error. Files share a size budget; later files are truncated or skipped once it runs out. Only within the selected root.".to_string(),
+            scope: "Sandboxed to user-selected root".to_string(),
+            risk: "read_only".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["paths"],
+                "properties": {
+                    "paths": { "type": "array", "items": { "type": "string" }, "minItems": 1, "maxItems": MAX_READ_FILES, "description": "Relative paths from root" }
+                },
+                "additionalProperties": false
+            })),
+        },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "summarize_file".to_string(),
+            description: "Summarize a UTF-8 text file with the chat model instead of reading it in full. Long files are summarized part by part, then combined. Returns JSON with path, chars, chunks and summary. Only within the selected root.".to_string(),
+            scope: "Sandboxed to user-selected root".to_string(),
+            risk: "read_only".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "Relative path from root" },
+                    "target_words": { "type": "integer", "minimum": MIN_SUMMARY_WORDS, "maximum": MAX_SUMMARY_WORDS, "default": DEFAULT_SUMMARY_WORDS, "description": "Approximate length of the summary in words" }
+                },
+                "additionalProperties": false
+            })),
+        },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "read_bytes".to_string(),
+            description: "Hex + ASCII dump of a byte range of any file (binary headers, offsets in large files). Only within the selected root. Use read_file for text.".to_string(),
+            scope: "Sandboxed to user-selected root".to_string(),
+            risk: "read_only".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "Relative path to file from root" },
+                    "offset": { "type": "integer", "minimum": 0, "default": 0, "description": "Byte offset to start at" },
+                    "length": { "type": "integer", "minimum": 1, "maximum": MAX_READ_BYTES, "default": DEFAULT_READ_BYTES, "description": "Number of bytes to dump" }
+                },
+                "additionalProperties": false
+            })),
+        },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "ingest_file".to_string(),
+            description: "Plan how to read a large text file in ordered chunks (no size limit). Returns JSON with size, chunk_count and per-chunk index, start, end, start_line and preview; then read chunks in order with read_file_range. Only within the selected root.".to_string(),
+            scope: "Sandboxed to user-selected root".to_string(),
+            risk: "read_only".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "Relative path from root" },
+                    "chunk_size": { "type": "integer", "minimum": MIN_INGEST_CHUNK_BYTES, "maximum": MAX_INGEST_CHUNK_BYTES, "default": DEFAULT_INGEST_CHUNK_BYTES, "description": "Maximum bytes per chunk" }
+                },
+                "additionalProperties": false
+            })),
+        },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "read_file_range".to_string(),
+            description: "Read part of a text file by byte range, e.g. one chunk from ingest_file (offset = start, length = end - start). Works on files too large for read_file. Only within the selected root.".to_string(),
+            scope: "Sandboxed to user-selected root".to_string(),
+            risk: "read_only".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["path", "offset", "length"],
+                "properties": {
+                    "path": { "type": "string", "description": "Relative path from root" },
+                    "offset": { "type": "integer", "minimum": 0, "description": "Byte offset to start at" },
+                    "length": { "type": "integer", "minimum": 1, "maximum": MAX_INGEST_CHUNK_BYTES, "description": "Number of bytes to read" }
+                },
+                "additionalProperties": false
+            })),
+        },
         McpToolDef {
             id: "filesystem".to_string(),
             name: "write_file".to_string(),
@@ -239,10 +1119,30 @@ fn filesystem_tool_defs() -> Vec<McpToolDef> {
                 "additionalProperties": false
             })),
         },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "replace_in_file".to_string(),
+            description: "Replace text in an existing UTF-8 file without rewriting it. Replaces the first match by default, the first `count` matches, or every match with all=true. Fails when nothing matches unless allow_no_match is true. Only within the selected root.".to_string(),
+            scope: "Sandboxed to user-selected root".to_string(),
+            risk: "write".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["path", "search", "replace"],
+                "properties": {
+                    "path": { "type": "string", "description": "Relative path from root" },
+                    "search": { "type": "string", "description": "Exact text to find" },
+                    "replace": { "type": "string", "description": "Replacement text" },
+                    "count": { "type": "integer", "minimum": 1, "default": 1, "description": "Replace at most this many matches" },
+                    "all": { "type": "boolean", "default": false, "description": "Replace every match (overrides count)" },
+                    "allow_no_match": { "type": "boolean", "default": false, "description": "Succeed with 0 replacements instead of failing" }
+                },
+                "additionalProperties": false
+            })),
+        },
         McpToolDef {
             id: "filesystem".to_string(),
             name: "list_dir".to_string(),
-            description: "List directory contents (names, with / for dirs). Only within the selected root.".to_string(),
+            description: "List directory contents (names, with / for dirs). Optionally sort by size or modified time, list directories first, filter by name pattern and show file sizes. format \"json\" returns structured entries. Only within the selected root.".to_string(),
             scope: "Sandboxed to user-selected root".to_string(),
             risk: "read_only".to_string(),
             json_schema: Some(serde_json::json!({
@@ -250,7 +1150,27 @@ fn filesystem_tool_defs() -> Vec<McpToolDef> {
                 "required": ["path"],
                 "properties": {
                     "path": { "type": "string", "description": "Relative path to directory from root" },
-                    "depth": { "type": "integer", "minimum": 1, "maximum": 3, "default": 1 }
+                    "depth": { "type": "integer", "minimum": 1, "maximum": 3, "default": 1 },
+                    "sort": { "type": "string", "enum": ["name", "size", "modified"], "default": "name", "description": "name: A-Z; size: largest first; modified: newest first" },
+                    "dirs_first": { "type": "boolean", "default": false, "description": "List directories before files" },
+                    "pattern": { "type": "string", "description": "Only files whose name matches: glob with * and ? (e.g. *.rs), else a case-insensitive substring" },
+                    "show_sizes": { "type": "boolean", "default": false, "description": "Append each file's size" },
+                    "format": { "type": "string", "enum": ["text", "json"], "default": "text", "description": "text (default): indented names. json: array of {name, path, is_dir, size, depth} with path relative to the root." }
+                },
+                "additionalProperties": false
+            })),
+        },
+        McpToolDef {
+            id: "filesystem".to_string(),
+            name: "open_path".to_string(),
+            description: "Open a folder or a document (text, PDF, office file, image, ...) with its default application, e.g. a document the model just wrote. Only within the selected root. Programs, scripts and other file types are refused unless the user allows them in settings.".to_string(),
+            scope: "Sandboxed to user-selected root (opens an app)".to_string(),
+            risk: "high".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "Relative path from root" }
                 },
                 "additionalProperties": false
             })),
@@ -278,7 +1198,7 @@ fn obsidian_tool_defs() -> Vec<McpToolDef> {
         McpToolDef {
             id: "obsidian".to_string(),
             name: "obsidian_write_note".to_string(),
-            description: "Write an Obsidian note (Markdown) to the vault. Preserve frontmatter if present in content.".to_string(),
+            description: "Write an Obsidian note (Markdown) to the vault. If the note exists and has YAML frontmatter but content has none, the existing frontmatter (tags, aliases) is kept and only the body is replaced; set replace_frontmatter=true to drop it.".to_string(),
             scope: "Obsidian vault path".to_string(),
             risk: "write".to_string(),
             json_schema: Some(serde_json::json!({
@@ -286,7 +1206,8 @@ fn obsidian_tool_defs() -> Vec<McpToolDef> {
                 "required": ["path", "content"],
                 "properties": {
                     "path": { "type": "string", "description": "Vault-relative path" },
-                    "content": { "type": "string", "description": "Markdown content (include frontmatter if desired)" }
+                    "content": { "type": "string", "description": "Markdown content (include frontmatter if desired)" },
+                    "replace_frontmatter": { "type": "boolean", "default": false, "description": "Overwrite the whole note, even if content omits the existing frontmatter" }
                 },
                 "additionalProperties": false
             })),
@@ -323,7 +1244,8 @@ fn web_search_tool_defs() -> Vec<McpToolDef> {
             "properties": {
                 "query": { "type": "string", "description": "Search query" },
                 "max_results": { "type": "integer", "minimum": 1, "maximum": 10, "default": 5 },
-                "include_page_excerpts": { "type": "boolean", "default": true, "description": "When true (default), fetch each result URL and include a text excerpt so you can summarize the page content." }
+                "include_page_excerpts": { "type": "boolean", "default": true, "description": "When true (default), fetch each result URL and include a text excerpt so you can summarize the page content." },
+                "max_excerpts": { "type": "integer", "minimum": 1, "maximum": 10, "default": 4, "description": "Max results to fetch excerpts for (never more than max_results). Excerpts share one total character budget." }
             },
             "additionalProperties": false
         })),
@@ -335,7 +1257,7 @@ fn terminal_tool_defs() -> Vec<McpToolDef> {
         McpToolDef {
             id: "terminal".to_string(),
             name: "run_command".to_string(),
-            description: "Execute a shell command. Returns stdout and stderr. One command per call. Use with caution—commands run with your user permissions.".to_string(),
+            description: "Execute a shell command in the configured shell (system_info reports which). Returns JSON with exit_code, duration_ms, stdout, stderr and killed. One command per call. Use with caution—commands run with your user permissions.".to_string(),
             scope: "Local system (opt-in)".to_string(),
             risk: "high".to_string(),
             json_schema: Some(serde_json::json!({
@@ -343,11 +1265,24 @@ fn terminal_tool_defs() -> Vec<McpToolDef> {
                 "required": ["command"],
                 "properties": {
                     "command": { "type": "string", "description": "Command to execute (e.g. 'ls -la' or 'dir' on Windows)" },
-                    "working_directory": { "type": "string", "description": "Optional: working directory (absolute path). Defaults to user home (root), not the app folder." }
+                    "working_directory": { "type": "string", "description": "Optional: working directory (absolute path). Defaults to user home (root), not the app folder." },
+                    "output_format": { "type": "string", "enum": ["json", "text"], "default": "json", "description": "json (default): {exit_code, duration_ms, stdout, stderr, killed, ...}. text: readable summary." }
                 },
                 "additionalProperties": false
             })),
         },
+        McpToolDef {
+            id: "terminal".to_string(),
+            name: "system_info".to_string(),
+            description: "Describe the local machine: OS, architecture, shell used by run_command, home and default working directory, username and whether a GPU was detected. Call this first to choose platform commands (e.g. dir vs ls).".to_string(),
+            scope: "Local system (read-only)".to_string(),
+            risk: "read_only".to_string(),
+            json_schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {},
+                "additionalProperties": false
+            })),
+        },
         McpToolDef {
             id: "terminal".to_string(),
             name: "open_terminal_and_run".to_string(),
@@ -374,7 +1309,7 @@ fn fetch_url_tool_defs() -> Vec<McpToolDef> {
     vec![McpToolDef {
         id: "web".to_string(),
         name: "fetch_url".to_string(),
-        description: "Fetch a URL and return the page content as plain text. Use when the user asks to summarize a link, explain a page, or gives you a URL—you receive the content as context and summarize or answer from it; the user does not need to copy-paste anything.".to_string(),
+        description: "Fetch a URL and return the page content as plain text (in a JSON object with status, final URL after redirects and content). Use when the user asks to summarize a link, explain a page, or gives you a URL—you receive the content as context and summarize or answer from it; the user does not need to copy-paste anything.".to_string(),
         scope: "Internet (opt-in)".to_string(),
         risk: "network".to_string(),
         json_schema: Some(serde_json::json!({
@@ -382,7 +1317,10 @@ fn fetch_url_tool_defs() -> Vec<McpToolDef> {
             "required": ["url"],
             "properties": {
                 "url": { "type": "string", "description": "Full URL to fetch (e.g. https://example.com/article)" },
-                "max_chars": { "type": "integer", "minimum": 500, "maximum": 20000, "default": 12000, "description": "Max plain-text characters to return (for context window)" }
+                "max_chars": { "type": "integer", "minimum": 500, "maximum": 20000, "default": 12000, "description": "Max plain-text characters to return (for context window)" },
+                "extract_tables": { "type": "boolean", "default": false, "description": "Return only the page's HTML tables (pipe-delimited rows) instead of the page text. Use for pricing tables, specs, and other tabular data." },
+                "headers": { "type": "object", "additionalProperties": { "type": "string" }, "description": "Extra request headers (e.g. Accept, Authorization). Only send credentials the user provided for this site. Host and connection-level headers cannot be set." },
+                "output_format": { "type": "string", "enum": ["json", "text"], "default": "json", "description": "json (default): {ok, url, final_url, status, content_type, char_count, content, redirects, error}. text: the page content after a short readable preamble." }
             },
             "additionalProperties": false
         })),
@@ -445,6 +1383,88 @@ pub fn enabled_tool_definitions(
     out
 }
 
+/// Whether a tool is switched on in the MCP settings and whether it can run right now.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ToolAvailability {
+    pub name: String,
+    pub id: String,
+    pub risk: String,
+    pub enabled: bool,
+    pub available: bool,
+    /// Why the tool is unavailable (disabled, missing root, no network).
+    pub reason: Option<String>,
+}
+
+/// Live inputs for tool_availability. `network_reachable` is probed by the caller since it blocks.
+pub struct ToolPreconditions<'a> {
+    pub filesystem_enabled: bool,
+    pub filesystem_root: &'a str,
+    pub obsidian_enabled: bool,
+    pub obsidian_vault: &'a str,
+    pub web_search_enabled: bool,
+    pub terminal_enabled: bool,
+    pub network_reachable: bool,
+    pub safe_mode: bool,
+    pub safe_mode_block_writes: bool,
+    pub offline_mode: bool,
+}
+
+/// Enabled and available flags for every tool, so the UI can grey out tools that are configured
+/// but currently broken.
+pub fn tool_availability(p: &ToolPreconditions) -> Vec<ToolAvailability> {
+    all_tool_definitions()
+        .into_iter()
+        .map(|d| {
+            let (enabled, check) = match d.id.as_str() {
+                "filesystem" => (
+                    p.filesystem_enabled,
+                    validate_tool_root(p.filesystem_root).map_err(|e| format!("filesystem root {}", e)),
+                ),
+                "obsidian" => (
+                    p.obsidian_enabled,
+                    validate_tool_root(p.obsidian_vault).map_err(|e| format!("Obsidian vault {}", e)),
+                ),
+                "web" | "web_search" | "browser" => (
+                    p.web_search_enabled,
+                    if p.network_reachable { Ok(()) } else { Err("network unreachable".to_string()) },
+                ),
+                _ => (p.terminal_enabled, Ok(())),
+            };
+            let reason = if !enabled {
+                Some("disabled in settings".to_string())
+            } else if p.offline_mode && needs_network(&d.id) {
+                Some("offline mode enabled".to_string())
+            } else if p.safe_mode && blocked_by_safe_mode(&d.risk, p.safe_mode_block_writes) {
+                Some("blocked by safe mode".to_string())
+            } else {
+                check.err()
+            };
+            ToolAvailability {
+                name: d.name,
+                id: d.id,
+                risk: d.risk,
+                enabled,
+                available: reason.is_none(),
+                reason,
+            }
+        })
+        .collect()
+}
+
+/// Host probed by network_reachable: the web_search provider.
+const NETWORK_PROBE_HOST: &str = "api.duckduckgo.com:443";
+
+/// Quick connectivity check for the web tools: resolve and open a TCP connection to the search
+/// provider within `timeout`.
+pub fn network_reachable(timeout: Duration) -> bool {
+    use std::net::ToSocketAddrs;
+    NETWORK_PROBE_HOST
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .is_some_and(|addr| std::net::TcpStream::connect_timeout(&addr, timeout).is_ok())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ToolCallArgs {
     pub path: Option<String>,
@@ -456,9 +1476,13 @@ pub struct ToolCallArgs {
     pub max_results: Option<u32>,
     /// When true (default), fetch result URLs and add page_excerpt to each result for summarization.
     pub include_page_excerpts: Option<bool>,
+    /// For web_search: cap on results that get a page excerpt (default PAGE_EXCERPT_MAX_RESULTS).
+    pub max_excerpts: Option<u32>,
     pub command: Option<String>,
     pub working_directory: Option<String>,
     pub shell: Option<String>,
+    /// Accepted for schema compatibility; open_terminal_and_run always keeps the window open.
+    #[allow(dead_code)]
     pub keep_open: Option<bool>,
     /// If true, open a new terminal tab/window. If false or unset, reuse the same terminal.
     pub new_tab: Option<bool>,
@@ -468,6 +1492,36 @@ pub struct ToolCallArgs {
     pub engine: Option<String>,
     /// For fetch_url: max plain-text characters to return.
     pub max_chars: Option<u32>,
+    /// For fetch_url: return the page's <table> elements (pipe-delimited) instead of flattened text.
+    pub extract_tables: Option<bool>,
+    /// For ingest_file: maximum bytes per chunk.
+    pub chunk_size: Option<u32>,
+    /// For read_bytes and read_file_range: byte offset and number of bytes.
+    pub offset: Option<u64>,
+    pub length: Option<u32>,
+    /// For fetch_url: extra request headers (validated by custom_request_headers).
+    pub headers: Option<HashMap<String, String>>,
+    /// For read_files: relative paths to read.
+    pub paths: Option<Vec<String>>,
+    /// For summarize_file: approximate summary length in words.
+    pub target_words: Option<u32>,
+    /// For run_command and fetch_url: "json" (default, structured) or "text" (readable). For
+    /// list_dir (as `format`): "text" (default) or "json".
+    #[serde(alias = "format")]
+    pub output_format: Option<String>,
+    /// For obsidian_write_note: overwrite existing frontmatter even when content has none.
+    pub replace_frontmatter: Option<bool>,
+    /// For list_dir: see ListDirOptions.
+    pub sort: Option<String>,
+    pub dirs_first: Option<bool>,
+    pub pattern: Option<String>,
+    pub show_sizes: Option<bool>,
+    /// replace_in_file: text to find, its replacement, and how many matches to replace.
+    pub search: Option<String>,
+    pub replace: Option<String>,
+    pub count: Option<u32>,
+    pub all: Option<bool>,
+    pub allow_no_match: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -563,54 +1617,643 @@ fn strip_html_to_text(html: &str) -> String {
         } else {
             out.push(c as char);
         }
-        i += 1;
+        i += 1;
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ").trim().to_string()
+}
+
+const PAGE_EXCERPT_MAX_CHARS: usize = 2200;
+/// Default page fetch timeout (fetch_url, page excerpts, open_browser_search); see ToolOptions.
+pub const DEFAULT_FETCH_TIMEOUT_SECS: u64 = 8;
+/// Default timeout of web_search's DuckDuckGo requests.
+pub const DEFAULT_WEB_SEARCH_TIMEOUT_SECS: u64 = 10;
+const MIN_TOOL_TIMEOUT_SECS: u64 = 1;
+const MAX_TOOL_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of tool calls allowed to run at the same time; further calls wait for a slot.
+pub const DEFAULT_MAX_CONCURRENT_TOOLS: usize = 3;
+const MAX_CONCURRENT_TOOLS_LIMIT: usize = 16;
+
+/// Keep a configured tool concurrency limit within 1–16.
+pub fn clamp_max_concurrent_tools(n: usize) -> usize {
+    n.clamp(1, MAX_CONCURRENT_TOOLS_LIMIT)
+}
+
+/// Keep a configured network timeout within 1–30 seconds.
+pub fn clamp_timeout_secs(secs: u64) -> u64 {
+    secs.clamp(MIN_TOOL_TIMEOUT_SECS, MAX_TOOL_TIMEOUT_SECS)
+}
+
+/// Default cap on how many results get a page excerpt (further bounded by max_results).
+const PAGE_EXCERPT_MAX_RESULTS: usize = 4;
+/// Combined character budget for all page excerpts in one web_search call.
+const PAGE_EXCERPT_TOTAL_BUDGET_CHARS: usize = 8000;
+/// Stop fetching excerpts once less than this much budget remains.
+const PAGE_EXCERPT_MIN_CHARS: usize = 200;
+/// Max chars for page content when open_browser_search fetches the page into context.
+const OPEN_BROWSER_FETCH_MAX_CHARS: usize = 12000;
+
+/// HTTP client for fetching pages (fetch_url, open_browser_search). Compressed (gzip/deflate) responses
+/// are advertised via Accept-Encoding and decoded transparently, so strip_html_to_text never sees binary.
+fn page_fetch_client(timeout: Duration) -> Result<reqwest::blocking::Client, McpToolError> {
+    page_fetch_client_builder(timeout)
+        .redirect(reqwest::redirect::Policy::limited(MAX_FETCH_REDIRECTS))
+        .build()
+        .map_err(|e| McpToolError::Network(e.to_string()))
+}
+
+fn page_fetch_client_builder(timeout: Duration) -> reqwest::blocking::ClientBuilder {
+    reqwest::blocking::Client::builder()
+        .timeout(timeout)
+        .gzip(true)
+        .deflate(true)
+        .default_headers({
+            let mut h = reqwest::header::HeaderMap::new();
+            h.insert(
+                reqwest::header::USER_AGENT,
+                reqwest::header::HeaderValue::from_static(
+                    "Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0",
+                ),
+            );
+            h
+        })
+}
+
+/// Max redirects followed by page fetches.
+const MAX_FETCH_REDIRECTS: usize = 10;
+
+/// One redirect followed by fetch_url.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedirectHop {
+    pub from: String,
+    pub to: String,
+    pub status: u16,
+}
+
+/// True when a redirect would leave https for plain http.
+fn is_downgrade_redirect(from: &reqwest::Url, to: &reqwest::Url) -> bool {
+    from.scheme() == "https" && to.scheme() == "http"
+}
+
+/// Page fetch client for fetch_url that records every redirect it follows into `hops`. At most
+/// MAX_FETCH_REDIRECTS are followed, and https → http downgrades are refused unless allowed.
+fn redirect_recording_client(
+    timeout: Duration,
+    hops: Arc<std::sync::Mutex<Vec<RedirectHop>>>,
+    allow_downgrade: bool,
+) -> Result<reqwest::blocking::Client, McpToolError> {
+    let policy = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_FETCH_REDIRECTS {
+            return attempt.error(format!("too many redirects (max {})", MAX_FETCH_REDIRECTS));
+        }
+        let from = attempt.previous().last().cloned();
+        if !allow_downgrade && from.as_ref().is_some_and(|f| is_downgrade_redirect(f, attempt.url())) {
+            let to = attempt.url().to_string();
+            return attempt.error(format!(
+                "refused redirect from https to http ({}); the user can allow insecure redirects in settings",
+                to
+            ));
+        }
+        if let (Some(from), Ok(mut hops)) = (from, hops.lock()) {
+            hops.push(RedirectHop {
+                from: from.to_string(),
+                to: attempt.url().to_string(),
+                status: attempt.status().as_u16(),
+            });
+        }
+        attempt.follow()
+    });
+    page_fetch_client_builder(timeout)
+        .redirect(policy)
+        .build()
+        .map_err(|e| McpToolError::Network(e.to_string()))
+}
+
+/// Structured fetch_url output (JSON content by default) so the agent can tell success from
+/// failure and see where redirects led.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchUrlOutput {
+    pub ok: bool,
+    pub url: String,
+    /// URL after redirects; None when no response was received.
+    pub final_url: Option<String>,
+    /// HTTP status of the final response (0 when no response was received).
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// True when `content` holds the page's tables (extract_tables) rather than its text.
+    pub tables: bool,
+    pub char_count: usize,
+    pub content: String,
+    pub redirects: Vec<RedirectHop>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FetchUrlOutput {
+    /// Human-readable format (output_format: "text"), the tool's original prose preamble.
+    fn to_text(&self) -> String {
+        let label = if self.tables { "Page tables" } else { "Page content" };
+        format!(
+            "{} (use this as context to summarize or answer; user did not paste this):\n\n{}",
+            label, self.content
+        )
+    }
+}
+
+/// Response details captured by fetch_url_body_with_meta before the body checks, so they are
+/// known even when the fetch fails.
+#[derive(Debug, Default)]
+struct FetchMeta {
+    status: u16,
+    final_url: Option<String>,
+    content_type: Option<String>,
+}
+
+/// Fetch a URL and return plain-text excerpt (at most max_chars) for the assistant to summarize.
+fn fetch_page_excerpt(client: &reqwest::blocking::Client, url: &str, max_chars: usize) -> Option<String> {
+    fetch_url_content_impl(client, url, max_chars, &HeaderMap::new()).ok()
+}
+
+/// Fetch excerpts for up to `max_excerpts` results within a shared character budget.
+/// Each excerpt gets min(PAGE_EXCERPT_MAX_CHARS, remaining budget), so later ones are trimmed shorter.
+/// Stops before the next fetch once `cancel` is set. Returns (excerpts fetched, chars used, canceled).
+fn fetch_page_excerpts_within_budget(
+    client: &reqwest::blocking::Client,
+    results: &mut [WebSearchResultItem],
+    max_excerpts: usize,
+    budget_chars: usize,
+    cancel: &AtomicBool,
+) -> (usize, usize, bool) {
+    let mut used = 0usize;
+    let mut fetched = 0usize;
+    for r in results.iter_mut().take(max_excerpts) {
+        if cancel.load(Ordering::SeqCst) {
+            return (fetched, used, true);
+        }
+        let remaining = budget_chars.saturating_sub(used);
+        if remaining < PAGE_EXCERPT_MIN_CHARS {
+            break;
+        }
+        if let Some(excerpt) = fetch_page_excerpt(client, &r.url, remaining.min(PAGE_EXCERPT_MAX_CHARS)) {
+            used += excerpt.chars().count();
+            fetched += 1;
+            r.page_excerpt = Some(excerpt);
+        }
+    }
+    (fetched, used, false)
+}
+
+/// Fetch a URL and return plain text (for fetch_url tool). Uses same timeout/size limits; max_chars caps output.
+fn fetch_url_content(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    max_chars: usize,
+    headers: &HeaderMap,
+    meta: &mut FetchMeta,
+) -> Result<String, McpToolError> {
+    let (text, kind) = fetch_url_body_with_meta(client, url, headers, meta)?;
+    fetched_page_text(text, kind, max_chars)
+}
+
+/// How a fetched body should be handled, based on its Content-Type.
+#[derive(Debug, PartialEq)]
+enum FetchedContentKind {
+    /// HTML/XML: strip tags to plain text.
+    Markup,
+    /// Plain text, JSON, etc.: use as-is.
+    Text,
+    Pdf,
+    Binary,
+}
+
+/// Classify a Content-Type header value. A missing header is treated as markup (the previous behavior).
+fn classify_content_type(content_type: Option<&str>) -> FetchedContentKind {
+    let mime = match content_type {
+        Some(ct) => ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase(),
+        None => return FetchedContentKind::Markup,
+    };
+    if mime.is_empty() {
+        return FetchedContentKind::Markup;
+    }
+    if mime == "text/html" || mime == "text/xml" || mime == "application/xml" || mime.ends_with("+xml") {
+        return FetchedContentKind::Markup;
+    }
+    if mime.starts_with("text/") || mime == "application/json" || mime.ends_with("+json") {
+        return FetchedContentKind::Text;
+    }
+    if mime == "application/pdf" {
+        return FetchedContentKind::Pdf;
+    }
+    FetchedContentKind::Binary
+}
+
+/// Max custom headers accepted by fetch_url, and their combined name+value size.
+const MAX_CUSTOM_HEADERS: usize = 16;
+const MAX_CUSTOM_HEADER_BYTES: usize = 8 * 1024;
+/// Headers fetch_url callers may not set: routing, framing and encoding are the HTTP client's job
+/// (overriding Accept-Encoding would also bypass transparent decompression).
+const BLOCKED_CUSTOM_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+    "upgrade",
+    "te",
+    "trailer",
+    "accept-encoding",
+    "proxy-authorization",
+    "proxy-connection",
+];
+
+/// Validate fetch_url's `headers` argument into a HeaderMap. Sending credentials is the user's
+/// call; this only keeps requests well-formed and bounded.
+fn custom_request_headers(headers: Option<&HashMap<String, String>>) -> Result<HeaderMap, McpToolError> {
+    let mut map = HeaderMap::new();
+    let Some(headers) = headers else { return Ok(map) };
+    if headers.len() > MAX_CUSTOM_HEADERS {
+        return Err(McpToolError::InvalidArg(format!("too many headers (max {})", MAX_CUSTOM_HEADERS)));
+    }
+    let total: usize = headers.iter().map(|(k, v)| k.len() + v.len()).sum();
+    if total > MAX_CUSTOM_HEADER_BYTES {
+        return Err(McpToolError::InvalidArg(format!(
+            "headers too large (max {} bytes)",
+            MAX_CUSTOM_HEADER_BYTES
+        )));
+    }
+    for (name, value) in headers {
+        let name = name.trim().to_ascii_lowercase();
+        if BLOCKED_CUSTOM_HEADERS.contains(&name.as_str()) {
+            return Err(McpToolError::InvalidArg(format!("header '{}' cannot be overridden", name)));
+        }
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| McpToolError::InvalidArg(format!("invalid header name '{}'", name)))?;
+        let header_value = HeaderValue::from_str(value.trim())
+            .map_err(|_| McpToolError::InvalidArg(format!("invalid value for header '{}'", name)))?;
+        map.insert(header_name, header_value);
+    }
+    Ok(map)
+}
+
+/// Fetch a URL and return its decoded body and content kind. Enforces the scheme, status,
+/// Content-Type (PDF/binary refused) and 512 KiB size limits shared by all page fetches.
+fn fetch_url_body(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    headers: &HeaderMap,
+) -> Result<(String, FetchedContentKind), McpToolError> {
+    fetch_url_body_with_meta(client, url, headers, &mut FetchMeta::default())
+}
+
+fn fetch_url_body_with_meta(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    headers: &HeaderMap,
+    meta: &mut FetchMeta,
+) -> Result<(String, FetchedContentKind), McpToolError> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(McpToolError::InvalidArg("url must start with http:// or https://".into()));
+    }
+    let res = client
+        .get(url)
+        .headers(headers.clone())
+        .send()
+        .map_err(|e| McpToolError::Network(e.to_string()))?;
+    let content_type = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+    meta.status = res.status().as_u16();
+    meta.final_url = Some(res.url().to_string());
+    meta.content_type = content_type.clone();
+    if !res.status().is_success() {
+        return Err(McpToolError::Network(format!("HTTP {}", res.status().as_u16())));
+    }
+    let kind = classify_content_type(content_type.as_deref());
+    match kind {
+        FetchedContentKind::Pdf => {
+            return Err(McpToolError::InvalidArg(
+                "URL points to a PDF (application/pdf); its text cannot be extracted here. Download the file and read it with read_pdf instead.".into(),
+            ));
+        }
+        FetchedContentKind::Binary => {
+            return Err(McpToolError::InvalidArg(format!(
+                "URL returned binary content ({}); only text and HTML pages can be fetched",
+                content_type.as_deref().unwrap_or("unknown type")
+            )));
+        }
+        FetchedContentKind::Markup | FetchedContentKind::Text => {}
+    }
+    let body = res.bytes().map_err(|e| McpToolError::Network(e.to_string()))?;
+    if body.len() > 512 * 1024 {
+        return Err(McpToolError::InvalidArg("page too large (max 512 KiB)".into()));
+    }
+    Ok((String::from_utf8_lossy(&body).into_owned(), kind))
+}
+
+fn truncate_fetched_text(text: String, max_chars: usize) -> String {
+    if text.len() > max_chars {
+        format!("{}…", text.chars().take(max_chars).collect::<String>().trim())
+    } else {
+        text
+    }
+}
+
+fn fetch_url_content_impl(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    max_chars: usize,
+    headers: &HeaderMap,
+) -> Result<String, McpToolError> {
+    let (text, kind) = fetch_url_body(client, url, headers)?;
+    fetched_page_text(text, kind, max_chars)
+}
+
+/// Plain text of a fetched body (markup stripped), capped at max_chars. Errors when nothing is left.
+fn fetched_page_text(text: String, kind: FetchedContentKind, max_chars: usize) -> Result<String, McpToolError> {
+    let stripped = if kind == FetchedContentKind::Markup {
+        strip_html_to_text(&text)
+    } else {
+        text.trim().to_string()
+    };
+    if stripped.is_empty() {
+        return Err(McpToolError::Network("fetch returned no text".into()));
+    }
+    Ok(truncate_fetched_text(stripped, max_chars))
+}
+
+/// Max tables returned by fetch_url with extract_tables.
+const MAX_EXTRACTED_TABLES: usize = 10;
+/// Max rows kept per extracted table (rows beyond this are counted, not returned).
+const MAX_EXTRACTED_TABLE_ROWS: usize = 50;
+
+/// One HTML table: rows of cell texts, plus how many rows were dropped by the row cap.
+#[derive(Debug, Default, PartialEq)]
+struct ExtractedTable {
+    rows: Vec<Vec<String>>,
+    omitted_rows: usize,
+}
+
+#[derive(Default)]
+struct TableBuilder {
+    table: ExtractedTable,
+    row: Option<Vec<String>>,
+    cell: Option<String>,
+}
+
+impl TableBuilder {
+    fn finish_cell(&mut self) {
+        if let Some(cell) = self.cell.take() {
+            let text = cell.split_whitespace().collect::<Vec<_>>().join(" ");
+            self.row.get_or_insert_with(Vec::new).push(text);
+        }
+    }
+
+    fn finish_row(&mut self) {
+        self.finish_cell();
+        if let Some(row) = self.row.take() {
+            if row.iter().all(|c| c.is_empty()) {
+                return;
+            }
+            if self.table.rows.len() < MAX_EXTRACTED_TABLE_ROWS {
+                self.table.rows.push(row);
+            } else {
+                self.table.omitted_rows += 1;
+            }
+        }
+    }
+}
+
+fn decode_basic_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Extract `<table>` elements from HTML (at most `max_tables`, in document order of their closing tag).
+/// Nested tables are returned as separate tables. Script/style content is ignored.
+fn extract_html_tables(html: &str, max_tables: usize) -> Vec<ExtractedTable> {
+    let mut tables = Vec::new();
+    let mut stack: Vec<TableBuilder> = Vec::new();
+    // ASCII lowercasing keeps byte offsets, so `lower` can be searched at `rest`'s position.
+    let lower = html.to_ascii_lowercase();
+    let mut rest = html;
+    while !rest.is_empty() && tables.len() < max_tables {
+        let Some(lt) = rest.find('<') else {
+            break;
+        };
+        if let Some(top) = stack.last_mut() {
+            if let Some(cell) = top.cell.as_mut() {
+                cell.push_str(&decode_basic_entities(&rest[..lt]));
+                cell.push(' ');
+            }
+        }
+        rest = &rest[lt + 1..];
+        let Some(gt) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..gt];
+        rest = &rest[gt + 1..];
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match (name.as_str(), closing) {
+            ("script", false) | ("style", false) => {
+                let end = format!("</{}", name);
+                match lower[html.len() - rest.len()..].find(&end) {
+                    Some(i) => rest = &rest[i..],
+                    None => break,
+                }
+            }
+            ("table", false) => stack.push(TableBuilder::default()),
+            ("table", true) => {
+                if let Some(mut b) = stack.pop() {
+                    b.finish_row();
+                    if !b.table.rows.is_empty() {
+                        tables.push(b.table);
+                    }
+                }
+            }
+            ("tr", false) => {
+                if let Some(top) = stack.last_mut() {
+                    top.finish_row();
+                    top.row = Some(Vec::new());
+                }
+            }
+            ("tr", true) => {
+                if let Some(top) = stack.last_mut() {
+                    top.finish_row();
+                }
+            }
+            ("td", false) | ("th", false) => {
+                if let Some(top) = stack.last_mut() {
+                    top.finish_cell();
+                    top.cell = Some(String::new());
+                }
+            }
+            ("td", true) | ("th", true) => {
+                if let Some(top) = stack.last_mut() {
+                    top.finish_cell();
+                }
+            }
+            ("br", _) => {
+                if let Some(cell) = stack.last_mut().and_then(|t| t.cell.as_mut()) {
+                    cell.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+    tables
+}
+
+/// Render extracted tables as pipe-delimited rows, one block per table.
+fn format_extracted_tables(tables: &[ExtractedTable]) -> String {
+    let mut out = String::new();
+    for (i, t) in tables.iter().enumerate() {
+        out.push_str(&format!("Table {}:\n", i + 1));
+        for row in &t.rows {
+            let cells: Vec<String> = row.iter().map(|c| c.replace('|', "\\|")).collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+        if t.omitted_rows > 0 {
+            out.push_str(&format!("({} more rows omitted)\n", t.omitted_rows));
+        }
+        out.push('\n');
     }
-    out.split_whitespace().collect::<Vec<_>>().join(" ").trim().to_string()
+    out.trim_end().to_string()
 }
 
-const PAGE_EXCERPT_MAX_CHARS: usize = 2200;
-const PAGE_EXCERPT_FETCH_TIMEOUT_SECS: u64 = 8;
-const PAGE_EXCERPT_MAX_RESULTS: usize = 4;
-/// Max chars for page content when open_browser_search fetches the page into context.
-const OPEN_BROWSER_FETCH_MAX_CHARS: usize = 12000;
+/// fetch_url with extract_tables: fetch (same limits as fetch_url) and return the page's tables.
+fn fetch_url_tables(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    max_chars: usize,
+    headers: &HeaderMap,
+    meta: &mut FetchMeta,
+) -> Result<String, McpToolError> {
+    let (text, kind) = fetch_url_body_with_meta(client, url, headers, meta)?;
+    if kind != FetchedContentKind::Markup {
+        return Err(McpToolError::InvalidArg("extract_tables requires an HTML page".into()));
+    }
+    let tables = extract_html_tables(&text, MAX_EXTRACTED_TABLES);
+    if tables.is_empty() {
+        return Err(McpToolError::InvalidArg(
+            "no HTML tables found on the page; fetch it without extract_tables to read the text".into(),
+        ));
+    }
+    Ok(truncate_fetched_text(format_extracted_tables(&tables), max_chars))
+}
 
-/// Fetch a URL and return plain-text excerpt for the assistant to summarize.
-fn fetch_page_excerpt(client: &reqwest::blocking::Client, url: &str) -> Option<String> {
-    fetch_url_content_impl(client, url, PAGE_EXCERPT_MAX_CHARS)
+/// Plain text of an HTML fragment: tags dropped, basic entities decoded, whitespace collapsed.
+fn html_fragment_text(fragment: &str) -> String {
+    let mut out = String::with_capacity(fragment.len());
+    let mut in_tag = false;
+    for c in fragment.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    decode_basic_entities(&out).split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-/// Fetch a URL and return plain text (for fetch_url tool). Uses same timeout/size limits; max_chars caps output.
-fn fetch_url_content(client: &reqwest::blocking::Client, url: &str, max_chars: usize) -> Result<String, McpToolError> {
-    fetch_url_content_impl(client, url, max_chars)
-        .ok_or_else(|| McpToolError::Network("fetch failed or returned no text".to_string()))
+/// DuckDuckGo HTML result links are redirects (`//duckduckgo.com/l/?uddg=<encoded url>&rut=...`);
+/// return the target URL. Ad links (`/y.js`) and non-http targets yield None.
+fn resolve_duckduckgo_html_href(href: &str) -> Option<String> {
+    let href = decode_basic_entities(href);
+    let target = match href.split_once("uddg=") {
+        Some((_, rest)) => {
+            let encoded = rest.split('&').next().unwrap_or("");
+            urlencoding::decode(encoded).ok()?.into_owned()
+        }
+        None if href.contains("duckduckgo.com/y.js") => return None,
+        None => href,
+    };
+    if target.starts_with("http://") || target.starts_with("https://") {
+        Some(target)
+    } else {
+        None
+    }
 }
 
-fn fetch_url_content_impl(client: &reqwest::blocking::Client, url: &str, max_chars: usize) -> Option<String> {
-    if !url.starts_with("http://") && !url.starts_with("https://") {
-        return None;
+/// Parse html.duckduckgo.com results: each `result__a` anchor gives title + URL, and the following
+/// `result__snippet` (before the next result) gives the snippet. Ads and duplicate URLs are skipped.
+fn parse_duckduckgo_html_results(html: &str, max_results: usize) -> Vec<WebSearchResultItem> {
+    let mut results: Vec<WebSearchResultItem> = Vec::new();
+    let anchors: Vec<usize> = html.match_indices("class=\"result__a\"").map(|(i, _)| i).collect();
+    for (n, &pos) in anchors.iter().enumerate() {
+        if results.len() >= max_results {
+            break;
+        }
+        let section_end = anchors.get(n + 1).copied().unwrap_or(html.len());
+        let tag_start = html[..pos].rfind('<').unwrap_or(pos);
+        let Some(tag_len) = html[tag_start..].find('>') else {
+            break;
+        };
+        let tag = &html[tag_start..tag_start + tag_len];
+        let Some(href) = tag
+            .split_once("href=\"")
+            .and_then(|(_, rest)| rest.split('"').next())
+            .and_then(resolve_duckduckgo_html_href)
+        else {
+            continue;
+        };
+        let body_start = tag_start + tag_len + 1;
+        let title_end = html[body_start..section_end]
+            .find("</a>")
+            .map(|i| body_start + i)
+            .unwrap_or(section_end);
+        let title = html_fragment_text(&html[body_start..title_end]);
+        let snippet = html[title_end..section_end]
+            .find("class=\"result__snippet\"")
+            .and_then(|i| {
+                let rest = &html[title_end + i..section_end];
+                let start = rest.find('>')? + 1;
+                let end = rest[start..].find("</a>").or_else(|| rest[start..].find("</div>"))?;
+                Some(html_fragment_text(&rest[start..start + end]))
+            })
+            .unwrap_or_default();
+        if title.is_empty() || results.iter().any(|r| r.url == href) {
+            continue;
+        }
+        results.push(WebSearchResultItem {
+            title,
+            snippet,
+            url: href,
+            page_excerpt: None,
+        });
     }
+    results
+}
+
+/// Fetch DuckDuckGo's HTML (no-JS) results page. Used only when web_search_html_fallback is enabled.
+fn duckduckgo_html_search(
+    client: &reqwest::blocking::Client,
+    query: &str,
+    max_results: usize,
+) -> Result<Vec<WebSearchResultItem>, McpToolError> {
     let res = client
-        .get(url)
-        .timeout(Duration::from_secs(PAGE_EXCERPT_FETCH_TIMEOUT_SECS))
+        .get("https://html.duckduckgo.com/html/")
+        .query(&[("q", query)])
         .send()
-        .ok()?;
+        .map_err(|e| McpToolError::Network(e.to_string()))?;
     if !res.status().is_success() {
-        return None;
-    }
-    let body = res.bytes().ok()?;
-    if body.len() > 512 * 1024 {
-        return None;
+        return Err(McpToolError::Network(format!("HTTP {}", res.status().as_u16())));
     }
-    let text = String::from_utf8_lossy(&body);
-    let stripped = strip_html_to_text(&text);
-    if stripped.is_empty() {
-        return None;
-    }
-    Some(if stripped.len() > max_chars {
-        format!("{}…", stripped.chars().take(max_chars).collect::<String>().trim())
-    } else {
-        stripped
-    })
+    let body = res.text().map_err(|e| McpToolError::Network(e.to_string()))?;
+    Ok(parse_duckduckgo_html_results(&body, max_results))
 }
 
 /// Parse DuckDuckGo response into a list of results (abstract + related topics, including nested Topics).
@@ -640,7 +2283,7 @@ fn parse_duckduckgo_results(body: &DuckDuckGoResult, max_results: usize) -> Vec<
                             if results.len() >= max_results {
                                 break;
                             }
-                            if let Some(ref o) = item.as_object() {
+                            if let Some(o) = item.as_object() {
                                 if let Some(r) = one_result_from_obj(o) {
                                     results.push(r);
                                 }
@@ -1010,29 +2653,140 @@ const BLOCKED_COMMAND_PATTERNS: &[&str] = &[
     "cipher /w:",            // secure wipe
 ];
 
-/// Check if a command matches any blocked pattern.
-fn is_command_blocked(command: &str) -> bool {
+/// Characters treated as part of a word for boundary matching ("reboot" must not match "reboot-checker.sh").
+fn is_command_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// True if `pattern` occurs in `haystack` with word boundaries at any edge where the pattern itself
+/// starts/ends with a word character (like regex `\b`).
+fn contains_on_word_boundary(haystack: &str, pattern: &str) -> bool {
+    let needs_start = pattern.chars().next().map(is_command_word_char).unwrap_or(false);
+    let needs_end = pattern.chars().last().map(is_command_word_char).unwrap_or(false);
+    haystack.match_indices(pattern).any(|(i, m)| {
+        let start_ok = !needs_start || !haystack[..i].chars().last().map(is_command_word_char).unwrap_or(false);
+        let end_ok = !needs_end || !haystack[i + m.len()..].chars().next().map(is_command_word_char).unwrap_or(false);
+        start_ok && end_ok
+    })
+}
+
+/// Check if a command matches any blocked pattern: the built-in list (always applied) plus
+/// user-configured extra patterns. Matching is case-insensitive; substring unless word boundaries are enabled.
+fn is_command_blocked(command: &str, options: &ToolOptions) -> bool {
     let lower = command.to_lowercase().trim().to_string();
-    BLOCKED_COMMAND_PATTERNS.iter().any(|p| lower.contains(p))
+    let extra = options
+        .terminal_extra_blocked_patterns
+        .iter()
+        .map(|p| p.trim().to_lowercase())
+        .filter(|p| !p.is_empty());
+    let mut patterns = BLOCKED_COMMAND_PATTERNS.iter().map(|p| p.to_string()).chain(extra);
+    if options.terminal_block_word_boundaries {
+        patterns.any(|p| contains_on_word_boundary(&lower, &p))
+    } else {
+        patterns.any(|p| lower.contains(&p))
+    }
+}
+
+/// Structured run_command output (JSON content by default) so the agent can branch on exit code.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunCommandOutput {
+    pub command: String,
+    pub working_directory: String,
+    /// None when the process was terminated by a signal.
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub stdout: String,
+    pub stderr: String,
+    /// True when the process did not exit normally (killed by a signal).
+    pub killed: bool,
+}
+
+impl RunCommandOutput {
+    /// Human-readable format (output_format: "text").
+    fn to_text(&self) -> String {
+        let mut result = Vec::new();
+        result.push(format!("Command: {}", self.command));
+        result.push(format!("Working directory: {}", self.working_directory));
+        result.push(format!("Exit code: {}", self.exit_code.unwrap_or(-1)));
+        if !self.stdout.is_empty() {
+            result.push(format!("STDOUT:\n{}", self.stdout));
+        }
+        if !self.stderr.is_empty() {
+            result.push(format!("STDERR:\n{}", self.stderr));
+        }
+        if self.stdout.is_empty() && self.stderr.is_empty() {
+            result.push("(No output)".to_string());
+        }
+        result.join("\n\n")
+    }
+}
+
+/// Windows process creation flag: run a console process without creating a console window.
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// Shells run_command may be configured to use (MCP setting `terminal_shell`).
+#[cfg(windows)]
+pub const ALLOWED_TERMINAL_SHELLS: &[&str] = &["cmd", "powershell", "pwsh"];
+#[cfg(not(windows))]
+pub const ALLOWED_TERMINAL_SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "fish"];
+
+/// run_command's shell when `terminal_shell` is empty.
+pub const DEFAULT_TERMINAL_SHELL: &str = if cfg!(windows) { "cmd" } else { "sh" };
+
+/// Err with the allowed names when `shell` is neither empty nor on ALLOWED_TERMINAL_SHELLS.
+pub fn validate_terminal_shell(shell: &str) -> Result<(), String> {
+    let shell = shell.trim();
+    if shell.is_empty() || ALLOWED_TERMINAL_SHELLS.contains(&shell) {
+        return Ok(());
+    }
+    Err(format!(
+        "unsupported shell \"{}\" (allowed: {})",
+        shell,
+        ALLOWED_TERMINAL_SHELLS.join(", ")
+    ))
+}
+
+/// The configured shell if allowed, otherwise the platform default.
+fn run_command_shell(configured: &str) -> &'static str {
+    let configured = configured.trim();
+    ALLOWED_TERMINAL_SHELLS
+        .iter()
+        .find(|s| **s == configured)
+        .copied()
+        .unwrap_or(DEFAULT_TERMINAL_SHELL)
+}
+
+/// Arguments placed before the command string. Quoting rules differ: cmd passes the line to
+/// `cmd /C` as-is, PowerShell parses it as a script, POSIX shells use `-c`.
+fn shell_command_flags(shell: &str) -> &'static [&'static str] {
+    match shell {
+        "cmd" => &["/C"],
+        "powershell" | "pwsh" => &["-NoProfile", "-NonInteractive", "-Command"],
+        _ => &["-c"],
+    }
 }
 
-fn tool_run_command(command: &str, working_directory: Option<&str>) -> Result<String, McpToolError> {
-    if is_command_blocked(command) {
+fn tool_run_command(
+    command: &str,
+    working_directory: Option<&str>,
+    options: &ToolOptions,
+) -> Result<RunCommandOutput, McpToolError> {
+    if is_command_blocked(command, options) {
         return Err(McpToolError::CommandFailed(
             "Command blocked: this command is on the safety blocklist. Dangerous system commands are not allowed.".into()
         ));
     }
-    #[cfg(windows)]
-    let shell = "cmd";
-    #[cfg(windows)]
-    let shell_flag = "/C";
-    #[cfg(not(windows))]
-    let shell = "sh";
-    #[cfg(not(windows))]
-    let shell_flag = "-c";
-    
+    let shell = run_command_shell(&options.terminal_shell);
     let mut cmd = Command::new(shell);
-    cmd.arg(shell_flag).arg(command);
+    cmd.args(shell_command_flags(shell)).arg(command);
+    // Background commands must not flash a console window. output() still pipes stdout/stderr,
+    // so capture is unaffected. open_terminal_and_run keeps CREATE_NEW_CONSOLE on purpose.
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
     
     let wd_path: PathBuf = match working_directory {
         Some(wd) if !wd.trim().is_empty() => {
@@ -1049,30 +2803,20 @@ fn tool_run_command(command: &str, working_directory: Option<&str>) -> Result<St
     };
     cmd.current_dir(&wd_path);
     
+    let start = std::time::Instant::now();
     let output = cmd
         .output()
         .map_err(|e| McpToolError::CommandFailed(format!("Failed to execute command: {}", e)))?;
     
-    let mut result = Vec::new();
-    result.push(format!("Command: {}", command));
-    result.push(format!("Working directory: {}", wd_path.display()));
-    result.push(format!("Exit code: {}", output.status.code().unwrap_or(-1)));
-    
-    if !output.stdout.is_empty() {
-        let stdout_str = String::from_utf8_lossy(&output.stdout);
-        result.push(format!("STDOUT:\n{}", stdout_str));
-    }
-    
-    if !output.stderr.is_empty() {
-        let stderr_str = String::from_utf8_lossy(&output.stderr);
-        result.push(format!("STDERR:\n{}", stderr_str));
-    }
-    
-    if output.stdout.is_empty() && output.stderr.is_empty() {
-        result.push("(No output)".to_string());
-    }
-    
-    Ok(result.join("\n\n"))
+    Ok(RunCommandOutput {
+        command: command.to_string(),
+        working_directory: wd_path.display().to_string(),
+        exit_code: output.status.code(),
+        duration_ms: start.elapsed().as_millis() as u64,
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        killed: output.status.code().is_none(),
+    })
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -1100,6 +2844,23 @@ fn persistent_terminal_last_wd() -> &'static Mutex<String> {
     PERSISTENT_TERMINAL_LAST_WD.get_or_init(|| Mutex::new(String::new()))
 }
 
+/// Terminate the persistent terminal, if one was started. Called on app exit so no PowerShell window is orphaned.
+#[cfg(windows)]
+pub fn shutdown_persistent_terminal() {
+    if let Some(lock) = PERSISTENT_TERMINAL.get() {
+        if let Ok(mut guard) = lock.lock() {
+            if let Some((mut child, stdin)) = guard.take() {
+                drop(stdin);
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn shutdown_persistent_terminal() {}
+
 /// Open a visible CLI window and run a command. Windows-only. Default: reuse same tab; working dir = user home.
 #[cfg(windows)]
 fn tool_open_terminal_and_run(
@@ -1108,8 +2869,9 @@ fn tool_open_terminal_and_run(
     keep_open: bool,
     working_directory: Option<&str>,
     new_tab: bool,
+    options: &ToolOptions,
 ) -> Result<(String, String, Vec<DiagnosticStep>), McpToolError> {
-    if is_command_blocked(command) {
+    if is_command_blocked(command, options) {
         return Err(McpToolError::CommandFailed(
             "Command blocked: this command is on the safety blocklist. Dangerous system commands are not allowed.".into()
         ));
@@ -1157,31 +2919,6 @@ fn tool_open_terminal_and_run(
         .unwrap_or_else(|| default_wd.clone());
 
 
-    if !new_tab {
-        if let Ok(mut guard) = persistent_terminal_lock().lock() {
-            if let Some((ref mut child, ref mut stdin)) = *guard {
-                if child.try_wait().map(|o| o.is_none()).unwrap_or(false) {
-                    // When reusing, do NOT prepend Set-Location: shell stays in current directory
-                    // so follow-up commands (e.g. cd Screenshots; dir) work from previous cwd.
-                    let cmd_ps = command.replace(" && ", "; ");
-                    let full = format!("{}\r\n", cmd_ps);
-                    let _ = stdin.write_all(full.as_bytes());
-                    let _ = stdin.flush();
-                    steps.push(DiagnosticStep {
-                        level: "INFO".to_string(),
-                        message: "Reused existing terminal; command sent (no Set-Location).".to_string(),
-                        meta: Some(serde_json::json!({ "command": cmd_ps })),
-                    });
-                    let content = format!(
-                        "Ran in existing terminal (PowerShell).\nCommand: {}",
-                        cmd_ps
-                    );
-                    return Ok((content, "powershell".to_string(), steps));
-                }
-            }
-        }
-    }
-
     if new_tab {
         let (shell_used, child) = match shell.to_lowercase().as_str() {
             "wt" => {
@@ -1257,6 +2994,42 @@ fn tool_open_terminal_and_run(
         return Ok((content, shell_used, steps));
     }
 
+    // Hold the lock from the liveness check until the new child is stored, so rapid concurrent calls
+    // either reuse the same terminal or replace it; they can never each spawn their own.
+    let mut guard = persistent_terminal_lock().lock().map_err(|e| {
+        McpToolError::CommandFailed(format!("terminal lock poisoned: {}", e))
+    })?;
+    let cmd_ps = command.replace(" && ", "; ");
+    if let Some((ref mut child, ref mut stdin)) = *guard {
+        if child.try_wait().map(|o| o.is_none()).unwrap_or(false) {
+            // When reusing, do NOT prepend Set-Location: shell stays in current directory
+            // so follow-up commands (e.g. cd Screenshots; dir) work from previous cwd.
+            let full = format!("{}\r\n", cmd_ps);
+            if stdin.write_all(full.as_bytes()).and_then(|_| stdin.flush()).is_ok() {
+                steps.push(DiagnosticStep {
+                    level: "INFO".to_string(),
+                    message: "Reused existing terminal; command sent (no Set-Location).".to_string(),
+                    meta: Some(serde_json::json!({ "command": cmd_ps })),
+                });
+                let content = format!(
+                    "Ran in existing terminal (PowerShell).\nCommand: {}",
+                    cmd_ps
+                );
+                return Ok((content, "powershell".to_string(), steps));
+            }
+            steps.push(DiagnosticStep {
+                level: "WARN".to_string(),
+                message: "Existing terminal stopped accepting input; replacing it.".to_string(),
+                meta: None,
+            });
+        }
+    }
+    if let Some((mut old_child, old_stdin)) = guard.take() {
+        drop(old_stdin);
+        let _ = old_child.kill();
+        let _ = old_child.wait();
+    }
+
     steps.push(DiagnosticStep {
         level: "INFO".to_string(),
         message: "Step: starting persistent PowerShell (reuse same tab)".to_string(),
@@ -1269,23 +3042,22 @@ fn tool_open_terminal_and_run(
     let mut child = cmd
         .spawn()
         .map_err(|e| McpToolError::CommandFailed(format!("powershell spawn failed: {}", e)))?;
-    let mut stdin = child
-        .stdin
-        .take()
-        .ok_or_else(|| McpToolError::CommandFailed("could not take stdin".into()))?;
-    let cmd_ps = command.replace(" && ", "; ");
+    let mut stdin = match child.stdin.take() {
+        Some(s) => s,
+        None => {
+            let _ = child.kill();
+            return Err(McpToolError::CommandFailed("could not take stdin".into()));
+        }
+    };
     let cd_ps = format!("Set-Location '{}'\r\n", wd.replace('\'', "''"));
     let full = format!("{}{}\r\n", cd_ps, cmd_ps);
-    stdin.write_all(full.as_bytes()).map_err(|e| {
-        McpToolError::CommandFailed(format!("write to terminal failed: {}", e))
-    })?;
-    stdin.flush().map_err(|e| McpToolError::CommandFailed(format!("flush failed: {}", e)))?;
-    {
-        let mut guard = persistent_terminal_lock().lock().map_err(|e| {
-            McpToolError::CommandFailed(format!("terminal lock poisoned: {}", e))
-        })?;
-        *guard = Some((child, stdin));
+    if let Err(e) = stdin.write_all(full.as_bytes()).and_then(|_| stdin.flush()) {
+        let _ = child.kill();
+        return Err(McpToolError::CommandFailed(format!("write to terminal failed: {}", e)));
     }
+    // Child and its stdin are stored together under the same lock acquisition.
+    *guard = Some((child, stdin));
+    drop(guard);
     if let Ok(mut last_wd) = persistent_terminal_last_wd().lock() {
         *last_wd = wd.clone();
     }
@@ -1308,6 +3080,7 @@ fn tool_open_terminal_and_run(
     _keep_open: bool,
     _working_directory: Option<&str>,
     _new_tab: bool,
+    _options: &ToolOptions,
 ) -> Result<(String, String, Vec<DiagnosticStep>), McpToolError> {
     let mut steps = Vec::new();
     steps.push(DiagnosticStep {
@@ -1321,49 +3094,62 @@ fn tool_open_terminal_and_run(
     )))
 }
 
+/// Hand `target` (URL or path) to the platform launcher: `start` on Windows, `open` on macOS,
+/// `xdg-open` elsewhere. `what` names the target in the error message.
+fn launch_default_handler(target: &str, what: &str) -> Result<(), McpToolError> {
+    // ShellExecute on Windows, `open` on macOS, xdg-open and friends elsewhere; no shell parses
+    // `target`, so it cannot smuggle in extra arguments or commands.
+    open::that_detached(target).map_err(|e| McpToolError::CommandFailed(format!("failed to open {}: {}", what, e)))
+}
+
 /// Open a URL in the default browser. Returns the opened URL.
 fn open_url_in_browser(url: &str) -> Result<String, McpToolError> {
     let url = url.trim();
     if url.is_empty() {
         return Err(McpToolError::InvalidArg("url cannot be empty".into()));
     }
-    #[cfg(windows)]
-    {
-        Command::new("cmd")
-            .args(["/C", "start", "", url])
-            .spawn()
-            .map_err(|e| McpToolError::CommandFailed(format!("failed to open browser: {}", e)))?;
-    }
-    #[cfg(target_os = "macos")]
-    {
-        Command::new("open").arg(url).spawn().map_err(|e| {
-            McpToolError::CommandFailed(format!("failed to open browser: {}", e))
-        })?;
-    }
-    #[cfg(not(any(windows, target_os = "macos")))]
-    {
-        Command::new("xdg-open").arg(url).spawn().map_err(|e| {
-            McpToolError::CommandFailed(format!("failed to open browser: {}", e))
-        })?;
-    }
+    launch_default_handler(url, "browser")?;
     Ok(url.to_string())
 }
 
-fn tool_open_browser_search(args: &ToolCallArgs) -> Result<String, McpToolError> {
-    let client = reqwest::blocking::Client::builder()
-        .timeout(Duration::from_secs(PAGE_EXCERPT_FETCH_TIMEOUT_SECS + 4))
-        .default_headers({
-            let mut h = reqwest::header::HeaderMap::new();
-            h.insert(
-                reqwest::header::USER_AGENT,
-                reqwest::header::HeaderValue::from_static(
-                    "Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0",
-                ),
-            );
-            h
-        })
-        .build()
-        .map_err(|e| McpToolError::Network(e.to_string()))?;
+/// Document extensions open_path opens by default (compared case-insensitively). Anything else,
+/// including programs and scripts, needs the user's open_path_allow_any_file setting.
+const OPENABLE_DOCUMENT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rtf", "pdf", "epub", "doc", "docx", "odt", "xls", "xlsx", "ods", "csv", "tsv",
+    "ppt", "pptx", "odp", "json", "xml", "yaml", "yml", "toml", "log", "html", "htm", "png", "jpg", "jpeg",
+    "gif", "webp", "bmp", "svg", "mp3", "wav", "ogg", "flac", "mp4", "webm", "mov",
+];
+
+fn has_document_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| OPENABLE_DOCUMENT_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Check `path` for open_path: must exist under root and be a folder or a document, unless the
+/// user allows any file. Returns the canonical absolute path.
+fn open_path_target(root: &Path, path: &str, allow_any_file: bool) -> Result<PathBuf, McpToolError> {
+    let target = validate_path_under_root(root, path)?;
+    if target.is_file() && !allow_any_file && !has_document_extension(&target) {
+        return Err(McpToolError::PathNotAllowed(format!(
+            "{} is not a document type open_path opens; the user can allow other files in settings",
+            path.trim()
+        )));
+    }
+    Ok(target)
+}
+
+/// Open a sandboxed file or folder with the default application. Returns the opened absolute path.
+fn tool_open_path(root: &Path, path: &str, allow_any_file: bool) -> Result<String, McpToolError> {
+    let target = open_path_target(root, path, allow_any_file)?;
+    let shown = target.to_string_lossy().to_string();
+    launch_default_handler(&shown, "path")?;
+    Ok(shown)
+}
+
+fn tool_open_browser_search(args: &ToolCallArgs, options: &ToolOptions) -> Result<String, McpToolError> {
+    let client = page_fetch_client(Duration::from_secs(clamp_timeout_secs(options.fetch_timeout_secs) + 4))?;
 
     let (opened_msg, url_to_fetch): (String, Option<String>) = if let Some(ref u) = args.url {
         let u = u.trim();
@@ -1402,48 +3188,323 @@ fn tool_open_browser_search(args: &ToolCallArgs) -> Result<String, McpToolError>
 
     let mut out = opened_msg;
     if let Some(ref url) = url_to_fetch {
-        if let Some(content) = fetch_url_content_impl(&client, url, OPEN_BROWSER_FETCH_MAX_CHARS) {
+        if let Ok(content) = fetch_url_content_impl(&client, url, OPEN_BROWSER_FETCH_MAX_CHARS, &HeaderMap::new()) {
             if !content.trim().is_empty() {
                 out.push_str("\n\nPage content (use this as context to summarize or answer; user did not paste this):\n\n");
                 out.push_str(&content);
             }
         }
-    }
-    Ok(out)
-}
-
-#[derive(Debug, Serialize)]
-pub struct ToolResult {
-    pub ok: bool,
-    pub content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub error: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub diagnostic_steps: Option<Vec<DiagnosticStep>>,
-}
-
-pub fn execute_tool(
-    name: &str,
-    args: &serde_json::Value,
-    filesystem_root: Option<&str>,
-    obsidian_vault: Option<&str>,
-) -> Result<ToolResult, McpToolError> {
-    let args: ToolCallArgs = serde_json::from_value(args.clone()).map_err(|e| {
-        McpToolError::InvalidArg(format!("Invalid arguments: {}", e))
-    })?;
-
-    let result = match name {
-        "read_file" => {
+    }
+    Ok(out)
+}
+
+/// Per-call settings from the MCP config that affect how tools behave (beyond which roots are enabled).
+#[derive(Debug, Clone)]
+pub struct ToolOptions {
+    /// Allow Wikipedia/Wikidata fallbacks when DuckDuckGo returns no results.
+    pub web_search_enable_fallbacks: bool,
+    /// User-defined command patterns blocked in addition to BLOCKED_COMMAND_PATTERNS.
+    pub terminal_extra_blocked_patterns: Vec<String>,
+    /// Match blocked patterns on word boundaries instead of as plain substrings.
+    pub terminal_block_word_boundaries: bool,
+    /// Scrape DuckDuckGo's HTML results page when the instant-answer API returns nothing.
+    pub web_search_html_fallback: bool,
+    /// Shell used by run_command (one of ALLOWED_TERMINAL_SHELLS); empty means the platform default.
+    pub terminal_shell: String,
+    /// Set by cancel_tool_execution. web_search checks it between excerpt fetches and returns
+    /// what it has so far.
+    pub cancel: Arc<AtomicBool>,
+    /// Page fetch timeout in seconds (fetch_url, excerpts, open_browser_search), clamped to 1–30.
+    pub fetch_timeout_secs: u64,
+    /// DuckDuckGo request timeout in seconds for web_search, clamped to 1–30.
+    pub web_search_timeout_secs: u64,
+    /// Refuse high-risk tools (and write tools with safe_mode_block_writes); see blocked_by_safe_mode.
+    pub safe_mode: bool,
+    pub safe_mode_block_writes: bool,
+    /// Refuse network tools (see needs_network) before they open any connection.
+    pub offline_mode: bool,
+    /// Let open_path open any file, not just folders and OPENABLE_DOCUMENT_EXTENSIONS.
+    pub open_path_allow_any_file: bool,
+    /// Let fetch_url follow redirects from https to plain http.
+    pub fetch_allow_insecure_redirect: bool,
+    /// Model access for summarize_file; None makes that tool fail.
+    pub summarizer: Option<Summarizer>,
+}
+
+impl Default for ToolOptions {
+    fn default() -> Self {
+        Self {
+            web_search_enable_fallbacks: true,
+            terminal_extra_blocked_patterns: Vec::new(),
+            terminal_block_word_boundaries: false,
+            web_search_html_fallback: false,
+            terminal_shell: String::new(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            fetch_timeout_secs: DEFAULT_FETCH_TIMEOUT_SECS,
+            web_search_timeout_secs: DEFAULT_WEB_SEARCH_TIMEOUT_SECS,
+            safe_mode: false,
+            safe_mode_block_writes: true,
+            offline_mode: false,
+            open_path_allow_any_file: false,
+            fetch_allow_insecure_redirect: false,
+            summarizer: None,
+        }
+    }
+}
+
+/// Whether tools with this id reach the internet (web_search with its Wikipedia/Wikidata
+/// fallbacks, fetch_url, open_browser_search); offline mode hides and refuses them.
+pub fn needs_network(tool_id: &str) -> bool {
+    matches!(tool_id, "web" | "web_search" | "browser")
+}
+
+/// Whether safe mode hides and refuses a tool of this risk level: always "high" (terminal), and
+/// "write" when `block_writes` is set.
+pub fn blocked_by_safe_mode(risk: &str, block_writes: bool) -> bool {
+    risk == "high" || (block_writes && risk == "write")
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolResult {
+    pub ok: bool,
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic_steps: Option<Vec<DiagnosticStep>>,
+    /// web_search only: the pipeline steps (validate/request/parse/fallbacks/done) with pass/fail,
+    /// same as the `steps` array in the JSON content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<WebSearchStep>>,
+}
+
+/// Strings shorter than this are never shortened when fitting a JSON tool result into the cap.
+const MIN_TRUNCATED_JSON_STRING_CHARS: usize = 80;
+const JSON_STRING_TRUNCATION_MARKER: &str = "… (truncated)";
+
+/// JSON pointers (RFC 6901) and char lengths of every string leaf in `value`.
+fn json_string_leaves(value: &serde_json::Value, pointer: String, out: &mut Vec<(String, usize)>) {
+    match value {
+        serde_json::Value::String(s) => out.push((pointer, s.chars().count())),
+        serde_json::Value::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                json_string_leaves(v, format!("{}/{}", pointer, i), out);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (k, v) in map {
+                let key = k.replace('~', "~0").replace('/', "~1");
+                json_string_leaves(v, format!("{}/{}", pointer, key), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Shrink the longest string fields of a JSON document until it serializes within `max_chars`,
+/// so the result stays valid JSON. None if it cannot be made small enough that way.
+fn shrink_json_strings(mut value: serde_json::Value, max_chars: usize) -> Option<String> {
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("truncated_to_chars".to_string(), serde_json::json!(max_chars));
+    }
+    let marker_chars = JSON_STRING_TRUNCATION_MARKER.chars().count();
+    loop {
+        let serialized = serde_json::to_string(&value).ok()?;
+        let len = serialized.chars().count();
+        if len <= max_chars {
+            return Some(serialized);
+        }
+        let mut leaves = Vec::new();
+        json_string_leaves(&value, String::new(), &mut leaves);
+        let (pointer, leaf_len) = leaves.into_iter().max_by_key(|(_, n)| *n)?;
+        if leaf_len <= MIN_TRUNCATED_JSON_STRING_CHARS {
+            return None;
+        }
+        let keep = leaf_len
+            .saturating_sub(len - max_chars + marker_chars)
+            .max(MIN_TRUNCATED_JSON_STRING_CHARS);
+        if let Some(serde_json::Value::String(s)) = value.pointer_mut(&pointer) {
+            let mut cut: String = s.chars().take(keep).collect();
+            cut.push_str(JSON_STRING_TRUNCATION_MARKER);
+            *s = cut;
+        }
+    }
+}
+
+/// Fit a tool result into `max_chars` for the model's context window. JSON results (web_search,
+/// run_command) keep their structure: long string fields are shortened instead of cutting mid-JSON.
+/// Other text is cut and gets a "(truncated to N chars)" suffix. None when no truncation is needed.
+pub fn truncate_tool_content(content: &str, max_chars: usize) -> Option<String> {
+    if content.chars().count() <= max_chars {
+        return None;
+    }
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(content) {
+        if value.is_object() || value.is_array() {
+            if let Some(shrunk) = shrink_json_strings(value, max_chars) {
+                return Some(shrunk);
+            }
+        }
+    }
+    let kept: String = content.chars().take(max_chars).collect();
+    Some(format!("{}\n… (truncated to {} chars)", kept.trim_end(), max_chars))
+}
+
+/// The failed-tool message fed back to the model: `{"tool", "ok": false, "error", "hint"}` on one
+/// line. Whitespace in the error is collapsed (stack traces, command output) and it is cut to
+/// TOOL_ERROR_MAX_CHARS so a long error cannot crowd out the conversation.
+pub fn compact_tool_error(tool: &str, error: &str, hint: &str) -> String {
+    let error = error.split_whitespace().collect::<Vec<_>>().join(" ");
+    let chars = error.chars().count();
+    let error = if chars > TOOL_ERROR_MAX_CHARS {
+        let kept: String = error.chars().take(TOOL_ERROR_MAX_CHARS).collect();
+        format!("{}… ({} more chars omitted)", kept.trim_end(), chars - TOOL_ERROR_MAX_CHARS)
+    } else {
+        error
+    };
+    serde_json::json!({ "tool": tool, "ok": false, "error": error, "hint": hint }).to_string()
+}
+
+pub fn execute_tool(
+    name: &str,
+    args: &serde_json::Value,
+    filesystem_root: Option<&str>,
+    obsidian_vault: Option<&str>,
+    options: &ToolOptions,
+) -> Result<ToolResult, McpToolError> {
+    if options.safe_mode || options.offline_mode {
+        if let Some(def) = all_tool_definitions().into_iter().find(|d| d.name == name) {
+            if options.offline_mode && needs_network(&def.id) {
+                return Err(McpToolError::OfflineMode(format!("{} needs network access", name)));
+            }
+            if options.safe_mode && blocked_by_safe_mode(&def.risk, options.safe_mode_block_writes) {
+                return Err(McpToolError::SafeMode(format!("{} ({} risk) is disabled", name, def.risk)));
+            }
+        }
+    }
+    let args: ToolCallArgs = serde_json::from_value(args.clone()).map_err(|e| {
+        McpToolError::InvalidArg(format!("Invalid arguments: {}", e))
+    })?;
+
+    let result = match name {
+        "read_file" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
+            let content = tool_read_file(Path::new(root), &path, args.head, args.tail)?;
+            ToolResult {
+                ok: true,
+                content,
+                error: None,
+                diagnostic_steps: None,
+                steps: None,
+            }
+        }
+        "read_files" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let paths = args.paths.ok_or(McpToolError::InvalidArg("paths required".into()))?;
+            let out = tool_read_files(Path::new(root), &paths, READ_FILES_BUDGET_CHARS)?;
+            ToolResult {
+                ok: true,
+                content: serde_json::to_string(&out).map_err(|e| McpToolError::InvalidArg(format!("serialize: {}", e)))?,
+                error: None,
+                diagnostic_steps: None,
+                steps: None,
+            }
+        }
+        "summarize_file" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
+            let summarizer = options
+                .summarizer
+                .as_ref()
+                .ok_or_else(|| McpToolError::CommandFailed("summarize_file needs the chat model, which is not available".into()))?;
+            let mut steps = Vec::new();
+            let out = tool_summarize_file(
+                Path::new(root),
+                &path,
+                args.target_words.unwrap_or(DEFAULT_SUMMARY_WORDS),
+                summarizer,
+                &options.cancel,
+                &mut steps,
+            )?;
+            ToolResult {
+                ok: true,
+                content: serde_json::to_string(&out).map_err(|e| McpToolError::InvalidArg(format!("serialize: {}", e)))?,
+                error: None,
+                diagnostic_steps: Some(steps),
+                steps: None,
+            }
+        }
+        "read_bytes" => {
             let root = filesystem_root
                 .filter(|s| !s.trim().is_empty())
                 .ok_or(McpToolError::RootNotConfigured)?;
             let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
-            let content = tool_read_file(Path::new(root), &path, args.head, args.tail)?;
+            let content = tool_read_bytes(
+                Path::new(root),
+                &path,
+                args.offset.unwrap_or(0),
+                args.length.unwrap_or(DEFAULT_READ_BYTES),
+            )?;
+            ToolResult {
+                ok: true,
+                content,
+                error: None,
+                diagnostic_steps: None,
+                steps: None,
+            }
+        }
+        "ingest_file" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
+            let plan = tool_ingest_file(
+                Path::new(root),
+                &path,
+                args.chunk_size.unwrap_or(DEFAULT_INGEST_CHUNK_BYTES),
+            )?;
+            ToolResult {
+                ok: true,
+                content: serde_json::to_string(&plan).map_err(|e| McpToolError::InvalidArg(format!("serialize: {}", e)))?,
+                error: None,
+                diagnostic_steps: None,
+                steps: None,
+            }
+        }
+        "read_file_range" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
+            let offset = args.offset.ok_or(McpToolError::InvalidArg("offset required".into()))?;
+            let length = args.length.ok_or(McpToolError::InvalidArg("length required".into()))?;
+            let content = tool_read_file_range(Path::new(root), &path, offset, length)?;
             ToolResult {
                 ok: true,
                 content,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
+            }
+        }
+        "open_path" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
+            let opened = tool_open_path(Path::new(root), &path, options.open_path_allow_any_file)?;
+            ToolResult {
+                ok: true,
+                content: format!("Opened {}", opened),
+                error: None,
+                diagnostic_steps: None,
+                steps: None,
             }
         }
         "write_file" => {
@@ -1458,6 +3519,31 @@ pub fn execute_tool(
                 content: msg,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
+            }
+        }
+        "replace_in_file" => {
+            let root = filesystem_root
+                .filter(|s| !s.trim().is_empty())
+                .ok_or(McpToolError::RootNotConfigured)?;
+            let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
+            let search = args.search.ok_or(McpToolError::InvalidArg("search required".into()))?;
+            let replace = args.replace.ok_or(McpToolError::InvalidArg("replace required".into()))?;
+            let (_, msg) = tool_replace_in_file(
+                Path::new(root),
+                &path,
+                &search,
+                &replace,
+                args.count,
+                args.all.unwrap_or(false),
+                args.allow_no_match.unwrap_or(false),
+            )?;
+            ToolResult {
+                ok: true,
+                content: msg,
+                error: None,
+                diagnostic_steps: None,
+                steps: None,
             }
         }
         "list_dir" => {
@@ -1465,12 +3551,20 @@ pub fn execute_tool(
                 .filter(|s| !s.trim().is_empty())
                 .ok_or(McpToolError::RootNotConfigured)?;
             let path = args.path.unwrap_or_else(|| ".".to_string());
-            let content = tool_list_dir(Path::new(root), &path, args.depth)?;
+            let options = ListDirOptions {
+                sort: args.sort,
+                dirs_first: args.dirs_first.unwrap_or(false),
+                pattern: args.pattern,
+                show_sizes: args.show_sizes.unwrap_or(false),
+                json: args.output_format.as_deref() == Some("json"),
+            };
+            let content = tool_list_dir(Path::new(root), &path, args.depth, &options)?;
             ToolResult {
                 ok: true,
                 content,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
             }
         }
         "obsidian_read_note" => {
@@ -1484,6 +3578,7 @@ pub fn execute_tool(
                 content,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
             }
         }
         "obsidian_write_note" => {
@@ -1492,12 +3587,14 @@ pub fn execute_tool(
                 .ok_or(McpToolError::RootNotConfigured)?;
             let path = args.path.ok_or(McpToolError::InvalidArg("path required".into()))?;
             let content = args.content.unwrap_or_default();
-            let msg = tool_write_file(Path::new(root), &path, &content)?;
+            let replace_frontmatter = args.replace_frontmatter.unwrap_or(false);
+            let msg = tool_obsidian_write_note(Path::new(root), &path, &content, replace_frontmatter)?;
             ToolResult {
                 ok: true,
                 content: msg,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
             }
         }
         "obsidian_list_notes" => {
@@ -1505,17 +3602,18 @@ pub fn execute_tool(
                 .filter(|s| !s.trim().is_empty())
                 .ok_or(McpToolError::RootNotConfigured)?;
             let path = args.path.unwrap_or_else(|| ".".to_string());
-            let content = tool_list_dir(Path::new(root), &path, args.depth)?;
+            let content = tool_list_dir(Path::new(root), &path, args.depth, &ListDirOptions::default())?;
             ToolResult {
                 ok: true,
                 content,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
             }
         }
         "web_search" => {
             let query = args.query.ok_or(McpToolError::InvalidArg("query required".into()))?;
-            let max_results = args.max_results.unwrap_or(5).min(10).max(1);
+            let max_results = args.max_results.unwrap_or(5).clamp(1, 10);
             let (query_rewritten, recency_days) = rewrite_web_search_query(&query, 30);
             let mut diag_steps = Vec::new();
             let mut output_steps = Vec::new();
@@ -1541,7 +3639,9 @@ pub fn execute_tool(
             });
 
             let client = reqwest::blocking::Client::builder()
-                .timeout(Duration::from_secs(10))
+                .timeout(Duration::from_secs(clamp_timeout_secs(options.web_search_timeout_secs)))
+                .gzip(true)
+                .deflate(true)
                 .user_agent("Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0")
                 .default_headers({
                     let mut h = reqwest::header::HeaderMap::new();
@@ -1581,6 +3681,7 @@ pub fn execute_tool(
                         content: serde_json::to_string(&out).unwrap_or_else(|_| "{}".to_string()),
                         error: Some(format!("web_search request failed: {}", e)),
                         diagnostic_steps: Some(diag_steps),
+                        steps: Some(out.steps.clone()),
                     });
                 }
             };
@@ -1621,6 +3722,7 @@ pub fn execute_tool(
                     content: serde_json::to_string(&out).unwrap_or_else(|_| "{}".to_string()),
                     error: Some(format!("HTTP {}", status)),
                     diagnostic_steps: Some(diag_steps),
+                    steps: Some(out.steps.clone()),
                 });
             }
 
@@ -1650,6 +3752,7 @@ pub fn execute_tool(
                         content: serde_json::to_string(&out).unwrap_or_else(|_| "{}".to_string()),
                         error: Some(e.to_string()),
                         diagnostic_steps: Some(diag_steps),
+                        steps: Some(out.steps.clone()),
                     });
                 }
             };
@@ -1668,7 +3771,44 @@ pub fn execute_tool(
                 detail: format!("result_count {}", results.len()),
             });
 
-            if results.is_empty() {
+            if results.is_empty() && options.web_search_html_fallback {
+                let html_results = duckduckgo_html_search(&client, query_rewritten.trim(), max_results as usize);
+                let (ok, detail) = match &html_results {
+                    Ok(r) if !r.is_empty() => (true, format!("{} result(s)", r.len())),
+                    Ok(_) => (false, "no results".to_string()),
+                    Err(e) => (false, e.to_string()),
+                };
+                diag_steps.push(DiagnosticStep {
+                    level: "INFO".to_string(),
+                    message: format!("Step 4a: DuckDuckGo HTML fallback: {}", detail),
+                    meta: Some(serde_json::json!({ "web_search_html_fallback": true, "ok": ok })),
+                });
+                output_steps.push(WebSearchStep {
+                    name: "duckduckgo_html".to_string(),
+                    ok,
+                    detail,
+                });
+                if let Ok(r) = html_results {
+                    if !r.is_empty() {
+                        results = r;
+                        provider = "duckduckgo_html".to_string();
+                    }
+                }
+            }
+
+            if results.is_empty() && !options.web_search_enable_fallbacks {
+                suggest_open_browser_search = Some(true);
+                diag_steps.push(DiagnosticStep {
+                    level: "INFO".to_string(),
+                    message: "Step 4b: fallbacks skipped (disabled in settings)".to_string(),
+                    meta: Some(serde_json::json!({ "web_search_enable_fallbacks": false })),
+                });
+                output_steps.push(WebSearchStep {
+                    name: "fallback_skipped".to_string(),
+                    ok: false,
+                    detail: "fallbacks disabled in settings; suggest open_browser_search".to_string(),
+                });
+            } else if results.is_empty() {
                 diag_steps.push(DiagnosticStep {
                     level: "INFO".to_string(),
                     message: "Step 4b: fallback selection (DDG returned 0 results)".to_string(),
@@ -1734,17 +3874,62 @@ pub fn execute_tool(
 
             let include_excerpts = args.include_page_excerpts.unwrap_or(true);
             if include_excerpts && !results.is_empty() {
-                for r in results.iter_mut().take(PAGE_EXCERPT_MAX_RESULTS) {
-                    if let Some(excerpt) = fetch_page_excerpt(&client, &r.url) {
-                        r.page_excerpt = Some(excerpt);
+                let excerpt_cap = args
+                    .max_excerpts
+                    .map(|n| n.clamp(1, 10) as usize)
+                    .unwrap_or(PAGE_EXCERPT_MAX_RESULTS);
+                let max_excerpts = (max_results as usize).min(excerpt_cap);
+                match page_fetch_client(Duration::from_secs(clamp_timeout_secs(options.fetch_timeout_secs))) {
+                    Ok(excerpt_client) => {
+                        let (with_excerpts, budget_used, canceled) = fetch_page_excerpts_within_budget(
+                            &excerpt_client,
+                            &mut results,
+                            max_excerpts,
+                            PAGE_EXCERPT_TOTAL_BUDGET_CHARS,
+                            &options.cancel,
+                        );
+                        diag_steps.push(DiagnosticStep {
+                            level: "INFO".to_string(),
+                            message: format!("Step 4c: page excerpts fetched for {} result(s)", with_excerpts),
+                            meta: Some(serde_json::json!({
+                                "include_page_excerpts": true,
+                                "with_excerpts": with_excerpts,
+                                "max_excerpts": max_excerpts,
+                                "budget_used_chars": budget_used,
+                                "budget_total_chars": PAGE_EXCERPT_TOTAL_BUDGET_CHARS
+                            })),
+                        });
+                        output_steps.push(WebSearchStep {
+                            name: "excerpts".to_string(),
+                            ok: true,
+                            detail: format!(
+                                "{} excerpt(s), {}/{} chars of budget",
+                                with_excerpts, budget_used, PAGE_EXCERPT_TOTAL_BUDGET_CHARS
+                            ),
+                        });
+                        if canceled {
+                            // Results found so far are still returned; only the remaining excerpts are skipped.
+                            output_steps.push(WebSearchStep {
+                                name: "partial".to_string(),
+                                ok: false,
+                                detail: format!("canceled after {} excerpt(s); remaining pages not fetched", with_excerpts),
+                            });
+                        }
+                    }
+                    Err(e) => {
+                        // The snippets are still useful; return them without page content.
+                        diag_steps.push(DiagnosticStep {
+                            level: "WARN".to_string(),
+                            message: format!("Step 4c: page excerpts skipped ({})", e),
+                            meta: None,
+                        });
+                        output_steps.push(WebSearchStep {
+                            name: "excerpts".to_string(),
+                            ok: false,
+                            detail: format!("skipped: {}", e),
+                        });
                     }
                 }
-                let with_excerpts = results.iter().filter(|r| r.page_excerpt.is_some()).count();
-                diag_steps.push(DiagnosticStep {
-                    level: "INFO".to_string(),
-                    message: format!("Step 4c: page excerpts fetched for {} result(s)", with_excerpts),
-                    meta: Some(serde_json::json!({ "include_page_excerpts": true, "with_excerpts": with_excerpts })),
-                });
             }
 
             let result_count = results.len();
@@ -1783,6 +3968,7 @@ pub fn execute_tool(
                 content,
                 error: None,
                 diagnostic_steps: Some(diag_steps),
+                steps: Some(out.steps.clone()),
             }
         }
         "fetch_url" => {
@@ -1794,46 +3980,75 @@ pub fn execute_tool(
             let max_chars = args
                 .max_chars
                 .unwrap_or(12000)
-                .min(20000)
-                .max(500) as usize;
-            let client = reqwest::blocking::Client::builder()
-                .timeout(Duration::from_secs(PAGE_EXCERPT_FETCH_TIMEOUT_SECS))
-                .default_headers({
-                    let mut h = reqwest::header::HeaderMap::new();
-                    h.insert(
-                        reqwest::header::USER_AGENT,
-                        reqwest::header::HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0"),
-                    );
-                    h
-                })
-                .build()
-                .map_err(|e| McpToolError::Network(e.to_string()))?;
-            match fetch_url_content(&client, url.trim(), max_chars) {
-                Ok(text) => ToolResult {
-                    ok: true,
-                    content: format!("Page content (use this as context to summarize or answer; user did not paste this):\n\n{}", text),
-                    error: None,
-                    diagnostic_steps: None,
-                },
-                Err(e) => ToolResult {
-                    ok: false,
-                    content: String::new(),
-                    error: Some(e.to_string()),
-                    diagnostic_steps: None,
-                },
+                .clamp(500, 20000) as usize;
+            let headers = custom_request_headers(args.headers.as_ref())?;
+            let hops = Arc::new(std::sync::Mutex::new(Vec::new()));
+            let client = redirect_recording_client(
+                Duration::from_secs(clamp_timeout_secs(options.fetch_timeout_secs)),
+                hops.clone(),
+                options.fetch_allow_insecure_redirect,
+            )?;
+            let extract_tables = args.extract_tables.unwrap_or(false);
+            let mut meta = FetchMeta::default();
+            let fetched = if extract_tables {
+                fetch_url_tables(&client, url.trim(), max_chars, &headers, &mut meta)
+            } else {
+                fetch_url_content(&client, url.trim(), max_chars, &headers, &mut meta)
+            };
+            let redirects = hops.lock().map(|h| h.clone()).unwrap_or_default();
+            let (content, error) = match fetched {
+                Ok(text) => (text, None),
+                Err(e) => (String::new(), Some(e.to_string())),
+            };
+            let out = FetchUrlOutput {
+                ok: error.is_none(),
+                url: url.trim().to_string(),
+                final_url: meta.final_url,
+                status: meta.status,
+                content_type: meta.content_type,
+                tables: extract_tables,
+                char_count: content.chars().count(),
+                content,
+                redirects,
+                error: error.clone(),
+            };
+            let content = if out.ok && args.output_format.as_deref() == Some("text") {
+                out.to_text()
+            } else {
+                serde_json::to_string(&out).map_err(|e| McpToolError::InvalidArg(format!("serialize: {}", e)))?
+            };
+            ToolResult {
+                ok: out.ok,
+                content,
+                error,
+                diagnostic_steps: None,
+                steps: None,
             }
         }
+        "system_info" => ToolResult {
+            ok: true,
+            content: tool_system_info(options),
+            error: None,
+            diagnostic_steps: None,
+            steps: None,
+        },
         "run_command" => {
             let command = args.command.ok_or(McpToolError::InvalidArg("command required".into()))?;
             if command.trim().is_empty() {
                 return Err(McpToolError::InvalidArg("command cannot be empty".into()));
             }
-            let content = tool_run_command(command.trim(), args.working_directory.as_deref())?;
+            let out = tool_run_command(command.trim(), args.working_directory.as_deref(), options)?;
+            let content = if args.output_format.as_deref() == Some("text") {
+                out.to_text()
+            } else {
+                serde_json::to_string(&out).map_err(|e| McpToolError::InvalidArg(format!("serialize: {}", e)))?
+            };
             ToolResult {
                 ok: true,
                 content,
                 error: None,
                 diagnostic_steps: None,
+                steps: None,
             }
         }
         "open_terminal_and_run" => {
@@ -1843,12 +4058,13 @@ pub fn execute_tool(
             let keep_open = true;
             let new_tab = args.new_tab.unwrap_or(false);
             let working_directory = args.working_directory.as_deref();
-            match tool_open_terminal_and_run(shell, command.trim(), keep_open, working_directory, new_tab) {
+            match tool_open_terminal_and_run(shell, command.trim(), keep_open, working_directory, new_tab, options) {
                 Ok((content, _shell_used, steps)) => ToolResult {
                     ok: true,
                     content,
                     error: None,
                     diagnostic_steps: Some(steps),
+                    steps: None,
                 },
                 Err(e) => {
                     let msg = e.to_string();
@@ -1857,23 +4073,26 @@ pub fn execute_tool(
                         content: String::new(),
                         error: Some(msg.clone()),
                         diagnostic_steps: None,
+                        steps: None,
                     }
                 }
             }
         }
         "open_browser_search" => {
-            match tool_open_browser_search(&args) {
+            match tool_open_browser_search(&args, options) {
                 Ok(content) => ToolResult {
                     ok: true,
                     content,
                     error: None,
                     diagnostic_steps: None,
+                    steps: None,
                 },
                 Err(e) => ToolResult {
                     ok: false,
                     content: String::new(),
                     error: Some(e.to_string()),
                     diagnostic_steps: None,
+                    steps: None,
                 },
             }
         }
@@ -1886,6 +4105,632 @@ pub fn execute_tool(
 mod tests {
     use super::*;
 
+    #[test]
+    fn tool_timeouts_are_clamped() {
+        assert_eq!(clamp_timeout_secs(0), 1);
+        assert_eq!(clamp_timeout_secs(12), 12);
+        assert_eq!(clamp_timeout_secs(600), 30);
+        let opts = ToolOptions::default();
+        assert_eq!(opts.fetch_timeout_secs, DEFAULT_FETCH_TIMEOUT_SECS);
+        assert_eq!(opts.web_search_timeout_secs, DEFAULT_WEB_SEARCH_TIMEOUT_SECS);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn run_command_returns_structured_output() {
+        let tmp = std::env::temp_dir();
+        let out = tool_run_command(
+            "echo hello; echo oops >&2; exit 3",
+            Some(tmp.to_str().unwrap()),
+            &ToolOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(out.exit_code, Some(3));
+        assert_eq!(out.stdout.trim(), "hello");
+        assert_eq!(out.stderr.trim(), "oops");
+        assert!(!out.killed);
+        let text = out.to_text();
+        assert!(text.contains("Exit code: 3"));
+        assert!(text.contains("STDOUT:\nhello"));
+    }
+
+    #[test]
+    fn blocked_commands_builtin_extra_and_word_boundaries() {
+        let mut options = ToolOptions::default();
+        assert!(is_command_blocked("sudo REBOOT now", &options));
+        assert!(is_command_blocked("./reboot-checker.sh", &options));
+        assert!(!is_command_blocked("./cleanup-prod.sh", &options));
+        options.terminal_extra_blocked_patterns = vec!["cleanup-prod".to_string(), "  ".to_string()];
+        assert!(is_command_blocked("./cleanup-prod.sh", &options));
+        options.terminal_block_word_boundaries = true;
+        assert!(!is_command_blocked("./reboot-checker.sh", &options));
+        assert!(is_command_blocked("sudo reboot", &options));
+        assert!(is_command_blocked("rm -rf /", &options));
+        assert!(is_command_blocked("x; cleanup-prod --all", &options));
+    }
+
+    #[test]
+    fn classify_content_type_handles_text_pdf_and_binary() {
+        assert_eq!(classify_content_type(None), FetchedContentKind::Markup);
+        assert_eq!(classify_content_type(Some("text/html; charset=utf-8")), FetchedContentKind::Markup);
+        assert_eq!(classify_content_type(Some("application/atom+xml")), FetchedContentKind::Markup);
+        assert_eq!(classify_content_type(Some("text/plain")), FetchedContentKind::Text);
+        assert_eq!(classify_content_type(Some("application/ld+json")), FetchedContentKind::Text);
+        assert_eq!(classify_content_type(Some("Application/PDF")), FetchedContentKind::Pdf);
+        assert_eq!(classify_content_type(Some("image/png")), FetchedContentKind::Binary);
+        assert_eq!(classify_content_type(Some("application/octet-stream")), FetchedContentKind::Binary);
+    }
+
+    #[test]
+    fn parse_duckduckgo_html_results_extracts_links_and_snippets() {
+        let html = r#"
+            <div class="result results_links result--ad">
+              <h2 class="result__title"><a rel="nofollow" class="result__a" href="https://duckduckgo.com/y.js?ad_provider=x">Sponsored</a></h2>
+            </div>
+            <div class="result results_links web-result">
+              <h2 class="result__title">
+                <a rel="nofollow" class="result__a" href="//duckduckgo.com/l/?uddg=https%3A%2F%2Fwww.rust%2Dlang.org%2Flearn&amp;rut=abc">Learn <b>Rust</b></a>
+              </h2>
+              <a class="result__snippet" href="//duckduckgo.com/l/?uddg=x">The Rust book &amp; more, it&#x27;s free.</a>
+            </div>
+            <div class="result results_links web-result">
+              <h2 class="result__title"><a rel="nofollow" class="result__a" href="https://doc.rust-lang.org/std/">std docs</a></h2>
+            </div>
+            <div class="result results_links web-result">
+              <h2 class="result__title"><a class="result__a" href="https://example.com/third">Third</a></h2>
+            </div>"#;
+        let results = parse_duckduckgo_html_results(html, 2);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].url, "https://www.rust-lang.org/learn");
+        assert_eq!(results[0].title, "Learn Rust");
+        assert_eq!(results[0].snippet, "The Rust book & more, it's free.");
+        assert_eq!(results[1].url, "https://doc.rust-lang.org/std/");
+        assert_eq!(results[1].snippet, "");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn list_dir_marks_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        std::fs::write(root.join("good.txt"), "x").unwrap();
+        let bad = std::ffi::OsStr::from_bytes(b"bad\xffname.txt");
+        std::fs::write(root.join(bad), "x").unwrap();
+        let listing = tool_list_dir(&root, ".", None, &ListDirOptions::default()).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.contains(&"good.txt"));
+        assert!(lines.iter().any(|l| l.starts_with("bad\u{fffd}name.txt") && l.ends_with(NON_UTF8_NAME_NOTE)));
+    }
+
+    #[test]
+    fn list_dir_sorts_filters_and_shows_sizes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        std::fs::create_dir_all(root.join("zdir")).unwrap();
+        std::fs::write(root.join("a.rs"), "x").unwrap();
+        std::fs::write(root.join("big.txt"), "x".repeat(2048)).unwrap();
+        std::fs::write(root.join("main.RS"), "xx").unwrap();
+        let list = |options: ListDirOptions| tool_list_dir(&root, ".", None, &options).unwrap();
+        assert_eq!(list(ListDirOptions::default()), "a.rs\nbig.txt\nmain.RS\nzdir/");
+        let dirs_first = ListDirOptions { dirs_first: true, ..Default::default() };
+        assert_eq!(list(dirs_first), "zdir/\na.rs\nbig.txt\nmain.RS");
+        let by_size = ListDirOptions { sort: Some("size".into()), show_sizes: true, ..Default::default() };
+        assert_eq!(list(by_size), "big.txt  (2.0 KB)\nmain.RS  (2 B)\na.rs  (1 B)\nzdir/");
+        let glob = ListDirOptions { pattern: Some("*.rs".into()), ..Default::default() };
+        assert_eq!(list(glob), "a.rs\nmain.RS");
+        let substring = ListDirOptions { pattern: Some("BIG".into()), ..Default::default() };
+        assert_eq!(list(substring), "big.txt");
+        let bad_sort = ListDirOptions { sort: Some("color".into()), ..Default::default() };
+        assert!(tool_list_dir(&root, ".", None, &bad_sort).is_err());
+        assert!(name_matches_pattern("report-2024.csv", "report-*.c?v"));
+        assert!(!name_matches_pattern("report.csv", "*.txt"));
+    }
+
+    #[test]
+    fn list_dir_json_format() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        std::fs::create_dir_all(root.join("sub/inner")).unwrap();
+        std::fs::write(root.join("sub/a.txt"), "abc").unwrap();
+        let options = ListDirOptions { json: true, ..Default::default() };
+        let listing = tool_list_dir(&root, "./sub/", Some(2), &options).unwrap();
+        let text = tool_list_dir(&root, "sub", Some(2), &ListDirOptions::default()).unwrap();
+        let entries: serde_json::Value = serde_json::from_str(&listing).unwrap();
+        assert_eq!(
+            entries,
+            serde_json::json!([
+                { "name": "a.txt", "path": "sub/a.txt", "is_dir": false, "size": 3, "depth": 1 },
+                { "name": "inner", "path": "sub/inner", "is_dir": true, "depth": 1 }
+            ])
+        );
+        assert_eq!(text, "a.txt\ninner/");
+        let args: ToolCallArgs = serde_json::from_value(serde_json::json!({ "path": ".", "format": "json" })).unwrap();
+        assert_eq!(args.output_format.as_deref(), Some("json"));
+    }
+
+    #[test]
+    fn excerpt_fetch_stops_when_canceled() {
+        let client = reqwest::blocking::Client::new();
+        let mut results = vec![
+            WebSearchResultItem {
+                title: "a".to_string(),
+                snippet: String::new(),
+                url: "http://127.0.0.1:9/a".to_string(),
+                page_excerpt: None,
+            };
+            2
+        ];
+        let cancel = AtomicBool::new(true);
+        let (fetched, used, canceled) = fetch_page_excerpts_within_budget(&client, &mut results, 2, 8000, &cancel);
+        assert_eq!((fetched, used, canceled), (0, 0, true));
+        assert!(results.iter().all(|r| r.page_excerpt.is_none()));
+    }
+
+    #[test]
+    fn truncate_tool_content_keeps_json_valid() {
+        assert_eq!(truncate_tool_content("short", 100), None);
+
+        let text = "line\n".repeat(100);
+        let cut = truncate_tool_content(&text, 50).unwrap();
+        assert!(cut.ends_with("(truncated to 50 chars)"));
+        assert!(cut.starts_with("line\nline"));
+
+        let json = serde_json::json!({
+            "exit_code": 0,
+            "stdout": "x".repeat(5000),
+            "stderr": "warning ".repeat(200),
+        })
+        .to_string();
+        let cut = truncate_tool_content(&json, 1000).unwrap();
+        assert!(cut.chars().count() <= 1000);
+        let parsed: serde_json::Value = serde_json::from_str(&cut).expect("still valid JSON");
+        assert_eq!(parsed["exit_code"], 0);
+        assert_eq!(parsed["truncated_to_chars"], 1000);
+        assert!(parsed["stdout"].as_str().unwrap().ends_with(JSON_STRING_TRUNCATION_MARKER));
+    }
+
+    #[test]
+    fn compact_tool_error_truncates_oversized_errors() {
+        let short = compact_tool_error("read_file", "IO: No such file\n  at line 3", "Check the path.");
+        let parsed: serde_json::Value = serde_json::from_str(&short).unwrap();
+        assert_eq!(parsed["tool"], "read_file");
+        assert_eq!(parsed["ok"], false);
+        assert_eq!(parsed["error"], "IO: No such file at line 3");
+        assert_eq!(parsed["hint"], "Check the path.");
+
+        let trace = "panicked at src/main.rs:10:5\n    frame 0x1234 ü\n".repeat(500);
+        let long = compact_tool_error("run_command", &trace, DEFAULT_TOOL_ERROR_HINT);
+        assert!(!long.contains('\n'));
+        let parsed: serde_json::Value = serde_json::from_str(&long).expect("still valid JSON");
+        let error = parsed["error"].as_str().unwrap();
+        assert!(error.starts_with("panicked at src/main.rs:10:5 frame"));
+        assert!(error.ends_with("more chars omitted)"));
+        assert!(error.chars().count() < TOOL_ERROR_MAX_CHARS + 40);
+        assert_eq!(parsed["hint"], DEFAULT_TOOL_ERROR_HINT);
+    }
+
+    #[test]
+    fn split_frontmatter_detects_closed_block() {
+        let note = "---\ntags: [a]\naliases: [x]\n---\n# Title\nbody\n";
+        assert_eq!(split_frontmatter(note), Some(("---\ntags: [a]\naliases: [x]\n---\n", "# Title\nbody\n")));
+        assert_eq!(split_frontmatter("---\r\ntags: a\r\n---\r\nbody").unwrap().1, "body");
+        assert!(split_frontmatter("# No frontmatter\n---\n").is_none());
+        assert!(split_frontmatter("---\nunterminated: true\nbody").is_none());
+        let huge = format!("---\n{}\n---\nbody", "k: v\n".repeat(MAX_FRONTMATTER_BYTES));
+        assert!(split_frontmatter(&huge).is_none());
+    }
+
+    #[test]
+    fn obsidian_write_note_preserves_existing_frontmatter() {
+        let tmp = tempfile::tempdir().unwrap();
+        let vault = tmp.path().to_path_buf();
+        let note = vault.join("note.md");
+        std::fs::write(&note, "---\ntags: [project]\n---\nold body\n").unwrap();
+
+        // Content without frontmatter: existing block is kept.
+        let msg = tool_obsidian_write_note(&vault, "note.md", "new body\n", false).unwrap();
+        assert!(msg.contains("kept existing frontmatter"));
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "---\ntags: [project]\n---\nnew body\n");
+
+        // Content with its own frontmatter replaces the old one.
+        tool_obsidian_write_note(&vault, "note.md", "---\ntags: [done]\n---\nbody 2\n", false).unwrap();
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "---\ntags: [done]\n---\nbody 2\n");
+
+        // Explicit override drops it.
+        tool_obsidian_write_note(&vault, "note.md", "plain\n", true).unwrap();
+        assert_eq!(std::fs::read_to_string(&note).unwrap(), "plain\n");
+
+        // New note without frontmatter is written as-is.
+        tool_obsidian_write_note(&vault, "new.md", "fresh\n", false).unwrap();
+        assert_eq!(std::fs::read_to_string(vault.join("new.md")).unwrap(), "fresh\n");
+    }
+
+    #[test]
+    fn extract_html_tables_parses_rows_and_caps() {
+        let html = r#"<html><script>var t = "<table><tr><td>x</td></tr></table>";</script>
+            <table class="pricing"><thead><tr><th>Plan</th><th>Price</th></tr></thead>
+            <tbody><tr><td>Free &amp; open</td><td>$0</td></tr>
+            <tr><td>Pro<br>annual</td><td>$10 | mo</td></tr></tbody></table>
+            <p>text</p><table><tr><td>only</td></tr></table></html>"#;
+        let tables = extract_html_tables(html, 10);
+        assert_eq!(tables.len(), 2);
+        assert_eq!(
+            tables[0].rows,
+            vec![
+                vec!["Plan".to_string(), "Price".to_string()],
+                vec!["Free & open".to_string(), "$0".to_string()],
+                vec!["Pro annual".to_string(), "$10 | mo".to_string()],
+            ]
+        );
+        assert_eq!(extract_html_tables(html, 1).len(), 1);
+        let shouting = "<SCRIPT>\"<table><tr><td>x</td></tr></table>\"</Script><TABLE><TR><TD>kept</TD></TR></TABLE>";
+        assert_eq!(extract_html_tables(shouting, 10)[0].rows, vec![vec!["kept".to_string()]]);
+        let text = format_extracted_tables(&tables);
+        assert!(text.starts_with("Table 1:\n| Plan | Price |"));
+        assert!(text.contains("| Pro annual | $10 \\| mo |"));
+
+        let many_rows = format!("<table>{}</table>", "<tr><td>r</td></tr>".repeat(MAX_EXTRACTED_TABLE_ROWS + 5));
+        let capped = extract_html_tables(&many_rows, 10);
+        assert_eq!(capped[0].rows.len(), MAX_EXTRACTED_TABLE_ROWS);
+        assert_eq!(capped[0].omitted_rows, 5);
+    }
+
+    #[test]
+    fn ingest_plan_chunks_at_line_ends_and_reads_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let text: String = (1..=400).map(|i| format!("line {:03} with some padding text\n", i)).collect();
+        std::fs::write(dir.join("big.txt"), &text).unwrap();
+        let plan = tool_ingest_file(&dir, "big.txt", 100).unwrap();
+        assert_eq!(plan.chunk_size, MIN_INGEST_CHUNK_BYTES);
+        assert_eq!(plan.size, text.len() as u64);
+        assert_eq!(plan.chunk_count, plan.chunks.len());
+        assert!(!plan.truncated);
+        assert_eq!(plan.chunks[0].preview, "line 001 with some padding text");
+        let mut rebuilt = String::new();
+        for (i, chunk) in plan.chunks.iter().enumerate() {
+            assert_eq!(chunk.index, i);
+            assert!(chunk.end - chunk.start <= MIN_INGEST_CHUNK_BYTES as u64);
+            let page = tool_read_file_range(&dir, "big.txt", chunk.start, (chunk.end - chunk.start) as u32).unwrap();
+            let (header, body) = page.split_once('\n').unwrap();
+            assert_eq!(header, format!("big.txt: bytes {}..{} of {}", chunk.start, chunk.end, text.len()));
+            assert!(body.ends_with('\n'));
+            rebuilt.push_str(body);
+        }
+        assert_eq!(rebuilt, text);
+        assert_eq!(plan.chunks[1].start_line, 1 + text[..plan.chunks[1].start as usize].matches('\n').count() as u64);
+        assert!(tool_read_file_range(&dir, "big.txt", text.len() as u64, 10).is_err());
+        assert!(tool_ingest_file(&dir, "../big.txt", 1024).is_err());
+    }
+
+    #[test]
+    fn ingest_cut_keeps_multibyte_characters_whole() {
+        // No newline: the cut must not split the trailing 3-byte "€".
+        let buf = "aaaa€".as_bytes();
+        assert_eq!(ingest_cut(&buf[..6]), 4);
+        assert_eq!(ingest_cut(buf), buf.len());
+        let (chunks, count) = plan_chunks("ab€cd€ef".as_bytes(), 4, 10).unwrap();
+        assert_eq!(count, chunks.len());
+        assert!(chunks.iter().all(|c| c.end > c.start));
+        assert_eq!(chunks.last().unwrap().end, "ab€cd€ef".len() as u64);
+    }
+
+    #[test]
+    fn read_bytes_dumps_hex_and_ascii() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend_from_slice(b"0123456789abcdefXYZ");
+        std::fs::write(dir.join("img.bin"), &data).unwrap();
+        let out = tool_read_bytes(&dir, "img.bin", 0, 16).unwrap();
+        assert!(out.starts_with("img.bin: 27 bytes total; bytes 0..16 (16 shown)\n"));
+        assert!(out.contains("00000000  89 50 4e 47 0d 0a 1a 0a  30 31 32 33 34 35 36 37  |.PNG....01234567|"));
+        let tail = tool_read_bytes(&dir, "img.bin", 24, 100).unwrap();
+        assert!(tail.contains("bytes 24..27 (3 shown)"));
+        assert!(tail.contains("00000018  58 59 5a"));
+        assert!(tool_read_bytes(&dir, "img.bin", 27, 1).is_err());
+        assert!(tool_read_bytes(&dir, "../img.bin", 0, 1).is_err());
+    }
+
+    #[test]
+    fn open_path_stays_in_root_and_only_opens_documents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("report.MD"), "x").unwrap();
+        std::fs::write(dir.join("setup.EXE"), "x").unwrap();
+        std::fs::write(dir.join("script.pyw"), "x").unwrap();
+        std::fs::write(dir.join("noext"), "x").unwrap();
+        let canonical = dir.canonicalize().unwrap();
+        assert_eq!(open_path_target(&dir, "report.MD", false).unwrap(), canonical.join("report.MD"));
+        assert!(open_path_target(&dir, "sub", false).is_ok());
+        for refused in ["setup.EXE", "script.pyw", "noext"] {
+            assert!(open_path_target(&dir, refused, false).unwrap_err().to_string().contains("settings"));
+        }
+        assert!(open_path_target(&dir, "setup.EXE", true).is_ok());
+        assert!(open_path_target(&dir, "../report.MD", false).is_err());
+        assert!(open_path_target(&dir, "missing.md", false).is_err());
+    }
+
+    #[test]
+    fn safe_mode_refuses_high_risk_and_optionally_write_tools() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let root = dir.to_string_lossy().to_string();
+        let mut opts = ToolOptions { safe_mode: true, ..ToolOptions::default() };
+        let err = execute_tool("run_command", &serde_json::json!({ "command": "echo hi" }), None, None, &opts).unwrap_err();
+        assert!(matches!(err, McpToolError::SafeMode(_)));
+        let write = serde_json::json!({ "path": "a.txt", "content": "x" });
+        assert!(matches!(
+            execute_tool("write_file", &write, Some(&root), None, &opts),
+            Err(McpToolError::SafeMode(_))
+        ));
+        opts.safe_mode_block_writes = false;
+        assert!(execute_tool("write_file", &write, Some(&root), None, &opts).unwrap().ok);
+        assert!(execute_tool("read_file", &serde_json::json!({ "path": "a.txt" }), Some(&root), None, &opts).unwrap().ok);
+    }
+
+    #[test]
+    fn summarize_file_chunks_long_files_and_combines() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let root = dir.to_string_lossy().to_string();
+        std::fs::write(dir.join("short.txt"), "one line\n").unwrap();
+        let line = "x".repeat(99) + "\n";
+        std::fs::write(dir.join("long.txt"), line.repeat(SUMMARIZE_CHUNK_CHARS * 2 / 100 + 1)).unwrap();
+        let prompts = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let seen = prompts.clone();
+        let opts = ToolOptions {
+            summarizer: Some(Summarizer(Arc::new(move |prompt: &str, _| {
+                seen.lock().unwrap().push(prompt.to_string());
+                Ok(" summary ".to_string())
+            }))),
+            ..ToolOptions::default()
+        };
+        let short = execute_tool("summarize_file", &serde_json::json!({ "path": "short.txt" }), Some(&root), None, &opts).unwrap();
+        let out: serde_json::Value = serde_json::from_str(&short.content).unwrap();
+        assert_eq!(out["chunks"], 1);
+        assert_eq!(out["summary"], "summary");
+        assert_eq!(prompts.lock().unwrap().len(), 1);
+        prompts.lock().unwrap().clear();
+        let long = execute_tool(
+            "summarize_file",
+            &serde_json::json!({ "path": "long.txt", "target_words": 50 }),
+            Some(&root),
+            None,
+            &opts,
+        )
+        .unwrap();
+        let out: serde_json::Value = serde_json::from_str(&long.content).unwrap();
+        assert_eq!(out["chunks"], 3);
+        // One call per chunk plus the combining call; each chunk step is logged.
+        assert_eq!(prompts.lock().unwrap().len(), 4);
+        assert!(prompts.lock().unwrap()[3].contains("Combine"));
+        assert_eq!(long.diagnostic_steps.unwrap().len(), 4);
+        let no_model = execute_tool("summarize_file", &serde_json::json!({ "path": "short.txt" }), Some(&root), None, &ToolOptions::default());
+        assert!(no_model.is_err());
+    }
+
+    #[test]
+    fn offline_mode_refuses_network_tools_without_connecting() {
+        let opts = ToolOptions { offline_mode: true, ..ToolOptions::default() };
+        for (name, args) in [
+            ("web_search", serde_json::json!({ "query": "rust" })),
+            ("fetch_url", serde_json::json!({ "url": "https://example.com" })),
+            ("open_browser_search", serde_json::json!({ "query": "rust" })),
+        ] {
+            let err = execute_tool(name, &args, None, None, &opts).unwrap_err();
+            assert!(matches!(err, McpToolError::OfflineMode(_)), "{}: {}", name, err);
+            assert!(err.to_string().starts_with("Offline mode enabled"));
+        }
+        let info = execute_tool("system_info", &serde_json::json!({}), None, None, &opts).unwrap();
+        assert!(info.ok);
+    }
+
+    #[test]
+    fn tool_availability_explains_unavailable_tools() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let root = dir.to_string_lossy().to_string();
+        let p = ToolPreconditions {
+            filesystem_enabled: true,
+            filesystem_root: &root,
+            obsidian_enabled: true,
+            obsidian_vault: "/definitely/not/a/vault",
+            web_search_enabled: true,
+            terminal_enabled: false,
+            network_reachable: false,
+            safe_mode: false,
+            safe_mode_block_writes: true,
+            offline_mode: false,
+        };
+        let all = tool_availability(&p);
+        let get = |name: &str| all.iter().find(|t| t.name == name).unwrap();
+        assert!(get("read_file").available);
+        let note = get("obsidian_read_note");
+        assert!(note.enabled && !note.available);
+        assert!(note.reason.as_deref().unwrap().contains("does not exist"));
+        assert_eq!(get("fetch_url").reason.as_deref(), Some("network unreachable"));
+        let terminal = get("run_command");
+        assert!(!terminal.enabled && !terminal.available);
+        assert_eq!(terminal.reason.as_deref(), Some("disabled in settings"));
+    }
+
+    #[test]
+    fn read_files_shares_budget_and_reports_per_file_errors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("a.txt"), "alpha").unwrap();
+        std::fs::write(dir.join("b.txt"), "bravo").unwrap();
+        std::fs::write(dir.join("c.txt"), "charlie").unwrap();
+        let paths: Vec<String> = ["a.txt", "missing.txt", "b.txt", "c.txt"].iter().map(|s| s.to_string()).collect();
+        let out = tool_read_files(&dir, &paths, 8).unwrap();
+        assert_eq!(out.files["a.txt"].content.as_deref(), Some("alpha"));
+        assert!(out.files["missing.txt"].error.is_some());
+        assert_eq!(out.files["b.txt"].content.as_deref(), Some("bra"));
+        assert!(out.files["b.txt"].truncated);
+        assert!(out.files["c.txt"].error.as_deref().unwrap().contains("budget"));
+        assert_eq!(out.chars_used, 8);
+        assert!(tool_read_files(&dir, &[], 8).is_err());
+        let too_many = vec!["a.txt".to_string(); MAX_READ_FILES + 1];
+        assert!(tool_read_files(&dir, &too_many, 8).is_err());
+    }
+
+    #[test]
+    fn replace_in_file_counts_and_respects_limits() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("a.txt"), "foo foo foo").unwrap();
+        let (n, _) = tool_replace_in_file(&dir, "a.txt", "foo", "bar", None, false, false).unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "bar foo foo");
+        let (n, _) = tool_replace_in_file(&dir, "a.txt", "foo", "baz", Some(5), false, false).unwrap();
+        assert_eq!(n, 2);
+        std::fs::write(dir.join("a.txt"), "aaa").unwrap();
+        let (n, _) = tool_replace_in_file(&dir, "a.txt", "a", "aa", None, true, false).unwrap();
+        assert_eq!(n, 3);
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "aaaaaa");
+        assert!(tool_replace_in_file(&dir, "a.txt", "zzz", "y", None, true, false).is_err());
+        assert_eq!(tool_replace_in_file(&dir, "a.txt", "zzz", "y", None, true, true).unwrap().0, 0);
+        assert!(tool_replace_in_file(&dir, "a.txt", "", "y", None, true, true).is_err());
+        assert!(tool_replace_in_file(&dir, "../a.txt", "a", "b", None, false, false).is_err());
+    }
+
+    #[test]
+    fn tool_root_must_be_an_existing_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("note.md"), "x").unwrap();
+        assert!(validate_tool_root(&dir.to_string_lossy()).is_ok());
+        assert_eq!(validate_tool_root("  ").unwrap_err(), "path is empty");
+        assert!(validate_tool_root(&dir.join("note.md").to_string_lossy()).unwrap_err().contains("is not a directory"));
+        assert!(validate_tool_root(&dir.join("missing").to_string_lossy()).unwrap_err().contains("does not exist"));
+        let canonical = dir.canonicalize().unwrap().to_string_lossy().trim_start_matches(r"\\?\").to_string();
+        let pasted = format!("  \"{}{}\" ", dir.to_string_lossy(), std::path::MAIN_SEPARATOR);
+        assert_eq!(normalize_tool_root(&pasted).unwrap(), canonical);
+        if let Some(home) = dirs::home_dir().filter(|h| h.is_dir()) {
+            assert_eq!(normalize_tool_root("~").unwrap(), home.canonicalize().unwrap().to_string_lossy().trim_start_matches(r"\\?\"));
+        }
+        assert_eq!(normalize_tool_root("''").unwrap_err(), "path is empty");
+    }
+
+    #[test]
+    fn system_info_reports_platform_without_env_dump() {
+        let result = execute_tool("system_info", &serde_json::json!({}), None, None, &ToolOptions::default()).unwrap();
+        let info: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(info["os"], std::env::consts::OS);
+        assert_eq!(info["arch"], std::env::consts::ARCH);
+        assert!(info["gpu_detected"].is_boolean());
+        assert!(info.get("path").is_none() && info.get("env").is_none());
+    }
+
+    #[test]
+    fn terminal_shell_is_allowlisted_with_platform_default() {
+        assert!(validate_terminal_shell("").is_ok());
+        assert!(validate_terminal_shell(ALLOWED_TERMINAL_SHELLS[1]).is_ok());
+        assert!(validate_terminal_shell("/tmp/evil.sh").is_err());
+        assert_eq!(run_command_shell(""), DEFAULT_TERMINAL_SHELL);
+        assert_eq!(run_command_shell("python"), DEFAULT_TERMINAL_SHELL);
+        assert_eq!(shell_command_flags("powershell").last(), Some(&"-Command"));
+        assert_eq!(shell_command_flags("bash"), ["-c"]);
+    }
+
+    #[test]
+    fn custom_request_headers_rejects_unsafe_overrides() {
+        assert!(custom_request_headers(None).unwrap().is_empty());
+        let ok = HashMap::from([("Accept".to_string(), "application/json".to_string())]);
+        assert_eq!(custom_request_headers(Some(&ok)).unwrap()["accept"], "application/json");
+        for name in ["Host", "content-length", "Accept-Encoding"] {
+            let bad = HashMap::from([(name.to_string(), "x".to_string())]);
+            assert!(custom_request_headers(Some(&bad)).is_err(), "{} accepted", name);
+        }
+        let bad_value = HashMap::from([("X-Test".to_string(), "a\r\nInjected: 1".to_string())]);
+        assert!(custom_request_headers(Some(&bad_value)).is_err());
+        let many: HashMap<String, String> = (0..=MAX_CUSTOM_HEADERS).map(|i| (format!("x-h{}", i), "v".to_string())).collect();
+        assert!(custom_request_headers(Some(&many)).is_err());
+    }
+
+    #[test]
+    fn https_to_http_redirects_are_downgrades() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(is_downgrade_redirect(&url("https://a.example/x"), &url("http://a.example/x")));
+        assert!(!is_downgrade_redirect(&url("http://a.example/x"), &url("https://a.example/x")));
+        assert!(!is_downgrade_redirect(&url("https://a.example/x"), &url("https://b.example/y")));
+    }
+
+    #[test]
+    fn fetch_url_reports_status_and_redirects_as_json() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 302 Found\r\nLocation: /final\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+                "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 4\r\nConnection: close\r\n\r\ngone",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let base = format!("http://{}", addr);
+        let opts = ToolOptions::default();
+        let result = execute_tool("fetch_url", &serde_json::json!({ "url": format!("{}/start", base) }), None, None, &opts).unwrap();
+        assert!(result.ok);
+        let out: FetchUrlOutput = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(out.status, 200);
+        assert_eq!(out.final_url.as_deref(), Some(format!("{}/final", base).as_str()));
+        assert_eq!(out.content, "hello");
+        assert_eq!(out.char_count, 5);
+        assert_eq!(out.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(
+            out.redirects,
+            vec![RedirectHop { from: format!("{}/start", base), to: format!("{}/final", base), status: 302 }]
+        );
+        let result = execute_tool("fetch_url", &serde_json::json!({ "url": format!("{}/missing", base) }), None, None, &opts).unwrap();
+        server.join().unwrap();
+        assert!(!result.ok);
+        let out: FetchUrlOutput = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(out.status, 404);
+        assert_eq!(out.error.as_deref(), Some(result.error.as_deref().unwrap()));
+    }
+
+    #[test]
+    fn fetch_url_decodes_gzip_response() {
+        use std::io::{Read, Write};
+        let html = "<html><body><h1>Hello</h1><p>compressed world</p></body></html>";
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(html.as_bytes()).unwrap();
+        let gz = enc.finish().unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let n = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            assert!(request.contains("accept-encoding") && request.contains("gzip"));
+            assert!(request.contains("x-api-key: secret"));
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                gz.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&gz).unwrap();
+        });
+        let client = page_fetch_client(Duration::from_secs(5)).unwrap();
+        let headers = HashMap::from([("X-Api-Key".to_string(), "secret".to_string())]);
+        let headers = custom_request_headers(Some(&headers)).unwrap();
+        let text = fetch_url_content(&client, &format!("http://{}/", addr), 1000, &headers, &mut FetchMeta::default()).unwrap();
+        server.join().unwrap();
+        assert_eq!(text, "Hello compressed world");
+    }
+
     #[test]
     fn parse_duckduckgo_abstract_returns_one_result() {
         let body = DuckDuckGoResult {
@@ -1,15 +1,71 @@
-//! Ollama HTTP API client: health, list models, pull, chat streaming.
+//! Ollama HTTP API client: health, list models, pull, chat streaming, embeddings.
 
 // No response timeout: slow PCs can take as long as they need for Ollama.
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Max concurrent /api/show calls when building the detailed model list.
+const MODEL_DETAILS_CONCURRENCY: usize = 4;
+/// /api/ps checks after an unload request before reporting it did not take effect.
+const UNLOAD_VERIFY_ATTEMPTS: u32 = 10;
+const UNLOAD_VERIFY_INTERVAL_MS: u64 = 200;
+/// /api/ps checks after a canceled chat before reporting the generation stop as unconfirmed.
+const CANCEL_VERIFY_ATTEMPTS: u32 = 10;
+const CANCEL_VERIFY_INTERVAL_MS: u64 = 200;
+/// The background health check must not hang when the server accepts but never answers.
+const VERSION_TIMEOUT_SECS: u64 = 3;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ModelInfo {
     pub name: String,
     pub size: u64,
     pub modified_at: Option<String>,
+    /// Manifest digest from /api/tags; changes when the model is re-pulled or replaced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Model family (e.g. "qwen2", "llama"), from tags details or /api/show.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    /// Parameter size label (e.g. "7.6B").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameter_size: Option<String>,
+    /// Quantization level (e.g. "Q4_K_M").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantization_level: Option<String>,
+    /// "chat", "vision", "embedding", "tools"… from /api/show; only filled by capability filtering.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<String>>,
+}
+
+impl ModelInfo {
+    /// What identifies this build of the model: the digest, else modified_at.
+    fn version_key(&self) -> Option<String> {
+        self.digest.clone().or_else(|| self.modified_at.clone())
+    }
+
+    fn has_details(&self) -> bool {
+        self.family.is_some() && self.parameter_size.is_some() && self.quantization_level.is_some()
+    }
+
+    /// Fill missing detail fields from an /api/show response (`details` object).
+    fn merge_show_details(mut self, show: &serde_json::Value) -> Self {
+        let details = show.get("details");
+        let field = |key: &str| {
+            details
+                .and_then(|d| d.get(key))
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        self.family = self.family.or_else(|| field("family"));
+        self.parameter_size = self.parameter_size.or_else(|| field("parameter_size"));
+        self.quantization_level = self.quantization_level.or_else(|| field("quantization_level"));
+        self
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -24,6 +80,12 @@ pub struct ChatOptions {
     pub num_predict: Option<u32>,
 }
 
+#[derive(Debug, Deserialize)]
+struct VersionResponse {
+    #[serde(default)]
+    version: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct TagsResponse {
     models: Option<Vec<TagModel>>,
@@ -34,6 +96,29 @@ struct TagModel {
     name: String,
     size: u64,
     modified_at: Option<String>,
+    digest: Option<String>,
+    details: Option<TagModelDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PsResponse {
+    models: Option<Vec<PsModel>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PsModel {
+    name: String,
+    expires_at: Option<String>,
+    /// Bytes of the model held in GPU memory; 0 when it runs on the CPU.
+    #[serde(default)]
+    size_vram: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct TagModelDetails {
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,8 +132,95 @@ pub struct PullEvent {
 #[derive(Debug, Deserialize)]
 struct ChatChunk {
     message: Option<ChatChunkMessage>,
-    #[allow(dead_code)]
     done: Option<bool>,
+    /// On the final chunk: "stop", "length" (hit num_predict) or "load".
+    done_reason: Option<String>,
+    /// Every other field (model, created_at, eval_count, total_duration, ...), kept as sent.
+    #[serde(flatten)]
+    extra: serde_json::Value,
+}
+
+impl ChatChunk {
+    /// Ollama reports a failure mid-stream (e.g. the runner crashed) as a line `{"error": "..."}`.
+    fn stream_error(&self) -> Option<&str> {
+        self.extra.get("error").and_then(|e| e.as_str())
+    }
+
+    /// The final chunk as Ollama sent it, minus `message` (its content is streamed as a delta).
+    fn into_final_object(self) -> serde_json::Value {
+        let mut obj = match self.extra {
+            serde_json::Value::Object(map) => map,
+            _ => serde_json::Map::new(),
+        };
+        obj.insert("done".to_string(), serde_json::json!(self.done));
+        if let Some(reason) = self.done_reason {
+            obj.insert("done_reason".to_string(), serde_json::json!(reason));
+        }
+        serde_json::Value::Object(obj)
+    }
+}
+
+/// Item of a chat stream: a content delta, or the final chunk's done_reason together with the
+/// whole final object (see ChatChunk::into_final_object).
+#[derive(Debug, PartialEq)]
+pub enum ChatStreamEvent {
+    Delta(String),
+    Done {
+        done_reason: Option<String>,
+        raw: serde_json::Value,
+    },
+}
+
+/// A chat request Ollama rejected (status and raw body kept) or that never got a response.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OllamaRequestError {
+    /// HTTP status; None when the request failed before a response (connection refused, timeout).
+    pub status: Option<u16>,
+    /// Raw response body, unparsed.
+    pub body: Option<String>,
+    /// The body's `error` field when it is Ollama's JSON error, else the body or transport error.
+    pub message: String,
+    /// "model_not_found", "runner_crashed", "out_of_memory", "unreachable" or "other", so the UI
+    /// can suggest the right fix.
+    pub kind: &'static str,
+}
+
+impl OllamaRequestError {
+    pub fn from_response(status: u16, body: String) -> Self {
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|v| v.get("error").and_then(|e| e.as_str()).map(str::to_string))
+            .unwrap_or_else(|| body.trim().to_string());
+        let lower = message.to_lowercase();
+        let kind = if status == 404 || (lower.contains("model") && lower.contains("not found")) {
+            "model_not_found"
+        } else if lower.contains("memory") && (lower.contains("more") || lower.contains("out of")) {
+            "out_of_memory"
+        } else if lower.contains("runner") {
+            "runner_crashed"
+        } else {
+            "other"
+        };
+        Self { status: Some(status), body: Some(body), message, kind }
+    }
+
+    pub fn unreachable(error: impl ToString) -> Self {
+        Self { status: None, body: None, message: error.to_string(), kind: "unreachable" }
+    }
+}
+
+impl std::fmt::Display for OllamaRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.status, &self.body) {
+            (Some(status), Some(body)) => write!(f, "Ollama error {}: {}", status, body),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateCountResponse {
+    prompt_eval_count: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,9 +228,265 @@ struct ChatChunkMessage {
     content: Option<String>,
 }
 
+/// Whether `url` points at localhost or a loopback IP (127.0.0.0/8, ::1).
+pub fn is_loopback_url(url: &str) -> bool {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let Some(host) = parsed.host_str() else {
+        return false;
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Context window Ollama gives a request that sets no num_ctx (its OLLAMA_CONTEXT_LENGTH default).
+pub const DEFAULT_NUM_CTX: u64 = 4096;
+
+/// Context window for requests from this app, which never send num_ctx: OLLAMA_CONTEXT_LENGTH when
+/// set in our environment (Ollama started alongside the app sees the same value), else the default.
+pub fn default_num_ctx() -> u64 {
+    std::env::var("OLLAMA_CONTEXT_LENGTH")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_NUM_CTX)
+}
+
+/// Trained context length from an /api/show response (`model_info["<arch>.context_length"]`).
+pub fn context_length_from_show(show: &serde_json::Value) -> Option<u64> {
+    show.get("model_info")?
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, v)| v.as_u64())
+}
+
+/// Capabilities a model list can be filtered by.
+pub const MODEL_CAPABILITIES: &[&str] = &["chat", "vision", "embedding"];
+
+/// Capabilities from an /api/show response. Newer Ollama versions list them ("completion" is
+/// reported as "chat"); older ones are inferred from the model families. None if undeterminable.
+pub fn capabilities_from_show(show: &serde_json::Value) -> Option<Vec<String>> {
+    if let Some(list) = show.get("capabilities").and_then(|c| c.as_array()) {
+        let caps: Vec<String> = list
+            .iter()
+            .filter_map(|c| c.as_str())
+            .map(|c| if c == "completion" { "chat".to_string() } else { c.to_string() })
+            .collect();
+        return Some(caps);
+    }
+    let details = show.get("details")?;
+    let mut families: Vec<String> = details
+        .get("families")
+        .and_then(|f| f.as_array())
+        .map(|f| f.iter().filter_map(|v| v.as_str()).map(|s| s.to_lowercase()).collect())
+        .unwrap_or_default();
+    if let Some(family) = details.get("family").and_then(|f| f.as_str()) {
+        families.push(family.to_lowercase());
+    }
+    if families.is_empty() {
+        return None;
+    }
+    if families.iter().any(|f| f.contains("bert")) {
+        return Some(vec!["embedding".to_string()]);
+    }
+    let mut caps = vec!["chat".to_string()];
+    if families.iter().any(|f| f == "clip" || f == "mllama" || f.contains("vision")) {
+        caps.push("vision".to_string());
+    }
+    Some(caps)
+}
+
+/// One `PARAMETER` line of a Modelfile. Repeatable parameters such as `stop` appear once per value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModelParameter {
+    pub name: String,
+    /// Unquoted value as written in the Modelfile (e.g. "0.7", "<|im_end|>").
+    pub value: String,
+}
+
+/// The editable parts of a model, from /api/show. Fields Ollama leaves out (older versions, or
+/// models created without them) are None or empty.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ModelfileInfo {
+    pub model: String,
+    /// The full Modelfile text, for copying or viewing.
+    pub modelfile: Option<String>,
+    pub system: Option<String>,
+    pub template: Option<String>,
+    pub parameters: Vec<ModelParameter>,
+    pub license: Option<String>,
+}
+
+/// Split the `parameters` text of /api/show ("name   value" per line) into name/value pairs.
+fn parse_show_parameters(text: &str) -> Vec<ModelParameter> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, value) = line.trim().split_once(char::is_whitespace)?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            Some(ModelParameter { name: name.to_string(), value: value.to_string() })
+        })
+        .collect()
+}
+
+/// The SYSTEM instruction of a Modelfile, for Ollama versions whose /api/show has no `system`.
+fn system_from_modelfile(modelfile: &str) -> Option<String> {
+    let start = modelfile.lines().position(|l| l.trim_start().to_uppercase().starts_with("SYSTEM "))?;
+    let lines: Vec<&str> = modelfile.lines().skip(start).collect();
+    let first = lines[0].trim_start()["SYSTEM ".len()..].trim();
+    if let Some(rest) = first.strip_prefix("\"\"\"") {
+        if let Some(end) = rest.find("\"\"\"") {
+            return Some(rest[..end].to_string());
+        }
+        let mut text = vec![rest];
+        for line in &lines[1..] {
+            if let Some(end) = line.find("\"\"\"") {
+                text.push(&line[..end]);
+                return Some(text.join("\n"));
+            }
+            text.push(line);
+        }
+        return None;
+    }
+    Some(first.trim_matches('"').to_string())
+}
+
+/// Modelfile details from an /api/show response.
+pub fn modelfile_from_show(model: &str, show: &serde_json::Value) -> ModelfileInfo {
+    let text = |key: &str| show.get(key).and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty()).map(str::to_string);
+    let modelfile = text("modelfile");
+    ModelfileInfo {
+        model: model.to_string(),
+        system: text("system").or_else(|| modelfile.as_deref().and_then(system_from_modelfile)),
+        template: text("template"),
+        parameters: text("parameters").map(|p| parse_show_parameters(&p)).unwrap_or_default(),
+        license: text("license"),
+        modelfile,
+    }
+}
+
+/// The `parameters` object of an /api/create request: numbers and booleans typed, `stop` (and any
+/// other repeated name) as an array.
+pub fn create_parameters_json(parameters: &[ModelParameter]) -> serde_json::Map<String, serde_json::Value> {
+    let mut out = serde_json::Map::new();
+    for p in parameters {
+        let name = p.name.trim().to_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+        let value = if let Ok(n) = p.value.parse::<i64>() {
+            serde_json::json!(n)
+        } else if let Ok(f) = p.value.parse::<f64>() {
+            serde_json::json!(f)
+        } else if let Ok(b) = p.value.parse::<bool>() {
+            serde_json::json!(b)
+        } else {
+            serde_json::json!(p.value)
+        };
+        let repeated = name == "stop" || parameters.iter().filter(|q| q.name.trim().eq_ignore_ascii_case(&name)).count() > 1;
+        if !repeated {
+            out.insert(name, value);
+            continue;
+        }
+        match out.entry(name).or_insert_with(|| serde_json::json!([])) {
+            serde_json::Value::Array(values) => values.push(serde_json::json!(p.value)),
+            _ => unreachable!("repeated parameters are always arrays"),
+        }
+    }
+    out
+}
+
+/// Take the next record off the front of an NDJSON buffer, or None until more bytes arrive.
+/// Objects are framed by brace balance outside strings rather than by line, so a raw newline
+/// inside a string value does not split a record; such control characters are escaped so the
+/// object still parses. Anything that does not start with `{` is returned up to its newline.
+fn next_json_frame(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let Some(start) = buf.iter().position(|b| !b.is_ascii_whitespace()) else {
+        buf.clear();
+        return None;
+    };
+    buf.drain(..start);
+    if buf[0] != b'{' {
+        let end = buf.iter().position(|&b| b == b'\n')?;
+        return Some(buf.drain(..=end).collect());
+    }
+    let mut frame = Vec::new();
+    let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+    for (i, &b) in buf.iter().enumerate() {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                b'\n' | b'\r' | b'\t' => {
+                    frame.extend_from_slice(match b {
+                        b'\n' => b"\\n",
+                        b'\r' => b"\\r",
+                        _ => b"\\t",
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+        } else {
+            match b {
+                b'"' => in_string = true,
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        frame.push(b);
+        if depth == 0 && !in_string {
+            buf.drain(..=i);
+            return Some(frame);
+        }
+    }
+    None
+}
+
+/// Per-model capabilities keyed by name, with the digest (or modified_at when Ollama reports no
+/// digest) they were read for, so a re-pulled model gets looked up again.
+type CapabilityCache = Arc<Mutex<HashMap<String, (Option<String>, Option<Vec<String>>)>>>;
+
+/// Characters per token last measured for each model (normalized name), used to estimate counts
+/// when the model can't be queried.
+type TokenRatioCache = Arc<Mutex<HashMap<String, f64>>>;
+
+/// Characters per token assumed for a model that was never measured.
+const DEFAULT_CHARS_PER_TOKEN: f64 = 4.0;
+
+#[derive(Clone)]
 pub struct OllamaClient {
     base: String,
     client: Client,
+    capability_cache: CapabilityCache,
+    token_ratio_cache: TokenRatioCache,
+    /// Offline mode (shared by clones): every request is refused unless the server is on loopback.
+    offline_mode: Arc<AtomicBool>,
+}
+
+/// Model name with an explicit tag, as Ollama resolves it: a missing tag means ":latest".
+pub fn normalize_model_name(name: &str) -> String {
+    let name = name.trim();
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+fn same_model_name(a: &str, b: &str) -> bool {
+    normalize_model_name(a) == normalize_model_name(b)
 }
 
 impl OllamaClient {
@@ -66,17 +494,41 @@ impl OllamaClient {
         let client = Client::builder()
             .build()
             .unwrap_or_default();
-        Self { base, client }
+        Self {
+            base,
+            client,
+            capability_cache: Arc::default(),
+            token_ratio_cache: Arc::default(),
+            offline_mode: Arc::default(),
+        }
     }
 
-    pub async fn health(&self) -> Result<bool, String> {
-        let url = format!("{}/api/tags", self.base);
-        let res = self.client.get(&url).send().await.map_err(|e| e.to_string())?;
-        Ok(res.status().is_success())
+    /// Follow the offline_mode MCP setting; set at startup and whenever the setting is saved.
+    pub fn set_offline_mode(&self, enabled: bool) {
+        self.offline_mode.store(enabled, Ordering::SeqCst);
+    }
+
+    /// URL of an API endpoint. The one offline-mode guard: a non-loopback server is refused before
+    /// any request is made.
+    fn endpoint(&self, path: &str) -> Result<String, String> {
+        if self.offline_mode.load(Ordering::SeqCst) && !self.is_loopback() {
+            return Err(format!("Offline mode enabled: Ollama at {} is not a loopback address", self.base));
+        }
+        Ok(format!("{}{}", self.base, path))
+    }
+
+    /// Base URL of the Ollama server (e.g. http://127.0.0.1:11434).
+    pub fn base_url(&self) -> &str {
+        &self.base
+    }
+
+    /// Whether the server is on this machine (localhost or a loopback IP); required in offline mode.
+    pub fn is_loopback(&self) -> bool {
+        is_loopback_url(&self.base)
     }
 
     pub async fn list_models(&self) -> Result<Vec<ModelInfo>, String> {
-        let url = format!("{}/api/tags", self.base);
+        let url = self.endpoint("/api/tags")?;
         let res = self.client.get(&url).send().await.map_err(|e| e.to_string())?;
         if !res.status().is_success() {
             return Err(format!("Ollama returned {}", res.status()));
@@ -86,18 +538,96 @@ impl OllamaClient {
             .models
             .unwrap_or_default()
             .into_iter()
-            .map(|m| ModelInfo {
-                name: m.name,
-                size: m.size,
-                modified_at: m.modified_at,
+            .map(|m| {
+                let details = m.details.unwrap_or_default();
+                ModelInfo {
+                    name: m.name,
+                    size: m.size,
+                    modified_at: m.modified_at,
+                    digest: m.digest.filter(|d| !d.is_empty()),
+                    family: details.family.filter(|s| !s.is_empty()),
+                    parameter_size: details.parameter_size.filter(|s| !s.is_empty()),
+                    quantization_level: details.quantization_level.filter(|s| !s.is_empty()),
+                    capabilities: None,
+                }
             })
             .collect();
         Ok(models)
     }
 
+    /// List models with family/parameter size/quantization. Uses /api/tags details when present and
+    /// falls back to a per-model /api/show call (bounded concurrency). Models whose show call fails
+    /// are returned with whatever the tags response had.
+    pub async fn list_models_detailed(&self) -> Result<Vec<ModelInfo>, String> {
+        let models = self.list_models().await?;
+        let detailed = futures_util::stream::iter(models.into_iter().map(|m| async move {
+            if m.has_details() {
+                return m;
+            }
+            match self.show_model(&m.name).await {
+                Ok(Some(show)) => m.merge_show_details(&show),
+                _ => m,
+            }
+        }))
+        .buffered(MODEL_DETAILS_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+        Ok(detailed)
+    }
+
+    /// Capabilities of one model, from the cache or /api/show. None when they can't be determined.
+    async fn model_capabilities(&self, model: &ModelInfo) -> Option<Vec<String>> {
+        if let Ok(cache) = self.capability_cache.lock() {
+            if let Some((version, caps)) = cache.get(&model.name) {
+                if version == &model.version_key() {
+                    return caps.clone();
+                }
+            }
+        }
+        // A failed show is not cached, so it is retried on the next call.
+        let show = self.show_model(&model.name).await.ok().flatten()?;
+        let caps = capabilities_from_show(&show);
+        if let Ok(mut cache) = self.capability_cache.lock() {
+            cache.insert(model.name.clone(), (model.version_key(), caps.clone()));
+        }
+        caps
+    }
+
+    /// Installed models that support `capability` ("chat", "vision" or "embedding"). Models whose
+    /// capabilities can't be determined are kept, so an older Ollama still gets the full list.
+    pub async fn list_models_with_capability(&self, capability: &str) -> Result<Vec<ModelInfo>, String> {
+        let models = self.list_models().await?;
+        let with_caps = futures_util::stream::iter(models.into_iter().map(|mut m| async move {
+            m.capabilities = self.model_capabilities(&m).await;
+            m
+        }))
+        .buffered(MODEL_DETAILS_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+        Ok(with_caps
+            .into_iter()
+            .filter(|m| {
+                m.capabilities
+                    .as_ref()
+                    .map(|caps| caps.iter().any(|c| c == capability))
+                    .unwrap_or(true)
+            })
+            .collect())
+    }
+
+    /// Current digest of an installed model (tag-insensitive name match). Ok(None) if the model is
+    /// not installed or Ollama reports no digest for it.
+    pub async fn model_digest(&self, model: &str) -> Result<Option<String>, String> {
+        let models = self.list_models().await?;
+        Ok(models
+            .into_iter()
+            .find(|m| same_model_name(&m.name, model))
+            .and_then(|m| m.digest))
+    }
+
     /// Delete a model by name (tag). Uses Ollama DELETE /api/delete.
     pub async fn delete_model(&self, model: &str) -> Result<(), String> {
-        let url = format!("{}/api/delete", self.base);
+        let url = self.endpoint("/api/delete")?;
         let body = serde_json::json!({ "model": model });
         let res = self
             .client
@@ -114,9 +644,117 @@ impl OllamaClient {
         Ok(())
     }
 
+    /// Server version from GET /api/version (short timeout, used by the health watchdog).
+    pub async fn version(&self) -> Result<String, String> {
+        let url = self.endpoint("/api/version")?;
+        let res = self
+            .client
+            .get(&url)
+            .timeout(std::time::Duration::from_secs(VERSION_TIMEOUT_SECS))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("Ollama version error {}", res.status()));
+        }
+        let body: VersionResponse = res.json().await.map_err(|e| e.to_string())?;
+        Ok(body.version)
+    }
+
+    /// Names of models currently loaded in memory. Uses Ollama GET /api/ps.
+    pub async fn running_models(&self) -> Result<Vec<String>, String> {
+        let url = self.endpoint("/api/ps")?;
+        let res = self.client.get(&url).send().await.map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("Ollama ps error {}", res.status()));
+        }
+        let body: PsResponse = res.json().await.map_err(|e| e.to_string())?;
+        Ok(body.models.unwrap_or_default().into_iter().map(|m| m.name).collect())
+    }
+
+    /// `size_vram` from /api/ps for `model`, or the largest among loaded models when None.
+    /// None when no matching model is loaded.
+    pub async fn loaded_model_vram(&self, model: Option<&str>) -> Result<Option<u64>, String> {
+        let url = self.endpoint("/api/ps")?;
+        let res = self.client.get(&url).send().await.map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("Ollama ps error {}", res.status()));
+        }
+        let body: PsResponse = res.json().await.map_err(|e| e.to_string())?;
+        Ok(body
+            .models
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|m| model.map_or(true, |name| same_model_name(&m.name, name)))
+            .map(|m| m.size_vram.unwrap_or(0))
+            .max())
+    }
+
+    /// keep-alive expiry reported by /api/ps for a loaded model (None if not loaded). Ollama resets it
+    /// whenever a request on that model finishes, so a change means an in-flight generation ended.
+    pub async fn model_expires_at(&self, model: &str) -> Result<Option<String>, String> {
+        let url = self.endpoint("/api/ps")?;
+        let res = self.client.get(&url).send().await.map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("Ollama ps error {}", res.status()));
+        }
+        let body: PsResponse = res.json().await.map_err(|e| e.to_string())?;
+        Ok(body
+            .models
+            .unwrap_or_default()
+            .into_iter()
+            .find(|m| same_model_name(&m.name, model))
+            .and_then(|m| m.expires_at))
+    }
+
+    /// After a chat request was dropped, poll /api/ps until the model's keep-alive expiry moves past
+    /// `expires_before` (the value seen while generating). Returns true once the stop is confirmed.
+    /// A missing expiry on either side proves nothing, so it never counts as confirmation.
+    pub async fn confirm_generation_stopped(&self, model: &str, expires_before: Option<String>) -> Result<bool, String> {
+        let Some(expires_before) = expires_before else {
+            return Ok(false);
+        };
+        for _ in 0..CANCEL_VERIFY_ATTEMPTS {
+            let now = self.model_expires_at(model).await?;
+            if now.is_some_and(|now| now != expires_before) {
+                return Ok(true);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(CANCEL_VERIFY_INTERVAL_MS)).await;
+        }
+        Ok(false)
+    }
+
+    /// Unload a model from memory (frees VRAM) by sending an empty generate request with keep_alive 0.
+    /// Returns true once /api/ps no longer lists the model.
+    pub async fn unload_model(&self, model: &str) -> Result<bool, String> {
+        let url = self.endpoint("/api/generate")?;
+        let body = serde_json::json!({ "model": model, "keep_alive": 0 });
+        let res = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(format!("Ollama unload error {}: {}", status, text));
+        }
+        // Unloading is asynchronous on the Ollama side; poll /api/ps briefly to confirm.
+        for _ in 0..UNLOAD_VERIFY_ATTEMPTS {
+            let running = self.running_models().await?;
+            if !running.iter().any(|name| same_model_name(name, model)) {
+                return Ok(true);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(UNLOAD_VERIFY_INTERVAL_MS)).await;
+        }
+        Ok(false)
+    }
+
     /// Show model details (optional). Uses Ollama POST /api/show.
     pub async fn show_model(&self, model: &str) -> Result<Option<serde_json::Value>, String> {
-        let url = format!("{}/api/show", self.base);
+        let url = self.endpoint("/api/show")?;
         let body = serde_json::json!({ "model": model });
         let res = self
             .client
@@ -132,8 +770,44 @@ impl OllamaClient {
         Ok(Some(json))
     }
 
+    /// Create `model` from an existing model (`from`) with a new system prompt, template and/or
+    /// parameters. Uses Ollama POST /api/create without streaming; fails if Ollama rejects it.
+    pub async fn create_model(
+        &self,
+        model: &str,
+        from: &str,
+        system: Option<&str>,
+        template: Option<&str>,
+        parameters: &[ModelParameter],
+    ) -> Result<(), String> {
+        let url = self.endpoint("/api/create")?;
+        let mut body = serde_json::json!({ "model": model, "from": from, "stream": false });
+        if let Some(system) = system {
+            body["system"] = serde_json::json!(system);
+        }
+        if let Some(template) = template {
+            body["template"] = serde_json::json!(template);
+        }
+        if !parameters.is_empty() {
+            body["parameters"] = serde_json::Value::Object(create_parameters_json(parameters));
+        }
+        let res = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(format!("Ollama create error {}: {}", status, text));
+        }
+        Ok(())
+    }
+
     pub async fn pull(&self, model: &str) -> Result<impl futures_util::Stream<Item = Result<PullEvent, String>>, String> {
-        let url = format!("{}/api/pull", self.base);
+        let url = self.endpoint("/api/pull")?;
         let body = serde_json::json!({ "name": model });
         let res = self
             .client
@@ -153,10 +827,9 @@ impl OllamaClient {
             |(mut stream, mut buf)| async move {
                 use futures_util::StreamExt;
                 loop {
-                    while let Some(line_end) = buf.iter().position(|&b| b == b'\n') {
-                        let line = buf.drain(..=line_end).collect::<Vec<_>>();
-                        let line_str = String::from_utf8_lossy(&line);
-                        let line_str = line_str.trim_end_matches('\n');
+                    while let Some(frame) = next_json_frame(&mut buf) {
+                        let line_str = String::from_utf8_lossy(&frame);
+                        let line_str = line_str.trim();
                         if line_str.is_empty() {
                             continue;
                         }
@@ -176,13 +849,132 @@ impl OllamaClient {
         Ok(stream)
     }
 
+    /// Exact token count of `text` with the model's tokenizer: a raw /api/generate with nothing to
+    /// predict, reading prompt_eval_count. Loads the model if needed. Also updates the model's
+    /// chars-per-token ratio used by estimate_tokens.
+    pub async fn count_tokens(&self, model: &str, text: &str) -> Result<u64, String> {
+        if text.is_empty() {
+            return Ok(0);
+        }
+        let url = self.endpoint("/api/generate")?;
+        let body = serde_json::json!({
+            "model": model,
+            "prompt": text,
+            "raw": true,
+            "stream": false,
+            "options": { "num_predict": 0 }
+        });
+        let res = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(format!("Ollama error {}: {}", status, text));
+        }
+        let out: GenerateCountResponse = res.json().await.map_err(|e| e.to_string())?;
+        let tokens = out
+            .prompt_eval_count
+            .ok_or("Ollama did not report prompt_eval_count")?;
+        if tokens > 0 {
+            if let Ok(mut cache) = self.token_ratio_cache.lock() {
+                let ratio = text.chars().count() as f64 / tokens as f64;
+                cache.insert(normalize_model_name(model), ratio);
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// Token estimate from the model's last measured chars-per-token ratio, else
+    /// DEFAULT_CHARS_PER_TOKEN. Rounds up; 0 only for empty text.
+    pub fn estimate_tokens(&self, model: &str, text: &str) -> u64 {
+        let ratio = self
+            .token_ratio_cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(&normalize_model_name(model)).copied())
+            .filter(|r| *r > 0.0)
+            .unwrap_or(DEFAULT_CHARS_PER_TOKEN);
+        (text.chars().count() as f64 / ratio).ceil() as u64
+    }
+
+    /// Non-streaming chat: returns the full assistant reply. Used for background work (e.g. summaries).
+    pub async fn chat(&self, model: &str, messages: Vec<ChatMessage>, options: ChatOptions) -> Result<String, String> {
+        let url = self.endpoint("/api/chat")?;
+        let mut body = serde_json::json!({
+            "model": model,
+            "messages": messages,
+            "stream": false
+        });
+        let mut opts = serde_json::json!({});
+        if let Some(t) = options.temperature {
+            opts["temperature"] = serde_json::json!(t);
+        }
+        if let Some(n) = options.num_predict {
+            opts["num_predict"] = serde_json::json!(n);
+        }
+        if opts.as_object().map(|o| !o.is_empty()).unwrap_or(false) {
+            body["options"] = opts;
+        }
+        let res = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(format!("Ollama error {}: {}", status, text));
+        }
+        let chunk: ChatChunk = res.json().await.map_err(|e| e.to_string())?;
+        Ok(chunk.message.and_then(|m| m.content).unwrap_or_default())
+    }
+
+    /// Embed several inputs in one /api/embed call; one vector per input, in order.
+    pub async fn embed(&self, model: &str, inputs: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        #[derive(Deserialize)]
+        struct EmbedResponse {
+            #[serde(default)]
+            embeddings: Vec<Vec<f32>>,
+        }
+        let url = self.endpoint("/api/embed")?;
+        let body = serde_json::json!({ "model": model, "input": inputs });
+        let res = self
+            .client
+            .post(&url)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(format!("Ollama error {}: {}", status, text));
+        }
+        let parsed: EmbedResponse = res.json().await.map_err(|e| e.to_string())?;
+        if parsed.embeddings.len() != inputs.len() {
+            return Err(format!(
+                "Ollama returned {} embeddings for {} inputs",
+                parsed.embeddings.len(),
+                inputs.len()
+            ));
+        }
+        Ok(parsed.embeddings)
+    }
+
     pub async fn chat_stream(
         &self,
         model: &str,
         messages: Vec<ChatMessage>,
         options: ChatOptions,
-    ) -> Result<impl futures_util::Stream<Item = Result<String, String>>, String> {
-        let url = format!("{}/api/chat", self.base);
+    ) -> Result<impl futures_util::Stream<Item = Result<ChatStreamEvent, String>>, OllamaRequestError> {
+        let url = self.endpoint("/api/chat").map_err(OllamaRequestError::unreachable)?;
         let mut body = serde_json::json!({
             "model": model,
             "messages": messages,
@@ -204,33 +996,52 @@ impl OllamaClient {
             .json(&body)
             .send()
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(OllamaRequestError::unreachable)?;
         if !res.status().is_success() {
-            let status = res.status();
+            let status = res.status().as_u16();
             let text = res.text().await.unwrap_or_default();
-            return Err(format!("Ollama error {}: {}", status, text));
+            return Err(OllamaRequestError::from_response(status, text));
         }
         let stream = res.bytes_stream();
+        // The third state field holds the Done event when the final chunk also carried content.
         let stream = futures_util::stream::try_unfold(
-            (stream, Vec::new()),
-            |(mut stream, mut buf)| async move {
+            (stream, Vec::new(), None::<ChatStreamEvent>),
+            |(mut stream, mut buf, mut pending)| async move {
+                if let Some(done) = pending.take() {
+                    return Ok(Some((done, (stream, buf, None))));
+                }
                 loop {
-                    while let Some(line_end) = buf.iter().position(|&b| b == b'\n') {
-                        let line: Vec<u8> = buf.drain(..=line_end).collect();
-                        let line_str = String::from_utf8_lossy(&line);
-                        let line_str = line_str.trim_end_matches('\n');
+                    while let Some(frame) = next_json_frame(&mut buf) {
+                        let line_str = String::from_utf8_lossy(&frame);
+                        let line_str = line_str.trim();
                         if line_str.is_empty() {
                             continue;
                         }
-                        if let Ok(chunk) = serde_json::from_str::<ChatChunk>(line_str) {
-                            if let Some(msg) = chunk.message.and_then(|m| m.content) {
-                                return Ok(Some((msg, (stream, buf))));
+                        if let Ok(mut chunk) = serde_json::from_str::<ChatChunk>(line_str) {
+                            if let Some(error) = chunk.stream_error() {
+                                return Err(format!("Ollama error: {}", error));
+                            }
+                            let content = chunk.message.take().and_then(|m| m.content).filter(|c| !c.is_empty());
+                            if chunk.done == Some(true) {
+                                let done = ChatStreamEvent::Done {
+                                    done_reason: chunk.done_reason.clone(),
+                                    raw: chunk.into_final_object(),
+                                };
+                                return Ok(Some(match content {
+                                    Some(msg) => (ChatStreamEvent::Delta(msg), (stream, buf, Some(done))),
+                                    None => (done, (stream, buf, None)),
+                                }));
+                            }
+                            if let Some(msg) = content {
+                                return Ok(Some((ChatStreamEvent::Delta(msg), (stream, buf, None))));
                             }
                         }
                     }
                     let chunk = match stream.next().await {
                         Some(Ok(c)) => c,
                         Some(Err(e)) => return Err(e.to_string()),
+                        // A complete last object needs no newline and was taken above; what is
+                        // left is a cut-off record, which the caller reports as an interrupted stream.
                         None => return Ok(None),
                     };
                     buf.extend_from_slice(&chunk);
@@ -240,3 +1051,170 @@ impl OllamaClient {
         Ok(stream)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_names_match_with_implicit_latest_tag() {
+        // unload_model checks /api/ps with this, which always reports the tag.
+        assert!(same_model_name("llama3", "llama3:latest"));
+        assert!(same_model_name(" llama3:latest", "llama3"));
+        assert!(!same_model_name("llama3", "llama3:8b"));
+    }
+
+    #[test]
+    fn offline_mode_refuses_remote_servers_for_every_request() {
+        let remote = OllamaClient::new("http://192.0.2.10:11434".to_string());
+        assert!(remote.endpoint("/api/tags").is_ok());
+        remote.clone().set_offline_mode(true);
+        let err = remote.endpoint("/api/embed").unwrap_err();
+        assert!(err.starts_with("Offline mode enabled"), "{err}");
+        let local = OllamaClient::new("http://127.0.0.1:11434".to_string());
+        local.set_offline_mode(true);
+        assert_eq!(local.endpoint("/api/tags").unwrap(), "http://127.0.0.1:11434/api/tags");
+    }
+
+    #[test]
+    fn mid_stream_error_lines_are_detected() {
+        let failed: ChatChunk = serde_json::from_str(r#"{"error":"llama runner process has terminated"}"#).unwrap();
+        assert_eq!(failed.stream_error(), Some("llama runner process has terminated"));
+        let normal: ChatChunk =
+            serde_json::from_str(r#"{"model":"llama3","message":{"role":"assistant","content":"hi"},"done":false}"#).unwrap();
+        assert_eq!(normal.stream_error(), None);
+    }
+
+    #[test]
+    fn modelfile_details_round_trip_to_create_parameters() {
+        let show = serde_json::json!({
+            "modelfile": "FROM qwen2.5:7b\nSYSTEM \"\"\"You are terse.\nAnswer in English.\"\"\"\nPARAMETER temperature 0.7\n",
+            "parameters": "temperature                    0.7\nnum_ctx                        8192\nstop                           \"<|im_start|>\"\nstop                           \"<|im_end|>\"",
+            "template": "{{ .Prompt }}"
+        });
+        let info = modelfile_from_show("qwen2.5:7b", &show);
+        assert_eq!(info.system.as_deref(), Some("You are terse.\nAnswer in English."));
+        assert_eq!(info.template.as_deref(), Some("{{ .Prompt }}"));
+        assert_eq!(info.license, None);
+        assert_eq!(info.parameters.len(), 4);
+        assert_eq!(info.parameters[2], ModelParameter { name: "stop".into(), value: "<|im_start|>".into() });
+        let params = serde_json::Value::Object(create_parameters_json(&info.parameters));
+        assert_eq!(
+            params,
+            serde_json::json!({ "temperature": 0.7, "num_ctx": 8192, "stop": ["<|im_start|>", "<|im_end|>"] })
+        );
+
+        // Older or minimal responses: only the fields that are present are filled.
+        let bare = modelfile_from_show("tiny", &serde_json::json!({ "details": {} }));
+        assert!(bare.modelfile.is_none() && bare.system.is_none() && bare.parameters.is_empty());
+    }
+
+    #[test]
+    fn ndjson_frames_survive_raw_newlines_and_split_chunks() {
+        let wire = "{\"message\":{\"content\":\"line one\nline two {not a brace}\"},\"done\":false}\n{\"message\":{\"content\":\"caf\u{e9} \\\"quoted\\\"\"},\"done\":true}";
+        let mut buf = Vec::new();
+        let mut contents = Vec::new();
+        // One byte at a time, so the é and every brace and quote land on chunk boundaries.
+        for byte in wire.as_bytes() {
+            buf.push(*byte);
+            while let Some(frame) = next_json_frame(&mut buf) {
+                let chunk: ChatChunk = serde_json::from_slice(&frame).unwrap();
+                contents.push(chunk.message.and_then(|m| m.content).unwrap());
+            }
+        }
+        assert_eq!(contents, vec!["line one\nline two {not a brace}", "caf\u{e9} \"quoted\""]);
+        assert!(buf.is_empty());
+
+        // Non-JSON lines still come back whole, so callers can skip them as before.
+        let mut buf = b"garbage line\n{\"done\":true}".to_vec();
+        assert_eq!(next_json_frame(&mut buf).unwrap(), b"garbage line\n");
+        assert_eq!(next_json_frame(&mut buf).unwrap(), br#"{"done":true}"#);
+        assert_eq!(next_json_frame(&mut buf), None);
+    }
+
+    #[test]
+    fn loopback_urls_are_recognized() {
+        assert!(is_loopback_url("http://127.0.0.1:11434"));
+        assert!(is_loopback_url("http://localhost:11434/"));
+        assert!(is_loopback_url("http://[::1]:11434"));
+        assert!(!is_loopback_url("http://192.168.1.20:11434"));
+        assert!(!is_loopback_url("http://ollama.example.com"));
+        assert!(!is_loopback_url("not a url"));
+    }
+
+    #[test]
+    fn final_chunk_keeps_unknown_fields() {
+        let line = r#"{"model":"llama3","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","eval_count":42,"total_duration":123456}"#;
+        let chunk: ChatChunk = serde_json::from_str(line).unwrap();
+        let raw = chunk.into_final_object();
+        assert_eq!(raw["eval_count"], 42);
+        assert_eq!(raw["total_duration"], 123456);
+        assert_eq!(raw["model"], "llama3");
+        assert_eq!(raw["done"], true);
+        assert_eq!(raw["done_reason"], "stop");
+        assert!(raw.get("message").is_none());
+    }
+
+    #[test]
+    fn token_estimate_uses_measured_ratio() {
+        let client = OllamaClient::new("http://127.0.0.1:1".to_string());
+        assert_eq!(client.estimate_tokens("llama3", ""), 0);
+        assert_eq!(client.estimate_tokens("llama3", "abcdefghi"), 3);
+        client.token_ratio_cache.lock().unwrap().insert("llama3:latest".to_string(), 3.0);
+        assert_eq!(client.estimate_tokens("llama3", "abcdefghi"), 3);
+        assert_eq!(client.estimate_tokens("llama3:latest", "abcdefghij"), 4);
+        assert_eq!(client.estimate_tokens("qwen2.5:3b", "abcdefgh"), 2);
+    }
+
+    #[test]
+    fn context_length_is_read_from_model_info() {
+        let show = serde_json::json!({
+            "model_info": { "general.architecture": "llama", "llama.context_length": 131072 }
+        });
+        assert_eq!(context_length_from_show(&show), Some(131072));
+        assert_eq!(context_length_from_show(&serde_json::json!({ "model_info": {} })), None);
+        assert_eq!(context_length_from_show(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn capabilities_from_show_reads_list_or_families() {
+        let listed = serde_json::json!({ "capabilities": ["completion", "vision", "tools"] });
+        assert_eq!(capabilities_from_show(&listed).unwrap(), ["chat", "vision", "tools"]);
+        let embed = serde_json::json!({ "details": { "family": "nomic-bert", "families": ["nomic-bert"] } });
+        assert_eq!(capabilities_from_show(&embed).unwrap(), ["embedding"]);
+        let llava = serde_json::json!({ "details": { "family": "llama", "families": ["llama", "clip"] } });
+        assert_eq!(capabilities_from_show(&llava).unwrap(), ["chat", "vision"]);
+        assert_eq!(capabilities_from_show(&serde_json::json!({ "details": {} })), None);
+    }
+
+    #[test]
+    fn request_errors_keep_status_and_body() {
+        let missing = OllamaRequestError::from_response(
+            404,
+            r#"{"error":"model \"llama9\" not found, try pulling it first"}"#.to_string(),
+        );
+        assert_eq!(missing.kind, "model_not_found");
+        assert_eq!(missing.message, "model \"llama9\" not found, try pulling it first");
+        assert_eq!(missing.status, Some(404));
+        assert!(missing.to_string().starts_with("Ollama error 404: {\"error\""));
+
+        let crashed = OllamaRequestError::from_response(
+            500,
+            r#"{"error":"llama runner process has terminated: exit status 2"}"#.to_string(),
+        );
+        assert_eq!(crashed.kind, "runner_crashed");
+
+        let oom = OllamaRequestError::from_response(
+            500,
+            r#"{"error":"model requires more system memory (9.1 GiB) than is available (6.2 GiB)"}"#.to_string(),
+        );
+        assert_eq!(oom.kind, "out_of_memory");
+
+        let plain = OllamaRequestError::from_response(502, "Bad Gateway\n".to_string());
+        assert_eq!((plain.kind, plain.message.as_str()), ("other", "Bad Gateway"));
+
+        let down = OllamaRequestError::unreachable("error sending request: connection refused");
+        assert_eq!((down.kind, down.status), ("unreachable", None));
+        assert_eq!(down.to_string(), "error sending request: connection refused");
+    }
+}
@@ -0,0 +1,157 @@
+//! Embeddings-backed note search: note discovery, chunking, content hashing and similarity.
+//! Embeddings come from Ollama (/api/embed) and are cached in the `embeddings` table.
+
+use std::path::{Path, PathBuf};
+
+/// Embedding model used when the caller does not name one.
+pub const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+/// Target chunk size; paragraphs are packed up to this many characters.
+const CHUNK_MAX_CHARS: usize = 1200;
+/// Notes larger than this are skipped (same cap as read_file).
+const MAX_NOTE_BYTES: u64 = 512 * 1024;
+/// Upper bound on notes indexed per root so a huge folder can't stall indexing.
+pub const MAX_INDEXED_NOTES: usize = 2000;
+const NOTE_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+
+/// A note found under the root: path relative to the root ("/"-separated) and its full path.
+#[derive(Debug, Clone)]
+pub struct NoteFile {
+    pub path: String,
+    pub full_path: PathBuf,
+}
+
+/// Text notes under `root`, sorted by path. Hidden files/folders (".obsidian", ".git") are skipped.
+pub fn collect_note_files(root: &Path) -> Vec<NoteFile> {
+    let mut out = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else { continue };
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else { continue };
+            let full_path = entry.path();
+            if file_type.is_dir() {
+                stack.push(full_path);
+                continue;
+            }
+            let is_note = full_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| NOTE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+                .unwrap_or(false);
+            let small = entry.metadata().map(|m| m.len() <= MAX_NOTE_BYTES).unwrap_or(false);
+            if !file_type.is_file() || !is_note || !small {
+                continue;
+            }
+            if let Ok(rel) = full_path.strip_prefix(root) {
+                let path = rel
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                out.push(NoteFile { path, full_path });
+            }
+        }
+    }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    out.truncate(MAX_INDEXED_NOTES);
+    out
+}
+
+/// FNV-1a 64-bit hash of the content, hex encoded. Only used to detect changed files.
+pub fn content_hash(content: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in content.as_bytes() {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Split note text into chunks of up to CHUNK_MAX_CHARS, on blank lines where possible.
+/// Paragraphs longer than the limit are split by characters.
+pub fn chunk_text(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for para in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let para_chars = para.chars().count();
+        if !current.is_empty() && current.chars().count() + 2 + para_chars > CHUNK_MAX_CHARS {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if para_chars > CHUNK_MAX_CHARS {
+            let chars: Vec<char> = para.chars().collect();
+            for piece in chars.chunks(CHUNK_MAX_CHARS) {
+                chunks.push(piece.iter().collect());
+            }
+            continue;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(para);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Cosine similarity; 0.0 for mismatched dimensions or zero vectors.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 {
+        return 0.0;
+    }
+    dot / (na.sqrt() * nb.sqrt())
+}
+
+/// Little-endian f32 blob for the embeddings table.
+pub fn encode_embedding(v: &[f32]) -> Vec<u8> {
+    v.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn decode_embedding(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_pack_paragraphs_and_split_long_ones() {
+        assert!(chunk_text("  \n\n ").is_empty());
+        assert_eq!(chunk_text("one\n\ntwo"), vec!["one\n\ntwo"]);
+        let long = "x".repeat(CHUNK_MAX_CHARS + 10);
+        let chunks = chunk_text(&format!("intro\n\n{}", long));
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0], "intro");
+        assert_eq!(chunks[1].chars().count(), CHUNK_MAX_CHARS);
+    }
+
+    #[test]
+    fn similarity_and_encoding() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 2.0]), 0.0);
+        let v = vec![0.5f32, -1.25, 3.0];
+        assert_eq!(decode_embedding(&encode_embedding(&v)), v);
+        assert_eq!(content_hash("a"), content_hash("a"));
+        assert_ne!(content_hash("a"), content_hash("b"));
+    }
+}
@@ -2,6 +2,7 @@
 
 use chrono::Utc;
 use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
 use std::path::Path;
 use thiserror::Error;
 use uuid::Uuid;
@@ -12,6 +13,22 @@ pub enum StorageError {
     Sqlite(#[from] rusqlite::Error),
     #[error("IO: {0}")]
     Io(#[from] std::io::Error),
+    #[error("The database could not be unlocked: wrong passphrase, or it is not an encrypted database")]
+    WrongPassphrase,
+}
+
+/// File name of the database inside the data directory.
+const DB_FILE_NAME: &str = "local_private_llm.db";
+
+/// True when `path` is an unencrypted SQLite database (it starts with the plain SQLite header).
+/// SQLCipher databases are indistinguishable from random bytes, so this is false for them.
+pub fn is_plaintext_db(path: &Path) -> bool {
+    use std::io::Read;
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut header))
+        .map(|_| &header == b"SQLite format 3\0")
+        .unwrap_or(false)
 }
 
 #[derive(Debug)]
@@ -21,14 +38,77 @@ pub struct ConversationRow {
     pub created_at: i64,
     pub updated_at: i64,
     pub message_ids: Vec<String>,
+    /// Model override set for this conversation, else the model of its latest assistant message.
+    pub last_model_used: Option<String>,
+    /// JSON of the chat options last sent for this conversation; None until its first chat request.
+    pub chat_options: Option<String>,
+    /// Position set by reorder_conversations; None keeps the conversation in updated_at order.
+    pub sort_order: Option<i64>,
+    /// System prompt for this conversation only (e.g. from a template); takes precedence over the
+    /// per-model and global prompts.
+    pub system_prompt: Option<String>,
 }
 
-#[derive(Debug)]
+/// id, title, created_at, updated_at, chat_options, sort_order, system_prompt as read from `conversations`.
+type ConversationColumns = (String, String, i64, i64, Option<String>, Option<i64>, Option<String>);
+
+#[derive(Debug, Clone)]
 pub struct MessageRow {
     pub id: String,
     pub role: String,
     pub content: String,
     pub timestamp: i64,
+    /// Model that produced the message (assistant messages); None for older rows and user messages.
+    pub model: Option<String>,
+    pub starred: bool,
+    /// The reply did not finish (stopped by the user, or the stream was cut off) and the partial
+    /// text was kept.
+    pub interrupted: bool,
+    /// Set when the message is one of several regenerated answers to the same turn.
+    pub variant: Option<MessageVariant>,
+}
+
+/// A web source an assistant message was based on (from a web_search result).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CitationRow {
+    pub title: String,
+    pub url: String,
+    /// Search provider that returned the result (e.g. "duckduckgo", "wikipedia").
+    pub provider: Option<String>,
+}
+
+/// Position of a message among the regenerations of its turn. Only the active variant is part
+/// of the chat history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageVariant {
+    pub group_id: String,
+    /// 0 for the original answer, then 1, 2, ... in regeneration order.
+    pub index: i64,
+    pub count: i64,
+    pub active: bool,
+}
+
+/// Conversation columns streamed by the NDJSON export/import (no message ids, so nothing is buffered).
+#[derive(Debug, Clone)]
+pub struct ConversationRecordRow {
+    pub id: String,
+    pub title: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub model_override: Option<String>,
+    pub system_prompt: Option<String>,
+}
+
+/// Aggregate usage stats for a conversation. All zeros for an empty conversation.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationStats {
+    pub message_count: i64,
+    pub user_chars: i64,
+    pub assistant_chars: i64,
+    pub total_words: i64,
+    pub estimated_tokens: i64,
+    pub first_timestamp: Option<i64>,
+    pub last_timestamp: Option<i64>,
 }
 
 #[derive(Debug, Clone)]
@@ -41,9 +121,73 @@ pub struct Settings {
     pub tool_calling_mode: bool,
     /// Inference device preference: "auto" | "prefer_gpu" | "force_cpu"
     pub inference_device_preference: String,
+    /// Summarize older messages once a conversation has more than rolling_summary_threshold unsummarized messages.
+    pub rolling_summary_enabled: bool,
+    pub rolling_summary_threshold: i64,
+    /// Reuse replies to identical temperature-0 requests from the response_cache table (opt-in).
+    pub response_cache_enabled: bool,
+    /// Seconds between background Ollama health checks (ollama-status-changed events).
+    pub health_check_interval_secs: i64,
+    /// Agent loop guardrails: model turns, tool calls and wall-clock seconds per request before the
+    /// model is asked for a final answer.
+    pub agent_max_iterations: i64,
+    pub agent_max_tool_calls: i64,
+    pub agent_timeout_secs: i64,
 }
 
-#[derive(Debug, Clone, Default)]
+/// Rolling summary of a conversation's older messages. Covers every message up to and including
+/// `summarized_through_id` (by timestamp, then insertion order); later messages are sent verbatim.
+#[derive(Debug, Clone)]
+pub struct ConversationSummaryRow {
+    pub content: String,
+    pub summarized_through_id: String,
+    pub updated_at: i64,
+}
+
+/// A file pinned to a conversation; re-read and sent as context on every chat turn.
+/// `root` is "filesystem" or "obsidian"; `path` is relative to that root.
+#[derive(Debug, Clone)]
+pub struct ContextFileRow {
+    pub root: String,
+    pub path: String,
+    pub added_at: i64,
+}
+
+/// System prompt used instead of the global one for models matching `pattern`
+/// (an exact model name, or a name prefix such as a family "qwen2.5-coder").
+#[derive(Debug, Clone)]
+pub struct ModelSystemPromptRow {
+    pub pattern: String,
+    pub prompt: String,
+    pub updated_at: i64,
+}
+
+/// A reusable starting point for new conversations. `seed_messages` is a JSON array of
+/// `{role, content}` objects, kept as text here and parsed by the caller.
+#[derive(Debug, Clone)]
+pub struct ConversationTemplateRow {
+    pub id: String,
+    pub name: String,
+    pub model: Option<String>,
+    pub system_prompt: Option<String>,
+    pub seed_messages: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// One embedded chunk of a note, as cached in the `embeddings` table.
+#[derive(Debug, Clone)]
+pub struct EmbeddingRow {
+    pub path: String,
+    pub chunk_index: i64,
+    pub chunk: String,
+    pub embedding: Vec<f32>,
+}
+
+/// Default cap on tool result size sent back to the model.
+pub const DEFAULT_TOOL_RESULT_MAX_CHARS: usize = 16000;
+
+#[derive(Debug, Clone)]
 pub struct McpSettings {
     pub filesystem_enabled: bool,
     pub filesystem_root: String,
@@ -51,6 +195,61 @@ pub struct McpSettings {
     pub obsidian_vault_path: String,
     pub web_search_enabled: bool,
     pub terminal_enabled: bool,
+    /// When false, an empty DuckDuckGo result is returned as-is (no Wikipedia/Wikidata fallback).
+    pub web_search_enable_fallbacks: bool,
+    /// Extra blocked command patterns (stored newline-separated), appended to the built-in blocklist.
+    pub terminal_extra_blocked_patterns: Vec<String>,
+    /// Match blocked command patterns on word boundaries.
+    pub terminal_block_word_boundaries: bool,
+    /// Scrape html.duckduckgo.com when the instant-answer API returns no results. Off by default.
+    pub web_search_html_fallback: bool,
+    /// Max characters of any tool result returned to the model (0 = no limit).
+    pub tool_result_max_chars: usize,
+    /// Shell run_command spawns (e.g. "bash", "powershell"); empty = platform default.
+    pub terminal_shell: String,
+    /// Page fetch timeout for fetch_url/excerpts/open_browser_search, in seconds (clamped 1–30 on use).
+    pub fetch_timeout_secs: u64,
+    /// DuckDuckGo request timeout for web_search, in seconds (clamped 1–30 on use).
+    pub web_search_timeout_secs: u64,
+    /// Safe mode: hide and refuse high-risk tools (terminal) regardless of the toggles above.
+    pub safe_mode: bool,
+    /// In safe mode, also hide and refuse tools that write files.
+    pub safe_mode_block_writes: bool,
+    /// Tool calls allowed to run at once (clamped 1–16 on use); extra calls queue.
+    pub max_concurrent_tools: usize,
+    /// Air-gapped mode: network tools fail without connecting and Ollama must be on loopback.
+    pub offline_mode: bool,
+    /// Let open_path open programs, scripts and other non-document files.
+    pub open_path_allow_any_file: bool,
+    /// Let fetch_url follow redirects from https to plain http.
+    pub fetch_allow_insecure_redirect: bool,
+}
+
+impl Default for McpSettings {
+    fn default() -> Self {
+        Self {
+            filesystem_enabled: false,
+            filesystem_root: String::new(),
+            obsidian_enabled: false,
+            obsidian_vault_path: String::new(),
+            web_search_enabled: false,
+            terminal_enabled: false,
+            web_search_enable_fallbacks: true,
+            terminal_extra_blocked_patterns: Vec::new(),
+            terminal_block_word_boundaries: false,
+            web_search_html_fallback: false,
+            tool_result_max_chars: DEFAULT_TOOL_RESULT_MAX_CHARS,
+            terminal_shell: String::new(),
+            fetch_timeout_secs: crate::mcp::DEFAULT_FETCH_TIMEOUT_SECS,
+            web_search_timeout_secs: crate::mcp::DEFAULT_WEB_SEARCH_TIMEOUT_SECS,
+            safe_mode: false,
+            safe_mode_block_writes: true,
+            max_concurrent_tools: crate::mcp::DEFAULT_MAX_CONCURRENT_TOOLS,
+            offline_mode: false,
+            open_path_allow_any_file: false,
+            fetch_allow_insecure_redirect: false,
+        }
+    }
 }
 
 impl Default for Settings {
@@ -63,6 +262,13 @@ impl Default for Settings {
             max_tokens: 2048,
             tool_calling_mode: true,
             inference_device_preference: "prefer_gpu".to_string(),
+            rolling_summary_enabled: false,
+            rolling_summary_threshold: 40,
+            response_cache_enabled: false,
+            health_check_interval_secs: 5,
+            agent_max_iterations: 10,
+            agent_max_tool_calls: 20,
+            agent_timeout_secs: 300,
         }
     }
 }
@@ -71,11 +277,170 @@ pub struct Storage {
     conn: Connection,
 }
 
+/// Maps `SELECT id, name, model, system_prompt, seed_messages, created_at, updated_at`.
+fn conversation_template_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ConversationTemplateRow> {
+    Ok(ConversationTemplateRow {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        model: row.get(2)?,
+        system_prompt: row.get(3)?,
+        seed_messages: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+/// Message columns plus variant info, for queries aliasing messages as `m`; read with
+/// message_row_with_variant.
+const VARIANT_MESSAGE_COLUMNS: &str = "m.id, m.role, m.content, m.timestamp, m.model, m.starred, m.interrupted,
+     m.variant_group_id, m.variant_index, m.variant_active,
+     (SELECT COUNT(*) FROM messages v WHERE v.variant_group_id = m.variant_group_id)";
+
+/// Delete the conversation's rolling summary if the message it was anchored to is gone: the summary
+/// then covers text that is no longer in the history.
+fn clear_orphaned_summary(conn: &Connection, conversation_id: &str) -> Result<(), StorageError> {
+    conn.execute(
+        "DELETE FROM conversation_summaries WHERE conversation_id = ?1
+         AND summarized_through_id NOT IN (SELECT id FROM messages WHERE conversation_id = ?1)",
+        params![conversation_id],
+    )?;
+    Ok(())
+}
+
+fn message_row_with_variant(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageRow> {
+    let group_id: Option<String> = row.get(7)?;
+    Ok(MessageRow {
+        id: row.get(0)?,
+        role: row.get(1)?,
+        content: row.get(2)?,
+        timestamp: row.get(3)?,
+        model: row.get(4)?,
+        starred: row.get(5)?,
+        interrupted: row.get(6)?,
+        variant: match group_id {
+            Some(group_id) => Some(MessageVariant {
+                group_id,
+                index: row.get(8)?,
+                active: row.get(9)?,
+                count: row.get(10)?,
+            }),
+            None => None,
+        },
+    })
+}
+
+/// Open import transaction from Storage::begin_record_import. Existing ids are left untouched.
+pub struct RecordImport<'a> {
+    tx: rusqlite::Transaction<'a>,
+}
+
+impl RecordImport<'_> {
+    /// Insert a conversation. Returns false if one with this id already exists.
+    pub fn conversation(&mut self, c: &ConversationRecordRow) -> Result<bool, StorageError> {
+        let inserted = self
+            .tx
+            .prepare_cached(
+                "INSERT OR IGNORE INTO conversations (id, title, created_at, updated_at, model_override, system_prompt)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![c.id, c.title, c.created_at, c.updated_at, c.model_override, c.system_prompt])?;
+        Ok(inserted > 0)
+    }
+
+    /// Insert a message. Returns false if the id already exists or its conversation does not.
+    pub fn message(&mut self, conversation_id: &str, m: &MessageRow) -> Result<bool, StorageError> {
+        let inserted = self
+            .tx
+            .prepare_cached(
+                "INSERT OR IGNORE INTO messages
+                     (id, conversation_id, role, content, timestamp, model, starred, interrupted, variant_group_id, variant_index, variant_active)
+                 SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11 WHERE EXISTS (SELECT 1 FROM conversations WHERE id = ?2)",
+            )?
+            .execute(params![
+                m.id,
+                conversation_id,
+                m.role,
+                m.content,
+                m.timestamp,
+                m.model,
+                m.starred,
+                m.interrupted,
+                m.variant.as_ref().map(|v| v.group_id.as_str()),
+                m.variant.as_ref().map_or(0, |v| v.index),
+                m.variant.as_ref().map_or(true, |v| v.active),
+            ])?;
+        Ok(inserted > 0)
+    }
+
+    pub fn commit(self) -> Result<(), StorageError> {
+        self.tx.commit()?;
+        Ok(())
+    }
+}
+
 impl Storage {
     pub fn new(data_dir: &str) -> Result<Self, StorageError> {
         std::fs::create_dir_all(data_dir)?;
-        let db_path = Path::new(data_dir).join("local_private_llm.db");
+        let db_path = Path::new(data_dir).join(DB_FILE_NAME);
+        let conn = Connection::open(&db_path)?;
+        Self::from_connection(conn)
+    }
+
+    /// Open (or create) the database encrypted with SQLCipher, keyed by `passphrase` (SQLCipher
+    /// derives the key itself). An existing plaintext database is encrypted in place first. The
+    /// passphrase is only held for the duration of this call.
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(data_dir: &str, passphrase: &str) -> Result<Self, StorageError> {
+        if passphrase.is_empty() {
+            return Err(StorageError::WrongPassphrase);
+        }
+        std::fs::create_dir_all(data_dir)?;
+        let db_path = Path::new(data_dir).join(DB_FILE_NAME);
+        if is_plaintext_db(&db_path) {
+            Self::encrypt_plaintext_db(&db_path, passphrase)?;
+        }
         let conn = Connection::open(&db_path)?;
+        conn.pragma_update(None, "key", passphrase)?;
+        // The key is only checked on first read; a wrong one makes the file look like garbage.
+        conn.query_row("SELECT count(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0))
+            .map_err(|e| match e.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::NotADatabase) => StorageError::WrongPassphrase,
+                _ => StorageError::Sqlite(e),
+            })?;
+        Self::from_connection(conn)
+    }
+
+    /// Copy a plaintext database into a new encrypted file with `sqlcipher_export`, then replace the
+    /// original. The plaintext file is untouched until the encrypted copy is complete.
+    #[cfg(feature = "encryption")]
+    fn encrypt_plaintext_db(db_path: &Path, passphrase: &str) -> Result<(), StorageError> {
+        let encrypted_path = db_path.with_extension("db.encrypting");
+        if encrypted_path.exists() {
+            std::fs::remove_file(&encrypted_path)?;
+        }
+        {
+            let conn = Connection::open(db_path)?;
+            conn.execute(
+                "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+                params![encrypted_path.to_string_lossy(), passphrase],
+            )?;
+            conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+            conn.execute("DETACH DATABASE encrypted", [])?;
+        }
+        std::fs::rename(&encrypted_path, db_path)?;
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let _ = std::fs::remove_file(format!("{}{}", db_path.display(), suffix));
+        }
+        Ok(())
+    }
+
+    /// In-memory database (nothing written to disk). Used by tests and ephemeral mode.
+    pub fn new_in_memory() -> Result<Self, StorageError> {
+        let conn = Connection::open_in_memory()?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, StorageError> {
         Self::migrate(&conn)?;
         Ok(Self { conn })
     }
@@ -102,37 +467,209 @@ impl Storage {
                 key TEXT PRIMARY KEY,
                 value TEXT NOT NULL
             );
+            CREATE TABLE IF NOT EXISTS conversation_summaries (
+                conversation_id TEXT PRIMARY KEY,
+                content TEXT NOT NULL,
+                summarized_through_id TEXT NOT NULL,
+                updated_at INTEGER NOT NULL,
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+            );
+            CREATE TABLE IF NOT EXISTS conversation_context_files (
+                conversation_id TEXT NOT NULL,
+                root TEXT NOT NULL,
+                path TEXT NOT NULL,
+                added_at INTEGER NOT NULL,
+                PRIMARY KEY (conversation_id, root, path),
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+            );
+            CREATE TABLE IF NOT EXISTS model_system_prompts (
+                pattern TEXT PRIMARY KEY,
+                prompt TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS embeddings (
+                root TEXT NOT NULL,
+                path TEXT NOT NULL,
+                chunk_index INTEGER NOT NULL,
+                content_hash TEXT NOT NULL,
+                model TEXT NOT NULL,
+                chunk TEXT NOT NULL,
+                embedding BLOB NOT NULL,
+                PRIMARY KEY (root, path, chunk_index)
+            );
+            CREATE TABLE IF NOT EXISTS message_citations (
+                message_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                url TEXT NOT NULL,
+                provider TEXT,
+                PRIMARY KEY (message_id, position)
+            );
+            CREATE TABLE IF NOT EXISTS conversation_templates (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                model TEXT,
+                system_prompt TEXT,
+                seed_messages TEXT NOT NULL DEFAULT '[]',
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS ui_state (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS tool_acknowledgments (
+                tool_name TEXT NOT NULL,
+                root TEXT NOT NULL DEFAULT '',
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (tool_name, root)
+            );
+            CREATE TABLE IF NOT EXISTS response_cache (
+                key TEXT PRIMARY KEY,
+                request TEXT NOT NULL,
+                model TEXT NOT NULL,
+                response TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
             "#,
         )?;
+        Self::add_column_if_missing(conn, "messages", "model", "TEXT")?;
+        Self::add_column_if_missing(conn, "conversations", "model_override", "TEXT")?;
+        Self::add_column_if_missing(conn, "messages", "starred", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column_if_missing(conn, "messages", "interrupted", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column_if_missing(conn, "conversations", "chat_options", "TEXT")?;
+        Self::add_column_if_missing(conn, "conversations", "sort_order", "INTEGER")?;
+        Self::add_column_if_missing(conn, "conversations", "system_prompt", "TEXT")?;
+        Self::add_column_if_missing(conn, "messages", "variant_group_id", "TEXT")?;
+        Self::add_column_if_missing(conn, "messages", "variant_index", "INTEGER NOT NULL DEFAULT 0")?;
+        Self::add_column_if_missing(conn, "messages", "variant_active", "INTEGER NOT NULL DEFAULT 1")?;
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_messages_variant_group ON messages(variant_group_id)")?;
+        Ok(())
+    }
+
+    /// Called on app exit: commits a transaction left open, lets SQLite update its query planner
+    /// statistics and checkpoints the WAL. Safe to call more than once.
+    pub fn flush(&mut self) -> Result<(), StorageError> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        self.conn.execute_batch("PRAGMA optimize")?;
+        // Folds a WAL (if the database uses one) back into the main file and empties it, so no
+        // -wal file is left behind. Reports a row even in rollback-journal mode.
+        self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// ALTER TABLE ... ADD COLUMN for databases created before the column existed.
+    fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<(), StorageError> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|r| r.ok())
+            .any(|name| name == column);
+        if !exists {
+            conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))?;
+        }
         Ok(())
     }
 
+    /// The conversation's model override, else the model of its most recent assistant message.
+    fn last_model_used(&self, conversation_id: &str) -> Result<Option<String>, StorageError> {
+        let model: Option<Option<String>> = self
+            .conn
+            .query_row(
+                "SELECT COALESCE(
+                    (SELECT model_override FROM conversations WHERE id = ?1),
+                    (SELECT model FROM messages
+                     WHERE conversation_id = ?1 AND role = 'assistant' AND model IS NOT NULL AND model <> ''
+                     ORDER BY timestamp DESC, rowid DESC LIMIT 1))",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(model.flatten().filter(|m| !m.trim().is_empty()))
+    }
+
+    /// Pin a model for a conversation (None clears the override).
+    pub fn set_conversation_model(&mut self, id: &str, model: Option<&str>) -> Result<(), StorageError> {
+        let model = model.map(str::trim).filter(|m| !m.is_empty());
+        self.conn.execute(
+            "UPDATE conversations SET model_override = ?1 WHERE id = ?2",
+            params![model, id],
+        )?;
+        Ok(())
+    }
+
+    /// Set the conversation's own system prompt (None or blank clears it).
+    pub fn set_conversation_system_prompt(&mut self, id: &str, prompt: Option<&str>) -> Result<(), StorageError> {
+        let prompt = prompt.filter(|p| !p.trim().is_empty());
+        self.conn.execute(
+            "UPDATE conversations SET system_prompt = ?1 WHERE id = ?2",
+            params![prompt, id],
+        )?;
+        Ok(())
+    }
+
+    /// Remember the chat options (as JSON) used for the conversation's latest request.
+    pub fn set_conversation_chat_options(&mut self, id: &str, options_json: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "UPDATE conversations SET chat_options = ?1 WHERE id = ?2",
+            params![options_json, id],
+        )?;
+        Ok(())
+    }
+
+    /// Manually ordered conversations first (by sort_order), then the rest by most recently updated.
     pub fn list_conversations(&self) -> Result<Vec<ConversationRow>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, title, created_at, updated_at FROM conversations ORDER BY updated_at DESC",
+            "SELECT id, title, created_at, updated_at, chat_options, sort_order, system_prompt FROM conversations
+             ORDER BY sort_order IS NULL, sort_order ASC, updated_at DESC",
         )?;
-        let rows: Vec<(String, String, i64, i64)> = stmt
+        let rows: Vec<ConversationColumns> = stmt
             .query_map([], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?))
             })?
             .collect::<Result<Vec<_>, _>>()?;
         let mut out = Vec::new();
-        for (id, title, created_at, updated_at) in rows {
+        for (id, title, created_at, updated_at, chat_options, sort_order, system_prompt) in rows {
             let message_ids = self.get_message_ids_for_conversation(&id).unwrap_or_default();
+            let last_model_used = self.last_model_used(&id).unwrap_or_default();
             out.push(ConversationRow {
                 id,
                 title,
                 created_at,
                 updated_at,
                 message_ids,
+                last_model_used,
+                chat_options,
+                sort_order,
+                system_prompt,
             });
         }
         Ok(out)
     }
 
+    /// Give the listed conversations sort_order 0, 1, 2, ... in the given order and clear it on
+    /// all others, which go back to updated_at order below them. An empty list restores the default
+    /// order. Unknown ids are skipped; returns how many conversations were ordered.
+    pub fn reorder_conversations(&mut self, ordered_ids: &[String]) -> Result<usize, StorageError> {
+        let tx = self.conn.transaction()?;
+        tx.execute("UPDATE conversations SET sort_order = NULL WHERE sort_order IS NOT NULL", [])?;
+        let mut ordered = 0;
+        {
+            let mut stmt = tx.prepare("UPDATE conversations SET sort_order = ?1 WHERE id = ?2")?;
+            for id in ordered_ids {
+                ordered += stmt.execute(params![ordered as i64, id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(ordered)
+    }
+
     fn get_message_ids_for_conversation(&self, conversation_id: &str) -> Result<Vec<String>, StorageError> {
         let mut stmt = self.conn.prepare(
-            "SELECT id FROM messages WHERE conversation_id = ? ORDER BY timestamp ASC",
+            "SELECT id FROM messages WHERE conversation_id = ? AND variant_active = 1 ORDER BY timestamp ASC, rowid ASC",
         )?;
         let rows = stmt.query_map(params![conversation_id], |row| row.get(0))?;
         let mut ids = Vec::new();
@@ -142,41 +679,47 @@ impl Storage {
         Ok(ids)
     }
 
-    pub fn get_conversation_with_messages(
-        &self,
-        id: &str,
-    ) -> Result<Option<(ConversationRow, Vec<MessageRow>)>, StorageError> {
-        let row: Option<(String, String, i64, i64)> = self
+    /// One conversation's row without its messages (only their ids).
+    pub fn get_conversation(&self, id: &str) -> Result<Option<ConversationRow>, StorageError> {
+        let row: Option<ConversationColumns> = self
             .conn
             .query_row(
-                "SELECT id, title, created_at, updated_at FROM conversations WHERE id = ?",
+                "SELECT id, title, created_at, updated_at, chat_options, sort_order, system_prompt FROM conversations WHERE id = ?",
                 params![id],
-                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?)),
             )
             .optional()?;
-        let (id, title, created_at, updated_at) = match row {
+        let (id, title, created_at, updated_at, chat_options, sort_order, system_prompt) = match row {
             Some(r) => r,
             None => return Ok(None),
         };
         let message_ids = self.get_message_ids_for_conversation(&id).unwrap_or_default();
-        let conv = ConversationRow {
-            id: id.clone(),
+        let last_model_used = self.last_model_used(&id)?;
+        Ok(Some(ConversationRow {
+            id,
             title,
             created_at,
             updated_at,
             message_ids,
+            last_model_used,
+            chat_options,
+            sort_order,
+            system_prompt,
+        }))
+    }
+
+    pub fn get_conversation_with_messages(
+        &self,
+        id: &str,
+    ) -> Result<Option<(ConversationRow, Vec<MessageRow>)>, StorageError> {
+        let Some(conv) = self.get_conversation(id)? else {
+            return Ok(None);
         };
-        let mut stmt = self.conn.prepare(
-            "SELECT id, role, content, timestamp FROM messages WHERE conversation_id = ? ORDER BY timestamp ASC",
-        )?;
-        let rows = stmt.query_map(params![id], |row| {
-            Ok(MessageRow {
-                id: row.get(0)?,
-                role: row.get(1)?,
-                content: row.get(2)?,
-                timestamp: row.get(3)?,
-            })
-        })?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM messages m WHERE m.conversation_id = ? AND m.variant_active = 1 ORDER BY m.timestamp ASC, m.rowid ASC",
+            VARIANT_MESSAGE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![id], message_row_with_variant)?;
         let mut messages = Vec::new();
         for m in rows {
             messages.push(m?);
@@ -197,6 +740,10 @@ impl Storage {
             created_at: now,
             updated_at: now,
             message_ids: vec![],
+            last_model_used: None,
+            chat_options: None,
+            sort_order: None,
+            system_prompt: None,
         })
     }
 
@@ -210,22 +757,348 @@ impl Storage {
     }
 
     pub fn delete_conversation(&mut self, id: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "DELETE FROM message_citations WHERE message_id IN (SELECT id FROM messages WHERE conversation_id = ?1)",
+            params![id],
+        )?;
         self.conn.execute("DELETE FROM messages WHERE conversation_id = ?1", params![id])?;
+        self.conn.execute("DELETE FROM conversation_summaries WHERE conversation_id = ?1", params![id])?;
+        self.conn.execute("DELETE FROM conversation_context_files WHERE conversation_id = ?1", params![id])?;
         self.conn.execute("DELETE FROM conversations WHERE id = ?1", params![id])?;
         Ok(())
     }
 
+    /// Delete conversations without any messages that were last updated at least `min_age_secs`
+    /// ago. `keep_id` (the open conversation) and hand-ordered (pinned via sort_order) ones are
+    /// left alone. Returns how many were deleted.
+    pub fn delete_empty_conversations(&mut self, min_age_secs: i64, keep_id: Option<&str>) -> Result<usize, StorageError> {
+        let cutoff = Utc::now().timestamp() - min_age_secs.max(0);
+        let ids: Vec<String> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT c.id FROM conversations c
+                 WHERE c.updated_at <= ?1 AND c.sort_order IS NULL AND c.id IS NOT ?2
+                   AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.conversation_id = c.id)",
+            )?;
+            let rows = stmt.query_map(params![cutoff, keep_id], |row| row.get(0))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        for id in &ids {
+            self.delete_conversation(id)?;
+        }
+        Ok(ids.len())
+    }
+
+    /// Current rolling summary, or None if there is none. Deleting the last summarized message (undo,
+    /// trim) clears the summary; the join also skips rows left dangling by older versions.
+    pub fn get_conversation_summary(&self, conversation_id: &str) -> Result<Option<ConversationSummaryRow>, StorageError> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT s.content, s.summarized_through_id, s.updated_at FROM conversation_summaries s
+                 JOIN messages m ON m.id = s.summarized_through_id AND m.conversation_id = s.conversation_id
+                 WHERE s.conversation_id = ?1",
+                params![conversation_id],
+                |row| {
+                    Ok(ConversationSummaryRow {
+                        content: row.get(0)?,
+                        summarized_through_id: row.get(1)?,
+                        updated_at: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(row)
+    }
+
+    /// Messages not yet covered by the rolling summary, oldest first.
+    fn unsummarized_messages(&self, conversation_id: &str) -> Result<Vec<MessageRow>, StorageError> {
+        let through = self.get_conversation_summary(conversation_id)?.map(|s| s.summarized_through_id);
+        let mut stmt = self.conn.prepare(
+            "SELECT m.id, m.role, m.content, m.timestamp, m.model, m.starred, m.interrupted FROM messages m
+             LEFT JOIN messages t ON t.id = ?2
+             WHERE m.conversation_id = ?1 AND m.variant_active = 1
+               AND (t.id IS NULL OR m.timestamp > t.timestamp OR (m.timestamp = t.timestamp AND m.rowid > t.rowid))
+             ORDER BY m.timestamp ASC, m.rowid ASC",
+        )?;
+        let rows = stmt.query_map(params![conversation_id, through], |row| {
+            Ok(MessageRow {
+                id: row.get(0)?,
+                role: row.get(1)?,
+                content: row.get(2)?,
+                timestamp: row.get(3)?,
+                model: row.get(4)?,
+                starred: row.get(5)?,
+                interrupted: row.get(6)?,
+                variant: None,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The oldest unsummarized messages to fold into the rolling summary, once more than `threshold`
+    /// are unsummarized. The newest `keep_recent` always stay verbatim. None when nothing is due.
+    pub fn pending_summary_chunk(
+        &self,
+        conversation_id: &str,
+        threshold: usize,
+        keep_recent: usize,
+    ) -> Result<Option<Vec<MessageRow>>, StorageError> {
+        let mut messages = self.unsummarized_messages(conversation_id)?;
+        if messages.len() <= threshold || messages.len() <= keep_recent {
+            return Ok(None);
+        }
+        messages.truncate(messages.len() - keep_recent);
+        Ok(Some(messages))
+    }
+
+    pub fn save_conversation_summary(
+        &mut self,
+        conversation_id: &str,
+        content: &str,
+        summarized_through_id: &str,
+    ) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO conversation_summaries (conversation_id, content, summarized_through_id, updated_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![conversation_id, content, summarized_through_id, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Pin a file to a conversation. Adding the same file twice is a no-op.
+    pub fn add_context_file(&mut self, conversation_id: &str, root: &str, path: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO conversation_context_files (conversation_id, root, path, added_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![conversation_id, root, path, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns false if the file was not pinned.
+    pub fn remove_context_file(&mut self, conversation_id: &str, root: &str, path: &str) -> Result<bool, StorageError> {
+        let removed = self.conn.execute(
+            "DELETE FROM conversation_context_files WHERE conversation_id = ?1 AND root = ?2 AND path = ?3",
+            params![conversation_id, root, path],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Context files of a conversation in the order they were added.
+    pub fn list_context_files(&self, conversation_id: &str) -> Result<Vec<ContextFileRow>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT root, path, added_at FROM conversation_context_files
+             WHERE conversation_id = ?1 ORDER BY added_at ASC, rowid ASC",
+        )?;
+        let rows = stmt.query_map(params![conversation_id], |row| {
+            Ok(ContextFileRow {
+                root: row.get(0)?,
+                path: row.get(1)?,
+                added_at: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn list_model_system_prompts(&self) -> Result<Vec<ModelSystemPromptRow>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT pattern, prompt, updated_at FROM model_system_prompts ORDER BY pattern")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ModelSystemPromptRow {
+                pattern: row.get(0)?,
+                prompt: row.get(1)?,
+                updated_at: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Insert or replace the prompt for a model name/prefix.
+    pub fn set_model_system_prompt(&mut self, pattern: &str, prompt: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO model_system_prompts (pattern, prompt, updated_at) VALUES (?1, ?2, ?3)",
+            params![pattern, prompt, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns false if no prompt was set for `pattern`.
+    pub fn delete_model_system_prompt(&mut self, pattern: &str) -> Result<bool, StorageError> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM model_system_prompts WHERE pattern = ?1", params![pattern])?;
+        Ok(removed > 0)
+    }
+
+    /// All conversation templates, by name.
+    pub fn list_conversation_templates(&self) -> Result<Vec<ConversationTemplateRow>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, model, system_prompt, seed_messages, created_at, updated_at
+             FROM conversation_templates ORDER BY name COLLATE NOCASE, created_at",
+        )?;
+        let rows = stmt.query_map([], conversation_template_row)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn get_conversation_template(&self, id: &str) -> Result<Option<ConversationTemplateRow>, StorageError> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT id, name, model, system_prompt, seed_messages, created_at, updated_at
+                 FROM conversation_templates WHERE id = ?1",
+                [id],
+                conversation_template_row,
+            )
+            .optional()?)
+    }
+
+    /// Create a template, or update it when `id` names an existing one. Empty model and system
+    /// prompt are stored as NULL. Returns the saved row.
+    pub fn save_conversation_template(
+        &mut self,
+        id: Option<&str>,
+        name: &str,
+        model: Option<&str>,
+        system_prompt: Option<&str>,
+        seed_messages: &str,
+    ) -> Result<ConversationTemplateRow, StorageError> {
+        let now = Utc::now().timestamp();
+        let model = model.map(str::trim).filter(|m| !m.is_empty());
+        let system_prompt = system_prompt.filter(|p| !p.trim().is_empty());
+        let id = id.map(str::to_string).unwrap_or_else(|| Uuid::new_v4().to_string());
+        self.conn.execute(
+            "INSERT INTO conversation_templates (id, name, model, system_prompt, seed_messages, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+             ON CONFLICT(id) DO UPDATE SET name = excluded.name, model = excluded.model,
+                system_prompt = excluded.system_prompt, seed_messages = excluded.seed_messages,
+                updated_at = excluded.updated_at",
+            params![id, name, model, system_prompt, seed_messages, now],
+        )?;
+        self.get_conversation_template(&id)?
+            .ok_or(StorageError::Sqlite(rusqlite::Error::QueryReturnedNoRows))
+    }
+
+    /// Returns false if no template had this id.
+    pub fn delete_conversation_template(&mut self, id: &str) -> Result<bool, StorageError> {
+        Ok(self.conn.execute("DELETE FROM conversation_templates WHERE id = ?1", [id])? > 0)
+    }
+
+    /// Stored UI state (a JSON document, opaque to storage) for `key`.
+    pub fn get_ui_state(&self, key: &str) -> Result<Option<String>, StorageError> {
+        Ok(self
+            .conn
+            .query_row("SELECT value FROM ui_state WHERE key = ?1", [key], |r| r.get(0))
+            .optional()?)
+    }
+
+    pub fn set_ui_state(&mut self, key: &str, value: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO ui_state (key, value, updated_at) VALUES (?1, ?2, ?3)",
+            params![key, value, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns false if nothing was stored under `key`.
+    pub fn delete_ui_state(&mut self, key: &str) -> Result<bool, StorageError> {
+        Ok(self.conn.execute("DELETE FROM ui_state WHERE key = ?1", [key])? > 0)
+    }
+
+    /// "Always allow" acknowledgments as (tool name, root); an empty root covers every root.
+    pub fn list_tool_acknowledgments(&self) -> Result<Vec<(String, String)>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tool_name, root FROM tool_acknowledgments ORDER BY tool_name, root")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub fn add_tool_acknowledgment(&mut self, tool_name: &str, root: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO tool_acknowledgments (tool_name, root, created_at) VALUES (?1, ?2, ?3)",
+            params![tool_name, root, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Removes the acknowledgment for `root`, or every acknowledgment of the tool when None.
+    /// Returns how many were removed.
+    pub fn remove_tool_acknowledgments(&mut self, tool_name: &str, root: Option<&str>) -> Result<usize, StorageError> {
+        Ok(match root {
+            Some(root) => self.conn.execute(
+                "DELETE FROM tool_acknowledgments WHERE tool_name = ?1 AND root = ?2",
+                params![tool_name, root],
+            )?,
+            None => self
+                .conn
+                .execute("DELETE FROM tool_acknowledgments WHERE tool_name = ?1", [tool_name])?,
+        })
+    }
+
+    /// Content hash and model of a file's cached embeddings, keyed by path, for one root.
+    pub fn embedded_file_hashes(&self, root: &str) -> Result<HashMap<String, (String, String)>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, content_hash, model FROM embeddings WHERE root = ?1 AND chunk_index = 0",
+        )?;
+        let rows = stmt.query_map(params![root], |row| {
+            Ok((row.get::<_, String>(0)?, (row.get::<_, String>(1)?, row.get::<_, String>(2)?)))
+        })?;
+        Ok(rows.collect::<Result<HashMap<_, _>, _>>()?)
+    }
+
+    /// Replace all cached chunks of one file. An empty `chunks` just removes the file.
+    pub fn replace_file_embeddings(
+        &mut self,
+        root: &str,
+        path: &str,
+        content_hash: &str,
+        model: &str,
+        chunks: &[(String, Vec<f32>)],
+    ) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM embeddings WHERE root = ?1 AND path = ?2", params![root, path])?;
+        for (i, (chunk, embedding)) in chunks.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO embeddings (root, path, chunk_index, content_hash, model, chunk, embedding)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![root, path, i as i64, content_hash, model, chunk, crate::semantic::encode_embedding(embedding)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Cached chunks of one root embedded with `model`.
+    pub fn list_embeddings(&self, root: &str, model: &str) -> Result<Vec<EmbeddingRow>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, chunk_index, chunk, embedding FROM embeddings
+             WHERE root = ?1 AND model = ?2 ORDER BY path, chunk_index",
+        )?;
+        let rows = stmt.query_map(params![root, model], |row| {
+            Ok(EmbeddingRow {
+                path: row.get(0)?,
+                chunk_index: row.get(1)?,
+                chunk: row.get(2)?,
+                embedding: crate::semantic::decode_embedding(&row.get::<_, Vec<u8>>(3)?),
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
     pub fn add_message(
         &mut self,
         conversation_id: &str,
         role: &str,
         content: &str,
+        model: Option<&str>,
     ) -> Result<MessageRow, StorageError> {
         let id = Uuid::new_v4().to_string();
         let now = Utc::now().timestamp();
+        let model = model.map(str::trim).filter(|m| !m.is_empty());
         self.conn.execute(
-            "INSERT INTO messages (id, conversation_id, role, content, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![id, conversation_id, role, content, now],
+            "INSERT INTO messages (id, conversation_id, role, content, timestamp, model) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![id, conversation_id, role, content, now, model],
         )?;
         self.conn.execute(
             "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
@@ -236,6 +1109,444 @@ impl Storage {
             role: role.to_string(),
             content: content.to_string(),
             timestamp: now,
+            model: model.map(str::to_string),
+            starred: false,
+            interrupted: false,
+            variant: None,
+        })
+    }
+
+    /// Insert `(role, content)` messages in order within one transaction, touching `updated_at` once.
+    /// Much faster than repeated `add_message` calls for bulk inserts such as imports.
+    pub fn add_messages(
+        &mut self,
+        conversation_id: &str,
+        messages: &[(String, String)],
+    ) -> Result<Vec<MessageRow>, StorageError> {
+        let now = Utc::now().timestamp();
+        let tx = self.conn.transaction()?;
+        let mut rows = Vec::with_capacity(messages.len());
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO messages (id, conversation_id, role, content, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (role, content) in messages {
+                let id = Uuid::new_v4().to_string();
+                stmt.execute(params![id, conversation_id, role, content, now])?;
+                rows.push(MessageRow {
+                    id,
+                    role: role.clone(),
+                    content: content.clone(),
+                    timestamp: now,
+                    model: None,
+                    starred: false,
+                    interrupted: false,
+                    variant: None,
+                });
+            }
+        }
+        if !rows.is_empty() {
+            tx.execute(
+                "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
+                params![now, conversation_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(rows)
+    }
+
+    /// Row counts of the conversations and messages tables.
+    pub fn count_conversations_and_messages(&self) -> Result<(usize, usize), StorageError> {
+        let (conversations, messages): (i64, i64) = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM conversations), (SELECT COUNT(*) FROM messages)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((conversations as usize, messages as usize))
+    }
+
+    /// Pass every conversation (oldest first) to `f` one row at a time. Returns the number visited.
+    pub fn for_each_conversation_record<F>(&self, mut f: F) -> Result<usize, StorageError>
+    where
+        F: FnMut(ConversationRecordRow) -> Result<(), StorageError>,
+    {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, created_at, updated_at, model_override, system_prompt FROM conversations ORDER BY created_at ASC, rowid ASC",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            f(ConversationRecordRow {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+                model_override: row.get(4)?,
+                system_prompt: row.get(5)?,
+            })?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Pass every message with its conversation id to `f` one row at a time, in insertion order
+    /// (which keeps same-timestamp messages in order on re-import). Returns the number visited.
+    pub fn for_each_message_record<F>(&self, mut f: F) -> Result<usize, StorageError>
+    where
+        F: FnMut(&str, MessageRow) -> Result<(), StorageError>,
+    {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, m.conversation_id FROM messages m ORDER BY m.rowid ASC",
+            VARIANT_MESSAGE_COLUMNS
+        ))?;
+        let mut rows = stmt.query([])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let conversation_id: String = row.get(11)?;
+            f(&conversation_id, message_row_with_variant(row)?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Start a bulk import; rows are inserted as they are read and committed together.
+    /// Dropping the import without commit rolls everything back.
+    pub fn begin_record_import(&mut self) -> Result<RecordImport<'_>, StorageError> {
+        Ok(RecordImport { tx: self.conn.transaction()? })
+    }
+
+    /// Store a regenerated answer for the assistant message `message_id` as a new variant of its
+    /// turn and make it the active one. The first regeneration turns the original into variant 0 of
+    /// a group named after its id. The new variant takes the original's timestamp so it stays in
+    /// place in the history. None if the conversation has no assistant message with that id.
+    pub fn add_message_variant(
+        &mut self,
+        conversation_id: &str,
+        message_id: &str,
+        content: &str,
+        model: Option<&str>,
+    ) -> Result<Option<MessageRow>, StorageError> {
+        let model = model.map(str::trim).filter(|m| !m.is_empty());
+        let tx = self.conn.transaction()?;
+        let original: Option<(i64, Option<String>)> = tx
+            .query_row(
+                "SELECT timestamp, variant_group_id FROM messages
+                 WHERE id = ?1 AND conversation_id = ?2 AND role = 'assistant'",
+                params![message_id, conversation_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((timestamp, group_id)) = original else {
+            return Ok(None);
+        };
+        let group_id = match group_id {
+            Some(g) => g,
+            None => {
+                tx.execute(
+                    "UPDATE messages SET variant_group_id = ?1, variant_index = 0 WHERE id = ?1",
+                    params![message_id],
+                )?;
+                message_id.to_string()
+            }
+        };
+        let (index, count): (i64, i64) = tx.query_row(
+            "SELECT COALESCE(MAX(variant_index), -1) + 1, COUNT(*) FROM messages WHERE variant_group_id = ?1",
+            params![group_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        tx.execute("UPDATE messages SET variant_active = 0 WHERE variant_group_id = ?1", params![group_id])?;
+        let id = Uuid::new_v4().to_string();
+        tx.execute(
+            "INSERT INTO messages (id, conversation_id, role, content, timestamp, model, variant_group_id, variant_index, variant_active)
+             VALUES (?1, ?2, 'assistant', ?3, ?4, ?5, ?6, ?7, 1)",
+            params![id, conversation_id, content, timestamp, model, group_id, index],
+        )?;
+        tx.execute(
+            "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
+            params![Utc::now().timestamp(), conversation_id],
+        )?;
+        tx.commit()?;
+        Ok(Some(MessageRow {
+            id,
+            role: "assistant".to_string(),
+            content: content.to_string(),
+            timestamp,
+            model: model.map(str::to_string),
+            starred: false,
+            interrupted: false,
+            variant: Some(MessageVariant { group_id, index, count: count + 1, active: true }),
+        }))
+    }
+
+    /// All variants of a regenerated turn, in regeneration order.
+    pub fn list_message_variants(&self, group_id: &str) -> Result<Vec<MessageRow>, StorageError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM messages m WHERE m.variant_group_id = ?1 ORDER BY m.variant_index ASC",
+            VARIANT_MESSAGE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![group_id], message_row_with_variant)?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Make `message_id` the active variant of its group. Returns false if it has no variant group.
+    pub fn select_variant(&mut self, message_id: &str) -> Result<bool, StorageError> {
+        let tx = self.conn.transaction()?;
+        let group_id: Option<Option<String>> = tx
+            .query_row(
+                "SELECT variant_group_id FROM messages WHERE id = ?1",
+                params![message_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(group_id) = group_id.flatten() else {
+            return Ok(false);
+        };
+        tx.execute(
+            "UPDATE messages SET variant_active = (id = ?2) WHERE variant_group_id = ?1",
+            params![group_id, message_id],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Append `text` to a message's content (continuing a cut-off reply). Returns false if no
+    /// message has that id.
+    pub fn append_message_content(&mut self, id: &str, text: &str) -> Result<bool, StorageError> {
+        let changed = self
+            .conn
+            .execute("UPDATE messages SET content = content || ?1 WHERE id = ?2", params![text, id])?;
+        Ok(changed > 0)
+    }
+
+    /// Star or unstar a message. Returns false if no message has that id.
+    pub fn set_message_starred(&mut self, id: &str, starred: bool) -> Result<bool, StorageError> {
+        let changed = self
+            .conn
+            .execute("UPDATE messages SET starred = ?1 WHERE id = ?2", params![starred, id])?;
+        Ok(changed > 0)
+    }
+
+    /// Mark a message as a reply that did not finish, or clear the mark once it is continued. Returns false if no message has that id.
+    pub fn set_message_interrupted(&mut self, id: &str, interrupted: bool) -> Result<bool, StorageError> {
+        let changed = self
+            .conn
+            .execute("UPDATE messages SET interrupted = ?1 WHERE id = ?2", params![interrupted, id])?;
+        Ok(changed > 0)
+    }
+
+    /// Replace the web sources cited by a message. Returns false if no message has that id.
+    pub fn set_message_citations(&mut self, message_id: &str, citations: &[CitationRow]) -> Result<bool, StorageError> {
+        let tx = self.conn.transaction()?;
+        let exists: bool = tx
+            .query_row("SELECT 1 FROM messages WHERE id = ?1", params![message_id], |_| Ok(true))
+            .optional()?
+            .unwrap_or(false);
+        if !exists {
+            return Ok(false);
+        }
+        tx.execute("DELETE FROM message_citations WHERE message_id = ?1", params![message_id])?;
+        for (position, c) in citations.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO message_citations (message_id, position, title, url, provider) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![message_id, position as i64, c.title, c.url, c.provider],
+            )?;
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Citations of every message in a conversation, keyed by message id, in stored order.
+    pub fn list_conversation_citations(
+        &self,
+        conversation_id: &str,
+    ) -> Result<HashMap<String, Vec<CitationRow>>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.message_id, c.title, c.url, c.provider FROM message_citations c
+             JOIN messages m ON m.id = c.message_id
+             WHERE m.conversation_id = ?1 ORDER BY c.message_id, c.position",
+        )?;
+        let rows = stmt.query_map(params![conversation_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                CitationRow {
+                    title: row.get(1)?,
+                    url: row.get(2)?,
+                    provider: row.get(3)?,
+                },
+            ))
+        })?;
+        let mut out: HashMap<String, Vec<CitationRow>> = HashMap::new();
+        for row in rows {
+            let (message_id, citation) = row?;
+            out.entry(message_id).or_default().push(citation);
+        }
+        Ok(out)
+    }
+
+    /// Starred messages of a conversation, oldest first.
+    pub fn list_starred_messages(&self, conversation_id: &str) -> Result<Vec<MessageRow>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, role, content, timestamp, model, starred, interrupted FROM messages
+             WHERE conversation_id = ?1 AND starred = 1 ORDER BY timestamp ASC, rowid ASC",
+        )?;
+        let rows = stmt.query_map(params![conversation_id], |row| {
+            Ok(MessageRow {
+                id: row.get(0)?,
+                role: row.get(1)?,
+                content: row.get(2)?,
+                timestamp: row.get(3)?,
+                model: row.get(4)?,
+                starred: row.get(5)?,
+                interrupted: row.get(6)?,
+                variant: None,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Delete the most recent assistant message and the user message right before it, in one transaction.
+    /// If the conversation ends with an unanswered user message, only that message is removed.
+    /// Returns the number of messages left in the conversation.
+    pub fn undo_last_exchange(&mut self, conversation_id: &str) -> Result<usize, StorageError> {
+        let tx = self.conn.transaction()?;
+        let last_two: Vec<(String, String)> = {
+            let mut stmt = tx.prepare(
+                "SELECT id, role FROM messages WHERE conversation_id = ?1 AND variant_active = 1
+                 ORDER BY timestamp DESC, rowid DESC LIMIT 2",
+            )?;
+            let rows = stmt.query_map(params![conversation_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+        let mut to_delete: Vec<&str> = Vec::new();
+        if let Some((last_id, last_role)) = last_two.first() {
+            to_delete.push(last_id);
+            if last_role == "assistant" {
+                if let Some((prev_id, prev_role)) = last_two.get(1) {
+                    if prev_role == "user" {
+                        to_delete.push(prev_id);
+                    }
+                }
+            }
+        }
+        for id in &to_delete {
+            // Regenerated answers go with the turn they belong to.
+            let group_id: Option<String> = tx.query_row(
+                "SELECT variant_group_id FROM messages WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?;
+            match group_id {
+                Some(group_id) => {
+                    tx.execute(
+                        "DELETE FROM message_citations WHERE message_id IN
+                         (SELECT id FROM messages WHERE variant_group_id = ?1)",
+                        params![group_id],
+                    )?;
+                    tx.execute("DELETE FROM messages WHERE variant_group_id = ?1", params![group_id])?
+                }
+                None => {
+                    tx.execute("DELETE FROM message_citations WHERE message_id = ?1", params![id])?;
+                    tx.execute("DELETE FROM messages WHERE id = ?1", params![id])?
+                }
+            };
+        }
+        if !to_delete.is_empty() {
+            tx.execute(
+                "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
+                params![Utc::now().timestamp(), conversation_id],
+            )?;
+            clear_orphaned_summary(&tx, conversation_id)?;
+        }
+        let remaining: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM messages WHERE conversation_id = ?1 AND variant_active = 1",
+            params![conversation_id],
+            |row| row.get(0),
+        )?;
+        tx.commit()?;
+        Ok(remaining as usize)
+    }
+
+    /// Delete every message after `message_id` (by timestamp, then insertion order) in one
+    /// transaction, and the rolling summary if it reached past that point. Returns the remaining
+    /// count, or None if the message is not in the conversation.
+    pub fn trim_after(&mut self, conversation_id: &str, message_id: &str) -> Result<Option<usize>, StorageError> {
+        let tx = self.conn.transaction()?;
+        let anchor: Option<(i64, i64)> = tx
+            .query_row(
+                "SELECT timestamp, rowid FROM messages WHERE id = ?1 AND conversation_id = ?2",
+                params![message_id, conversation_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((timestamp, rowid)) = anchor else {
+            return Ok(None);
+        };
+        tx.execute(
+            "DELETE FROM message_citations WHERE message_id IN
+             (SELECT id FROM messages WHERE conversation_id = ?1
+              AND (timestamp > ?2 OR (timestamp = ?2 AND rowid > ?3)))",
+            params![conversation_id, timestamp, rowid],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM messages WHERE conversation_id = ?1
+             AND (timestamp > ?2 OR (timestamp = ?2 AND rowid > ?3))",
+            params![conversation_id, timestamp, rowid],
+        )?;
+        if deleted > 0 {
+            tx.execute(
+                "UPDATE conversations SET updated_at = ?1 WHERE id = ?2",
+                params![Utc::now().timestamp(), conversation_id],
+            )?;
+            clear_orphaned_summary(&tx, conversation_id)?;
+        }
+        let remaining: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM messages WHERE conversation_id = ?1 AND variant_active = 1",
+            params![conversation_id],
+            |row| row.get(0),
+        )?;
+        tx.commit()?;
+        Ok(Some(remaining as usize))
+    }
+
+    /// Message count, per-role character totals and first/last timestamps via SQL aggregates.
+    /// Word count needs whitespace splitting, so only the content column is streamed for it.
+    pub fn conversation_stats(&self, conversation_id: &str) -> Result<ConversationStats, StorageError> {
+        let (message_count, user_chars, assistant_chars, total_chars, first_timestamp, last_timestamp): (
+            i64,
+            i64,
+            i64,
+            i64,
+            Option<i64>,
+            Option<i64>,
+        ) = self.conn.query_row(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(CASE WHEN role = 'user' THEN LENGTH(content) ELSE 0 END), 0),
+                    COALESCE(SUM(CASE WHEN role = 'assistant' THEN LENGTH(content) ELSE 0 END), 0),
+                    COALESCE(SUM(LENGTH(content)), 0),
+                    MIN(timestamp),
+                    MAX(timestamp)
+             FROM messages WHERE conversation_id = ?1 AND variant_active = 1",
+            params![conversation_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )?;
+        let mut stmt = self
+            .conn
+            .prepare("SELECT content FROM messages WHERE conversation_id = ?1 AND variant_active = 1")?;
+        let mut rows = stmt.query(params![conversation_id])?;
+        let mut total_words: i64 = 0;
+        while let Some(row) = rows.next()? {
+            let content: String = row.get(0)?;
+            total_words += content.split_whitespace().count() as i64;
+        }
+        Ok(ConversationStats {
+            message_count,
+            user_chars,
+            assistant_chars,
+            total_words,
+            // Rough heuristic (~4 chars per token); the real count depends on the model tokenizer.
+            estimated_tokens: (total_chars + 3) / 4,
+            first_timestamp,
+            last_timestamp,
         })
     }
 
@@ -247,6 +1558,59 @@ impl Storage {
         Ok(v)
     }
 
+    /// Cached reply for `key` if it was stored for exactly `request` within the last `ttl_secs`.
+    pub fn get_cached_response(&self, key: &str, request: &str, ttl_secs: i64) -> Result<Option<String>, StorageError> {
+        let cutoff = Utc::now().timestamp() - ttl_secs;
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT response FROM response_cache WHERE key = ?1 AND request = ?2 AND created_at >= ?3",
+                params![key, request, cutoff],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Store a reply and drop entries older than `ttl_secs`.
+    pub fn put_cached_response(
+        &mut self,
+        key: &str,
+        request: &str,
+        model: &str,
+        response: &str,
+        ttl_secs: i64,
+    ) -> Result<(), StorageError> {
+        let now = Utc::now().timestamp();
+        self.conn
+            .execute("DELETE FROM response_cache WHERE created_at < ?1", params![now - ttl_secs])?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO response_cache (key, request, model, response, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![key, request, model, response, now],
+        )?;
+        Ok(())
+    }
+
+    /// Remove every cached reply; returns how many were removed.
+    pub fn clear_response_cache(&mut self) -> Result<usize, StorageError> {
+        Ok(self.conn.execute("DELETE FROM response_cache", [])?)
+    }
+
+    /// Pinned model names, in the order they were added (stored newline-separated).
+    pub fn get_favorite_models(&self) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .get_setting_optional("favorite_models")?
+            .map(|s| s.lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect())
+            .unwrap_or_default())
+    }
+
+    pub fn set_favorite_models(&mut self, models: &[String]) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('favorite_models', ?1)",
+            params![models.join("\n")],
+        )?;
+        Ok(())
+    }
+
     pub fn get_settings(&self) -> Result<Settings, StorageError> {
         let theme: String = self
             .get_setting_optional("theme")?
@@ -274,6 +1638,34 @@ impl Storage {
             .get_setting_optional("inference_device_preference")?
             .filter(|s| matches!(s.as_str(), "auto" | "prefer_gpu" | "force_cpu"))
             .unwrap_or_else(|| "prefer_gpu".to_string());
+        let rolling_summary_enabled: bool = self
+            .get_setting_optional("rolling_summary_enabled")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(false);
+        let rolling_summary_threshold: i64 = self
+            .get_setting_optional("rolling_summary_threshold")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(40);
+        let response_cache_enabled: bool = self
+            .get_setting_optional("response_cache_enabled")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(false);
+        let health_check_interval_secs: i64 = self
+            .get_setting_optional("health_check_interval_secs")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5);
+        let agent_max_iterations: i64 = self
+            .get_setting_optional("agent_max_iterations")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10);
+        let agent_max_tool_calls: i64 = self
+            .get_setting_optional("agent_max_tool_calls")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(20);
+        let agent_timeout_secs: i64 = self
+            .get_setting_optional("agent_timeout_secs")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(300);
         Ok(Settings {
             theme,
             selected_model,
@@ -282,6 +1674,13 @@ impl Storage {
             max_tokens,
             tool_calling_mode,
             inference_device_preference,
+            rolling_summary_enabled,
+            rolling_summary_threshold,
+            response_cache_enabled,
+            health_check_interval_secs,
+            agent_max_iterations,
+            agent_max_tool_calls,
+            agent_timeout_secs,
         })
     }
 
@@ -309,6 +1708,64 @@ impl Storage {
                 .get_setting_optional("mcp_terminal_enabled")?
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(false),
+            web_search_enable_fallbacks: self
+                .get_setting_optional("mcp_web_search_enable_fallbacks")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(true),
+            terminal_extra_blocked_patterns: self
+                .get_setting_optional("mcp_terminal_extra_blocked_patterns")?
+                .map(|s| {
+                    s.lines()
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            terminal_block_word_boundaries: self
+                .get_setting_optional("mcp_terminal_block_word_boundaries")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+            web_search_html_fallback: self
+                .get_setting_optional("mcp_web_search_html_fallback")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+            tool_result_max_chars: self
+                .get_setting_optional("mcp_tool_result_max_chars")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_TOOL_RESULT_MAX_CHARS),
+            terminal_shell: self.get_setting_optional("mcp_terminal_shell")?.unwrap_or_default(),
+            fetch_timeout_secs: self
+                .get_setting_optional("mcp_fetch_timeout_secs")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::mcp::DEFAULT_FETCH_TIMEOUT_SECS),
+            web_search_timeout_secs: self
+                .get_setting_optional("mcp_web_search_timeout_secs")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::mcp::DEFAULT_WEB_SEARCH_TIMEOUT_SECS),
+            safe_mode: self
+                .get_setting_optional("mcp_safe_mode")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+            safe_mode_block_writes: self
+                .get_setting_optional("mcp_safe_mode_block_writes")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(true),
+            max_concurrent_tools: self
+                .get_setting_optional("mcp_max_concurrent_tools")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(crate::mcp::DEFAULT_MAX_CONCURRENT_TOOLS),
+            offline_mode: self
+                .get_setting_optional("mcp_offline_mode")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+            open_path_allow_any_file: self
+                .get_setting_optional("mcp_open_path_allow_any_file")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
+            fetch_allow_insecure_redirect: self
+                .get_setting_optional("mcp_fetch_allow_insecure_redirect")?
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(false),
         })
     }
 
@@ -337,6 +1794,62 @@ impl Storage {
             "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_terminal_enabled', ?1)",
             params![s.terminal_enabled.to_string()],
         )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_web_search_enable_fallbacks', ?1)",
+            params![s.web_search_enable_fallbacks.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_terminal_extra_blocked_patterns', ?1)",
+            params![s.terminal_extra_blocked_patterns.join("\n")],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_terminal_block_word_boundaries', ?1)",
+            params![s.terminal_block_word_boundaries.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_web_search_html_fallback', ?1)",
+            params![s.web_search_html_fallback.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_tool_result_max_chars', ?1)",
+            params![s.tool_result_max_chars.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_terminal_shell', ?1)",
+            params![s.terminal_shell],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_fetch_timeout_secs', ?1)",
+            params![s.fetch_timeout_secs.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_web_search_timeout_secs', ?1)",
+            params![s.web_search_timeout_secs.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_safe_mode', ?1)",
+            params![s.safe_mode.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_safe_mode_block_writes', ?1)",
+            params![s.safe_mode_block_writes.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_max_concurrent_tools', ?1)",
+            params![s.max_concurrent_tools.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_offline_mode', ?1)",
+            params![s.offline_mode.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_open_path_allow_any_file', ?1)",
+            params![s.open_path_allow_any_file.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('mcp_fetch_allow_insecure_redirect', ?1)",
+            params![s.fetch_allow_insecure_redirect.to_string()],
+        )?;
         Ok(())
     }
 
@@ -369,6 +1882,34 @@ impl Storage {
             "INSERT OR REPLACE INTO settings (key, value) VALUES ('inference_device_preference', ?1)",
             params![s.inference_device_preference],
         )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('rolling_summary_enabled', ?1)",
+            params![s.rolling_summary_enabled.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('rolling_summary_threshold', ?1)",
+            params![s.rolling_summary_threshold.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('response_cache_enabled', ?1)",
+            params![s.response_cache_enabled.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('health_check_interval_secs', ?1)",
+            params![s.health_check_interval_secs.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('agent_max_iterations', ?1)",
+            params![s.agent_max_iterations.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('agent_max_tool_calls', ?1)",
+            params![s.agent_max_tool_calls.to_string()],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('agent_timeout_secs', ?1)",
+            params![s.agent_timeout_secs.to_string()],
+        )?;
         Ok(())
     }
 }
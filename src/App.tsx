@@ -1,66 +1,76 @@
 import { useEffect, useState } from "react";
 import { Onboarding } from "@/components/onboarding/Onboarding";
+import { UnlockScreen } from "@/components/unlock/UnlockScreen";
 import { ChatLayout } from "@/components/chat/ChatLayout";
 import { Toaster } from "@/components/ui/toaster";
+import { Button } from "@/components/ui/button";
+import { AlertTriangle, X } from "lucide-react";
 import { api } from "@/lib/api";
 import { DEFAULT_MODEL } from "@/lib/constants";
 
-type AppPhase = "loading" | "onboarding" | "chat";
+type AppPhase = "loading" | "locked" | "onboarding" | "chat";
+
+function applyTheme(theme: string) {
+  const isDark =
+    theme === "dark" ||
+    (theme === "system" &&
+      window.matchMedia("(prefers-color-scheme: dark)").matches);
+  document.documentElement.classList.toggle("dark", isDark);
+}
 
 export default function App() {
   const [phase, setPhase] = useState<AppPhase>("loading");
   const [ollamaOk, setOllamaOk] = useState(false);
   const [models, setModels] = useState<string[]>([]);
   const [defaultModel, setDefaultModel] = useState(DEFAULT_MODEL);
+  const [startupNotices, setStartupNotices] = useState<string[]>([]);
 
-  useEffect(() => {
-    const applyTheme = (theme: string) => {
-      const isDark =
-        theme === "dark" ||
-        (theme === "system" &&
-          window.matchMedia("(prefers-color-scheme: dark)").matches);
-      document.documentElement.classList.toggle("dark", isDark);
-    };
+  const loadApp = async () => {
+    try {
+      const settings = await api.getSettings();
+      applyTheme(settings.theme);
+      setDefaultModel(settings.selected_model || DEFAULT_MODEL);
+    } catch {
+      applyTheme("system");
+    }
 
+    let ollamaHealthy = false;
+    let modelList: string[] = [];
+    try {
+      const health = await api.ollamaHealth();
+      ollamaHealthy = health.reachable;
+      modelList = health.models;
+      setOllamaOk(ollamaHealthy);
+      setModels(modelList);
+    } catch {
+      setOllamaOk(false);
+    }
+
+    const hasModel = modelList.length > 0;
+    setPhase(ollamaHealthy && hasModel ? "chat" : "onboarding");
+  };
+
+  useEffect(() => {
+    api.getStartupNotices().then(setStartupNotices).catch(() => {});
     (async () => {
-      try {
-        const settings = await api.getSettings();
-        applyTheme(settings.theme);
-        setDefaultModel(settings.selected_model || DEFAULT_MODEL);
-      } catch {
+      // An encrypted database opened without a terminal is unlocked here before anything reads it.
+      if (await api.databaseLocked().catch(() => false)) {
         applyTheme("system");
+        setPhase("locked");
+        return;
       }
-
-      let ollamaHealthy = false;
-      let modelList: string[] = [];
-      try {
-        ollamaHealthy = await api.ollamaHealth();
-        setOllamaOk(ollamaHealthy);
-        if (ollamaHealthy) {
-          const list = await api.ollamaListModels();
-          modelList = list.map((m) => m.name);
-          setModels(modelList);
-        }
-      } catch {
-        setOllamaOk(false);
-      }
-
-      const hasModel = modelList.length > 0;
-      setPhase(ollamaHealthy && hasModel ? "chat" : "onboarding");
+      await loadApp();
     })();
   }, []);
 
   const refreshOllama = async () => {
     try {
-      const ok = await api.ollamaHealth();
+      const health = await api.ollamaHealth();
+      const ok = health.reachable;
       setOllamaOk(ok);
-      let list: string[] = [];
-      if (ok) {
-        const res = await api.ollamaListModels();
-        list = res.map((m) => m.name);
-        setModels(list);
-      }
-      if (ok && list.length > 0) setPhase("chat");
+      // Always replace the list, so a deleted last model does not linger.
+      setModels(health.models);
+      if (ok && health.models.length > 0) setPhase("chat");
     } catch {
       setOllamaOk(false);
     }
@@ -84,6 +94,20 @@ export default function App() {
     );
   }
 
+  if (phase === "locked") {
+    return (
+      <>
+        <UnlockScreen
+          onUnlocked={() => {
+            setPhase("loading");
+            loadApp();
+          }}
+        />
+        <Toaster />
+      </>
+    );
+  }
+
   if (phase === "onboarding") {
     return (
       <>
@@ -94,6 +118,7 @@ export default function App() {
           onRefresh={refreshOllama}
           onComplete={finishOnboarding}
         />
+        <StartupNotices notices={startupNotices} onDismiss={() => setStartupNotices([])} />
         <Toaster />
       </>
     );
@@ -102,7 +127,26 @@ export default function App() {
   return (
     <>
       <ChatLayout />
+      <StartupNotices notices={startupNotices} onDismiss={() => setStartupNotices([])} />
       <Toaster />
     </>
   );
 }
+
+/** Startup problems from the backend (e.g. a fallback data directory), shown until dismissed. */
+function StartupNotices({ notices, onDismiss }: { notices: string[]; onDismiss: () => void }) {
+  if (notices.length === 0) return null;
+  return (
+    <div role="alert" className="fixed inset-x-0 top-0 z-50 flex items-start gap-2 border-b bg-amber-100 px-4 py-2 text-sm text-amber-900 dark:bg-amber-950 dark:text-amber-100">
+      <AlertTriangle className="mt-0.5 h-4 w-4 shrink-0" />
+      <div className="flex-1 space-y-1">
+        {notices.map((notice) => (
+          <p key={notice}>{notice}</p>
+        ))}
+      </div>
+      <Button variant="ghost" size="icon" className="h-6 w-6" onClick={onDismiss} aria-label="Dismiss">
+        <X className="h-4 w-4" />
+      </Button>
+    </div>
+  );
+}
@@ -24,6 +24,22 @@ export function ChatLayout() {
     api.getSettings().then((s) => setActiveModel(s.selected_model || DEFAULT_MODEL)).catch(() => {});
   }, []);
 
+  // Restore the model this conversation last used when switching chats.
+  useEffect(() => {
+    if (!currentId) return;
+    let cancelled = false;
+    api
+      .getConversation(currentId)
+      .then((result) => {
+        const model = result?.[0].last_model_used;
+        if (!cancelled && model) setActiveModel(model);
+      })
+      .catch(() => {});
+    return () => {
+      cancelled = true;
+    };
+  }, [currentId]);
+
   useEffect(() => {
     const unlistenPromise = listen<DiagnosticLogEntry>("diagnostic-log", (e) => {
       setDiagnosticLogs((prev) => [...prev.slice(-(MAX_DIAGNOSTIC_LOGS - 1)), e.payload]);
@@ -69,6 +85,26 @@ export function ChatLayout() {
     }
   };
 
+  const pruneEmptyConversations = async () => {
+    try {
+      const deleted = await api.pruneEmptyConversations(currentId);
+      if (deleted > 0) await loadConversations();
+    } catch (e) {
+      console.error("Failed to prune empty conversations", e);
+    }
+  };
+
+  /** Put the conversation first, keeping the relative order of the other hand-ordered ones. */
+  const moveConversationToTop = async (id: string) => {
+    const ordered = conversations.filter((c) => c.sort_order != null && c.id !== id).map((c) => c.id);
+    try {
+      await api.reorderConversations([id, ...ordered]);
+      await loadConversations();
+    } catch (e) {
+      console.error("Failed to reorder conversations", e);
+    }
+  };
+
   const renameConversation = async (id: string, title: string) => {
     try {
       await api.updateConversationTitle(id, title);
@@ -89,6 +125,8 @@ export function ChatLayout() {
         onCreate={createNew}
         onDelete={deleteConversation}
         onRename={renameConversation}
+        onMoveToTop={moveConversationToTop}
+        onPruneEmpty={pruneEmptyConversations}
         onRefresh={loadConversations}
         loading={loading}
         aboutOpen={aboutOpen}
@@ -3,11 +3,11 @@ import { listen } from "@tauri-apps/api/event";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { useToast } from "@/components/ui/use-toast";
-import { api } from "@/lib/api";
-import type { ConversationDto, MessageDto } from "@/lib/api";
+import { api, errorMessage, isOllamaRequestError } from "@/lib/api";
+import type { ConversationDto, ConversationSummaryDto, InferenceFinishedEvent, McpToolResultDto, MessageDto, ModelLoadingEvent, OllamaStatusPayload, SettingsDto } from "@/lib/api";
 import { DEFAULT_SYSTEM_PROMPT, buildToolBlock } from "@/lib/defaultSystemPrompt";
 import { DEFAULT_MODEL } from "@/lib/constants";
-import { parseToolResponse } from "@/lib/toolPrompt";
+import { parseToolRequests, parseToolResponse } from "@/lib/toolPrompt";
 import type { McpToolDefDto } from "@/lib/api";
 import {
   createLedger,
@@ -17,20 +17,31 @@ import {
   hasFakeWebSearchClaim,
   CORRECTED_MESSAGE_NO_WEB_SEARCH,
   buildProvenanceFooter,
+  getWebCitationsFromLedger,
   type ToolLedger,
 } from "@/lib/toolLedger";
-import type { DiagnosticLogEntry } from "@/components/diagnostics/DiagnosticsPanel";
+import type { DiagnosticLogEntry, ToolInvocationEntry } from "@/components/diagnostics/DiagnosticsPanel";
 import { MessageBubble } from "./MessageBubble";
+import { ToolConfirmDialog, type ToolApproval } from "./ToolConfirmDialog";
 import { DiagnosticsPanel } from "@/components/diagnostics/DiagnosticsPanel";
 import { Send, Square, Loader2, Terminal, RefreshCw, Code2 } from "lucide-react";
 import { STARTER_SUGGESTIONS } from "@/lib/starterSuggestions";
 
 const MAX_MESSAGES_IN_PROMPT = 50;
-const HEALTH_POLL_INTERVAL_MS = 8000;
 const THINKING_STILL_WORKING_MS = 1000;
 
 interface ChatDonePayload {
   canceled?: boolean;
+  done_reason?: string | null;
+  /** The reply hit the length limit; offer Continue. */
+  truncated?: boolean;
+  /** Replayed from the response cache. */
+  cached?: boolean;
+  /** The stream ended before Ollama finished the reply (crash, restart, dropped connection). */
+  error?: string | null;
+  hint?: string | null;
+  /** Set when canceled: false means the user chose to discard the partial reply. */
+  keep_partial?: boolean | null;
 }
 
 interface ChatViewProps {
@@ -49,10 +60,51 @@ interface ChatViewProps {
   onOpenModelLibrary?: () => void;
 }
 
+const MODEL_LOADING_LABEL = "Loading model into memory…";
+const MODEL_SLOW_LABEL = "Waiting for the model to respond…";
+
 function logUi(level: string, message: string, meta?: Record<string, unknown>) {
   api.emitDiagnosticLog(level, message, meta).catch(() => {});
 }
 
+/** Agent loop progress for one request, carried through the tool follow-ups. */
+interface AgentBudgetState {
+  /** Model turns started, including the first. */
+  iterations: number;
+  /** Tools actually run; a turn can request several, and denied calls do not count. */
+  toolCalls: number;
+  startedAt: number;
+}
+
+function agentTimeoutSecs(settings: SettingsDto | null): number {
+  return settings?.agent_timeout_secs ?? 300;
+}
+
+/** The agent budget that stops another tool call, or null while within all of them. */
+function agentBudgetExceeded(agent: AgentBudgetState, settings: SettingsDto | null): string | null {
+  const maxIterations = settings?.agent_max_iterations ?? 10;
+  const maxToolCalls = settings?.agent_max_tool_calls ?? 20;
+  const timeoutSecs = agentTimeoutSecs(settings);
+  if (agent.iterations >= maxIterations) return `${maxIterations} turns`;
+  if (agent.toolCalls >= maxToolCalls) return `${maxToolCalls} tool calls`;
+  if (Date.now() - agent.startedAt >= timeoutSecs * 1000) return `${timeoutSecs} seconds`;
+  return null;
+}
+
+/** Milliseconds left before the agent's wall-clock limit (0 once it has passed). */
+function agentTimeLeftMs(agent: AgentBudgetState, settings: SettingsDto | null): number {
+  return Math.max(0, agent.startedAt + agentTimeoutSecs(settings) * 1000 - Date.now());
+}
+
+/** The value of `promise`, or null if the agent's wall-clock limit passes first. */
+function raceAgentDeadline<T>(promise: Promise<T>, agent: AgentBudgetState, settings: SettingsDto | null): Promise<T | null> {
+  let timer: ReturnType<typeof setTimeout> | undefined;
+  const deadline = new Promise<null>((resolve) => {
+    timer = setTimeout(() => resolve(null), agentTimeLeftMs(agent, settings));
+  });
+  return Promise.race([promise, deadline]).finally(() => clearTimeout(timer));
+}
+
 export function ChatView({
   conversationId,
   onConversationCreated,
@@ -68,11 +120,24 @@ export function ChatView({
 }: ChatViewProps) {
   const [messages, setMessages] = useState<MessageDto[]>([]);
   const [title, setTitle] = useState("");
+  const [summary, setSummary] = useState<ConversationSummaryDto | null>(null);
   const [input, setInput] = useState("");
   const [streaming, setStreaming] = useState(false);
   const [streamingForCid, setStreamingForCid] = useState<string | null>(null);
+  /** Per-model system prompt for the current model; overrides the global one when set. */
+  const [modelSystemPrompt, setModelSystemPrompt] = useState<string | null>(null);
+  /** The open conversation's own system prompt (from a template); overrides both of the above. */
+  const [conversationSystemPrompt, setConversationSystemPrompt] = useState<string | null>(null);
+  /** Conversation whose last reply was cut off by the length limit. */
+  const [truncatedCid, setTruncatedCid] = useState<string | null>(null);
   const [streamContent, setStreamContent] = useState("");
-  const [thinkingLabel, setThinkingLabel] = useState<"Thinking…" | "Still working…" | "Generating…">("Thinking…");
+  /** Tool call waiting for the user's approval (see confirmToolCall). */
+  const [pendingTool, setPendingTool] = useState<{
+    name: string;
+    args: Record<string, unknown>;
+    resolve: (approval: ToolApproval) => void;
+  } | null>(null);
+  const [thinkingLabel, setThinkingLabel] = useState<"Thinking…" | "Still working…" | "Generating…" | "Continuing…" | typeof MODEL_LOADING_LABEL | typeof MODEL_SLOW_LABEL>("Thinking…");
   const [modelInternal, setModelInternal] = useState(DEFAULT_MODEL);
   const model = modelProp ?? modelInternal;
   const setModel = onModelChange ?? setModelInternal;
@@ -83,9 +148,7 @@ export function ChatView({
     gpu_name: string;
     active_device: string;
   } | null>(null);
-  const [toolInvocations, setToolInvocations] = useState<
-    Array<{ name: string; args: Record<string, unknown>; rawOutput: string }>
-  >([]);
+  const [toolInvocations, setToolInvocations] = useState<ToolInvocationEntry[]>([]);
   const [devMode, setDevMode] = useState(false);
   const scrollRef = useRef<HTMLDivElement>(null);
   const abortRef = useRef(false);
@@ -99,9 +162,8 @@ export function ChatView({
     conversationIdRef.current = conversationId;
   }, [conversationId]);
 
-  const checkOllama = useCallback(async () => {
-    try {
-      const ok = await api.ollamaHealth();
+  const applyConnected = useCallback(
+    (ok: boolean) => {
       if (!prevConnectedRef.current && ok) {
         toast({ title: "Reconnected", description: "Connected to Ollama again.", variant: "success" });
       }
@@ -110,26 +172,53 @@ export function ChatView({
       }
       prevConnectedRef.current = ok;
       setOllamaConnected(ok);
-      return ok;
+    },
+    [toast]
+  );
+
+  const checkOllama = useCallback(async () => {
+    let ok = false;
+    try {
+      ok = (await api.ollamaHealth()).reachable;
     } catch {
-      if (prevConnectedRef.current) {
-        toast({ title: "Ollama disconnected", description: "Check that Ollama is running.", variant: "destructive" });
-      }
-      prevConnectedRef.current = false;
-      setOllamaConnected(false);
-      return false;
+      ok = false;
     }
-  }, [toast]);
+    applyConnected(ok);
+    return ok;
+  }, [applyConnected]);
 
   useEffect(() => {
     api.getPerformanceStatus().then(setPerformanceStatus).catch(() => setPerformanceStatus(null));
   }, [ollamaConnected]);
 
+  // The backend health watchdog emits ollama-status-changed only when reachability flips.
   useEffect(() => {
     checkOllama();
-    const id = setInterval(checkOllama, HEALTH_POLL_INTERVAL_MS);
-    return () => clearInterval(id);
-  }, [checkOllama]);
+    const unlistenPromise = listen<OllamaStatusPayload>("ollama-status-changed", (e) => {
+      applyConnected(e.payload.reachable);
+    });
+    return () => {
+      unlistenPromise.then((u) => u());
+    };
+  }, [checkOllama, applyConnected]);
+
+  // Slow first token: the backend reports a likely cold model load so the wait is not mistaken for a hang.
+  useEffect(() => {
+    const unlistenPromise = listen<ModelLoadingEvent>("model-loading", (e) => {
+      // Another conversation's request (e.g. one still streaming in the background).
+      if (e.payload.conversation_id !== null && e.payload.conversation_id !== conversationIdRef.current) return;
+      if (e.payload.loading) {
+        // A model already in memory is not loading; the request itself is slow.
+        setThinkingLabel(e.payload.was_loaded ? MODEL_SLOW_LABEL : MODEL_LOADING_LABEL);
+        logUi("INFO", "model loading", { model: e.payload.model, wasLoaded: e.payload.was_loaded });
+      } else {
+        setThinkingLabel((l) => (l === MODEL_LOADING_LABEL || l === MODEL_SLOW_LABEL ? "Thinking…" : l));
+      }
+    });
+    return () => {
+      unlistenPromise.then((u) => u());
+    };
+  }, []);
 
   const loadConversation = useCallback(async (id: string) => {
     try {
@@ -137,7 +226,9 @@ export function ChatView({
       if (!result) return;
       const [conv, msgs] = result;
       setTitle(conv.title);
+      setConversationSystemPrompt(conv.system_prompt ?? null);
       setMessages(msgs);
+      setSummary(await api.getConversationSummary(id).catch(() => null));
     } catch (e) {
       console.error("Failed to load conversation", e);
     }
@@ -149,9 +240,40 @@ export function ChatView({
     } else {
       setMessages([]);
       setTitle("");
+      setConversationSystemPrompt(null);
+      setSummary(null);
     }
   }, [conversationId, loadConversation]);
 
+  // After a reload the backend may still be streaming into this conversation; restore the stop
+  // button until it reports the request finished.
+  useEffect(() => {
+    if (!conversationId) return;
+    let disposed = false;
+    let unlisten: (() => void) | null = null;
+    api
+      .isGenerating(conversationId)
+      .then(async (running) => {
+        if (!running || disposed) return;
+        setStreaming(true);
+        setStreamingForCid(conversationId);
+        unlisten = await listen<InferenceFinishedEvent>("inference-finished", (e) => {
+          if (e.payload.conversation_id !== conversationId) return;
+          setStreaming(false);
+          setStreamingForCid(null);
+          unlisten?.();
+          unlisten = null;
+          if (conversationIdRef.current === conversationId) loadConversation(conversationId);
+        });
+        if (disposed) unlisten();
+      })
+      .catch(() => {});
+    return () => {
+      disposed = true;
+      unlisten?.();
+    };
+  }, [conversationId, loadConversation]);
+
   useEffect(() => {
     const settingsPromise = api.getSettings().then((s) => {
       if (modelProp == null) setModel(s.selected_model || DEFAULT_MODEL);
@@ -162,41 +284,87 @@ export function ChatView({
     };
   }, [modelProp]);
 
+  useEffect(() => {
+    let active = true;
+    api.getModelSystemPrompt(model).then((p) => {
+      if (active) setModelSystemPrompt(p);
+    }).catch(() => {
+      if (active) setModelSystemPrompt(null);
+    });
+    return () => {
+      active = false;
+    };
+  }, [model]);
+
   useEffect(() => {
     scrollRef.current?.scrollTo({ top: scrollRef.current.scrollHeight, behavior: "smooth" });
   }, [messages, streamContent]);
 
   const buildOllamaMessagesFromList = useCallback((list: MessageDto[], toolBlock?: string) => {
-    const bounded = list.slice(-MAX_MESSAGES_IN_PROMPT);
-    let effectiveSystemPrompt = (systemPrompt?.trim() && systemPrompt !== "You are a helpful assistant.")
-      ? systemPrompt
-      : DEFAULT_SYSTEM_PROMPT;
+    // With a rolling summary, messages it covers are replaced by the summary text.
+    const summaryIdx = summary ? list.findIndex((m) => m.id === summary.summarized_through_id) : -1;
+    const history = summaryIdx >= 0 ? list.slice(summaryIdx + 1) : list;
+    const bounded = history.slice(-MAX_MESSAGES_IN_PROMPT);
+    let effectiveSystemPrompt = conversationSystemPrompt?.trim()
+      ? conversationSystemPrompt
+      : modelSystemPrompt?.trim()
+      ? modelSystemPrompt
+      : (systemPrompt?.trim() && systemPrompt !== "You are a helpful assistant.")
+        ? systemPrompt
+        : DEFAULT_SYSTEM_PROMPT;
     if (!effectiveSystemPrompt?.trim()) {
       effectiveSystemPrompt = DEFAULT_SYSTEM_PROMPT;
     }
     const systemContent = toolBlock ? effectiveSystemPrompt + toolBlock : effectiveSystemPrompt;
     return [
       { role: "system", content: systemContent },
+      ...(summary && summaryIdx >= 0
+        ? [{ role: "system", content: `Summary of earlier messages in this conversation:\n${summary.content}` }]
+        : []),
       ...bounded.map((m) => ({ role: m.role, content: m.content })),
     ];
-  }, [systemPrompt]);
+  }, [systemPrompt, modelSystemPrompt, conversationSystemPrompt, summary]);
+
+  /** Asks the user before an unacknowledged high-risk or write tool runs; null when no prompt was needed. */
+  const confirmToolCall = useCallback(
+    async (name: string, args: Record<string, unknown>): Promise<ToolApproval | null> => {
+      if (!(await api.toolNeedsConfirmation(name))) return null;
+      const approval = await new Promise<ToolApproval>((resolve) => setPendingTool({ name, args, resolve }));
+      setPendingTool(null);
+      logUi("INFO", "tool confirmation", { tool: name, approval });
+      if (approval === "session" || approval === "always") {
+        await api.grantToolAcknowledgment(name, approval === "always").catch(console.error);
+      }
+      return approval;
+    },
+    []
+  );
 
   const runStreamWithMessages = useCallback(
     async (
       cid: string,
       messagesForPrompt: MessageDto[],
-      options?: { toolsEnabled?: boolean; toolDefs?: McpToolDefDto[]; ledger?: ToolLedger }
+      options?: {
+        toolsEnabled?: boolean;
+        toolDefs?: McpToolDefDto[];
+        ledger?: ToolLedger;
+        variantOf?: string;
+        agent?: AgentBudgetState;
+      }
     ) => {
       const toolsEnabled = options?.toolsEnabled === true && (options.toolDefs?.length ?? 0) > 0;
       const toolDefs = options?.toolDefs ?? [];
       const toolNames = toolDefs.map((d) => d.name);
       const ledger: ToolLedger = options?.ledger ?? createLedger(toolNames);
+      const agent: AgentBudgetState = options?.agent ?? { iterations: 0, toolCalls: 0, startedAt: Date.now() };
+      agent.iterations += 1;
       const toolBlock = toolsEnabled ? buildToolBlock(toolDefs) : undefined;
       const settings = await api.getSettings().catch(() => null);
       const toolCallingMode = settings?.tool_calling_mode !== false;
       const temperature = (toolCallingMode && toolsEnabled) ? 0.3 : (settings?.temperature ?? 0.7);
 
       setStreaming(true);
+      setTruncatedCid(null);
       setStreamContent("");
       setThinkingLabel("Thinking…");
       streamBufferRef.current = "";
@@ -215,6 +383,16 @@ export function ChatView({
 
       const allowedToolNames = new Set(toolNames);
 
+      // A tool-using turn still generating when the agent's time runs out is stopped, keeping the partial reply.
+      let turnDeadlineHit = false;
+      const turnDeadline = toolsEnabled
+        ? setTimeout(() => {
+            turnDeadlineHit = true;
+            logUi("WARN", "agent time limit reached mid-turn, stopping generation", { limit_secs: agentTimeoutSecs(settings) });
+            api.cancelChatGeneration(cid, true).catch(() => {});
+          }, agentTimeLeftMs(agent, settings))
+        : null;
+
       let firstTokenReceived = false;
       const unlistenDelta = await listen<string>("ollama-chat-delta", (e) => {
         if (!abortRef.current) {
@@ -230,6 +408,7 @@ export function ChatView({
       const unlistenDone = await listen<ChatDonePayload>("ollama-chat-done", async (evt) => {
         const payload = evt.payload ?? {};
         const canceled = payload.canceled === true;
+        if (turnDeadline) clearTimeout(turnDeadline);
         if (stillWorkingTimerRef.current) {
           clearTimeout(stillWorkingTimerRef.current);
           stillWorkingTimerRef.current = null;
@@ -240,21 +419,55 @@ export function ChatView({
         setStreamContent("");
 
         if (abortRef.current || !cid || canceled) {
-          if (!canceled && full && cid) {
-            api.addMessage(cid, "assistant", full).catch(console.error);
+          const keep = !canceled || payload.keep_partial !== false;
+          if (keep && full && cid) {
+            api.addMessage(cid, "assistant", full, model, canceled).catch(console.error);
             if (conversationIdRef.current === cid) {
-              setMessages((prev) => [...prev, { id: "", role: "assistant", content: full, timestamp: Math.floor(Date.now() / 1000) }]);
+              setMessages((prev) => [
+                ...prev,
+                { id: "", role: "assistant", content: full, timestamp: Math.floor(Date.now() / 1000), interrupted: canceled },
+              ]);
             }
           }
           setStreaming(false);
           setStreamingForCid(null);
-          if (canceled) {
-            logUi("WARN", "stopped");
-            toast({ title: "Stopped", description: "Generation was canceled." });
+          if (canceled && turnDeadlineHit) {
+            toast({ title: "Agent limit reached", description: `Stopped after ${agentTimeoutSecs(settings)} seconds; the partial reply was kept.` });
+          } else if (canceled) {
+            logUi("WARN", "stopped", { keepPartial: keep, chars: full.length });
+            toast({
+              title: "Stopped",
+              description: full
+                ? keep
+                  ? "Generation was canceled; the partial reply was kept."
+                  : "Generation was canceled; the partial reply was discarded."
+                : "Generation was canceled.",
+            });
           }
           return;
         }
 
+        if (payload.error) {
+          // Keep what arrived, but not as a finished answer: skip tool parsing and offer Continue.
+          logUi("ERROR", "stream interrupted", { error: payload.error, chars: full.length });
+          if (full) {
+            api.addMessage(cid, "assistant", full, model, true).then((added) => {
+              if (conversationIdRef.current === cid) {
+                setMessages((prev) => [...prev, added]);
+              }
+            }).catch(console.error);
+            setTruncatedCid(cid);
+          }
+          setStreaming(false);
+          setStreamingForCid(null);
+          toast({
+            title: "Reply interrupted",
+            description: [payload.error, payload.hint].filter(Boolean).join(" "),
+            variant: "destructive",
+          });
+          return;
+        }
+
         if (!full) {
           setStreaming(false);
           setStreamingForCid(null);
@@ -271,48 +484,133 @@ export function ChatView({
               contentToShow = CORRECTED_MESSAGE_NO_WEB_SEARCH;
             }
           } else if (parsed?.type === "tool_request" && allowedToolNames.has(parsed.tool_name)) {
+            // The model may ask for several tools in one turn; each one run counts as a tool call.
+            const requests = parseToolRequests(full).filter((r) => allowedToolNames.has(r.tool_name));
+            if (requests.length === 0) requests.push(parsed);
+            const requestedNames = requests.map((r) => r.tool_name).join(", ");
+            const budgetHit = agentBudgetExceeded(agent, settings);
+            if (budgetHit) {
+              logUi("WARN", "agent budget reached, asking for final answer", {
+                limit: budgetHit,
+                iterations: agent.iterations,
+                tool_calls: agent.toolCalls,
+                elapsed_ms: Date.now() - agent.startedAt,
+                skipped_tools: requestedNames,
+              });
+              const assistantMsg: MessageDto = {
+                id: "",
+                role: "assistant",
+                content: full,
+                timestamp: Math.floor(Date.now() / 1000),
+              };
+              const stopMsg: MessageDto = {
+                id: "",
+                role: "user",
+                content: `[Agent limit reached: ${budgetHit}. Not run: ${requestedNames}.] Do not request more tools. Give your final answer now using the tool results so far.`,
+                timestamp: Math.floor(Date.now() / 1000),
+              };
+              try {
+                assistantMsg.id = (await api.addMessage(cid, "assistant", full, model)).id;
+                stopMsg.id = (await api.addMessage(cid, "user", stopMsg.content)).id;
+              } catch (e) {
+                console.error("Failed to save agent limit messages", e);
+              }
+              if (conversationIdRef.current === cid) {
+                setMessages((prev) => [...prev, assistantMsg, stopMsg]);
+              }
+              toast({ title: "Agent limit reached", description: `Stopped after ${budgetHit}; asking for a final answer.` });
+              await runStreamWithMessages(cid, [...messagesForPrompt, assistantMsg, stopMsg], { ledger, agent });
+              return;
+            }
             try {
-              let argsToUse = { ...parsed.arguments };
-              const isWriteTool = parsed.tool_name === "write_file" || parsed.tool_name === "obsidian_write_note";
-              if (isWriteTool) {
-                let body = (parsed.arguments.content as string) ?? "";
-                const lastWeb = getLastWebSearchResult(ledger);
-                const webSearchHadNoResults =
-                  lastWeb !== null && (lastWeb.result_count === 0 || lastWeb.urls.length === 0);
-                if (hasFakeWebSearchClaim(body) && !webSearchSucceeded(ledger)) {
-                  body =
-                    "Note: Web search was not performed. The following is from the assistant's general knowledge or other tools.\n\n" +
-                    body;
-                } else if (webSearchSucceeded(ledger) && webSearchHadNoResults) {
-                  body =
-                    "Could not verify via web_search (no explicit officeholder found).";
+              const resultBlocks: string[] = [];
+              let limitHit: string | null = null;
+              for (const request of requests) {
+                limitHit = limitHit ?? agentBudgetExceeded(agent, settings);
+                if (limitHit) {
+                  resultBlocks.push(`[${request.tool_name} was not run: agent limit reached (${limitHit}).]`);
+                  continue;
                 }
-                argsToUse = { ...parsed.arguments, content: body + buildProvenanceFooter(ledger) };
-              }
-              const result = await api.executeMcpTool(parsed.tool_name, argsToUse);
-              if (result.diagnostic_steps?.length) {
-                for (const step of result.diagnostic_steps) {
-                  logUi(step.level, step.message, step.meta ?? undefined);
+                const isWriteTool = request.tool_name === "write_file" || request.tool_name === "obsidian_write_note";
+                if (isWriteTool && requests.length > 1) {
+                  // A write batched with reads would be written before the model has seen what they return.
+                  resultBlocks.push(`[${request.tool_name} was not run: request writes on their own, after reading the other results.]`);
+                  continue;
+                }
+                let argsToUse = { ...request.arguments };
+                if (isWriteTool) {
+                  let body = (request.arguments.content as string) ?? "";
+                  const lastWeb = getLastWebSearchResult(ledger);
+                  const webSearchHadNoResults =
+                    lastWeb !== null && (lastWeb.result_count === 0 || lastWeb.urls.length === 0);
+                  if (hasFakeWebSearchClaim(body) && !webSearchSucceeded(ledger)) {
+                    body =
+                      "Note: Web search was not performed. The following is from the assistant's general knowledge or other tools.\n\n" +
+                      body;
+                  } else if (webSearchSucceeded(ledger) && webSearchHadNoResults) {
+                    body =
+                      "Could not verify via web_search (no explicit officeholder found).";
+                  }
+                  argsToUse = { ...request.arguments, content: body + buildProvenanceFooter(ledger) };
+                }
+                const approval = await confirmToolCall(request.tool_name, argsToUse);
+                let result: McpToolResultDto;
+                if (approval === "deny") {
+                  result = declinedToolResult(request.tool_name);
+                } else {
+                  agent.toolCalls += 1;
+                  const outcome = await raceAgentDeadline(
+                    api.executeMcpTool(request.tool_name, argsToUse, approval !== null),
+                    agent,
+                    settings
+                  );
+                  if (outcome === null) {
+                    api.cancelToolExecution().catch(() => {});
+                    limitHit = `${agentTimeoutSecs(settings)} seconds`;
+                    resultBlocks.push(`[${request.tool_name} was stopped: agent limit reached (${limitHit}).]`);
+                    continue;
+                  }
+                  result = outcome;
                 }
+                if (result.diagnostic_steps?.length) {
+                  for (const step of result.diagnostic_steps) {
+                    logUi(step.level, step.message, step.meta ?? undefined);
+                  }
+                }
+                // Failed calls get the bounded error JSON; the full error is in diagnostics.
+                const toolResultText = result.ok
+                  ? result.content
+                  : (result.model_error ?? `Error: ${result.error ?? "unknown"}`);
+                const summary = result.ok
+                  ? (result.content.slice(0, 200) + (result.content.length > 200 ? "…" : ""))
+                  : (result.error ?? "error");
+                recordInvocation(
+                  ledger,
+                  request.tool_name,
+                  argsToUse,
+                  result.ok ? "success" : "error",
+                  summary,
+                  result.content ?? ""
+                );
+                setToolInvocations((prev) => {
+                  const next = [
+                    ...prev,
+                    { name: request.tool_name, args: argsToUse, rawOutput: result.content ?? "", steps: result.steps },
+                  ];
+                  return next.slice(-50);
+                });
+                resultBlocks.push(`[Tool result from ${request.tool_name}]\n${toolResultText}`);
+              }
+              if (limitHit) {
+                logUi("WARN", "agent budget reached, asking for final answer", {
+                  limit: limitHit,
+                  iterations: agent.iterations,
+                  tool_calls: agent.toolCalls,
+                  elapsed_ms: Date.now() - agent.startedAt,
+                });
+                resultBlocks.push("Do not request more tools. Give your final answer now using the tool results so far.");
+                toast({ title: "Agent limit reached", description: `Stopped after ${limitHit}; asking for a final answer.` });
               }
-              const toolResultText = result.ok
-                ? result.content
-                : `Error: ${result.error ?? "unknown"}`;
-              const summary = result.ok
-                ? (result.content.slice(0, 200) + (result.content.length > 200 ? "…" : ""))
-                : (result.error ?? "error");
-              recordInvocation(
-                ledger,
-                parsed.tool_name,
-                argsToUse,
-                result.ok ? "success" : "error",
-                summary,
-                result.content ?? ""
-              );
-              setToolInvocations((prev) => {
-                const next = [...prev, { name: parsed.tool_name, args: argsToUse, rawOutput: result.content ?? "" }];
-                return next.slice(-50);
-              });
               const assistantMsg: MessageDto = {
                 id: "",
                 role: "assistant",
@@ -322,11 +620,11 @@ export function ChatView({
               const toolUserMsg: MessageDto = {
                 id: "",
                 role: "user",
-                content: `[Tool result from ${parsed.tool_name}]\n${toolResultText}`,
+                content: resultBlocks.join("\n\n"),
                 timestamp: Math.floor(Date.now() / 1000),
               };
               try {
-                const addedAssistant = await api.addMessage(cid, "assistant", full);
+                const addedAssistant = await api.addMessage(cid, "assistant", full, model);
                 assistantMsg.id = addedAssistant.id;
                 const addedUser = await api.addMessage(cid, "user", toolUserMsg.content);
                 toolUserMsg.id = addedUser.id;
@@ -336,9 +634,13 @@ export function ChatView({
               if (conversationIdRef.current === cid) {
                 setMessages((prev) => [...prev, assistantMsg, toolUserMsg]);
               }
-              logUi("INFO", "tool executed, requesting follow-up", { tool: parsed.tool_name });
-              toast({ title: "Tool used", description: `${parsed.tool_name} → follow-up`, variant: "default" });
-              await runStreamWithMessages(cid, [...messagesForPrompt, assistantMsg, toolUserMsg], { toolsEnabled: true, toolDefs, ledger });
+              logUi("INFO", "tool executed, requesting follow-up", { tools: requestedNames });
+              toast({ title: "Tool used", description: `${requestedNames} → follow-up`, variant: "default" });
+              await runStreamWithMessages(
+                cid,
+                [...messagesForPrompt, assistantMsg, toolUserMsg],
+                limitHit ? { ledger, agent } : { toolsEnabled: true, toolDefs, ledger, agent }
+              );
               return;
             } catch (e) {
               console.error("Tool execution error", e);
@@ -347,13 +649,37 @@ export function ChatView({
           }
         }
 
-        api.addMessage(cid, "assistant", contentToShow).then((assistantMsg) => {
+        // A regenerated answer is stored as a variant of the one it replaces.
+        const saveReply = options?.variantOf
+          ? api.addMessageVariant(cid, options.variantOf, contentToShow, model)
+          : api.addMessage(cid, "assistant", contentToShow, model);
+        // Sources returned by web_search this request are kept with the answer.
+        const citations = getWebCitationsFromLedger(ledger);
+        saveReply.then((assistantMsg) => {
+          if (citations.length > 0 && assistantMsg.id) {
+            api.setMessageCitations(assistantMsg.id, citations).catch((e) =>
+              logUi("WARN", "failed to save citations", { error: String(e) })
+            );
+          }
           if (conversationIdRef.current === cid) {
-            setMessages((prev) => [...prev, { ...assistantMsg, role: "assistant", content: contentToShow }]);
+            setMessages((prev) => [...prev, { ...assistantMsg, role: "assistant", content: contentToShow, citations }]);
           }
+          // Background: fold older messages into the rolling summary when enabled and due.
+          api.summarizeConversationIfNeeded(cid, model).then(async (updated) => {
+            if (updated && conversationIdRef.current === cid) {
+              setSummary(await api.getConversationSummary(cid));
+            }
+          }).catch((e) => logUi("WARN", "rolling summary failed", { error: String(e) }));
         }).catch(console.error);
         setStreaming(false);
         setStreamingForCid(null);
+        if (payload.cached) {
+          logUi("INFO", "reply served from response cache");
+        }
+        if (payload.truncated) {
+          setTruncatedCid(cid);
+          logUi("WARN", "reply truncated by length limit", { done_reason: payload.done_reason });
+        }
         logUi("INFO", "done");
         toast({ title: "Response finished", variant: "success" });
         if (typeof window !== "undefined" && "Notification" in window && Notification.permission === "granted" && !document.hasFocus()) {
@@ -372,24 +698,38 @@ export function ChatView({
           prompt_length: systemContent.length,
           first_80_chars: first80 + (systemContent.length > 80 ? "…" : ""),
         });
-        await api.ollamaChatStream(model, messagesToSend, {
-          temperature,
-          num_predict: 2048,
-        });
+        await api.ollamaChatStream(
+          model,
+          messagesToSend,
+          {
+            temperature,
+            num_predict: 2048,
+          },
+          cid,
+          false,
+          toolsEnabled
+        );
       } catch (err) {
+        if (turnDeadline) clearTimeout(turnDeadline);
         console.error("Chat stream error", err);
-        logUi("ERROR", "stream error", { error: String(err) });
+        logUi("ERROR", "stream error", isOllamaRequestError(err) ? { ...err } : { error: String(err) });
         const ok = await checkOllama();
-        if (!ok) {
+        if (isOllamaRequestError(err) && err.kind === "model_not_found") {
+          toast({ title: "Model not installed", description: `${err.message}. Download it from the model library.`, variant: "destructive" });
+        } else if (isOllamaRequestError(err) && err.kind === "out_of_memory") {
+          toast({ title: "Not enough memory", description: `${err.message}. Try a smaller model or close other apps.`, variant: "destructive" });
+        } else if (isOllamaRequestError(err) && err.kind === "runner_crashed") {
+          toast({ title: "Model crashed", description: `${err.message}. Restart Ollama and try again.`, variant: "destructive" });
+        } else if (!ok) {
           toast({ title: "Connection lost", description: "Ollama disconnected. Use Retry in the status pill.", variant: "destructive" });
         } else {
           toast({ title: "Error", description: "Something went wrong while generating.", variant: "destructive" });
         }
         const full = streamBufferRef.current;
         if (full && cid) {
-          api.addMessage(cid, "assistant", full).catch(console.error);
+          api.addMessage(cid, "assistant", full, model, true).catch(console.error);
           if (conversationIdRef.current === cid) {
-            setMessages((m) => [...m, { id: "", role: "assistant", content: full, timestamp: Math.floor(Date.now() / 1000) }]);
+            setMessages((m) => [...m, { id: "", role: "assistant", content: full, timestamp: Math.floor(Date.now() / 1000), interrupted: true }]);
           }
         }
         setStreamContent("");
@@ -397,7 +737,7 @@ export function ChatView({
         setStreamingForCid(null);
       }
     },
-    [model, buildOllamaMessagesFromList, checkOllama, toast]
+    [model, buildOllamaMessagesFromList, checkOllama, confirmToolCall, toast]
   );
 
   const sendMessage = async (prefill?: string) => {
@@ -485,12 +825,88 @@ export function ChatView({
     await runStreamWithMessages(cid, sliced, {
       toolsEnabled: toolDefs.length > 0,
       toolDefs,
+      variantOf: last.id || undefined,
+    });
+  };
+
+  const switchVariant = async (m: MessageDto, step: number) => {
+    const cid = conversationId;
+    if (!cid || !m.variant || streaming) return;
+    try {
+      const variants = await api.listMessageVariants(m.variant.group_id);
+      const target = variants[m.variant.index + step];
+      if (!target) return;
+      await api.selectVariant(target.id);
+      await loadConversation(cid);
+    } catch (e) {
+      toast({ title: "Could not switch answer", description: String(e), variant: "destructive" });
+    }
+  };
+
+  const continueReply = async () => {
+    const cid = conversationId;
+    if (streaming || !cid) return;
+    setTruncatedCid(null);
+    setStreaming(true);
+    setStreamingForCid(cid);
+    setStreamContent("");
+    setThinkingLabel("Continuing…");
+    streamBufferRef.current = "";
+    abortRef.current = false;
+    logUi("INFO", "continue clicked");
+    const unlistenDelta = await listen<string>("ollama-chat-delta", (e) => {
+      if (!abortRef.current) {
+        streamBufferRef.current += e.payload;
+        setStreamContent(streamBufferRef.current);
+      }
+    });
+    const unlistenDone = await listen<ChatDonePayload>("ollama-chat-done", (evt) => {
+      unlistenDelta();
+      unlistenDone();
+      const full = streamBufferRef.current;
+      setStreamContent("");
+      setStreaming(false);
+      setStreamingForCid(null);
+      if (evt.payload?.error) {
+        toast({
+          title: "Reply interrupted",
+          description: [evt.payload.error, evt.payload.hint].filter(Boolean).join(" "),
+          variant: "destructive",
+        });
+      }
+      // continueGeneration saves the continuation onto the cut-off message and resolves to it.
+      if (!full || (evt.payload?.canceled && evt.payload.keep_partial === false)) return;
+      if (evt.payload?.truncated || evt.payload?.error) setTruncatedCid(cid);
     });
+    try {
+      const updated = await api.continueGeneration(cid, model, { num_predict: 2048 });
+      if (updated && conversationIdRef.current === cid) {
+        setMessages((prev) => prev.map((m) => (m.id === updated.id ? updated : m)));
+      }
+    } catch (err) {
+      unlistenDelta();
+      unlistenDone();
+      setStreaming(false);
+      setStreamingForCid(null);
+      toast({ title: "Could not continue", description: errorMessage(err), variant: "destructive" });
+    }
   };
 
-  const stopStreaming = () => {
+  const toggleStar = async (m: MessageDto) => {
+    const starred = !m.starred;
+    try {
+      await api.setMessageStarred(m.id, starred);
+      setMessages((prev) => prev.map((x) => (x.id === m.id ? { ...x, starred } : x)));
+    } catch (e) {
+      toast({ title: "Could not update star", description: String(e), variant: "destructive" });
+    }
+  };
+
+  /** Stop generating; the partial reply is kept unless keepPartial is false (Shift+click Stop). */
+  const stopStreaming = (keepPartial = true) => {
     abortRef.current = true;
-    api.cancelChatGeneration().catch(() => {});
+    api.cancelChatGeneration(undefined, keepPartial).catch(() => {});
+    api.cancelToolExecution().catch(() => {});
   };
 
   const handleKeyDown = (e: React.KeyboardEvent) => {
@@ -590,7 +1006,7 @@ export function ChatView({
                 className="flex-1"
               />
               {streaming ? (
-                <Button variant="outline" onClick={stopStreaming}>
+                <Button variant="outline" onClick={(e) => stopStreaming(!e.shiftKey)} title="Stop (Shift+click discards the partial reply)">
                   <Square className="h-4 w-4" />
                 </Button>
               ) : (
@@ -607,6 +1023,9 @@ export function ChatView({
 
   return (
     <main className="relative flex flex-1 flex-col bg-gradient-to-b from-background to-muted/30">
+      {pendingTool && (
+        <ToolConfirmDialog toolName={pendingTool.name} args={pendingTool.args} onChoose={pendingTool.resolve} />
+      )}
       <div className="flex min-h-0 items-center justify-between gap-2 border-b px-4 py-2">
         <h1 className="min-w-0 flex-1 truncate text-sm font-medium" title={title || "New chat"}>{title || "New chat"}</h1>
         <div className="flex shrink-0 items-center gap-2">
@@ -653,7 +1072,13 @@ export function ChatView({
       >
         <div className="mx-auto flex max-w-3xl flex-col gap-5">
           {messages.map((m) => (
-            <MessageBubble key={m.id} message={m} devMode={devMode} />
+            <MessageBubble
+              key={m.id}
+              message={m}
+              devMode={devMode}
+              onToggleStar={m.id ? () => toggleStar(m) : undefined}
+              onSwitchVariant={m.variant && m.variant.count > 1 ? (step) => switchVariant(m, step) : undefined}
+            />
           ))}
           {streaming && streamingForCid === conversationId && !streamContent && (
             <ThinkingBubble label={thinkingLabel} />
@@ -685,7 +1110,7 @@ export function ChatView({
               className="flex-1"
             />
             {streaming && streamingForCid === conversationId ? (
-              <Button variant="outline" onClick={stopStreaming} title="Stop (Esc)">
+              <Button variant="outline" onClick={(e) => stopStreaming(!e.shiftKey)} title="Stop (Esc; Shift+click discards the partial reply)">
                 <Square className="h-4 w-4" />
               </Button>
             ) : (
@@ -708,6 +1133,18 @@ export function ChatView({
                 Regenerate
               </Button>
             )}
+            {truncatedCid === conversationId && !streaming && (
+              <Button
+                variant="ghost"
+                size="sm"
+                className="h-7 px-2 text-xs text-muted-foreground hover:text-foreground"
+                disabled={!ollamaConnected}
+                onClick={continueReply}
+                title="The reply hit the length limit; generate the rest"
+              >
+                Continue
+              </Button>
+            )}
             <span>Try:</span>
             <Button
               variant="ghost"
@@ -725,6 +1162,22 @@ export function ChatView({
   );
 }
 
+/** Result fed back to the model when the user denies a tool call, in the compact error form. */
+function declinedToolResult(toolName: string): McpToolResultDto {
+  const error = "The user declined this tool call";
+  return {
+    ok: false,
+    content: "",
+    error,
+    model_error: JSON.stringify({
+      tool: toolName,
+      ok: false,
+      error,
+      hint: "Do not call this tool again; answer without it or ask the user what to do.",
+    }),
+  };
+}
+
 function ConnectionPill({
   connected,
   onRetry,
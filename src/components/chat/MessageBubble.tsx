@@ -2,7 +2,7 @@ import ReactMarkdown from "react-markdown";
 import remarkGfm from "remark-gfm";
 import { cn } from "@/lib/utils";
 import type { MessageDto } from "@/lib/api";
-import { Copy } from "lucide-react";
+import { ChevronLeft, ChevronRight, Copy, Star } from "lucide-react";
 import {
   isToolRequestMessage,
   parseToolResultUserContent,
@@ -17,9 +17,13 @@ interface MessageBubbleProps {
   generatingLabel?: boolean;
   /** When true, show raw JSON in tool cards */
   devMode?: boolean;
+  /** Toggle the message's starred flag; the star button is hidden when not provided */
+  onToggleStar?: () => void;
+  /** Show the previous (-1) or next (+1) regenerated answer; the "2/3" switcher is hidden when not provided */
+  onSwitchVariant?: (step: number) => void;
 }
 
-export function MessageBubble({ message, isStreaming, generatingLabel, devMode }: MessageBubbleProps) {
+export function MessageBubble({ message, isStreaming, generatingLabel, devMode, onToggleStar, onSwitchVariant }: MessageBubbleProps) {
   const isUser = message.role === "user";
   const toolRequest = !isUser ? isToolRequestMessage(message.content) : null;
   const toolResult = isUser ? parseToolResultUserContent(message.content) : null;
@@ -55,6 +59,20 @@ export function MessageBubble({ message, isStreaming, generatingLabel, devMode }
           >
             <Copy className="h-3.5 w-3.5" />
           </button>
+          {onToggleStar && (
+            <button
+              type="button"
+              onClick={onToggleStar}
+              className={cn(
+                "shrink-0 rounded p-1 transition",
+                message.starred ? "opacity-100" : "opacity-0 group-hover/bubble:opacity-100",
+                isUser ? "hover:bg-primary-foreground/20" : "hover:bg-muted-foreground/10"
+              )}
+              title={message.starred ? "Unstar message" : "Star message"}
+            >
+              <Star className={cn("h-3.5 w-3.5", message.starred && "fill-current")} />
+            </button>
+          )}
           {toolRequest ? (
             <ToolRequestCard parsed={toolRequest} devMode={devMode} />
           ) : toolResult ? (
@@ -110,6 +128,50 @@ export function MessageBubble({ message, isStreaming, generatingLabel, devMode }
             </div>
           )}
         </div>
+        {!isUser && message.interrupted && !isStreaming && (
+          <p className="mt-1 text-xs italic text-muted-foreground">Incomplete reply</p>
+        )}
+        {!isUser && message.citations && message.citations.length > 0 && (
+          <div className="mt-1 flex flex-wrap gap-1 text-xs">
+            {message.citations.map((c, i) => (
+              <a
+                key={c.url}
+                href={c.url}
+                target="_blank"
+                rel="noreferrer"
+                className="max-w-[16rem] truncate rounded border border-border px-1.5 py-0.5 text-muted-foreground hover:bg-muted-foreground/10"
+                title={c.provider ? `${c.url} (via ${c.provider})` : c.url}
+              >
+                [{i + 1}] {c.title}
+              </a>
+            ))}
+          </div>
+        )}
+        {onSwitchVariant && message.variant && (
+          <div className="mt-1 flex items-center gap-1 text-xs text-muted-foreground">
+            <button
+              type="button"
+              onClick={() => onSwitchVariant(-1)}
+              disabled={message.variant.index === 0}
+              className="rounded p-0.5 hover:bg-muted-foreground/10 disabled:opacity-40"
+              title="Previous answer"
+            >
+              <ChevronLeft className="h-3.5 w-3.5" />
+            </button>
+            <span>
+              {message.variant.index + 1}/{message.variant.count}
+            </span>
+            <button
+              type="button"
+              onClick={() => onSwitchVariant(1)}
+              disabled={message.variant.index + 1 >= message.variant.count}
+              className="rounded p-0.5 hover:bg-muted-foreground/10 disabled:opacity-40"
+              title="Next answer"
+            >
+              <ChevronRight className="h-3.5 w-3.5" />
+            </button>
+          </div>
+        )}
       </div>
     </div>
   );
@@ -62,7 +62,16 @@ function compactArgs(toolName: string, args: Record<string, unknown>): string {
     return args.path;
   if ((toolName === "read_file" || toolName === "obsidian_read_note") && typeof args.path === "string")
     return args.path;
+  if (toolName === "read_bytes" && typeof args.path === "string")
+    return typeof args.offset === "number" ? `${args.path} @ ${args.offset}` : args.path;
   if (toolName === "list_dir" && typeof args.path === "string") return args.path;
+  if (toolName === "open_path" && typeof args.path === "string") return args.path;
+  if (toolName === "replace_in_file" && typeof args.path === "string") return args.path;
+  if (toolName === "summarize_file" && typeof args.path === "string") return args.path;
+  if (toolName === "ingest_file" && typeof args.path === "string") return args.path;
+  if (toolName === "read_file_range" && typeof args.path === "string")
+    return typeof args.offset === "number" ? `${args.path} @ ${args.offset}` : args.path;
+  if (toolName === "read_files" && Array.isArray(args.paths)) return args.paths.join(", ");
   if (toolName === "obsidian_list_notes" && typeof args.path === "string") return args.path;
   if ((toolName === "run_command" || toolName === "open_terminal_and_run") && typeof args.command === "string")
     return args.command;
@@ -82,12 +91,28 @@ function toolMeta(toolName: string): { icon: React.ReactNode; label: string } {
       return { icon: <Link className="h-3.5 w-3.5" />, label: "Fetch URL" };
     case "write_file":
       return { icon: <FileText className="h-3.5 w-3.5" />, label: "Write file" };
+    case "read_files":
+      return { icon: <FileText className="h-3.5 w-3.5" />, label: "Read files" };
+    case "replace_in_file":
+      return { icon: <FileText className="h-3.5 w-3.5" />, label: "Replace in file" };
+    case "summarize_file":
+      return { icon: <FileText className="h-3.5 w-3.5" />, label: "Summarize file" };
     case "read_file":
       return { icon: <FileText className="h-3.5 w-3.5" />, label: "Read file" };
+    case "read_bytes":
+      return { icon: <FileText className="h-3.5 w-3.5" />, label: "Read bytes" };
+    case "ingest_file":
+      return { icon: <FileText className="h-3.5 w-3.5" />, label: "Plan file chunks" };
+    case "read_file_range":
+      return { icon: <FileText className="h-3.5 w-3.5" />, label: "Read file range" };
     case "list_dir":
       return { icon: <FolderOpen className="h-3.5 w-3.5" />, label: "List directory" };
+    case "open_path":
+      return { icon: <ExternalLink className="h-3.5 w-3.5" />, label: "Open file" };
     case "run_command":
       return { icon: <Terminal className="h-3.5 w-3.5" />, label: "Run command" };
+    case "system_info":
+      return { icon: <Terminal className="h-3.5 w-3.5" />, label: "System info" };
     case "open_terminal_and_run":
       return { icon: <Terminal className="h-3.5 w-3.5" />, label: "Open terminal" };
     case "open_browser_search":
@@ -154,22 +179,81 @@ function getResultSummary(
     if (match) return { status: "success", summary: `${match[1]} bytes → ${match[2].split(/[\\/]/).pop()}` };
     return { status: "success", summary: "Written" };
   }
+  if (toolName === "read_files") {
+    try {
+      const data = JSON.parse(resultBody) as { files?: Record<string, { error?: string }> };
+      const entries = Object.values(data.files ?? {});
+      const failed = entries.filter((e) => e.error).length;
+      return { status: "success", summary: `${entries.length - failed} file(s) read${failed ? `, ${failed} failed` : ""}` };
+    } catch {
+      return { status: "success", summary: "Done" };
+    }
+  }
+  if (toolName === "summarize_file") {
+    try {
+      const data = JSON.parse(resultBody) as { chunks?: number; chars?: number };
+      return { status: "success", summary: `${data.chars ?? 0} chars in ${data.chunks ?? 1} part(s)` };
+    } catch {
+      return { status: "success", summary: "Summarized" };
+    }
+  }
+  if (toolName === "ingest_file") {
+    try {
+      const data = JSON.parse(resultBody) as { chunk_count?: number; size?: number };
+      return { status: "success", summary: `${data.chunk_count ?? 0} chunk(s), ${data.size ?? 0} bytes` };
+    } catch {
+      return { status: "success", summary: "Planned" };
+    }
+  }
+  if (toolName === "read_file_range") {
+    const match = resultBody.match(/bytes (\d+)\.\.(\d+) of/);
+    return { status: "success", summary: match ? `bytes ${match[1]}–${match[2]}` : "Done" };
+  }
+  if (toolName === "replace_in_file") {
+    const match = resultBody.match(/Replaced (\d+) occurrence/);
+    return { status: "success", summary: match ? `${match[1]} replacement(s)` : "No matches" };
+  }
   if (toolName === "read_file" || toolName === "obsidian_read_note") {
     const lines = resultBody.split("\n").length;
     return { status: "success", summary: `${lines} line(s)` };
   }
+  if (toolName === "list_dir" && resultBody.trimStart().startsWith("[")) {
+    try {
+      const entries = JSON.parse(resultBody) as unknown[];
+      return { status: "success", summary: `${entries.length} entries` };
+    } catch {
+      // Not JSON after all: fall through to the line count.
+    }
+  }
   if (toolName === "list_dir" || toolName === "obsidian_list_notes") {
     const entries = resultBody.split("\n").filter(Boolean).length;
     return { status: "success", summary: `${entries} entries` };
   }
+  if (toolName === "run_command") {
+    try {
+      const data = JSON.parse(resultBody) as { exit_code?: number | null; duration_ms?: number; killed?: boolean };
+      if (data.killed) return { status: "fail", summary: "Killed" };
+      const code = typeof data.exit_code === "number" ? data.exit_code : -1;
+      const took = typeof data.duration_ms === "number" ? ` in ${data.duration_ms} ms` : "";
+      return { status: code === 0 ? "success" : "fail", summary: `Exit code ${code}${took}` };
+    } catch {
+      // output_format "text": fall through to the readable format below.
+    }
+  }
   if (toolName === "run_command" || toolName === "open_terminal_and_run") {
     const exitMatch = resultBody.match(/Exit code: (-?\d+)/);
     const code = exitMatch ? exitMatch[1] : "0";
     return { status: code === "0" ? "success" : "fail", summary: `Exit code ${code}` };
   }
   if (toolName === "fetch_url") {
-    const chars = resultBody.length;
-    return { status: "success", summary: `${chars} chars fetched` };
+    try {
+      const data = JSON.parse(resultBody) as { char_count?: number; status?: number; redirects?: unknown[] };
+      const hops = Array.isArray(data.redirects) ? data.redirects.length : 0;
+      const redirectNote = hops > 0 ? `, ${hops} redirect(s)` : "";
+      return { status: "success", summary: `${data.char_count ?? 0} chars fetched (HTTP ${data.status ?? "?"}${redirectNote})` };
+    } catch {
+      return { status: "success", summary: `${resultBody.length} chars fetched` };
+    }
   }
   if (toolName === "open_browser_search") {
     return { status: "success", summary: "Browser opened" };
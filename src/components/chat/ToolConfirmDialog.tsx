@@ -0,0 +1,52 @@
+import { Button } from "@/components/ui/button";
+import { ShieldAlert } from "lucide-react";
+
+export type ToolApproval = "once" | "session" | "always" | "deny";
+
+interface ToolConfirmDialogProps {
+  toolName: string;
+  args: Record<string, unknown>;
+  onChoose: (approval: ToolApproval) => void;
+}
+
+/** Longest argument preview shown; write_file content can be large. */
+const MAX_ARGS_PREVIEW_CHARS = 1200;
+
+/** Asks before a high-risk or write tool the user has not acknowledged runs. */
+export function ToolConfirmDialog({ toolName, args, onChoose }: ToolConfirmDialogProps) {
+  const json = JSON.stringify(args, null, 2);
+  const preview = json.length > MAX_ARGS_PREVIEW_CHARS ? `${json.slice(0, MAX_ARGS_PREVIEW_CHARS)}\n…` : json;
+
+  return (
+    <div className="fixed inset-0 z-50 flex items-center justify-center bg-black/50" role="presentation">
+      <div
+        role="alertdialog"
+        aria-labelledby="tool-confirm-title"
+        className="z-50 w-full max-w-lg rounded-lg border bg-background p-6 shadow-lg"
+      >
+        <h2 id="tool-confirm-title" className="flex items-center gap-2 text-lg font-semibold">
+          <ShieldAlert className="h-5 w-5 text-amber-500" />
+          Allow {toolName}?
+        </h2>
+        <p className="mt-2 text-sm text-muted-foreground">
+          The model wants to run a tool that can change files or your system. Check the arguments before allowing it.
+        </p>
+        <pre className="mt-3 max-h-60 overflow-auto rounded bg-muted p-2 text-xs">{preview}</pre>
+        <div className="mt-4 flex flex-wrap justify-end gap-2">
+          <Button variant="outline" size="sm" onClick={() => onChoose("deny")}>
+            Deny
+          </Button>
+          <Button variant="outline" size="sm" onClick={() => onChoose("always")}>
+            Always allow
+          </Button>
+          <Button variant="outline" size="sm" onClick={() => onChoose("session")}>
+            Allow this session
+          </Button>
+          <Button size="sm" onClick={() => onChoose("once")}>
+            Allow once
+          </Button>
+        </div>
+      </div>
+    </div>
+  );
+}
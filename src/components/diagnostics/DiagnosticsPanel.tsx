@@ -2,6 +2,7 @@ import { useEffect, useRef, useState, useMemo } from "react";
 import { Button } from "@/components/ui/button";
 import { X, Copy, Filter } from "lucide-react";
 import { cn } from "@/lib/utils";
+import type { ToolStepDto } from "@/lib/api";
 import { useToast } from "@/components/ui/use-toast";
 
 export interface DiagnosticLogEntry {
@@ -15,6 +16,8 @@ export interface ToolInvocationEntry {
   name: string;
   args: Record<string, unknown>;
   rawOutput: string;
+  /** web_search pipeline steps, shown as a pass/fail list. */
+  steps?: ToolStepDto[] | null;
 }
 
 interface DiagnosticsPanelProps {
@@ -187,6 +190,24 @@ export function DiagnosticsPanel({ open, onClose, logs, toolInvocations = [], cl
               </option>
             ))}
           </select>
+          {inv?.steps && inv.steps.length > 0 && (
+            <ul className="mt-2 space-y-0.5 text-xs">
+              {inv.steps.map((step, i) => (
+                <li key={`${step.name}-${i}`} className="flex items-start gap-2">
+                  <span
+                    className={cn(
+                      "shrink-0 rounded px-1.5 font-semibold",
+                      step.ok ? "bg-green-500/10 text-green-700 dark:text-green-400" : "bg-destructive/10 text-destructive"
+                    )}
+                  >
+                    {step.ok ? "PASS" : "FAIL"}
+                  </span>
+                  <span className="font-mono">{step.name}</span>
+                  <span className="min-w-0 break-words text-muted-foreground">{step.detail}</span>
+                </li>
+              ))}
+            </ul>
+          )}
         </div>
       )}
       <div
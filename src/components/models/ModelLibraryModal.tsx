@@ -20,7 +20,7 @@ import {
 import { api, type OllamaModelInfo } from "@/lib/api";
 import { DEFAULT_MODEL } from "@/lib/constants";
 import { useToast } from "@/components/ui/use-toast";
-import { Search, Download, Trash2, Check, Package, ExternalLink } from "lucide-react";
+import { Search, Download, Trash2, Check, Package, ExternalLink, Star } from "lucide-react";
 
 const OLLAMA_LIBRARY_URL = "https://ollama.com/library";
 import { cn } from "@/lib/utils";
@@ -34,6 +34,12 @@ function formatBytes(n: number): string {
   return String(n);
 }
 
+function formatEta(seconds: number): string {
+  if (seconds >= 3600) return `${Math.floor(seconds / 3600)}h ${Math.floor((seconds % 3600) / 60)}m`;
+  if (seconds >= 60) return `${Math.floor(seconds / 60)}m ${seconds % 60}s`;
+  return `${seconds}s`;
+}
+
 interface ModelLibraryModalProps {
   open: boolean;
   onClose: () => void;
@@ -59,8 +65,11 @@ export function ModelLibraryModal({
     completed: number;
     total: number;
     status?: string;
+    bytesPerSec?: number;
+    etaSeconds?: number;
   } | null>(null);
   const [removingTag, setRemovingTag] = useState<string | null>(null);
+  const [favorites, setFavorites] = useState<string[]>([]);
   const { toast } = useToast();
 
   const refreshInstalled = useCallback(async () => {
@@ -70,6 +79,7 @@ export function ModelLibraryModal({
       const list = await api.ollamaListModels();
       setInstalled(list);
       onDiagnosticLog?.("model list refresh", { count: list.length });
+      setFavorites(await api.listFavoriteModels().catch(() => []));
     } catch (e) {
       setInstalled([]);
       toast({ title: "Could not load models", description: String(e), variant: "destructive" });
@@ -94,15 +104,23 @@ export function ModelLibraryModal({
       total?: number;
       percent?: number;
       status?: string;
+      bytes_per_sec?: number | null;
+      eta_seconds?: number | null;
     }>("model-pull-progress", (e) => {
       const p = e.payload;
       if (pullingTag && p.tag === pullingTag) {
-        setPullProgress({
-          percent: p.percent ?? 0,
-          completed: p.completed ?? 0,
-          total: p.total ?? 0,
+        // Status-only events (manifest, verifying) carry no numbers: keep the last progress.
+        // Speed and ETA are recomputed on every byte update; a missing value (new layer, stalled
+        // download) clears them rather than leaving a stale estimate on screen.
+        const hasBytes = p.completed != null;
+        setPullProgress((prev) => ({
+          percent: p.percent ?? prev?.percent ?? 0,
+          completed: p.completed ?? prev?.completed ?? 0,
+          total: p.total ?? prev?.total ?? 0,
           status: p.status,
-        });
+          bytesPerSec: hasBytes ? p.bytes_per_sec ?? undefined : prev?.bytesPerSec,
+          etaSeconds: hasBytes ? p.eta_seconds ?? undefined : prev?.etaSeconds,
+        }));
       }
     });
     const unsubDone = listen<{ tag: string }>("model-pull-done", (e) => {
@@ -148,8 +166,14 @@ export function ModelLibraryModal({
         }));
     }
     if (tab === "installed") {
+      // Favorites are pinned to the top, in the order they were added.
+      const rank = (name: string) => {
+        const i = favorites.indexOf(name);
+        return i === -1 ? favorites.length : i;
+      };
       return installed
         .filter((m) => match(m.name) || match(m.name.split(":")[0]))
+        .sort((a, b) => rank(a.name) - rank(b.name))
         .map((m) => ({
           tag: m.name,
           name: getCatalogDisplayName(m.name),
@@ -204,6 +228,17 @@ export function ModelLibraryModal({
     }
   };
 
+  const toggleFavorite = async (tag: string) => {
+    try {
+      const next = favorites.includes(tag)
+        ? await api.removeFavoriteModel(tag)
+        : await api.addFavoriteModel(tag);
+      setFavorites(next);
+    } catch (e) {
+      toast({ title: "Could not update favorites", description: String(e), variant: "destructive" });
+    }
+  };
+
   const handleSetActive = async (tag: string) => {
     try {
       const s = await api.getSettings();
@@ -270,6 +305,8 @@ export function ModelLibraryModal({
             {pullProgress && pullProgress.total > 0 && (
               <p className="mt-1 text-xs text-muted-foreground">
                 {formatBytes(pullProgress.completed)} / {formatBytes(pullProgress.total)}
+                {pullProgress.bytesPerSec != null && pullProgress.bytesPerSec > 0 && ` · ${formatBytes(pullProgress.bytesPerSec)}/s`}
+                {pullProgress.etaSeconds != null && ` · ${formatEta(pullProgress.etaSeconds)} left`}
               </p>
             )}
           </div>
@@ -328,6 +365,17 @@ export function ModelLibraryModal({
                       </p>
                     </div>
                     <div className="flex shrink-0 flex-wrap items-center gap-2">
+                      {isInstalled && (
+                        <Button
+                          variant="ghost"
+                          size="sm"
+                          onClick={() => toggleFavorite(row.tag)}
+                          className={cn("text-muted-foreground", favorites.includes(row.tag) && "text-yellow-500")}
+                          title={favorites.includes(row.tag) ? "Unpin from favorites" : "Pin to favorites"}
+                        >
+                          <Star className={cn("h-3.5 w-3.5", favorites.includes(row.tag) && "fill-current")} />
+                        </Button>
+                      )}
                       {isActive ? (
                         <span className="text-sm text-muted-foreground">Current model</span>
                       ) : (
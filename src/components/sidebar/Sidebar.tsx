@@ -12,12 +12,14 @@ import {
   Pencil,
   Info,
   Cpu,
+  ArrowUpToLine,
+  Eraser,
 } from "lucide-react";
 import { LogoPlaceholder } from "@/components/LogoPlaceholder";
 import { AboutModal } from "@/components/AboutModal";
 import { cn } from "@/lib/utils";
 import { formatDate } from "@/lib/utils";
-import { api, type ConversationDto, type McpSettingsDto } from "@/lib/api";
+import { api, errorMessage, type ConversationDto, type McpSettingsDto } from "@/lib/api";
 import { DEFAULT_SYSTEM_PROMPT } from "@/lib/defaultSystemPrompt";
 import { DEFAULT_MODEL } from "@/lib/constants";
 import {
@@ -35,6 +37,10 @@ interface SidebarProps {
   onCreate: () => void;
   onDelete: (id: string) => void;
   onRename: (id: string, title: string) => void;
+  /** Pin the conversation to the top of the list (manual order). */
+  onMoveToTop?: (id: string) => void;
+  /** Delete old empty chats (keeps the open one). */
+  onPruneEmpty?: () => void;
   onRefresh: () => void;
   loading: boolean;
   aboutOpen?: boolean;
@@ -54,6 +60,8 @@ export function Sidebar({
   onCreate,
   onDelete,
   onRename,
+  onMoveToTop,
+  onPruneEmpty,
   loading,
   aboutOpen = false,
   onAboutOpenChange,
@@ -103,6 +111,17 @@ export function Sidebar({
           onChange={(e) => setSearch(e.target.value)}
           className="h-8 border-0 bg-transparent focus-visible:ring-0"
         />
+        {onPruneEmpty && (
+          <Button
+            variant="ghost"
+            size="icon"
+            className="h-7 w-7 shrink-0"
+            onClick={onPruneEmpty}
+            title="Delete empty chats older than an hour"
+          >
+            <Eraser className="h-3.5 w-3.5" />
+          </Button>
+        )}
       </div>
       <ScrollArea className="flex-1">
         <div className="p-2">
@@ -149,6 +168,17 @@ export function Sidebar({
                       </span>
                     </button>
                     <div className="flex opacity-0 group-hover:opacity-100">
+                      {onMoveToTop && (
+                        <Button
+                          variant="ghost"
+                          size="icon"
+                          className="h-7 w-7"
+                          title="Move to top"
+                          onClick={() => onMoveToTop(c.id)}
+                        >
+                          <ArrowUpToLine className="h-3 w-3" />
+                        </Button>
+                      )}
                       <Button
                         variant="ghost"
                         size="icon"
@@ -224,17 +254,71 @@ const defaultMcpSettings: McpSettingsDto = {
   obsidian_vault_path: "",
   web_search_enabled: false,
   terminal_enabled: false,
+  web_search_enable_fallbacks: true,
+  tool_result_max_chars: 16000,
+  terminal_shell: "",
+  fetch_timeout_secs: 8,
+  web_search_timeout_secs: 10,
+  safe_mode: false,
+  safe_mode_block_writes: true,
+  max_concurrent_tools: 3,
+  offline_mode: false,
+  open_path_allow_any_file: false,
+  fetch_allow_insecure_redirect: false,
 };
 
+/** Runs one tool against the saved settings and shows the outcome inline. */
+function ToolTestButton({ tool, args }: { tool: string; args: Record<string, unknown> }) {
+  const [running, setRunning] = useState(false);
+  const [outcome, setOutcome] = useState<{ ok: boolean; text: string } | null>(null);
+  const run = async () => {
+    setRunning(true);
+    try {
+      const result = await api.testMcpTool(tool, args);
+      const steps = result.diagnostic_steps?.length ?? 0;
+      setOutcome(
+        result.ok
+          ? { ok: true, text: `${tool} works (${result.content.length} chars, ${steps} steps)` }
+          : { ok: false, text: result.error ?? `${tool} failed` }
+      );
+    } catch (e) {
+      setOutcome({ ok: false, text: errorMessage(e) });
+    } finally {
+      setRunning(false);
+    }
+  };
+  return (
+    <div className="flex items-center gap-2">
+      <Button type="button" variant="outline" size="sm" className="h-7 text-xs" disabled={running} onClick={run} title="Runs with the saved settings">
+        {running ? "Testing…" : "Test"}
+      </Button>
+      {outcome && (
+        <span className={cn("text-xs", outcome.ok ? "text-green-600 dark:text-green-400" : "text-destructive")}>
+          {outcome.text}
+        </span>
+      )}
+    </div>
+  );
+}
+
 function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; onOpenDiagnostics?: () => void }) {
   const [theme, setTheme] = useState("system");
   const [model, setModel] = useState("");
   const [systemPrompt, setSystemPrompt] = useState("");
   const [toolCallingMode, setToolCallingMode] = useState(true);
   const [inferenceDevicePreference, setInferenceDevicePreference] = useState<"auto" | "prefer_gpu" | "force_cpu">("auto");
+  const [rollingSummaryEnabled, setRollingSummaryEnabled] = useState(false);
+  const [responseCacheEnabled, setResponseCacheEnabled] = useState(false);
+  const [temperature, setTemperature] = useState(0.7);
+  const [rollingSummaryThreshold, setRollingSummaryThreshold] = useState(40);
+  const [healthCheckIntervalSecs, setHealthCheckIntervalSecs] = useState(5);
+  const [agentMaxIterations, setAgentMaxIterations] = useState(10);
+  const [agentMaxToolCalls, setAgentMaxToolCalls] = useState(20);
+  const [agentTimeoutSecs, setAgentTimeoutSecs] = useState(300);
   const [performanceStatus, setPerformanceStatus] = useState<{ gpu_detected: boolean; gpu_name: string; active_device: string } | null>(null);
   const [models, setModels] = useState<string[]>([]);
   const [mcp, setMcp] = useState<McpSettingsDto>(defaultMcpSettings);
+  const [saveError, setSaveError] = useState<string | null>(null);
 
   useEffect(() => {
     (async () => {
@@ -247,6 +331,14 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
       setInferenceDevicePreference(
         pref === "prefer_gpu" || pref === "force_cpu" ? pref : "auto"
       );
+      setRollingSummaryEnabled(s.rolling_summary_enabled === true);
+      setRollingSummaryThreshold(s.rolling_summary_threshold ?? 40);
+      setResponseCacheEnabled(s.response_cache_enabled === true);
+      setTemperature(s.temperature ?? 0.7);
+      setHealthCheckIntervalSecs(s.health_check_interval_secs ?? 5);
+      setAgentMaxIterations(s.agent_max_iterations ?? 10);
+      setAgentMaxToolCalls(s.agent_max_tool_calls ?? 20);
+      setAgentTimeoutSecs(s.agent_timeout_secs ?? 300);
       try {
         const status = await api.getPerformanceStatus();
         setPerformanceStatus({
@@ -279,12 +371,26 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
       theme,
       selected_model: model || DEFAULT_MODEL,
       system_prompt: promptToSave,
-      temperature: 0.7,
+      temperature,
       max_tokens: 2048,
       tool_calling_mode: toolCallingMode,
       inference_device_preference: inferenceDevicePreference,
+      rolling_summary_enabled: rollingSummaryEnabled,
+      rolling_summary_threshold: rollingSummaryThreshold,
+      response_cache_enabled: responseCacheEnabled,
+      health_check_interval_secs: healthCheckIntervalSecs,
+      agent_max_iterations: agentMaxIterations,
+      agent_max_tool_calls: agentMaxToolCalls,
+      agent_timeout_secs: agentTimeoutSecs,
     });
-    await api.saveMcpSettings(mcp);
+    try {
+      await api.saveMcpSettings(mcp);
+    } catch (e) {
+      // e.g. an enabled Obsidian vault or filesystem root that is not an existing folder
+      setSaveError(errorMessage(e));
+      return;
+    }
+    setSaveError(null);
     document.documentElement.classList.toggle(
       "dark",
       theme === "dark" ||
@@ -361,6 +467,115 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
             <p className="mt-1 text-xs text-muted-foreground">
               When ON: lower temperature and stricter JSON for tool calls. Recommended when using MCP tools.
             </p>
+            <p className="mt-3 text-xs font-medium text-muted-foreground">Agent limits per request</p>
+            <p className="text-xs text-muted-foreground">
+              When a limit is reached the model stops calling tools and answers with what it has.
+            </p>
+            <div className="mt-1 flex flex-wrap items-center gap-3">
+              <label className="flex items-center gap-2 text-xs">
+                Turns
+                <input
+                  type="number"
+                  min={1}
+                  max={50}
+                  value={agentMaxIterations}
+                  onChange={(e) => setAgentMaxIterations(Number(e.target.value) || 10)}
+                  className="w-16 rounded border bg-background px-2 py-1 text-sm"
+                />
+              </label>
+              <label className="flex items-center gap-2 text-xs">
+                Tool calls
+                <input
+                  type="number"
+                  min={1}
+                  max={200}
+                  value={agentMaxToolCalls}
+                  onChange={(e) => setAgentMaxToolCalls(Number(e.target.value) || 20)}
+                  className="w-16 rounded border bg-background px-2 py-1 text-sm"
+                />
+              </label>
+              <label className="flex items-center gap-2 text-xs">
+                Seconds
+                <input
+                  type="number"
+                  min={10}
+                  max={3600}
+                  value={agentTimeoutSecs}
+                  onChange={(e) => setAgentTimeoutSecs(Number(e.target.value) || 300)}
+                  className="w-20 rounded border bg-background px-2 py-1 text-sm"
+                />
+              </label>
+            </div>
+          </div>
+          <div>
+            <label className="flex items-center gap-2 cursor-pointer">
+              <input
+                type="checkbox"
+                checked={rollingSummaryEnabled}
+                onChange={(e) => setRollingSummaryEnabled(e.target.checked)}
+                className="rounded"
+              />
+              <span className="text-sm font-medium">Summarize long chats</span>
+            </label>
+            <p className="mt-1 text-xs text-muted-foreground">
+              Once a chat has more messages than the limit below, older messages are summarized in the background and the model gets the summary plus recent messages. All messages stay visible.
+            </p>
+            {rollingSummaryEnabled && (
+              <input
+                type="number"
+                min={12}
+                max={1000}
+                value={rollingSummaryThreshold}
+                onChange={(e) => setRollingSummaryThreshold(Number(e.target.value) || 40)}
+                className="mt-2 w-24 rounded border bg-background px-3 py-2 text-sm"
+              />
+            )}
+          </div>
+          <div>
+            <label className="flex items-center gap-2 cursor-pointer">
+              <input
+                type="checkbox"
+                checked={responseCacheEnabled}
+                onChange={(e) => setResponseCacheEnabled(e.target.checked)}
+                className="rounded"
+              />
+              <span className="text-sm font-medium">Cache identical replies</span>
+            </label>
+            <p className="mt-1 text-xs text-muted-foreground">
+              Reuse the reply to an identical request (same model, messages and options) from the last 24 hours. Only applies when the temperature below is 0 and no tools are offered.
+            </p>
+            <label className="mt-2 flex items-center gap-2 text-sm">
+              Temperature
+              <input
+                type="number"
+                min={0}
+                max={2}
+                step={0.1}
+                value={temperature}
+                onChange={(e) => {
+                  const value = Number(e.target.value);
+                  setTemperature(Number.isFinite(value) ? value : 0.7);
+                }}
+                className="w-20 rounded border bg-background px-2 py-1 text-sm"
+              />
+            </label>
+            <p className="mt-1 text-xs text-muted-foreground">
+              0 gives the same reply every time; higher values vary more. Tool calls use 0.3 while tool-calling mode is on.
+            </p>
+            <Button
+              variant="outline"
+              size="sm"
+              className="mt-2"
+              onClick={async () => {
+                try {
+                  await api.clearResponseCache();
+                } catch (e) {
+                  console.error("Failed to clear response cache", e);
+                }
+              }}
+            >
+              Clear cache
+            </Button>
           </div>
 
           <Separator className="my-4" />
@@ -388,7 +603,7 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
                     ? `GPU available (${performanceStatus.gpu_name || "NVIDIA/AMD/Apple"})`
                     : "CPU only"}
                 </p>
-                <p>
+                <p title="Estimated from how much of the loaded model sits in GPU memory.">
                   Current run: {performanceStatus.active_device === "unknown"
                     ? "Unknown (Ollama-managed)"
                     : performanceStatus.active_device === "gpu"
@@ -399,6 +614,17 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
                 </p>
               </div>
             )}
+            <div className="mt-2">
+              <label className="text-xs font-medium text-muted-foreground">Ollama check interval (seconds)</label>
+              <input
+                type="number"
+                min={1}
+                max={300}
+                value={healthCheckIntervalSecs}
+                onChange={(e) => setHealthCheckIntervalSecs(Number(e.target.value) || 5)}
+                className="mt-1 w-24 rounded border bg-background px-3 py-2 text-sm"
+              />
+            </div>
           </div>
 
           <Separator className="my-4" />
@@ -408,6 +634,45 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
               When enabled, the assistant can use these tools. Only enabled tools are sent to the model. You choose the root paths; the app never accesses files outside them.
             </p>
             <div className="mt-3 space-y-3">
+              <div className="rounded border p-3 space-y-2">
+                <label className="flex items-center gap-2 cursor-pointer">
+                  <input
+                    type="checkbox"
+                    checked={mcp.offline_mode === true}
+                    onChange={(e) => setMcp((prev) => ({ ...prev, offline_mode: e.target.checked }))}
+                    className="rounded"
+                  />
+                  <span className="text-sm font-medium">Offline mode</span>
+                </label>
+                <p className="text-xs text-muted-foreground">
+                  No outbound connections except to a local Ollama. Web search, URL fetching and browser search are refused without touching the network.
+                </p>
+              </div>
+              <div className="rounded border p-3 space-y-2">
+                <label className="flex items-center gap-2 cursor-pointer">
+                  <input
+                    type="checkbox"
+                    checked={mcp.safe_mode === true}
+                    onChange={(e) => setMcp((prev) => ({ ...prev, safe_mode: e.target.checked }))}
+                    className="rounded"
+                  />
+                  <span className="text-sm font-medium">Safe mode</span>
+                </label>
+                <p className="text-xs text-muted-foreground">
+                  Turns off terminal tools whatever their toggles say. Useful for demos and untrusted models.
+                </p>
+                {mcp.safe_mode && (
+                  <label className="flex items-center gap-2 cursor-pointer text-xs">
+                    <input
+                      type="checkbox"
+                      checked={mcp.safe_mode_block_writes !== false}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, safe_mode_block_writes: e.target.checked }))}
+                      className="rounded"
+                    />
+                    Also block tools that write files
+                  </label>
+                )}
+              </div>
               <div className="rounded border p-3 space-y-2">
                 <label className="flex items-center gap-2 cursor-pointer">
                   <input
@@ -430,6 +695,18 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
                     />
                   </div>
                 )}
+                {mcp.filesystem_enabled && (
+                  <label className="flex items-center gap-2 cursor-pointer text-xs">
+                    <input
+                      type="checkbox"
+                      checked={mcp.open_path_allow_any_file === true}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, open_path_allow_any_file: e.target.checked }))}
+                      className="rounded"
+                    />
+                    Let open_path open programs and scripts, not just documents and folders
+                  </label>
+                )}
+                {mcp.filesystem_enabled && <ToolTestButton tool="list_dir" args={{}} />}
               </div>
               <div className="rounded border p-3 space-y-2">
                 <label className="flex items-center gap-2 cursor-pointer">
@@ -453,6 +730,7 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
                     />
                   </div>
                 )}
+                {mcp.obsidian_enabled && <ToolTestButton tool="obsidian_list_notes" args={{}} />}
               </div>
               <div className="rounded border p-3 space-y-2">
                 <label className="flex items-center gap-2 cursor-pointer">
@@ -465,6 +743,40 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
                   <span className="text-sm font-medium">Web search</span>
                 </label>
                 <p className="text-xs text-muted-foreground">Search the web via DuckDuckGo. Returns snippets and URLs. Requires internet.</p>
+                {mcp.web_search_enabled && (
+                  <label className="flex items-center gap-2 cursor-pointer">
+                    <input
+                      type="checkbox"
+                      checked={mcp.web_search_enable_fallbacks ?? true}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, web_search_enable_fallbacks: e.target.checked }))}
+                      className="rounded"
+                    />
+                    <span className="text-xs">Use Wikipedia/Wikidata fallbacks when DuckDuckGo has no results</span>
+                  </label>
+                )}
+                {mcp.web_search_enabled && (
+                  <label className="flex items-center gap-2 cursor-pointer">
+                    <input
+                      type="checkbox"
+                      checked={mcp.web_search_html_fallback ?? false}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, web_search_html_fallback: e.target.checked }))}
+                      className="rounded"
+                    />
+                    <span className="text-xs">Scrape DuckDuckGo's HTML results page when the instant-answer API has no results</span>
+                  </label>
+                )}
+                {mcp.web_search_enabled && (
+                  <label className="flex items-center gap-2 cursor-pointer">
+                    <input
+                      type="checkbox"
+                      checked={mcp.fetch_allow_insecure_redirect === true}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, fetch_allow_insecure_redirect: e.target.checked }))}
+                      className="rounded"
+                    />
+                    <span className="text-xs">Let fetch_url follow redirects from https to plain http (the page can then be read or altered in transit)</span>
+                  </label>
+                )}
+                {mcp.web_search_enabled && <ToolTestButton tool="web_search" args={{ query: "Wikipedia" }} />}
               </div>
               <div className="rounded border p-3 space-y-2 border-orange-200 dark:border-orange-800">
                 <label className="flex items-center gap-2 cursor-pointer">
@@ -480,10 +792,104 @@ function SettingsModal({ onClose, onOpenDiagnostics }: { onClose: () => void; on
                   Execute shell commands. On Windows a new PowerShell window opens when the assistant runs a command. <strong className="text-orange-600 dark:text-orange-400">High risk:</strong> Commands run with your user permissions.
                   One command per call. Click Save to apply.
                 </p>
+                {mcp.terminal_enabled && (
+                  <div className="space-y-2">
+                    <label className="text-xs text-muted-foreground">
+                      Shell for run_command (bash, zsh, sh, dash, fish; on Windows cmd, powershell, pwsh). Empty uses sh, or cmd on Windows. PowerShell and cmd quote arguments differently.
+                    </label>
+                    <input
+                      type="text"
+                      value={mcp.terminal_shell ?? ""}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, terminal_shell: e.target.value }))}
+                      className="w-32 rounded border bg-background px-3 py-2 text-xs"
+                      placeholder="default"
+                    />
+                    <label className="text-xs text-muted-foreground">Extra blocked command patterns (one per line; built-in blocklist always applies)</label>
+                    <textarea
+                      value={(mcp.terminal_extra_blocked_patterns ?? []).join("\n")}
+                      onChange={(e) =>
+                        setMcp((prev) => ({ ...prev, terminal_extra_blocked_patterns: e.target.value.split("\n") }))
+                      }
+                      className="w-full rounded border bg-background px-3 py-2 text-xs min-h-[60px]"
+                      placeholder="cleanup-prod.sh"
+                    />
+                    <label className="flex items-center gap-2 cursor-pointer">
+                      <input
+                        type="checkbox"
+                        checked={mcp.terminal_block_word_boundaries ?? false}
+                        onChange={(e) => setMcp((prev) => ({ ...prev, terminal_block_word_boundaries: e.target.checked }))}
+                        className="rounded"
+                      />
+                      <span className="text-xs">Match patterns as whole words (fewer false positives)</span>
+                    </label>
+                    <ToolTestButton tool="system_info" args={{}} />
+                  </div>
+                )}
+              </div>
+              <div className="rounded border p-3 space-y-2">
+                <label className="text-sm font-medium">Max tool result size (characters)</label>
+                <p className="text-xs text-muted-foreground">
+                  Longer tool results are truncated before they reach the model. 0 disables the limit.
+                </p>
+                <input
+                  type="number"
+                  min={0}
+                  step={1000}
+                  value={mcp.tool_result_max_chars ?? 16000}
+                  onChange={(e) => setMcp((prev) => ({ ...prev, tool_result_max_chars: Math.max(0, Number(e.target.value) || 0) }))}
+                  className="w-24 rounded border bg-background px-3 py-2 text-sm"
+                />
+              </div>
+              <div className="rounded border p-3 space-y-2">
+                <label className="text-sm font-medium">Network timeouts (seconds)</label>
+                <p className="text-xs text-muted-foreground">
+                  Raise these on slow connections. Values are clamped to 1–30.
+                </p>
+                <div className="flex items-center gap-3">
+                  <label className="flex items-center gap-2 text-sm">
+                    Page fetch
+                    <input
+                      type="number"
+                      min={1}
+                      max={30}
+                      value={mcp.fetch_timeout_secs ?? 8}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, fetch_timeout_secs: Math.min(30, Math.max(1, Number(e.target.value) || 1)) }))}
+                      className="w-16 rounded border bg-background px-2 py-1 text-sm"
+                    />
+                  </label>
+                  <label className="flex items-center gap-2 text-sm">
+                    Web search
+                    <input
+                      type="number"
+                      min={1}
+                      max={30}
+                      value={mcp.web_search_timeout_secs ?? 10}
+                      onChange={(e) => setMcp((prev) => ({ ...prev, web_search_timeout_secs: Math.min(30, Math.max(1, Number(e.target.value) || 1)) }))}
+                      className="w-16 rounded border bg-background px-2 py-1 text-sm"
+                    />
+                  </label>
+                </div>
+              </div>
+              <div className="rounded border p-3 space-y-2">
+                <label className="flex items-center gap-2 text-sm font-medium">
+                  Max concurrent tool calls
+                  <input
+                    type="number"
+                    min={1}
+                    max={16}
+                    value={mcp.max_concurrent_tools ?? 3}
+                    onChange={(e) => setMcp((prev) => ({ ...prev, max_concurrent_tools: Math.min(16, Math.max(1, Number(e.target.value) || 1)) }))}
+                    className="w-16 rounded border bg-background px-2 py-1 text-sm"
+                  />
+                </label>
+                <p className="text-xs text-muted-foreground">
+                  Further calls wait for a running one to finish instead of failing.
+                </p>
               </div>
             </div>
           </div>
         </div>
+        {saveError && <p className="mt-4 text-xs text-destructive">{saveError}</p>}
         <div className="mt-6 flex flex-wrap justify-end gap-2">
           {onOpenDiagnostics && (
             <Button variant="outline" onClick={onOpenDiagnostics}>
@@ -0,0 +1,61 @@
+import { useState } from "react";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { api } from "@/lib/api";
+import { Lock } from "lucide-react";
+
+interface UnlockScreenProps {
+  onUnlocked: () => void;
+}
+
+/** Asks for the passphrase of the encrypted database when the app was started without a terminal. */
+export function UnlockScreen({ onUnlocked }: UnlockScreenProps) {
+  const [passphrase, setPassphrase] = useState("");
+  const [unlocking, setUnlocking] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  const unlock = async () => {
+    if (!passphrase || unlocking) return;
+    setUnlocking(true);
+    setError(null);
+    try {
+      await api.unlockDatabase(passphrase);
+      setPassphrase("");
+      onUnlocked();
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setUnlocking(false);
+    }
+  };
+
+  return (
+    <div className="flex h-screen w-full items-center justify-center bg-background">
+      <form
+        className="w-full max-w-sm space-y-4 rounded-lg border p-6 shadow-sm"
+        onSubmit={(e) => {
+          e.preventDefault();
+          unlock();
+        }}
+      >
+        <h1 className="flex items-center gap-2 text-lg font-semibold">
+          <Lock className="h-5 w-5" />
+          Unlock your data
+        </h1>
+        <p className="text-sm text-muted-foreground">Your conversations are encrypted. Enter the database passphrase to open them.</p>
+        <Input
+          type="password"
+          autoFocus
+          autoComplete="current-password"
+          placeholder="Passphrase"
+          value={passphrase}
+          onChange={(e) => setPassphrase(e.target.value)}
+        />
+        {error && <p className="text-sm text-destructive">{error}</p>}
+        <Button type="submit" className="w-full" disabled={!passphrase || unlocking}>
+          {unlocking ? "Unlocking…" : "Unlock"}
+        </Button>
+      </form>
+    </div>
+  );
+}
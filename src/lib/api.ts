@@ -1,11 +1,40 @@
 import { invoke } from "@tauri-apps/api/core";
 
+export interface ChatOptions {
+  temperature?: number | null;
+  num_predict?: number | null;
+}
+
+export interface SeedMessageDto {
+  role: "user" | "assistant" | "system";
+  content: string;
+}
+
+/** A reusable start for new conversations (see createConversationFromTemplate). */
+export interface ConversationTemplateDto {
+  id: string;
+  name: string;
+  model: string | null;
+  system_prompt: string | null;
+  seed_messages: SeedMessageDto[];
+  created_at: number;
+  updated_at: number;
+}
+
 export interface ConversationDto {
   id: string;
   title: string;
   created_at: number;
   updated_at: number;
   message_ids: string[];
+  /** Model to pre-select for this chat (override, else latest assistant message's model, else selected_model). */
+  last_model_used?: string | null;
+  /** Options last sent for this chat, with unset fields filled from the global settings. */
+  chat_options?: ChatOptions;
+  /** Manual sidebar position (see reorderConversations); null when not hand-ordered. */
+  sort_order?: number | null;
+  /** The conversation's own system prompt (from a template); used instead of the model or global prompt. */
+  system_prompt?: string | null;
 }
 
 export interface MessageDto {
@@ -13,6 +42,39 @@ export interface MessageDto {
   role: string;
   content: string;
   timestamp: number;
+  /** Model that produced the message (assistant messages). */
+  model?: string | null;
+  starred?: boolean;
+  /** The reply did not finish (stopped, or the stream was cut off) and the partial text was kept. */
+  interrupted?: boolean;
+  /** Set when the message is one of several regenerated answers to the same turn. */
+  variant?: MessageVariantDto | null;
+  /** Web sources recorded for an assistant answer (from web_search results). */
+  citations?: CitationDto[];
+}
+
+export interface CitationDto {
+  title: string;
+  url: string;
+  provider?: string | null;
+}
+
+export interface MessageVariantDto {
+  group_id: string;
+  /** 0-based position in regeneration order. */
+  index: number;
+  count: number;
+  active: boolean;
+}
+
+export interface ConversationStatsDto {
+  message_count: number;
+  user_chars: number;
+  assistant_chars: number;
+  total_words: number;
+  estimated_tokens: number;
+  first_timestamp?: number | null;
+  last_timestamp?: number | null;
 }
 
 export interface SettingsDto {
@@ -24,6 +86,120 @@ export interface SettingsDto {
   tool_calling_mode?: boolean;
   /** "auto" | "prefer_gpu" | "force_cpu" */
   inference_device_preference?: string;
+  /** Summarize older messages once a chat has more than rolling_summary_threshold unsummarized messages. */
+  rolling_summary_enabled?: boolean;
+  rolling_summary_threshold?: number;
+  /** Reuse replies to identical temperature-0 requests without tools (opt-in). */
+  response_cache_enabled?: boolean;
+  /** Seconds between background Ollama health checks (1-300). */
+  health_check_interval_secs?: number;
+  /** Agent loop budgets per request: model turns (1-50), tool calls (1-200) and seconds (10-3600). */
+  agent_max_iterations?: number;
+  agent_max_tool_calls?: number;
+  agent_timeout_secs?: number;
+}
+
+/** "ollama-status-changed" event, emitted when Ollama becomes reachable or unreachable. */
+export interface OllamaStatusPayload {
+  reachable: boolean;
+  version?: string | null;
+}
+
+/** "ndjson-export-progress" / "ndjson-import-progress" events; total is only known for exports. */
+export interface NdjsonProgress {
+  phase: "conversations" | "messages" | "importing";
+  done: number;
+  total?: number | null;
+}
+
+export interface NdjsonImportSummary {
+  conversations_imported: number;
+  messages_imported: number;
+  /** Rows whose id already existed or whose conversation is missing. */
+  skipped: number;
+}
+
+export interface ConversationSummaryDto {
+  content: string;
+  /** Messages up to and including this id are covered by the summary. */
+  summarized_through_id: string;
+  updated_at: number;
+}
+
+/** A file pinned to a conversation; its current contents are sent as context on every turn. */
+export interface ContextFileDto {
+  /** "filesystem" (MCP filesystem root) or "obsidian" (vault) */
+  root: string;
+  path: string;
+  added_at: number;
+}
+
+/** System prompt used instead of the global one for matching models. */
+export interface ModelSystemPromptDto {
+  /** Exact model name ("qwen2.5-coder:7b") or name prefix ("qwen2.5-coder"). */
+  pattern: string;
+  prompt: string;
+  updated_at: number;
+}
+
+export interface NoteIndexReportDto {
+  indexed_files: number;
+  unchanged_files: number;
+  removed_files: number;
+  chunks_embedded: number;
+}
+
+export interface SemanticSearchHitDto {
+  path: string;
+  chunk_index: number;
+  chunk: string;
+  /** Cosine similarity to the query (higher is closer). */
+  score: number;
+}
+
+export interface LogFileInfoDto {
+  /** Absolute path to app.log. */
+  path: string;
+  exists: boolean;
+  size_bytes: number;
+  /** app.log.old left by rotation, if present. */
+  rotated_path: string | null;
+  rotated_size_bytes: number;
+  /** False in ephemeral mode (nothing is written to disk). */
+  file_logging_enabled: boolean;
+}
+
+export interface OllamaHealthDto {
+  reachable: boolean;
+  base_url: string;
+  version: string | null;
+  model_count: number;
+  /** Names of the installed models; empty when unreachable. */
+  models: string[];
+  latency_ms: number;
+}
+
+/** Disk used by app.log plus every rotated copy. */
+export interface LogDiskUsageDto {
+  total_bytes: number;
+  file_count: number;
+}
+
+export interface NetworkProbeDto {
+  name: string;
+  url: string;
+  /** True when the server answered (any HTTP status). */
+  reachable: boolean;
+  latency_ms: number | null;
+  status: number | null;
+  /** "dns" | "connect" | "tls" | "timeout" | "other" */
+  error_kind: string | null;
+  error: string | null;
+}
+
+export interface SettingsFieldErrorDto {
+  field: string;
+  message: string;
 }
 
 export interface GpuInfoDto {
@@ -31,9 +207,31 @@ export interface GpuInfoDto {
   name: string;
 }
 
+export interface TokenCountDto {
+  tokens: number;
+  /** False when Ollama could not be queried and tokens is an estimate. */
+  exact: boolean;
+}
+
+/** Estimated context fill for a conversation (see the context_usage command). */
+export interface ContextUsageDto {
+  model: string;
+  used_tokens: number;
+  context_window: number;
+  model_context_length: number | null;
+  /** Kept free for the reply (num_predict, at most half the window). */
+  reserved_tokens: number;
+  budget_tokens: number;
+  message_count: number;
+  /** Oldest messages that would fall out of the window at the current budget. */
+  dropped_messages: number;
+  estimated: boolean;
+}
+
 export interface PerformanceStatusDto {
   gpu_detected: boolean;
   gpu_name: string;
+  /** "gpu" | "cpu" | "unknown": heuristic from the loaded model's VRAM use (/api/ps). */
   active_device: string;
 }
 
@@ -44,6 +242,34 @@ export interface McpSettingsDto {
   obsidian_vault_path: string;
   web_search_enabled: boolean;
   terminal_enabled: boolean;
+  /** When false, web_search returns no results instead of using Wikipedia/Wikidata fallbacks. */
+  web_search_enable_fallbacks?: boolean;
+  /** Extra command patterns blocked for run_command/open_terminal_and_run (built-in blocklist always applies). */
+  terminal_extra_blocked_patterns?: string[];
+  /** Match blocked patterns on word boundaries ("reboot" does not block "reboot-checker.sh"). */
+  terminal_block_word_boundaries?: boolean;
+  /** Scrape DuckDuckGo's HTML results page when the instant-answer API returns nothing (off by default). */
+  web_search_html_fallback?: boolean;
+  /** Max characters of any tool result sent to the model (0 = no limit). */
+  tool_result_max_chars?: number;
+  /** Shell run_command uses (e.g. "bash", "powershell"); empty = platform default. Affects command quoting. */
+  terminal_shell?: string;
+  /** Page fetch timeout in seconds for fetch_url and web_search excerpts (1–30). */
+  fetch_timeout_secs?: number;
+  /** DuckDuckGo request timeout in seconds for web_search (1–30). */
+  web_search_timeout_secs?: number;
+  /** Hide and refuse high-risk (terminal) tools regardless of the toggles above. */
+  safe_mode?: boolean;
+  /** In safe mode, also hide and refuse tools that write files (default true). */
+  safe_mode_block_writes?: boolean;
+  /** Tool calls allowed to run at once (1–16); extra calls wait. */
+  max_concurrent_tools?: number;
+  /** No outbound connections: web tools fail immediately and Ollama must be on localhost. */
+  offline_mode?: boolean;
+  /** Let open_path open programs, scripts and other non-document files (off by default). */
+  open_path_allow_any_file?: boolean;
+  /** Let fetch_url follow redirects from https to plain http (off by default). */
+  fetch_allow_insecure_redirect?: boolean;
 }
 
 export interface McpToolDefDto {
@@ -55,23 +281,142 @@ export interface McpToolDefDto {
   json_schema?: Record<string, unknown>;
 }
 
+/** A high-risk or write tool the user chose not to confirm; root null covers every root. */
+export interface ToolAcknowledgmentDto {
+  tool_name: string;
+  root: string | null;
+  scope: "session" | "always";
+}
+
+/** A tool's settings toggle plus whether it can run right now; `reason` explains why not. */
+export interface McpToolAvailabilityDto {
+  name: string;
+  id: string;
+  risk: string;
+  enabled: boolean;
+  available: boolean;
+  reason: string | null;
+}
+
 export interface DiagnosticStepDto {
   level: string;
   message: string;
   meta?: Record<string, unknown>;
 }
 
+/** One web_search pipeline step (validate, request, parse, fallbacks, done). */
+export interface ToolStepDto {
+  name: string;
+  ok: boolean;
+  detail: string;
+}
+
 export interface McpToolResultDto {
   ok: boolean;
   content: string;
   error?: string;
+  /** Failed calls only: compact `{tool, ok: false, error, hint}` JSON to send to the model instead of `error`. */
+  model_error?: string | null;
   diagnostic_steps?: DiagnosticStepDto[];
+  /** web_search only: structured steps for a pass/fail list. */
+  steps?: ToolStepDto[] | null;
+  /** True for test_mcp_tool results. */
+  test?: boolean;
+}
+
+/**
+ * Payload of "ollama-chat-final": Ollama's last stream object (done: true) as sent, without
+ * `message`. Fields vary by Ollama version, e.g. eval_count, prompt_eval_count, total_duration.
+ */
+export type OllamaChatFinalEvent = { done: boolean; done_reason?: string } & Record<string, unknown>;
+
+/** Payload of the "tool-running" event emitted when execute_mcp_tool starts. */
+export interface ToolRunningEvent {
+  name: string;
+}
+
+/** Payload of the "tool-finished" event emitted when execute_mcp_tool returns. */
+export interface ToolFinishedEvent {
+  name: string;
+  ok: boolean;
+  elapsed_ms: number;
+}
+
+/** Payload of the "mcp-settings-changed" event emitted when save_mcp_settings changes something. */
+export interface McpSettingsChangedEvent {
+  /** McpSettingsDto field names that changed. */
+  changed: string[];
+  /** Tools enabled under the new settings. */
+  enabled_tools: string[];
+}
+
+/** Payload of the "inference-started" event emitted when a chat request begins. */
+export interface InferenceStartedEvent {
+  model: string;
+  conversation_id: string | null;
+  inference_device_preference: string;
+  gpu_detected: boolean;
+  gpu_name: string;
+}
+
+/**
+ * Payload of the "model-loading" event: `loading` is true when the first token is unusually slow
+ * (usually a cold model load) and false once it arrives or the request ends.
+ */
+export interface ModelLoadingEvent {
+  model: string;
+  conversation_id: string | null;
+  loading: boolean;
+  /** Whether the model was already in memory before the request. */
+  was_loaded: boolean;
+  elapsed_ms: number;
+}
+
+export interface InferenceStats {
+  canceled: boolean;
+  done_reason: string | null;
+  truncated: boolean;
+  chunk_count: number;
+  duration_ms: number;
+  time_to_first_token_ms: number;
+  tokens_per_sec: number;
+}
+
+/** Payload of the "inference-finished" event; `error` is set (and `stats` null) when the request failed. */
+export interface InferenceFinishedEvent {
+  model: string;
+  conversation_id: string | null;
+  stats: InferenceStats | null;
+  error: string | null;
 }
 
 export interface OllamaModelInfo {
   name: string;
   size: number;
   modified_at?: string;
+  /** Manifest digest; changes when the model is re-pulled. */
+  digest?: string;
+  family?: string;
+  parameter_size?: string;
+  quantization_level?: string;
+  /** Set when listing with a capability filter, e.g. ["chat", "vision"]. */
+  capabilities?: string[];
+}
+
+/** One Modelfile PARAMETER line; `stop` can repeat. */
+export interface ModelParameter {
+  name: string;
+  value: string;
+}
+
+/** From /api/show; fields the model or Ollama version doesn't report are null or empty. */
+export interface ModelfileInfo {
+  model: string;
+  modelfile: string | null;
+  system: string | null;
+  template: string | null;
+  parameters: ModelParameter[];
+  license: string | null;
 }
 
 export interface PullProgress {
@@ -81,47 +426,258 @@ export interface PullProgress {
   completed?: number;
 }
 
+/** Rejection value of ollamaChatStream/continueGeneration when Ollama refused the request (other commands reject with a string). */
+export interface OllamaRequestError {
+  /** HTTP status; null when Ollama could not be reached. */
+  status: number | null;
+  /** Raw response body. */
+  body: string | null;
+  /** Ollama's `error` field, else the body or connection error. */
+  message: string;
+  kind: "model_not_found" | "runner_crashed" | "out_of_memory" | "unreachable" | "other";
+}
+
+export function isOllamaRequestError(e: unknown): e is OllamaRequestError {
+  return typeof e === "object" && e !== null && "kind" in e && "message" in e;
+}
+
+/** Readable text for any command rejection (string or structured). */
+export function errorMessage(e: unknown): string {
+  return isOllamaRequestError(e) ? e.message : String(e);
+}
+
 export const api = {
   getConversations: () => invoke<ConversationDto[]>("get_conversations"),
   getConversation: (id: string) =>
     invoke<[ConversationDto, MessageDto[]] | null>("get_conversation", { id }),
+  /** The conversation without its messages, for headers; null if it doesn't exist. */
+  getConversationMetadata: (id: string) =>
+    invoke<ConversationDto | null>("get_conversation_metadata", { id }),
   createConversation: (title?: string) =>
     invoke<ConversationDto>("create_conversation", { title }),
   updateConversationTitle: (id: string, title: string) =>
     invoke<void>("update_conversation_title", { id, title }),
   deleteConversation: (id: string) =>
     invoke<void>("delete_conversation", { id }),
-  addMessage: (conversationId: string, role: string, content: string) =>
+  /** Deletes message-less chats idle for minAgeSecs (default 1h), except keepId and hand-ordered ones. */
+  pruneEmptyConversations: (keepId?: string | null, minAgeSecs?: number) =>
+    invoke<number>("prune_empty_conversations", { keepId: keepId ?? null, minAgeSecs: minAgeSecs ?? null }),
+  /** Lists orderedIds first in that order, the rest by last update; [] restores the default order. */
+  reorderConversations: (orderedIds: string[]) =>
+    invoke<number>("reorder_conversations", { orderedIds }),
+  /** interrupted marks a partial reply that was kept (stopped by the user or cut off). */
+  addMessage: (conversationId: string, role: string, content: string, model?: string, interrupted?: boolean) =>
     invoke<MessageDto>("add_message", {
       conversationId,
       role,
       content,
+      model: model ?? null,
+      interrupted: interrupted ?? null,
+    }),
+  addMessages: (conversationId: string, messages: { role: string; content: string }[]) =>
+    invoke<MessageDto[]>("add_messages", { conversationId, messages }),
+  setMessageStarred: (id: string, starred: boolean) =>
+    invoke<void>("set_message_starred", { id, starred }),
+  /** Replace the web sources stored for an assistant message. */
+  setMessageCitations: (messageId: string, citations: CitationDto[]) =>
+    invoke<void>("set_message_citations", { messageId, citations }),
+  listStarredMessages: (conversationId: string) =>
+    invoke<MessageDto[]>("list_starred_messages", { conversationId }),
+  /** Store a regenerated answer to messageId as the new active variant of that turn. */
+  addMessageVariant: (conversationId: string, messageId: string, content: string, model?: string) =>
+    invoke<MessageDto>("add_message_variant", { conversationId, messageId, content, model: model ?? null }),
+  listMessageVariants: (groupId: string) => invoke<MessageDto[]>("list_message_variants", { groupId }),
+  selectVariant: (messageId: string) => invoke<void>("select_variant", { messageId }),
+  setConversationModel: (id: string, model: string | null) =>
+    invoke<void>("set_conversation_model", { id, model }),
+  listConversationTemplates: () => invoke<ConversationTemplateDto[]>("list_conversation_templates"),
+  /** Creates a template, or updates the one with id. */
+  saveConversationTemplate: (template: {
+    id?: string;
+    name: string;
+    model?: string | null;
+    systemPrompt?: string | null;
+    seedMessages?: SeedMessageDto[];
+  }) =>
+    invoke<ConversationTemplateDto>("save_conversation_template", {
+      id: template.id ?? null,
+      name: template.name,
+      model: template.model ?? null,
+      systemPrompt: template.systemPrompt ?? null,
+      seedMessages: template.seedMessages ?? [],
     }),
+  deleteConversationTemplate: (id: string) => invoke<boolean>("delete_conversation_template", { id }),
+  /** New conversation with the template's model, system prompt (first message) and seed messages. */
+  createConversationFromTemplate: (templateId: string) =>
+    invoke<ConversationDto>("create_conversation_from_template", { templateId }),
+  undoLastExchange: (conversationId: string) =>
+    invoke<number>("undo_last_exchange", { conversationId }),
+  /** Deletes every message after messageId (rewind for edit-and-resend). Resolves to the remaining count. */
+  trimConversationTo: (conversationId: string, messageId: string) =>
+    invoke<number>("trim_conversation_to", { conversationId, messageId }),
+  conversationStats: (id: string) =>
+    invoke<ConversationStatsDto>("conversation_stats", { id }),
+  /** Writes the conversation to disk and returns the file path. */
+  exportConversation: (id: string, format: "markdown" | "json", directory?: string) =>
+    invoke<string>("export_conversation", { id, format, directory: directory ?? null }),
+  /** Write a self-contained HTML page for the conversation; resolves to its path. */
+  exportConversationHtml: (id: string, directory?: string) =>
+    invoke<string>("export_conversation_html", { id, directory: directory ?? null }),
+  /** Stream the whole history to an .ndjson file; resolves to its path. */
+  exportAllNdjson: (directory?: string) =>
+    invoke<string>("export_all_ndjson", { directory: directory ?? null }),
+  importNdjson: (path: string) => invoke<NdjsonImportSummary>("import_ndjson", { path }),
+  getConversationSummary: (id: string) =>
+    invoke<ConversationSummaryDto | null>("get_conversation_summary", { id }),
+  /** Folds older messages into the rolling summary when enabled and due. Resolves true if a summary was written. */
+  summarizeConversationIfNeeded: (id: string, model: string) =>
+    invoke<boolean>("summarize_conversation_if_needed", { id, model }),
+  addContextFile: (conversationId: string, root: "filesystem" | "obsidian", path: string) =>
+    invoke<ContextFileDto>("add_context_file", { conversationId, root, path }),
+  removeContextFile: (conversationId: string, root: string, path: string) =>
+    invoke<boolean>("remove_context_file", { conversationId, root, path }),
+  listContextFiles: (conversationId: string) =>
+    invoke<ContextFileDto[]>("list_context_files", { conversationId }),
+  /** Embeds new/changed notes under the root. model defaults to nomic-embed-text. */
+  indexNotes: (root: "filesystem" | "obsidian", model?: string) =>
+    invoke<NoteIndexReportDto>("index_notes", { root, model: model ?? null }),
+  semanticSearchNotes: (query: string, root: "filesystem" | "obsidian", topK?: number, model?: string) =>
+    invoke<SemanticSearchHitDto[]>("semantic_search_notes", {
+      query,
+      root,
+      topK: topK ?? null,
+      model: model ?? null,
+    }),
+  listModelSystemPrompts: () => invoke<ModelSystemPromptDto[]>("list_model_system_prompts"),
+  setModelSystemPrompt: (pattern: string, prompt: string) =>
+    invoke<void>("set_model_system_prompt", { pattern, prompt }),
+  deleteModelSystemPrompt: (pattern: string) =>
+    invoke<boolean>("delete_model_system_prompt", { pattern }),
+  /** Small frontend-owned JSON state (sidebar width, last tab…); null when unset. */
+  getUiState: <T = unknown>(key: string) => invoke<T | null>("get_ui_state", { key }),
+  /** Store a JSON value under key; null removes it. */
+  setUiState: (key: string, value: unknown) => invoke<void>("set_ui_state", { key, value }),
+  /** Per-model prompt for this model (exact name, then longest prefix), or null for the global one. */
+  getModelSystemPrompt: (model: string) =>
+    invoke<string | null>("get_model_system_prompt", { model }),
   getSettings: () => invoke<SettingsDto>("get_settings"),
+  /** Range-checks settings without saving; save_settings rejects the same errors. */
+  validateSettings: (settings: SettingsDto) =>
+    invoke<SettingsFieldErrorDto[]>("validate_settings", { settings }),
   saveSettings: (settings: SettingsDto) =>
     invoke<void>("save_settings", { settings }),
-  ollamaHealth: () => invoke<boolean>("ollama_health"),
-  ollamaListModels: () => invoke<OllamaModelInfo[]>("ollama_list_models"),
+  /** Reachability, version and model count; an unreachable server resolves with reachable: false. */
+  ollamaHealth: () => invoke<OllamaHealthDto>("ollama_health"),
+  /** Token count with the model's tokenizer (loads the model); estimated if Ollama is unavailable. */
+  countTokens: (model: string, text: string) => invoke<TokenCountDto>("count_tokens", { model, text }),
+  /** Context fill for a conversation; model defaults to the conversation's, then the selected one. */
+  contextUsage: (conversationId: string, model?: string) =>
+    invoke<ContextUsageDto>("context_usage", { conversationId, model: model ?? null }),
+  /** Probes DuckDuckGo, Wikipedia and the Ollama server; useful when web_search fails on restricted networks. */
+  checkNetwork: () => invoke<NetworkProbeDto[]>("check_network"),
+  /** With a capability, only models that support it (models with unknown capabilities are kept). */
+  ollamaListModels: (capability?: "chat" | "vision" | "embedding") =>
+    invoke<OllamaModelInfo[]>("ollama_list_models", { capability: capability ?? null }),
+  ollamaModelsDetailed: () => invoke<OllamaModelInfo[]>("ollama_models_detailed"),
   ollamaPullModel: (model: string) =>
     invoke<void>("ollama_pull_model", { model }),
   ollamaDeleteModel: (model: string) =>
     invoke<void>("ollama_delete_model", { model }),
+  ollamaUnloadModel: (model: string) =>
+    invoke<boolean>("ollama_unload_model", { model }),
+  /** True if the model's digest differs from knownDigest (also when it is missing or unknown). */
+  modelChangedSince: (model: string, knownDigest: string) =>
+    invoke<boolean>("model_changed_since", { model, knownDigest }),
+  /** Removes all cached replies; returns how many were removed. */
+  clearResponseCache: () => invoke<number>("clear_response_cache"),
+  /** Pinned models (still installed ones only; missing favorites are pruned). */
+  listFavoriteModels: () => invoke<string[]>("list_favorite_models"),
+  addFavoriteModel: (model: string) => invoke<string[]>("add_favorite_model", { model }),
+  removeFavoriteModel: (model: string) => invoke<string[]>("remove_favorite_model", { model }),
   ollamaShowModel: (model: string) =>
     invoke<unknown>("ollama_show_model", { model }),
+  getModelfile: (model: string) =>
+    invoke<ModelfileInfo>("get_modelfile", { model }),
+  /** Save an edited copy of `from` as `model`; omitted fields are inherited from `from`. */
+  createModel: (
+    model: string,
+    from: string,
+    edits: { system?: string; template?: string; parameters?: ModelParameter[] } = {}
+  ) =>
+    invoke<void>("create_model", {
+      model,
+      from,
+      system: edits.system ?? null,
+      template: edits.template ?? null,
+      parameters: edits.parameters ?? null,
+    }),
   ollamaChatStream: (
     model: string,
     messages: { role: string; content: string }[],
-    options?: { temperature?: number; num_predict?: number }
+    options?: { temperature?: number; num_predict?: number },
+    /** When set, the conversation's context files are re-read and sent along. */
+    conversationId?: string,
+    /** Also emit Ollama's final chunk verbatim as "ollama-chat-final" (see OllamaChatFinalEvent). */
+    emitFinalChunk?: boolean,
+    /** Tools are offered in this request; its reply is never cached. */
+    toolsEnabled?: boolean
   ) =>
     invoke<void>("ollama_chat_stream", {
       model,
       messages,
       options: options ?? {},
+      conversationId: conversationId ?? null,
+      flags: { emit_final_chunk: emitFinalChunk ?? false, tools_enabled: toolsEnabled ?? false },
     }),
-  cancelChatGeneration: () => invoke<void>("cancel_chat_generation"),
+  /**
+   * Streams the rest of a reply cut off by the length limit (same delta/done events as ollamaChatStream).
+   * The backend appends the continuation to the cut-off message and saves it; listeners should not
+   * save it again. Resolves to the updated message, or null when nothing was appended.
+   */
+  continueGeneration: (
+    conversationId: string,
+    model: string,
+    options?: { temperature?: number; num_predict?: number }
+  ) => invoke<MessageDto | null>("continue_generation", { conversationId, model, options: options ?? {} }),
+  /**
+   * Answers the last user message again with `newModel` (same delta/done events as ollamaChatStream).
+   * The backend saves the reply tagged with `newModel`, as a new variant of the previous answer when
+   * keepPrevious (default true), else in its place; listeners should not save it again.
+   * Resolves to the saved message, or null when nothing was saved.
+   */
+  retryWithModel: (
+    conversationId: string,
+    newModel: string,
+    options?: { temperature?: number; num_predict?: number },
+    keepPrevious = true
+  ) =>
+    invoke<MessageDto | null>("retry_with_model", {
+      conversationId,
+      newModel,
+      options: options ?? {},
+      keepPrevious,
+    }),
+  /** Cancels the stream for `conversationId`, or every running stream when omitted. */
+  /** keepPartial (default true) is echoed in ollama-chat-done so the UI saves or drops the partial reply. */
+  cancelChatGeneration: (conversationId?: string, keepPartial = true) =>
+    invoke<void>("cancel_chat_generation", { conversationId: conversationId ?? null, keepPartial }),
+  /** Whether a chat stream is still running for the conversation (e.g. after a reload). */
+  isGenerating: (conversationId: string) => invoke<boolean>("is_generating", { conversationId }),
+  /** Stops the running tool call early; web_search returns the results gathered so far. */
+  cancelToolExecution: () => invoke<void>("cancel_tool_execution"),
   emitDiagnosticLog: (level: string, message: string, meta?: Record<string, unknown>) =>
     invoke<void>("emit_diagnostic_log", { level, message, meta }),
   getAppDataDir: () => invoke<string>("get_app_data_dir"),
+  /** Startup problems the user should know about, e.g. a fallback data directory. */
+  getStartupNotices: () => invoke<string[]>("get_startup_notices"),
+  /** True while the encrypted database waits for its passphrase (show the unlock screen first). */
+  databaseLocked: () => invoke<boolean>("database_locked"),
+  /** Rejects with the reason (e.g. wrong passphrase); the app stays locked. */
+  unlockDatabase: (passphrase: string) => invoke<void>("unlock_database", { passphrase }),
+  getDiagnosticsFilePath: () => invoke<LogFileInfoDto>("get_diagnostics_file_path"),
+  /** Empties app.log and removes all rotated copies. Resolves to the bytes freed. */
+  clearDiagnostics: () => invoke<number>("clear_diagnostics"),
+  getLogDiskUsage: () => invoke<LogDiskUsageDto>("get_log_disk_usage"),
   openUrl: (url: string) => invoke<string>("open_url", { url }),
   getGpuInfo: () => invoke<GpuInfoDto>("get_gpu_info"),
   getPerformanceStatus: () => invoke<PerformanceStatusDto>("get_performance_status"),
@@ -131,6 +687,30 @@ export const api = {
     invoke<void>("save_mcp_settings", { settings }),
   getMcpToolDefinitions: (enabledOnly: boolean) =>
     invoke<McpToolDefDto[]>("get_mcp_tool_definitions", { enabledOnly }),
-  executeMcpTool: (name: string, args: Record<string, unknown>) =>
-    invoke<McpToolResultDto>("execute_mcp_tool", { name, arguments: args }),
+  getMcpToolAvailability: () => invoke<McpToolAvailabilityDto[]>("get_mcp_tool_availability"),
+  /**
+   * Unacknowledged high-risk and write tools are refused (a failed result) unless `confirmed`
+   * says the user approved this call; ask with toolNeedsConfirmation first.
+   */
+  executeMcpTool: (name: string, args: Record<string, unknown>, confirmed = false) =>
+    invoke<McpToolResultDto>("execute_mcp_tool", { name, arguments: args, confirmed }),
+  /** Run a tool as execute_mcp_tool would, with full diagnostic steps; uses the saved settings. */
+  testMcpTool: (name: string, args: Record<string, unknown>) =>
+    invoke<McpToolResultDto>("test_mcp_tool", { name, arguments: args }),
+  /** True for high-risk and write tools that were not acknowledged (for this session or always). */
+  toolNeedsConfirmation: (name: string) =>
+    invoke<boolean>("tool_needs_confirmation", { name }),
+  grantToolAcknowledgment: (toolName: string, always: boolean, currentRootOnly = false) =>
+    invoke<ToolAcknowledgmentDto>("grant_tool_acknowledgment", { toolName, always, currentRootOnly }),
+  /** root undefined revokes every acknowledgment of the tool; "" only the any-root one. */
+  revokeToolAcknowledgment: (toolName: string, root?: string) =>
+    invoke<number>("revoke_tool_acknowledgment", { toolName, root: root ?? null }),
+  listToolAcknowledgments: () =>
+    invoke<ToolAcknowledgmentDto[]>("list_tool_acknowledgments"),
+  /** Runs calls in order; stops at the first failure unless continueOnError is set. */
+  executeMcpToolsBatch: (
+    calls: { name: string; arguments: Record<string, unknown> }[],
+    continueOnError = false
+  ) =>
+    invoke<McpToolResultDto[]>("execute_mcp_tools_batch", { calls, continueOnError }),
 };
@@ -33,7 +33,7 @@ export function buildToolBlock(toolDefs: { name: string; description: string; js
   const lines = [
     "",
     "---",
-    "TOOLS ARE ENABLED. You MUST respond with ONLY a single JSON object—no markdown, no extra text.",
+    "TOOLS ARE ENABLED. You MUST respond with ONLY JSON—no markdown, no extra text.",
     "When the user asks you to create a file, write a file, or save something, use the write_file tool. Path is relative to the root you are given (e.g. Desktop/test_mcp.txt if root is the user's home).",
     "When the user says \"use MCP\" or \"use your tools\", you MUST respond with a tool_request.",
     "CONTEXT FROM TOOLS: Whatever content tools return (web_search results with page_excerpt, fetch_url page text) is stored as context for you. Parse it and summarize or answer from it—the user does NOT need to copy-paste anything. Use that content directly in your reply.",
@@ -41,14 +41,14 @@ export function buildToolBlock(toolDefs: { name: string; description: string; js
       ? "If the user asks for web or current information, use the web_search tool. Do NOT claim you searched if you do not call web_search. When web_search returns results (with or without page_excerpt), use the returned content as context: summarize what the pages say, cite URLs and key points; do not just list links."
       : "Web search is NOT available. Do NOT claim you searched the web, looked anything up online, or have current/live data. Say web search is not available and offer offline alternatives.",
     hasFetchUrl
-      ? "When the user asks to summarize a link, explain a page, or gives you a URL, use fetch_url with that URL. The tool returns the page content as plain text—use it as context and summarize or answer; the user does not need to paste the content."
+      ? "When the user asks to summarize a link, explain a page, or gives you a URL, use fetch_url with that URL. The tool returns JSON with ok, status, final_url and the page text in content—use that content as context and summarize or answer, citing final_url (where any redirects ended) as the source; the user does not need to paste the content."
       : "",
     hasBrowserSearch
       ? "If web_search returns suggest_open_browser_search or no usable results for time-sensitive queries (e.g. recent sports, news), call open_browser_search with the query. The tool opens the browser and returns the first result page content in the same response—use that content as context to summarize or answer; the user does not need to paste anything."
       : "",
     "",
     "Choose exactly one:",
-    "1) To use a tool: {\"type\":\"tool_request\",\"tool_name\":\"<name>\",\"arguments\":{...}}",
+    "1) To use a tool: {\"type\":\"tool_request\",\"tool_name\":\"<name>\",\"arguments\":{...}} (for several independent tools at once, one tool_request per line)",
     "2) To answer without a tool: {\"type\":\"final_answer\",\"content\":\"your reply here\"}",
     "",
     ...(hasWrite
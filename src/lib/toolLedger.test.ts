@@ -7,8 +7,9 @@ import {
   buildProvenanceFooter,
   CORRECTED_MESSAGE_NO_WEB_SEARCH,
   getWebSourcesFromLedger,
+  getWebCitationsFromLedger,
 } from "./toolLedger";
-import { parseToolResponse } from "./toolPrompt";
+import { parseToolRequests, parseToolResponse } from "./toolPrompt";
 
 describe("hasFakeWebSearchClaim", () => {
   it("detects 'After searching…'", () => {
@@ -94,6 +95,27 @@ describe("getWebSourcesFromLedger", () => {
   });
 });
 
+describe("getWebCitationsFromLedger", () => {
+  it("returns title, url and provider from structured results, deduped", () => {
+    const ledger = createLedger(["web_search"]);
+    const output = JSON.stringify({
+      ok: true,
+      provider: "duckduckgo",
+      query: "x",
+      status: 200,
+      result_count: 2,
+      results: [
+        { title: "Page", snippet: "s", url: "https://example.com/page" },
+        { title: "Page again", snippet: "s", url: "https://example.com/page" },
+      ],
+    });
+    recordInvocation(ledger, "web_search", { query: "x" }, "success", "ok", output);
+    expect(getWebCitationsFromLedger(ledger)).toEqual([
+      { title: "Page", url: "https://example.com/page", provider: "duckduckgo" },
+    ]);
+  });
+});
+
 describe("parseToolResponse (multiple JSON objects)", () => {
   it("parses first tool_request when model outputs two on separate lines", () => {
     const two = '{"type":"tool_request","tool_name":"web_search","arguments":{"query":"current president","max_results":1}}\n{"type":"tool_request","tool_name":"write_file","arguments":{"path":"Desktop/current_president.txt","content":"The current president is [result from web search]."}}';
@@ -108,3 +130,19 @@ describe("parseToolResponse (multiple JSON objects)", () => {
     expect(parsed?.type === "tool_request" && parsed.tool_name).toBe("write_file");
   });
 });
+
+describe("parseToolRequests", () => {
+  it("returns every tool_request line in order", () => {
+    const two = '{"type":"tool_request","tool_name":"read_file","arguments":{"path":"a.txt"}}\n{"type":"tool_request","tool_name":"list_dir","arguments":{"path":"."}}';
+    expect(parseToolRequests(two).map((r) => r.tool_name)).toEqual(["read_file", "list_dir"]);
+  });
+  it("keeps braces and newlines inside a single request's arguments", () => {
+    const one = '{"type":"tool_request","tool_name":"write_file","arguments":{"path":"a.json","content":"{\\n  \\"a\\": 1\\n}"}}';
+    const requests = parseToolRequests(one);
+    expect(requests).toHaveLength(1);
+    expect(requests[0].arguments.content).toBe('{\n  "a": 1\n}');
+  });
+  it("returns nothing for a final answer", () => {
+    expect(parseToolRequests('{"type":"final_answer","content":"done"}')).toEqual([]);
+  });
+});
@@ -100,6 +100,25 @@ export function getWebSourcesFromLedger(ledger: ToolLedger): string[] {
   return [...new Set(urls)].slice(0, 20);
 }
 
+/** Structured web_search results (title, url, provider) for storing as message citations. Deduped by URL. */
+export function getWebCitationsFromLedger(
+  ledger: ToolLedger
+): Array<{ title: string; url: string; provider: string | null }> {
+  const seen = new Set<string>();
+  const out: Array<{ title: string; url: string; provider: string | null }> = [];
+  for (let i = 0; i < ledger.invoked_tools.length; i++) {
+    if (ledger.invoked_tools[i].name !== "web_search" || ledger.invoked_tools[i].status !== "success") continue;
+    const parsed = parseWebSearchOutput(ledger.tool_outputs[i] ?? "");
+    if (!parsed) continue;
+    for (const r of parsed.results) {
+      if (!r?.url || seen.has(r.url)) continue;
+      seen.add(r.url);
+      out.push({ title: r.title || r.url, url: r.url, provider: parsed.provider || null });
+    }
+  }
+  return out.slice(0, 20);
+}
+
 /** Last successful web_search result in this request: result_count, provider, and source URLs. */
 export function getLastWebSearchResult(ledger: ToolLedger): {
   result_count: number;
@@ -2,6 +2,7 @@
  * Parse model output for JSON tool_request or final_answer.
  * Model may wrap JSON in markdown code blocks; we try to extract.
  * Model may output multiple JSON objects (e.g. two tool_request lines); we parse the FIRST valid one.
+ * parseToolRequests returns every tool_request object, for running several tools in one turn.
  */
 export type ParsedToolRequest = {
   type: "tool_request";
@@ -31,6 +32,40 @@ function parseOneJsonObject(str: string): ParsedResponse {
   return null;
 }
 
+/** Every tool_request in the model output, in order (one per JSON object; duplicates kept). */
+export function parseToolRequests(raw: string): ParsedToolRequest[] {
+  const trimmed = raw.trim();
+  const codeBlock = trimmed.match(/```(?:json)?\s*([\s\S]*?)```/);
+  const jsonStr = codeBlock ? codeBlock[1].trim() : trimmed;
+  const tryParse = (str: string): ParsedResponse => {
+    try {
+      return parseOneJsonObject(str);
+    } catch {
+      return null;
+    }
+  };
+  // A single object first: its string arguments may contain newlines or braces.
+  const single = tryParse(jsonStr);
+  if (single) return single.type === "tool_request" ? [single] : [];
+  // One object per line, then balanced {...} objects anywhere in the text.
+  const byLine = jsonStr
+    .split(/\r?\n/)
+    .map((line) => line.trim())
+    .filter((line) => line.startsWith("{"))
+    .map(tryParse);
+  if (byLine.some((r) => r !== null)) {
+    return byLine.filter((r): r is ParsedToolRequest => r?.type === "tool_request");
+  }
+  const requests: ParsedToolRequest[] = [];
+  let rest = jsonStr;
+  for (let obj = extractFirstJsonObject(rest); obj; obj = extractFirstJsonObject(rest)) {
+    rest = rest.slice(rest.indexOf(obj) + obj.length);
+    const parsed = tryParse(obj);
+    if (parsed?.type === "tool_request") requests.push(parsed);
+  }
+  return requests;
+}
+
 /** Extract the first complete {...} from text (balanced braces). */
 function extractFirstJsonObject(text: string): string | null {
   const start = text.indexOf("{");